@@ -0,0 +1,286 @@
+/*
+ * C ABI of the Soroban host environment.
+ *
+ * This header mirrors the `soroban-env-host-ffi` crate and has to be kept in
+ * sync with it. All the structured values are exchanged as XDR.
+ *
+ * Buffer ownership rules:
+ *
+ * - Input buffers (`SorobanBytes` and arrays of them) are only borrowed by
+ *   the library for the duration of the call.
+ * - Output buffers (`SorobanOwnedBytes` and the arrays) are allocated by the
+ *   library and owned by the result structure that contains them. They must
+ *   not be freed individually. Every result structure must be released
+ *   exactly once with its `_free` function. The library initializes the
+ *   result structure whenever a non-null pointer to it is passed, so it has
+ *   to be released even if the call has failed.
+ * - Module caches are opaque handles owned by the caller. They are created
+ *   with `soroban_module_cache_new` and released with
+ *   `soroban_module_cache_free`.
+ *
+ * Thread safety: module caches are not thread-safe. A cache may be used from
+ * one thread at a time only; this includes the invocations that are passed
+ * the cache. Callers that share a cache between threads have to serialize
+ * all the calls that use it. Calls that don't share a cache can run
+ * concurrently.
+ *
+ * Panics in the library never cross the ABI boundary and are reported as
+ * `SOROBAN_STATUS_PANIC`.
+ */
+#ifndef SOROBAN_ENV_HOST_H
+#define SOROBAN_ENV_HOST_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum SorobanStatus {
+    /* The call has been performed. For the invocations this doesn't mean
+     * that the invocation has succeeded, see the `success` field of the
+     * result. */
+    SOROBAN_STATUS_OK = 0,
+    /* A required pointer is null or an argument is malformed. */
+    SOROBAN_STATUS_INVALID_ARGUMENT = 1,
+    /* The host has returned an error outside of an invocation. */
+    SOROBAN_STATUS_HOST_ERROR = 2,
+    /* The library has panicked; this indicates a bug in the library. */
+    SOROBAN_STATUS_PANIC = 3,
+} SorobanStatus;
+
+/* Borrowed byte buffer. `data` may be NULL only when `len` is 0. */
+typedef struct SorobanBytes {
+    const uint8_t *data;
+    size_t len;
+} SorobanBytes;
+
+/* Byte buffer allocated by the library. `data` is NULL when `len` is 0. */
+typedef struct SorobanOwnedBytes {
+    uint8_t *data;
+    size_t len;
+} SorobanOwnedBytes;
+
+typedef struct SorobanOwnedBytesArray {
+    SorobanOwnedBytes *data;
+    size_t len;
+} SorobanOwnedBytesArray;
+
+typedef struct SorobanU32Array {
+    uint32_t *data;
+    size_t len;
+} SorobanU32Array;
+
+typedef struct SorobanLedgerInfo {
+    uint32_t protocol_version;
+    uint32_t sequence_number;
+    uint64_t timestamp;
+    uint8_t network_id[32];
+    uint32_t base_reserve;
+    uint32_t min_temp_entry_ttl;
+    uint32_t min_persistent_entry_ttl;
+    uint32_t max_entry_ttl;
+} SorobanLedgerInfo;
+
+/* Budget for a single invocation.
+ *
+ * When both cost parameter buffers are empty, the default budget of the host
+ * is used and the limits are ignored. The default budget is only meant for
+ * local testing; the network cost parameters should be used otherwise. */
+typedef struct SorobanBudgetConfig {
+    uint64_t cpu_insns_limit;
+    uint64_t mem_bytes_limit;
+    SorobanBytes cpu_cost_params; /* ContractCostParams */
+    SorobanBytes mem_cost_params; /* ContractCostParams */
+} SorobanBudgetConfig;
+
+/* Opaque module cache handle. Not thread-safe: must only be used from one
+ * thread at a time, including by the invocations it is passed to. */
+typedef struct SorobanModuleCache SorobanModuleCache;
+
+typedef struct SorobanInvokeArgs {
+    bool enable_diagnostics;
+    SorobanBudgetConfig budget;
+    SorobanLedgerInfo ledger_info;
+    SorobanBytes host_function; /* HostFunction */
+    SorobanBytes resources;     /* SorobanResources */
+    const uint32_t *restored_rw_entry_indices;
+    size_t restored_rw_entry_indices_len;
+    SorobanBytes source_account; /* AccountId */
+    const SorobanBytes *auth_entries; /* SorobanAuthorizationEntry */
+    size_t auth_entries_len;
+    const SorobanBytes *ledger_entries; /* LedgerEntry */
+    /* TtlEntry, one per ledger entry; empty for entries without TTL. */
+    const SorobanBytes *ttl_entries;
+    size_t ledger_entries_len;
+    SorobanBytes base_prng_seed; /* 32 bytes */
+    const SorobanModuleCache *module_cache; /* may be NULL */
+} SorobanInvokeArgs;
+
+typedef struct SorobanLedgerEntryLiveUntilChange {
+    uint8_t key_hash[32];
+    uint32_t durability; /* ContractDataDurability */
+    uint32_t entry_type; /* LedgerEntryType */
+    uint32_t old_live_until_ledger;
+    uint32_t new_live_until_ledger;
+} SorobanLedgerEntryLiveUntilChange;
+
+typedef struct SorobanLedgerEntryChange {
+    bool read_only;
+    SorobanOwnedBytes encoded_key; /* LedgerKey */
+    uint32_t old_entry_size_bytes_for_rent;
+    /* LedgerEntry, empty when the entry has been removed. */
+    SorobanOwnedBytes encoded_new_value;
+    uint32_t new_entry_size_bytes_for_rent;
+    bool has_ttl_change;
+    /* Only meaningful when `has_ttl_change` is set. */
+    SorobanLedgerEntryLiveUntilChange ttl_change;
+} SorobanLedgerEntryChange;
+
+typedef struct SorobanLedgerEntryChangeArray {
+    SorobanLedgerEntryChange *data;
+    size_t len;
+} SorobanLedgerEntryChangeArray;
+
+typedef struct SorobanInvokeResult {
+    bool success;
+    /* ScErrorType of the error when `success` is not set. */
+    uint32_t error_type;
+    /* ScErrorCode (or the contract error code) when `success` is not set. */
+    uint32_t error_code;
+    SorobanOwnedBytes encoded_result; /* ScVal, empty on failure */
+    SorobanLedgerEntryChangeArray ledger_changes; /* empty on failure */
+    SorobanOwnedBytesArray encoded_contract_events; /* ContractEvent */
+    /* DiagnosticEvent, populated when diagnostics are enabled, even on
+     * failure. */
+    SorobanOwnedBytesArray encoded_diagnostic_events;
+    uint64_t cpu_insns_consumed;
+    uint64_t mem_bytes_consumed;
+} SorobanInvokeResult;
+
+typedef struct SorobanRecordingInvokeArgs {
+    bool enable_diagnostics;
+    SorobanBudgetConfig budget;
+    SorobanLedgerInfo ledger_info;
+    SorobanBytes host_function;  /* HostFunction */
+    SorobanBytes source_account; /* AccountId */
+    /* When set, `auth_entries` are enforced. Otherwise the authorization is
+     * recorded. */
+    bool enforce_auth;
+    const SorobanBytes *auth_entries; /* SorobanAuthorizationEntry */
+    size_t auth_entries_len;
+    /* Whether to disallow the non-root authorization when recording it. */
+    bool disable_non_root_auth;
+    /* Ledger snapshot the invocation is performed against. */
+    const SorobanBytes *ledger_entries; /* LedgerEntry */
+    /* TtlEntry, one per ledger entry; empty for entries without TTL. */
+    const SorobanBytes *ttl_entries;
+    size_t ledger_entries_len;
+    SorobanBytes base_prng_seed; /* 32 bytes */
+} SorobanRecordingInvokeArgs;
+
+typedef struct SorobanRecordingInvokeResult {
+    SorobanInvokeResult invoke_result;
+    SorobanOwnedBytes encoded_resources; /* SorobanResources */
+    SorobanU32Array restored_rw_entry_indices;
+    SorobanOwnedBytesArray encoded_auth_entries; /* SorobanAuthorizationEntry */
+    uint32_t contract_events_and_return_value_size;
+} SorobanRecordingInvokeResult;
+
+typedef struct SorobanTransactionResources {
+    uint32_t instructions;
+    uint32_t disk_read_entries;
+    uint32_t write_entries;
+    uint32_t disk_read_bytes;
+    uint32_t write_bytes;
+    uint32_t contract_events_size_bytes;
+    uint32_t transaction_size_bytes;
+} SorobanTransactionResources;
+
+typedef struct SorobanFeeConfiguration {
+    int64_t fee_per_instruction_increment;
+    int64_t fee_per_disk_read_entry;
+    int64_t fee_per_write_entry;
+    int64_t fee_per_disk_read_1kb;
+    int64_t fee_per_write_1kb;
+    int64_t fee_per_historical_1kb;
+    int64_t fee_per_contract_event_1kb;
+    int64_t fee_per_transaction_size_1kb;
+} SorobanFeeConfiguration;
+
+typedef struct SorobanLedgerEntryRentChange {
+    bool is_persistent;
+    bool is_code_entry;
+    uint32_t old_size_bytes;
+    uint32_t new_size_bytes;
+    uint32_t old_live_until_ledger;
+    uint32_t new_live_until_ledger;
+} SorobanLedgerEntryRentChange;
+
+typedef struct SorobanRentFeeConfiguration {
+    int64_t fee_per_write_1kb;
+    int64_t fee_per_rent_1kb;
+    int64_t fee_per_write_entry;
+    int64_t persistent_rent_rate_denominator;
+    int64_t temporary_rent_rate_denominator;
+} SorobanRentFeeConfiguration;
+
+/* Invokes a host function in the enforcing mode, as the network does.
+ * `result` must be released with `soroban_invoke_result_free`. */
+SorobanStatus soroban_invoke_host_function(const SorobanInvokeArgs *args,
+                                           SorobanInvokeResult *result);
+
+void soroban_invoke_result_free(SorobanInvokeResult *result);
+
+/* Invokes a host function in the recording mode, as the transaction
+ * simulation does. `result` must be released with
+ * `soroban_recording_invoke_result_free`. */
+SorobanStatus soroban_invoke_host_function_in_recording_mode(
+    const SorobanRecordingInvokeArgs *args,
+    SorobanRecordingInvokeResult *result);
+
+void soroban_recording_invoke_result_free(SorobanRecordingInvokeResult *result);
+
+SorobanStatus soroban_compute_transaction_resource_fee(
+    const SorobanTransactionResources *resources,
+    const SorobanFeeConfiguration *fee_config,
+    int64_t *non_refundable_fee,
+    int64_t *refundable_fee);
+
+SorobanStatus soroban_compute_rent_fee(
+    const SorobanLedgerEntryRentChange *changes,
+    size_t changes_len,
+    const SorobanRentFeeConfiguration *fee_config,
+    uint32_t current_ledger_seq,
+    int64_t *rent_fee);
+
+/* Creates an empty module cache. On success `*cache` receives the handle,
+ * which must be released with `soroban_module_cache_free`. */
+SorobanStatus soroban_module_cache_new(SorobanModuleCache **cache);
+
+/* Parses and caches the contract from the ContractCodeEntry XDR. */
+SorobanStatus soroban_module_cache_add(const SorobanModuleCache *cache,
+                                       uint32_t protocol_version,
+                                       SorobanBytes encoded_code_entry);
+
+/* `wasm_hash` points to 32 bytes. */
+SorobanStatus soroban_module_cache_contains(const SorobanModuleCache *cache,
+                                            const uint8_t *wasm_hash,
+                                            bool *contains);
+
+/* `wasm_hash` points to 32 bytes. Removing a missing module is not an
+ * error. */
+SorobanStatus soroban_module_cache_remove(const SorobanModuleCache *cache,
+                                          const uint8_t *wasm_hash);
+
+/* Releases the module cache. Invocations that have been performed with the
+ * cache are not affected. */
+void soroban_module_cache_free(SorobanModuleCache *cache);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SOROBAN_ENV_HOST_H */
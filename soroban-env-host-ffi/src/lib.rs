@@ -0,0 +1,1066 @@
+//! Stable C ABI for embedding the Soroban host into non-Rust programs.
+//!
+//! Every item exported from this crate is mirrored by the hand-written header
+//! `include/soroban_env_host.h`, which is the reference for the C users. The
+//! header and this module have to be kept in sync: the field order of every
+//! `#[repr(C)]` structure is a part of the ABI.
+//!
+//! All the structured data is exchanged as XDR, just like in the
+//! [e2e_invoke](soroban_env_host::e2e_invoke) API the functions are built on.
+//!
+//! Buffer ownership rules:
+//!
+//! - Input buffers ([SorobanBytes] and arrays of them) are borrowed by the
+//!   library only for the duration of the call.
+//! - Output buffers ([SorobanOwnedBytes] and the arrays) are allocated by the
+//!   library and owned by the result structure that contains them. Every
+//!   result structure has to be released exactly once via its `_free`
+//!   function. The result structure is always initialized when a non-null
+//!   pointer to it is passed, so it has to be released even if the call has
+//!   failed.
+//! - Module caches are opaque handles owned by the caller; they are created
+//!   with [soroban_module_cache_new] and released with
+//!   [soroban_module_cache_free].
+//!
+//! Thread safety: module caches are not thread-safe. A cache may be used from
+//! one thread at a time only, including by the invocations it is passed to,
+//! so callers that share a cache between threads have to serialize all the
+//! calls that use it. Calls that don't share a cache can run concurrently.
+//!
+//! Panics never cross the ABI boundary: they are caught and reported as
+//! [SorobanStatus::Panic].
+
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+    rc::Rc,
+    slice,
+};
+
+use soroban_env_host::{
+    budget::{AsBudget, Budget},
+    e2e_invoke::{
+        invoke_host_function, invoke_host_function_in_recording_mode, LedgerEntryChange,
+        RecordingInvocationAuthMode,
+    },
+    fees::{
+        compute_rent_fee, compute_transaction_resource_fee, FeeConfiguration,
+        LedgerEntryRentChange, RentFeeConfiguration, TransactionResources,
+    },
+    storage::{EntryWithLiveUntil, SnapshotSource},
+    vm::VersionedContractCodeCostInputs,
+    xdr::{
+        AccountId, ContractCodeEntry, ContractCodeEntryExt, ContractCostParams, DiagnosticEvent,
+        Hash, HostFunction, LedgerEntry, LedgerKey, ReadXdr, ScErrorType,
+        SorobanAuthorizationEntry, TtlEntry, WriteXdr,
+    },
+    CompilationContext, Error, ErrorHandler, HostError, LedgerInfo, ModuleCache, Val,
+    DEFAULT_XDR_RW_LIMITS,
+};
+
+/// Status of a call into the library.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SorobanStatus {
+    /// The call has been performed. For the invocations this doesn't mean that
+    /// the invocation has succeeded, see the `success` field of the result.
+    Ok = 0,
+    /// A required pointer is null or an argument is malformed.
+    InvalidArgument = 1,
+    /// The host has returned an error outside of an invocation.
+    HostError = 2,
+    /// The library has panicked; this indicates a bug in the library.
+    Panic = 3,
+}
+
+impl From<HostError> for SorobanStatus {
+    fn from(_: HostError) -> Self {
+        SorobanStatus::HostError
+    }
+}
+
+/// Borrowed byte buffer. `data` may be null only when `len` is 0.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SorobanBytes {
+    pub data: *const u8,
+    pub len: usize,
+}
+
+impl SorobanBytes {
+    unsafe fn as_slice<'a>(&self) -> Result<&'a [u8], SorobanStatus> {
+        borrowed_slice(self.data, self.len)
+    }
+}
+
+/// Byte buffer allocated by the library. `data` is null when `len` is 0.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SorobanOwnedBytes {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl SorobanOwnedBytes {
+    fn new(bytes: Vec<u8>) -> Self {
+        let (data, len) = into_owned_slice(bytes);
+        Self { data, len }
+    }
+
+    fn empty() -> Self {
+        Self::new(vec![])
+    }
+
+    unsafe fn free(&mut self) {
+        drop(from_owned_slice(self.data, self.len));
+        *self = Self::empty();
+    }
+}
+
+/// Array of byte buffers allocated by the library.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SorobanOwnedBytesArray {
+    pub data: *mut SorobanOwnedBytes,
+    pub len: usize,
+}
+
+impl SorobanOwnedBytesArray {
+    fn new(buffers: Vec<Vec<u8>>) -> Self {
+        let (data, len) =
+            into_owned_slice(buffers.into_iter().map(SorobanOwnedBytes::new).collect());
+        Self { data, len }
+    }
+
+    fn empty() -> Self {
+        Self::new(vec![])
+    }
+
+    unsafe fn free(&mut self) {
+        for mut buffer in from_owned_slice(self.data, self.len) {
+            buffer.free();
+        }
+        *self = Self::empty();
+    }
+}
+
+/// Array of `u32` allocated by the library.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SorobanU32Array {
+    pub data: *mut u32,
+    pub len: usize,
+}
+
+impl SorobanU32Array {
+    fn new(values: Vec<u32>) -> Self {
+        let (data, len) = into_owned_slice(values);
+        Self { data, len }
+    }
+
+    unsafe fn free(&mut self) {
+        drop(from_owned_slice(self.data, self.len));
+        *self = Self::new(vec![]);
+    }
+}
+
+/// Mirror of [LedgerInfo].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SorobanLedgerInfo {
+    pub protocol_version: u32,
+    pub sequence_number: u32,
+    pub timestamp: u64,
+    pub network_id: [u8; 32],
+    pub base_reserve: u32,
+    pub min_temp_entry_ttl: u32,
+    pub min_persistent_entry_ttl: u32,
+    pub max_entry_ttl: u32,
+}
+
+impl From<&SorobanLedgerInfo> for LedgerInfo {
+    fn from(info: &SorobanLedgerInfo) -> Self {
+        LedgerInfo {
+            protocol_version: info.protocol_version,
+            sequence_number: info.sequence_number,
+            timestamp: info.timestamp,
+            network_id: info.network_id,
+            base_reserve: info.base_reserve,
+            min_temp_entry_ttl: info.min_temp_entry_ttl,
+            min_persistent_entry_ttl: info.min_persistent_entry_ttl,
+            max_entry_ttl: info.max_entry_ttl,
+        }
+    }
+}
+
+/// Budget for a single invocation.
+///
+/// When both cost parameter buffers are empty, the default budget of the host
+/// is used and the limits are ignored. The default budget is only meant for
+/// local testing; the network cost parameters should be used otherwise.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SorobanBudgetConfig {
+    pub cpu_insns_limit: u64,
+    pub mem_bytes_limit: u64,
+    /// `ContractCostParams` XDR.
+    pub cpu_cost_params: SorobanBytes,
+    /// `ContractCostParams` XDR.
+    pub mem_cost_params: SorobanBytes,
+}
+
+impl SorobanBudgetConfig {
+    unsafe fn to_budget(&self) -> Result<Result<Budget, HostError>, SorobanStatus> {
+        let cpu_cost_params = self.cpu_cost_params.as_slice()?;
+        let mem_cost_params = self.mem_cost_params.as_slice()?;
+        if cpu_cost_params.is_empty() && mem_cost_params.is_empty() {
+            return Ok(Ok(Budget::default()));
+        }
+        Ok((|| {
+            Budget::try_from_configs(
+                self.cpu_insns_limit,
+                self.mem_bytes_limit,
+                ContractCostParams::from_xdr(cpu_cost_params, DEFAULT_XDR_RW_LIMITS)?,
+                ContractCostParams::from_xdr(mem_cost_params, DEFAULT_XDR_RW_LIMITS)?,
+            )
+        })())
+    }
+}
+
+/// Arguments of [soroban_invoke_host_function].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SorobanInvokeArgs {
+    pub enable_diagnostics: bool,
+    pub budget: SorobanBudgetConfig,
+    pub ledger_info: SorobanLedgerInfo,
+    /// `HostFunction` XDR.
+    pub host_function: SorobanBytes,
+    /// `SorobanResources` XDR.
+    pub resources: SorobanBytes,
+    pub restored_rw_entry_indices: *const u32,
+    pub restored_rw_entry_indices_len: usize,
+    /// `AccountId` XDR.
+    pub source_account: SorobanBytes,
+    /// `SorobanAuthorizationEntry` XDR buffers.
+    pub auth_entries: *const SorobanBytes,
+    pub auth_entries_len: usize,
+    /// `LedgerEntry` XDR buffers for the footprint entries.
+    pub ledger_entries: *const SorobanBytes,
+    /// `TtlEntry` XDR buffers, one per ledger entry. Entries that don't have
+    /// TTL use empty buffers.
+    pub ttl_entries: *const SorobanBytes,
+    pub ledger_entries_len: usize,
+    /// 32-byte PRNG seed.
+    pub base_prng_seed: SorobanBytes,
+    /// Optional module cache; may be null.
+    pub module_cache: *const SorobanModuleCache,
+}
+
+/// TTL change of a ledger entry, see
+/// [LedgerEntryLiveUntilChange](soroban_env_host::e2e_invoke::LedgerEntryLiveUntilChange).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SorobanLedgerEntryLiveUntilChange {
+    pub key_hash: [u8; 32],
+    /// `ContractDataDurability` XDR value.
+    pub durability: u32,
+    /// `LedgerEntryType` XDR value.
+    pub entry_type: u32,
+    pub old_live_until_ledger: u32,
+    pub new_live_until_ledger: u32,
+}
+
+/// Ledger change caused by an invocation, see [LedgerEntryChange].
+#[repr(C)]
+#[derive(Debug)]
+pub struct SorobanLedgerEntryChange {
+    pub read_only: bool,
+    /// `LedgerKey` XDR.
+    pub encoded_key: SorobanOwnedBytes,
+    pub old_entry_size_bytes_for_rent: u32,
+    /// `LedgerEntry` XDR, empty when the entry has been removed.
+    pub encoded_new_value: SorobanOwnedBytes,
+    pub new_entry_size_bytes_for_rent: u32,
+    pub has_ttl_change: bool,
+    /// Only meaningful when `has_ttl_change` is set.
+    pub ttl_change: SorobanLedgerEntryLiveUntilChange,
+}
+
+impl From<LedgerEntryChange> for SorobanLedgerEntryChange {
+    fn from(change: LedgerEntryChange) -> Self {
+        let ttl_change = change.ttl_change.as_ref().map(|c| {
+            let mut key_hash = [0; 32];
+            key_hash.copy_from_slice(&c.key_hash);
+            SorobanLedgerEntryLiveUntilChange {
+                key_hash,
+                durability: c.durability as u32,
+                entry_type: c.entry_type as u32,
+                old_live_until_ledger: c.old_live_until_ledger,
+                new_live_until_ledger: c.new_live_until_ledger,
+            }
+        });
+        Self {
+            read_only: change.read_only,
+            encoded_key: SorobanOwnedBytes::new(change.encoded_key),
+            old_entry_size_bytes_for_rent: change.old_entry_size_bytes_for_rent,
+            encoded_new_value: SorobanOwnedBytes::new(change.encoded_new_value.unwrap_or_default()),
+            new_entry_size_bytes_for_rent: change.new_entry_size_bytes_for_rent,
+            has_ttl_change: ttl_change.is_some(),
+            ttl_change: ttl_change.unwrap_or_default(),
+        }
+    }
+}
+
+/// Array of ledger changes allocated by the library.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SorobanLedgerEntryChangeArray {
+    pub data: *mut SorobanLedgerEntryChange,
+    pub len: usize,
+}
+
+impl SorobanLedgerEntryChangeArray {
+    fn new(changes: Vec<LedgerEntryChange>) -> Self {
+        let (data, len) = into_owned_slice(changes.into_iter().map(Into::into).collect());
+        Self { data, len }
+    }
+
+    unsafe fn free(&mut self) {
+        for mut change in from_owned_slice(self.data, self.len) {
+            change.encoded_key.free();
+            change.encoded_new_value.free();
+        }
+        *self = Self::new(vec![]);
+    }
+}
+
+/// Result of [soroban_invoke_host_function].
+#[repr(C)]
+#[derive(Debug)]
+pub struct SorobanInvokeResult {
+    pub success: bool,
+    /// `ScErrorType` XDR value of the error when `success` is not set.
+    pub error_type: u32,
+    /// `ScErrorCode` XDR value of the error (or the contract error code) when
+    /// `success` is not set.
+    pub error_code: u32,
+    /// `ScVal` XDR of the returned value, empty on failure.
+    pub encoded_result: SorobanOwnedBytes,
+    /// Ledger changes, empty on failure.
+    pub ledger_changes: SorobanLedgerEntryChangeArray,
+    /// `ContractEvent` XDR buffers, empty on failure.
+    pub encoded_contract_events: SorobanOwnedBytesArray,
+    /// `DiagnosticEvent` XDR buffers, populated when diagnostics are enabled,
+    /// even on failure.
+    pub encoded_diagnostic_events: SorobanOwnedBytesArray,
+    pub cpu_insns_consumed: u64,
+    pub mem_bytes_consumed: u64,
+}
+
+impl SorobanInvokeResult {
+    fn empty() -> Self {
+        Self {
+            success: false,
+            error_type: 0,
+            error_code: 0,
+            encoded_result: SorobanOwnedBytes::empty(),
+            ledger_changes: SorobanLedgerEntryChangeArray::new(vec![]),
+            encoded_contract_events: SorobanOwnedBytesArray::empty(),
+            encoded_diagnostic_events: SorobanOwnedBytesArray::empty(),
+            cpu_insns_consumed: 0,
+            mem_bytes_consumed: 0,
+        }
+    }
+
+    fn set_outcome(&mut self, outcome: Result<Vec<u8>, HostError>) {
+        match outcome {
+            Ok(encoded_result) => {
+                self.success = true;
+                self.encoded_result = SorobanOwnedBytes::new(encoded_result);
+            }
+            Err(e) => {
+                self.success = false;
+                self.error_type = ScErrorType::variants()
+                    .into_iter()
+                    .find(|ty| e.error.is_type(*ty))
+                    .map_or(0, |ty| ty as u32);
+                self.error_code = e.error.get_code();
+            }
+        }
+    }
+
+    fn set_diagnostics_and_budget(
+        &mut self,
+        diagnostic_events: &[DiagnosticEvent],
+        budget: &Budget,
+    ) -> Result<(), HostError> {
+        self.encoded_diagnostic_events =
+            SorobanOwnedBytesArray::new(encode_all(diagnostic_events)?);
+        self.cpu_insns_consumed = budget.get_cpu_insns_consumed()?;
+        self.mem_bytes_consumed = budget.get_mem_bytes_consumed()?;
+        Ok(())
+    }
+
+    unsafe fn free(&mut self) {
+        self.encoded_result.free();
+        self.ledger_changes.free();
+        self.encoded_contract_events.free();
+        self.encoded_diagnostic_events.free();
+        *self = Self::empty();
+    }
+}
+
+/// Arguments of [soroban_invoke_host_function_in_recording_mode].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SorobanRecordingInvokeArgs {
+    pub enable_diagnostics: bool,
+    pub budget: SorobanBudgetConfig,
+    pub ledger_info: SorobanLedgerInfo,
+    /// `HostFunction` XDR.
+    pub host_function: SorobanBytes,
+    /// `AccountId` XDR.
+    pub source_account: SorobanBytes,
+    /// When set, `auth_entries` are enforced. Otherwise the authorization is
+    /// recorded.
+    pub enforce_auth: bool,
+    /// `SorobanAuthorizationEntry` XDR buffers, only used when `enforce_auth`
+    /// is set.
+    pub auth_entries: *const SorobanBytes,
+    pub auth_entries_len: usize,
+    /// Whether to disallow the non-root authorization when recording it.
+    pub disable_non_root_auth: bool,
+    /// `LedgerEntry` XDR buffers of the ledger snapshot the invocation is
+    /// performed against.
+    pub ledger_entries: *const SorobanBytes,
+    /// `TtlEntry` XDR buffers, one per ledger entry. Entries that don't have
+    /// TTL use empty buffers.
+    pub ttl_entries: *const SorobanBytes,
+    pub ledger_entries_len: usize,
+    /// 32-byte PRNG seed.
+    pub base_prng_seed: SorobanBytes,
+}
+
+/// Result of [soroban_invoke_host_function_in_recording_mode].
+#[repr(C)]
+#[derive(Debug)]
+pub struct SorobanRecordingInvokeResult {
+    /// Outcome of the invocation, same as for the enforcing mode.
+    pub invoke_result: SorobanInvokeResult,
+    /// Recorded `SorobanResources` XDR.
+    pub encoded_resources: SorobanOwnedBytes,
+    /// Indices of the read-write footprint entries to auto-restore.
+    pub restored_rw_entry_indices: SorobanU32Array,
+    /// Enforced or recorded `SorobanAuthorizationEntry` XDR buffers.
+    pub encoded_auth_entries: SorobanOwnedBytesArray,
+    pub contract_events_and_return_value_size: u32,
+}
+
+impl SorobanRecordingInvokeResult {
+    fn empty() -> Self {
+        Self {
+            invoke_result: SorobanInvokeResult::empty(),
+            encoded_resources: SorobanOwnedBytes::empty(),
+            restored_rw_entry_indices: SorobanU32Array::new(vec![]),
+            encoded_auth_entries: SorobanOwnedBytesArray::empty(),
+            contract_events_and_return_value_size: 0,
+        }
+    }
+
+    unsafe fn free(&mut self) {
+        self.invoke_result.free();
+        self.encoded_resources.free();
+        self.restored_rw_entry_indices.free();
+        self.encoded_auth_entries.free();
+        *self = Self::empty();
+    }
+}
+
+/// Mirror of [TransactionResources].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SorobanTransactionResources {
+    pub instructions: u32,
+    pub disk_read_entries: u32,
+    pub write_entries: u32,
+    pub disk_read_bytes: u32,
+    pub write_bytes: u32,
+    pub contract_events_size_bytes: u32,
+    pub transaction_size_bytes: u32,
+}
+
+/// Mirror of [FeeConfiguration].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SorobanFeeConfiguration {
+    pub fee_per_instruction_increment: i64,
+    pub fee_per_disk_read_entry: i64,
+    pub fee_per_write_entry: i64,
+    pub fee_per_disk_read_1kb: i64,
+    pub fee_per_write_1kb: i64,
+    pub fee_per_historical_1kb: i64,
+    pub fee_per_contract_event_1kb: i64,
+    pub fee_per_transaction_size_1kb: i64,
+}
+
+/// Mirror of [LedgerEntryRentChange].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SorobanLedgerEntryRentChange {
+    pub is_persistent: bool,
+    pub is_code_entry: bool,
+    pub old_size_bytes: u32,
+    pub new_size_bytes: u32,
+    pub old_live_until_ledger: u32,
+    pub new_live_until_ledger: u32,
+}
+
+/// Mirror of [RentFeeConfiguration].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SorobanRentFeeConfiguration {
+    pub fee_per_write_1kb: i64,
+    pub fee_per_rent_1kb: i64,
+    pub fee_per_write_entry: i64,
+    pub persistent_rent_rate_denominator: i64,
+    pub temporary_rent_rate_denominator: i64,
+}
+
+/// Opaque handle of a [ModuleCache].
+///
+/// The handle is not thread-safe: the cache and its compilation budget are
+/// reference-counted without synchronization, so it must only be used from
+/// one thread at a time (this includes the invocations it is passed to).
+pub struct SorobanModuleCache {
+    ctx: FfiCompilationContext,
+    cache: ModuleCache,
+}
+
+// Compilation context of the module caches. The compilation cost is not
+// charged to the invocations, so it uses an unlimited budget with zero costs.
+#[derive(Clone)]
+struct FfiCompilationContext(Budget);
+
+impl ErrorHandler for FfiCompilationContext {
+    fn map_err<T, E>(&self, res: Result<T, E>) -> Result<T, HostError>
+    where
+        Error: From<E>,
+        E: Debug,
+    {
+        res.map_err(HostError::from)
+    }
+
+    fn error(&self, error: Error, _msg: &str, _args: &[Val]) -> HostError {
+        HostError::from(error)
+    }
+}
+
+impl AsBudget for FfiCompilationContext {
+    fn as_budget(&self) -> &Budget {
+        &self.0
+    }
+}
+
+impl CompilationContext for FfiCompilationContext {}
+
+// In-memory ledger snapshot for the recording mode invocations.
+struct FfiSnapshotSource(BTreeMap<LedgerKey, EntryWithLiveUntil>);
+
+impl SnapshotSource for FfiSnapshotSource {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        Ok(self.0.get(key.as_ref()).cloned())
+    }
+}
+
+unsafe fn borrowed_slice<'a, T>(data: *const T, len: usize) -> Result<&'a [T], SorobanStatus> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if data.is_null() {
+        return Err(SorobanStatus::InvalidArgument);
+    }
+    Ok(slice::from_raw_parts(data, len))
+}
+
+unsafe fn borrowed_buffers<'a>(
+    data: *const SorobanBytes,
+    len: usize,
+) -> Result<Vec<&'a [u8]>, SorobanStatus> {
+    borrowed_slice(data, len)?
+        .iter()
+        .map(|b| b.as_slice())
+        .collect()
+}
+
+unsafe fn borrowed_seed(seed: &SorobanBytes) -> Result<[u8; 32], SorobanStatus> {
+    seed.as_slice()?
+        .try_into()
+        .map_err(|_| SorobanStatus::InvalidArgument)
+}
+
+unsafe fn borrowed_hash(hash: *const u8) -> Result<Hash, SorobanStatus> {
+    let hash: [u8; 32] = borrowed_slice(hash, 32)?
+        .try_into()
+        .map_err(|_| SorobanStatus::InvalidArgument)?;
+    Ok(Hash(hash))
+}
+
+// Owned arrays are passed across the ABI as boxed slices, so that they can be
+// released given just the pointer and the length.
+fn into_owned_slice<T>(values: Vec<T>) -> (*mut T, usize) {
+    if values.is_empty() {
+        return (ptr::null_mut(), 0);
+    }
+    let len = values.len();
+    (Box::into_raw(values.into_boxed_slice()) as *mut T, len)
+}
+
+unsafe fn from_owned_slice<T>(data: *mut T, len: usize) -> Vec<T> {
+    if data.is_null() {
+        return vec![];
+    }
+    Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)).into_vec()
+}
+
+fn encode_all<T: WriteXdr>(values: &[T]) -> Result<Vec<Vec<u8>>, HostError> {
+    values
+        .iter()
+        .map(|v| Ok(v.to_xdr(DEFAULT_XDR_RW_LIMITS)?))
+        .collect()
+}
+
+fn ffi_call(f: impl FnOnce() -> Result<(), SorobanStatus>) -> SorobanStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => SorobanStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => SorobanStatus::Panic,
+    }
+}
+
+/// Invokes a host function in the enforcing mode, as the network does.
+///
+/// See `invoke_host_function` in the Rust `e2e_invoke` module for the
+/// semantics of the arguments.
+///
+/// # Safety
+///
+/// `args` must point to valid arguments with all the buffers valid for reads
+/// of their lengths and `module_cache` either null or a live cache handle.
+/// `result` must be valid for writes; it is overwritten without being
+/// released and has to be released via [soroban_invoke_result_free].
+#[no_mangle]
+pub unsafe extern "C" fn soroban_invoke_host_function(
+    args: *const SorobanInvokeArgs,
+    result: *mut SorobanInvokeResult,
+) -> SorobanStatus {
+    if result.is_null() {
+        return SorobanStatus::InvalidArgument;
+    }
+    result.write(SorobanInvokeResult::empty());
+    let result = &mut *result;
+    ffi_call(|| {
+        let args = args.as_ref().ok_or(SorobanStatus::InvalidArgument)?;
+        let host_function = args.host_function.as_slice()?;
+        let resources = args.resources.as_slice()?;
+        let restored_rw_entry_indices = borrowed_slice(
+            args.restored_rw_entry_indices,
+            args.restored_rw_entry_indices_len,
+        )?;
+        let source_account = args.source_account.as_slice()?;
+        let auth_entries = borrowed_buffers(args.auth_entries, args.auth_entries_len)?;
+        let ledger_entries = borrowed_buffers(args.ledger_entries, args.ledger_entries_len)?;
+        let ttl_entries = borrowed_buffers(args.ttl_entries, args.ledger_entries_len)?;
+        let base_prng_seed = args.base_prng_seed.as_slice()?;
+        let module_cache = args.module_cache.as_ref().map(|c| c.cache.clone());
+
+        let budget = match args.budget.to_budget()? {
+            Ok(budget) => budget,
+            Err(e) => {
+                result.set_outcome(Err(e));
+                return Ok(());
+            }
+        };
+        let mut diagnostic_events = vec![];
+        let outcome = invoke_host_function(
+            &budget,
+            args.enable_diagnostics,
+            host_function,
+            resources,
+            restored_rw_entry_indices,
+            source_account,
+            auth_entries.into_iter(),
+            (&args.ledger_info).into(),
+            ledger_entries.into_iter(),
+            ttl_entries.into_iter(),
+            base_prng_seed,
+            &mut diagnostic_events,
+            None,
+            module_cache,
+        )
+        .and_then(|res| {
+            result.ledger_changes = SorobanLedgerEntryChangeArray::new(res.ledger_changes);
+            result.encoded_contract_events =
+                SorobanOwnedBytesArray::new(res.encoded_contract_events);
+            res.encoded_invoke_result
+        });
+        result.set_outcome(outcome);
+        result.set_diagnostics_and_budget(&diagnostic_events, &budget)?;
+        Ok(())
+    })
+}
+
+/// Releases all the buffers owned by the result of
+/// [soroban_invoke_host_function] and resets it to the empty state.
+///
+/// # Safety
+///
+/// `result` must be null or point to a result initialized by the library.
+#[no_mangle]
+pub unsafe extern "C" fn soroban_invoke_result_free(result: *mut SorobanInvokeResult) {
+    if let Some(result) = result.as_mut() {
+        result.free();
+    }
+}
+
+/// Invokes a host function in the recording mode, as the transaction
+/// simulation does: the footprint, the resources and (optionally) the
+/// authorization are recorded instead of being enforced.
+///
+/// # Safety
+///
+/// `args` must point to valid arguments with all the buffers valid for reads
+/// of their lengths. `result` must be valid for writes; it is overwritten
+/// without being released and has to be released via
+/// [soroban_recording_invoke_result_free].
+#[no_mangle]
+pub unsafe extern "C" fn soroban_invoke_host_function_in_recording_mode(
+    args: *const SorobanRecordingInvokeArgs,
+    result: *mut SorobanRecordingInvokeResult,
+) -> SorobanStatus {
+    if result.is_null() {
+        return SorobanStatus::InvalidArgument;
+    }
+    result.write(SorobanRecordingInvokeResult::empty());
+    let result = &mut *result;
+    ffi_call(|| {
+        let args = args.as_ref().ok_or(SorobanStatus::InvalidArgument)?;
+        let host_function = args.host_function.as_slice()?;
+        let source_account = args.source_account.as_slice()?;
+        let auth_entries = if args.enforce_auth {
+            borrowed_buffers(args.auth_entries, args.auth_entries_len)?
+        } else {
+            vec![]
+        };
+        let ledger_entries = borrowed_buffers(args.ledger_entries, args.ledger_entries_len)?;
+        let ttl_entries = borrowed_buffers(args.ttl_entries, args.ledger_entries_len)?;
+        let base_prng_seed = borrowed_seed(&args.base_prng_seed)?;
+
+        let budget = match args.budget.to_budget()? {
+            Ok(budget) => budget,
+            Err(e) => {
+                result.invoke_result.set_outcome(Err(e));
+                return Ok(());
+            }
+        };
+        // The inputs are decoded outside of the invocation budget, just like
+        // the simulation does.
+        let decoded_inputs = (|| -> Result<_, HostError> {
+            let host_function = HostFunction::from_xdr(host_function, DEFAULT_XDR_RW_LIMITS)?;
+            let source_account = AccountId::from_xdr(source_account, DEFAULT_XDR_RW_LIMITS)?;
+            let auth_mode = if args.enforce_auth {
+                RecordingInvocationAuthMode::Enforcing(
+                    auth_entries
+                        .iter()
+                        .map(|e| SorobanAuthorizationEntry::from_xdr(e, DEFAULT_XDR_RW_LIMITS))
+                        .collect::<Result<_, _>>()?,
+                )
+            } else {
+                RecordingInvocationAuthMode::Recording(args.disable_non_root_auth)
+            };
+            let mut snapshot = BTreeMap::new();
+            for (entry, ttl_entry) in ledger_entries.iter().zip(ttl_entries.iter()) {
+                let entry = LedgerEntry::from_xdr(entry, DEFAULT_XDR_RW_LIMITS)?;
+                let live_until = if ttl_entry.is_empty() {
+                    None
+                } else {
+                    Some(
+                        TtlEntry::from_xdr(ttl_entry, DEFAULT_XDR_RW_LIMITS)?.live_until_ledger_seq,
+                    )
+                };
+                snapshot.insert(entry.to_key(), (Rc::new(entry), live_until));
+            }
+            Ok((host_function, source_account, auth_mode, snapshot))
+        })();
+        let (host_function, source_account, auth_mode, snapshot) = match decoded_inputs {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                result.invoke_result.set_outcome(Err(e));
+                return Ok(());
+            }
+        };
+
+        let mut diagnostic_events = vec![];
+        let outcome = invoke_host_function_in_recording_mode(
+            &budget,
+            args.enable_diagnostics,
+            &host_function,
+            &source_account,
+            auth_mode,
+            (&args.ledger_info).into(),
+            Rc::new(FfiSnapshotSource(snapshot)),
+            base_prng_seed,
+            &mut diagnostic_events,
+        )
+        .and_then(|res| {
+            result.encoded_resources =
+                SorobanOwnedBytes::new(res.resources.to_xdr(DEFAULT_XDR_RW_LIMITS)?);
+            result.restored_rw_entry_indices = SorobanU32Array::new(res.restored_rw_entry_indices);
+            result.encoded_auth_entries = SorobanOwnedBytesArray::new(encode_all(&res.auth)?);
+            result.contract_events_and_return_value_size =
+                res.contract_events_and_return_value_size;
+            result.invoke_result.ledger_changes =
+                SorobanLedgerEntryChangeArray::new(res.ledger_changes);
+            result.invoke_result.encoded_contract_events =
+                SorobanOwnedBytesArray::new(encode_all(&res.contract_events)?);
+            Ok(res.invoke_result?.to_xdr(DEFAULT_XDR_RW_LIMITS)?)
+        });
+        result.invoke_result.set_outcome(outcome);
+        result
+            .invoke_result
+            .set_diagnostics_and_budget(&diagnostic_events, &budget)?;
+        Ok(())
+    })
+}
+
+/// Releases all the buffers owned by the result of
+/// [soroban_invoke_host_function_in_recording_mode] and resets it to the
+/// empty state.
+///
+/// # Safety
+///
+/// `result` must be null or point to a result initialized by the library.
+#[no_mangle]
+pub unsafe extern "C" fn soroban_recording_invoke_result_free(
+    result: *mut SorobanRecordingInvokeResult,
+) {
+    if let Some(result) = result.as_mut() {
+        result.free();
+    }
+}
+
+/// Computes the non-refundable and refundable parts of the transaction
+/// resource fee.
+///
+/// # Safety
+///
+/// All the pointers must be valid and non-null.
+#[no_mangle]
+pub unsafe extern "C" fn soroban_compute_transaction_resource_fee(
+    resources: *const SorobanTransactionResources,
+    fee_config: *const SorobanFeeConfiguration,
+    non_refundable_fee: *mut i64,
+    refundable_fee: *mut i64,
+) -> SorobanStatus {
+    ffi_call(|| {
+        let r = resources.as_ref().ok_or(SorobanStatus::InvalidArgument)?;
+        let c = fee_config.as_ref().ok_or(SorobanStatus::InvalidArgument)?;
+        if non_refundable_fee.is_null() || refundable_fee.is_null() {
+            return Err(SorobanStatus::InvalidArgument);
+        }
+        let (non_refundable, refundable) = compute_transaction_resource_fee(
+            &TransactionResources {
+                instructions: r.instructions,
+                disk_read_entries: r.disk_read_entries,
+                write_entries: r.write_entries,
+                disk_read_bytes: r.disk_read_bytes,
+                write_bytes: r.write_bytes,
+                contract_events_size_bytes: r.contract_events_size_bytes,
+                transaction_size_bytes: r.transaction_size_bytes,
+            },
+            &FeeConfiguration {
+                fee_per_instruction_increment: c.fee_per_instruction_increment,
+                fee_per_disk_read_entry: c.fee_per_disk_read_entry,
+                fee_per_write_entry: c.fee_per_write_entry,
+                fee_per_disk_read_1kb: c.fee_per_disk_read_1kb,
+                fee_per_write_1kb: c.fee_per_write_1kb,
+                fee_per_historical_1kb: c.fee_per_historical_1kb,
+                fee_per_contract_event_1kb: c.fee_per_contract_event_1kb,
+                fee_per_transaction_size_1kb: c.fee_per_transaction_size_1kb,
+            },
+        );
+        non_refundable_fee.write(non_refundable);
+        refundable_fee.write(refundable);
+        Ok(())
+    })
+}
+
+/// Computes the rent fee for the given ledger entry changes.
+///
+/// # Safety
+///
+/// `changes` must be valid for reads of `changes_len` elements (or null when
+/// `changes_len` is 0). The other pointers must be valid and non-null.
+#[no_mangle]
+pub unsafe extern "C" fn soroban_compute_rent_fee(
+    changes: *const SorobanLedgerEntryRentChange,
+    changes_len: usize,
+    fee_config: *const SorobanRentFeeConfiguration,
+    current_ledger_seq: u32,
+    rent_fee: *mut i64,
+) -> SorobanStatus {
+    ffi_call(|| {
+        let changes: Vec<LedgerEntryRentChange> = borrowed_slice(changes, changes_len)?
+            .iter()
+            .map(|c| LedgerEntryRentChange {
+                is_persistent: c.is_persistent,
+                is_code_entry: c.is_code_entry,
+                old_size_bytes: c.old_size_bytes,
+                new_size_bytes: c.new_size_bytes,
+                old_live_until_ledger: c.old_live_until_ledger,
+                new_live_until_ledger: c.new_live_until_ledger,
+            })
+            .collect();
+        let c = fee_config.as_ref().ok_or(SorobanStatus::InvalidArgument)?;
+        if rent_fee.is_null() {
+            return Err(SorobanStatus::InvalidArgument);
+        }
+        rent_fee.write(compute_rent_fee(
+            &changes,
+            &RentFeeConfiguration {
+                fee_per_write_1kb: c.fee_per_write_1kb,
+                fee_per_rent_1kb: c.fee_per_rent_1kb,
+                fee_per_write_entry: c.fee_per_write_entry,
+                persistent_rent_rate_denominator: c.persistent_rent_rate_denominator,
+                temporary_rent_rate_denominator: c.temporary_rent_rate_denominator,
+            },
+            current_ledger_seq,
+        ));
+        Ok(())
+    })
+}
+
+/// Creates an empty module cache.
+///
+/// # Safety
+///
+/// `cache` must be valid for writes. On success it receives the handle that
+/// has to be released via [soroban_module_cache_free].
+#[no_mangle]
+pub unsafe extern "C" fn soroban_module_cache_new(
+    cache: *mut *mut SorobanModuleCache,
+) -> SorobanStatus {
+    if cache.is_null() {
+        return SorobanStatus::InvalidArgument;
+    }
+    cache.write(ptr::null_mut());
+    ffi_call(|| {
+        let no_costs = || ContractCostParams(Default::default());
+        let ctx = FfiCompilationContext(Budget::try_from_configs(
+            u64::MAX,
+            u64::MAX,
+            no_costs(),
+            no_costs(),
+        )?);
+        let module_cache = ModuleCache::new(&ctx)?;
+        cache.write(Box::into_raw(Box::new(SorobanModuleCache {
+            ctx,
+            cache: module_cache,
+        })));
+        Ok(())
+    })
+}
+
+/// Parses and caches the contract from the `ContractCodeEntry` XDR.
+///
+/// # Safety
+///
+/// `cache` must be a live cache handle and `encoded_code_entry` must be valid
+/// for reads of its length.
+#[no_mangle]
+pub unsafe extern "C" fn soroban_module_cache_add(
+    cache: *const SorobanModuleCache,
+    protocol_version: u32,
+    encoded_code_entry: SorobanBytes,
+) -> SorobanStatus {
+    ffi_call(|| {
+        let cache = cache.as_ref().ok_or(SorobanStatus::InvalidArgument)?;
+        let code =
+            ContractCodeEntry::from_xdr(encoded_code_entry.as_slice()?, DEFAULT_XDR_RW_LIMITS)
+                .map_err(|_| SorobanStatus::InvalidArgument)?;
+        let cost_inputs = match &code.ext {
+            ContractCodeEntryExt::V0 => VersionedContractCodeCostInputs::V0 {
+                wasm_bytes: code.code.len(),
+            },
+            ContractCodeEntryExt::V1(v1) => {
+                VersionedContractCodeCostInputs::V1(v1.cost_inputs.clone())
+            }
+        };
+        cache.cache.parse_and_cache_module(
+            &cache.ctx,
+            protocol_version,
+            &code.hash,
+            &code.code,
+            cost_inputs,
+        )?;
+        Ok(())
+    })
+}
+
+/// Checks whether the module with the given 32-byte Wasm hash is cached.
+///
+/// # Safety
+///
+/// `cache` must be a live cache handle, `wasm_hash` must be valid for reads
+/// of 32 bytes and `contains` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn soroban_module_cache_contains(
+    cache: *const SorobanModuleCache,
+    wasm_hash: *const u8,
+    contains: *mut bool,
+) -> SorobanStatus {
+    ffi_call(|| {
+        let cache = cache.as_ref().ok_or(SorobanStatus::InvalidArgument)?;
+        let hash = borrowed_hash(wasm_hash)?;
+        if contains.is_null() {
+            return Err(SorobanStatus::InvalidArgument);
+        }
+        contains.write(cache.cache.contains_module(&hash)?);
+        Ok(())
+    })
+}
+
+/// Removes the module with the given 32-byte Wasm hash from the cache, if
+/// it's present.
+///
+/// # Safety
+///
+/// `cache` must be a live cache handle and `wasm_hash` must be valid for
+/// reads of 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn soroban_module_cache_remove(
+    cache: *const SorobanModuleCache,
+    wasm_hash: *const u8,
+) -> SorobanStatus {
+    ffi_call(|| {
+        let cache = cache.as_ref().ok_or(SorobanStatus::InvalidArgument)?;
+        cache.cache.remove_module(&borrowed_hash(wasm_hash)?)?;
+        Ok(())
+    })
+}
+
+/// Releases the module cache. Invocations that have been performed with the
+/// cache are not affected.
+///
+/// # Safety
+///
+/// `cache` must be null or a live cache handle, which must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn soroban_module_cache_free(cache: *mut SorobanModuleCache) {
+    if !cache.is_null() {
+        drop(Box::from_raw(cache));
+    }
+}
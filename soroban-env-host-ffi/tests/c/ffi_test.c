@@ -0,0 +1,298 @@
+/*
+ * Round-trip test of the C ABI, driven by `tests/c_abi.rs`.
+ *
+ * Usage: ffi_test <DIR>
+ *
+ * Reads the XDR inputs prepared by the driver from DIR, performs the calls
+ * and reports the outcomes as `key value` lines on stdout. XDR outputs are
+ * written back to DIR, so that the driver can compare them to the outputs of
+ * the Rust API.
+ */
+#include <inttypes.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "soroban_env_host.h"
+
+static const char *dir;
+
+#define CHECK(cond)                                                          \
+    do {                                                                     \
+        if (!(cond)) {                                                       \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+                    #cond);                                                  \
+            exit(1);                                                         \
+        }                                                                    \
+    } while (0)
+
+static void path_for(char *path, size_t size, const char *name) {
+    CHECK(snprintf(path, size, "%s/%s", dir, name) < (int)size);
+}
+
+/* The returned buffer is intentionally leaked, the process is short-lived. */
+static SorobanBytes read_input(const char *name) {
+    char path[4096];
+    path_for(path, sizeof(path), name);
+    FILE *f = fopen(path, "rb");
+    CHECK(f != NULL);
+    CHECK(fseek(f, 0, SEEK_END) == 0);
+    long len = ftell(f);
+    CHECK(len >= 0);
+    CHECK(fseek(f, 0, SEEK_SET) == 0);
+    uint8_t *data = malloc(len > 0 ? (size_t)len : 1);
+    CHECK(data != NULL);
+    CHECK(fread(data, 1, (size_t)len, f) == (size_t)len);
+    fclose(f);
+    SorobanBytes bytes = {data, (size_t)len};
+    return bytes;
+}
+
+static void write_output(const char *name, const SorobanOwnedBytes *bytes) {
+    char path[4096];
+    path_for(path, sizeof(path), name);
+    FILE *f = fopen(path, "wb");
+    CHECK(f != NULL);
+    CHECK(fwrite(bytes->data, 1, bytes->len, f) == bytes->len);
+    fclose(f);
+}
+
+static SorobanLedgerInfo read_ledger_info(void) {
+    SorobanLedgerInfo info;
+    memset(&info, 0, sizeof(info));
+    char path[4096];
+    path_for(path, sizeof(path), "ledger_info.txt");
+    FILE *f = fopen(path, "r");
+    CHECK(f != NULL);
+    CHECK(fscanf(f, "%" SCNu32 " %" SCNu32 " %" SCNu64 " %" SCNu32 " %" SCNu32
+                    " %" SCNu32 " %" SCNu32,
+                 &info.protocol_version, &info.sequence_number, &info.timestamp,
+                 &info.base_reserve, &info.min_temp_entry_ttl,
+                 &info.min_persistent_entry_ttl, &info.max_entry_ttl) == 7);
+    fclose(f);
+    SorobanBytes network_id = read_input("network_id.bin");
+    CHECK(network_id.len == sizeof(info.network_id));
+    memcpy(info.network_id, network_id.data, sizeof(info.network_id));
+    return info;
+}
+
+static void report_invoke_result(const char *name,
+                                 const SorobanInvokeResult *result) {
+    printf("%s_success %d\n", name, result->success);
+    printf("%s_error %" PRIu32 " %" PRIu32 "\n", name, result->error_type,
+           result->error_code);
+    printf("%s_ledger_changes %zu\n", name, result->ledger_changes.len);
+    printf("%s_contract_events %zu\n", name,
+           result->encoded_contract_events.len);
+    printf("%s_diagnostic_events %zu\n", name,
+           result->encoded_diagnostic_events.len);
+    printf("%s_cpu_insns %" PRIu64 "\n", name, result->cpu_insns_consumed);
+    printf("%s_mem_bytes %" PRIu64 "\n", name, result->mem_bytes_consumed);
+}
+
+static void test_upload_wasm(SorobanLedgerInfo ledger_info,
+                             SorobanBytes source_account, SorobanBytes seed) {
+    SorobanInvokeArgs args;
+    memset(&args, 0, sizeof(args));
+    args.enable_diagnostics = true;
+    args.ledger_info = ledger_info;
+    args.host_function = read_input("upload_host_fn.xdr");
+    args.resources = read_input("upload_resources.xdr");
+    args.source_account = source_account;
+    args.base_prng_seed = seed;
+
+    SorobanInvokeResult result;
+    CHECK(soroban_invoke_host_function(&args, &result) == SOROBAN_STATUS_OK);
+    report_invoke_result("upload", &result);
+    write_output("upload_result.xdr", &result.encoded_result);
+    CHECK(result.ledger_changes.len == 1);
+    write_output("upload_change_key.xdr",
+                 &result.ledger_changes.data[0].encoded_key);
+    write_output("upload_change_value.xdr",
+                 &result.ledger_changes.data[0].encoded_new_value);
+    soroban_invoke_result_free(&result);
+    /* The result is reset by `free`, so releasing it again is a no-op. */
+    CHECK(result.encoded_result.data == NULL);
+    CHECK(result.ledger_changes.len == 0);
+    soroban_invoke_result_free(&result);
+}
+
+static void test_create_contract_with_module_cache(SorobanLedgerInfo ledger_info,
+                                                   SorobanBytes seed) {
+    SorobanBytes code_entry = read_input("wasm_code.xdr");
+    SorobanBytes wasm_hash = read_input("wasm_hash.bin");
+    CHECK(wasm_hash.len == 32);
+
+    SorobanModuleCache *cache = NULL;
+    CHECK(soroban_module_cache_new(&cache) == SOROBAN_STATUS_OK);
+    CHECK(soroban_module_cache_add(cache, ledger_info.protocol_version,
+                                   code_entry) == SOROBAN_STATUS_OK);
+    bool contains = false;
+    CHECK(soroban_module_cache_contains(cache, wasm_hash.data, &contains) ==
+          SOROBAN_STATUS_OK);
+    printf("cache_contains %d\n", contains);
+
+    SorobanBytes auth_entry = read_input("create_auth.xdr");
+    SorobanBytes ledger_entry = read_input("wasm_entry.xdr");
+    SorobanBytes ttl_entry = read_input("wasm_ttl.xdr");
+    SorobanInvokeArgs args;
+    memset(&args, 0, sizeof(args));
+    args.enable_diagnostics = false;
+    args.ledger_info = ledger_info;
+    args.host_function = read_input("create_host_fn.xdr");
+    args.resources = read_input("create_resources.xdr");
+    args.source_account = read_input("deployer.xdr");
+    args.auth_entries = &auth_entry;
+    args.auth_entries_len = 1;
+    args.ledger_entries = &ledger_entry;
+    args.ttl_entries = &ttl_entry;
+    args.ledger_entries_len = 1;
+    args.base_prng_seed = seed;
+    args.module_cache = cache;
+
+    SorobanInvokeResult result;
+    CHECK(soroban_invoke_host_function(&args, &result) == SOROBAN_STATUS_OK);
+    report_invoke_result("create", &result);
+    write_output("create_result.xdr", &result.encoded_result);
+    size_t ttl_changes = 0;
+    for (size_t i = 0; i < result.ledger_changes.len; ++i) {
+        if (result.ledger_changes.data[i].has_ttl_change) {
+            ++ttl_changes;
+        }
+    }
+    printf("create_ttl_changes %zu\n", ttl_changes);
+    soroban_invoke_result_free(&result);
+
+    CHECK(soroban_module_cache_remove(cache, wasm_hash.data) ==
+          SOROBAN_STATUS_OK);
+    CHECK(soroban_module_cache_contains(cache, wasm_hash.data, &contains) ==
+          SOROBAN_STATUS_OK);
+    printf("cache_contains_after_remove %d\n", contains);
+    soroban_module_cache_free(cache);
+}
+
+static void test_recording_mode(SorobanLedgerInfo ledger_info,
+                                SorobanBytes seed) {
+    SorobanBytes ledger_entry = read_input("wasm_entry.xdr");
+    SorobanBytes ttl_entry = read_input("wasm_ttl.xdr");
+    SorobanRecordingInvokeArgs args;
+    memset(&args, 0, sizeof(args));
+    args.enable_diagnostics = false;
+    args.ledger_info = ledger_info;
+    args.host_function = read_input("create_host_fn.xdr");
+    args.source_account = read_input("deployer.xdr");
+    args.enforce_auth = false;
+    args.disable_non_root_auth = true;
+    args.ledger_entries = &ledger_entry;
+    args.ttl_entries = &ttl_entry;
+    args.ledger_entries_len = 1;
+    args.base_prng_seed = seed;
+
+    SorobanRecordingInvokeResult result;
+    CHECK(soroban_invoke_host_function_in_recording_mode(&args, &result) ==
+          SOROBAN_STATUS_OK);
+    report_invoke_result("recording", &result.invoke_result);
+    printf("recording_auth_entries %zu\n", result.encoded_auth_entries.len);
+    printf("recording_restored_entries %zu\n",
+           result.restored_rw_entry_indices.len);
+    printf("recording_events_and_return_value_size %" PRIu32 "\n",
+           result.contract_events_and_return_value_size);
+    write_output("recording_resources.xdr", &result.encoded_resources);
+    CHECK(result.encoded_auth_entries.len == 1);
+    write_output("recording_auth.xdr", &result.encoded_auth_entries.data[0]);
+    soroban_recording_invoke_result_free(&result);
+}
+
+static void test_failures(SorobanLedgerInfo ledger_info,
+                          SorobanBytes source_account, SorobanBytes seed) {
+    SorobanInvokeResult result;
+    /* Invalid pointers are reported via the status, while the result is
+     * still initialized. */
+    CHECK(soroban_invoke_host_function(NULL, &result) ==
+          SOROBAN_STATUS_INVALID_ARGUMENT);
+    soroban_invoke_result_free(&result);
+
+    static const uint8_t garbage[] = {0xff, 0xff, 0xff, 0xff};
+    SorobanInvokeArgs args;
+    memset(&args, 0, sizeof(args));
+    args.enable_diagnostics = true;
+    args.ledger_info = ledger_info;
+    args.host_function.data = garbage;
+    args.host_function.len = sizeof(garbage);
+    args.resources = read_input("upload_resources.xdr");
+    args.source_account = source_account;
+    args.base_prng_seed = seed;
+    CHECK(soroban_invoke_host_function(&args, &result) == SOROBAN_STATUS_OK);
+    report_invoke_result("invalid", &result);
+    soroban_invoke_result_free(&result);
+
+    SorobanModuleCache *cache = NULL;
+    CHECK(soroban_module_cache_new(&cache) == SOROBAN_STATUS_OK);
+    printf("cache_add_invalid %d\n",
+           soroban_module_cache_add(cache, ledger_info.protocol_version,
+                                    args.host_function));
+    soroban_module_cache_free(cache);
+}
+
+static void test_fees(void) {
+    SorobanTransactionResources resources = {
+        .instructions = 12345678,
+        .disk_read_entries = 3,
+        .write_entries = 2,
+        .disk_read_bytes = 5000,
+        .write_bytes = 3000,
+        .contract_events_size_bytes = 1500,
+        .transaction_size_bytes = 900,
+    };
+    SorobanFeeConfiguration fee_config = {
+        .fee_per_instruction_increment = 25,
+        .fee_per_disk_read_entry = 6250,
+        .fee_per_write_entry = 10000,
+        .fee_per_disk_read_1kb = 1786,
+        .fee_per_write_1kb = 11800,
+        .fee_per_historical_1kb = 16235,
+        .fee_per_contract_event_1kb = 10000,
+        .fee_per_transaction_size_1kb = 1624,
+    };
+    int64_t non_refundable = 0, refundable = 0;
+    CHECK(soroban_compute_transaction_resource_fee(
+              &resources, &fee_config, &non_refundable, &refundable) ==
+          SOROBAN_STATUS_OK);
+    printf("resource_fee %" PRId64 " %" PRId64 "\n", non_refundable,
+           refundable);
+    CHECK(soroban_compute_transaction_resource_fee(&resources, &fee_config,
+                                                   NULL, &refundable) ==
+          SOROBAN_STATUS_INVALID_ARGUMENT);
+
+    SorobanLedgerEntryRentChange changes[] = {
+        {true, false, 0, 1000, 0, 100500},
+        {false, false, 200, 300, 100020, 100100},
+        {true, true, 5000, 5000, 100010, 200000},
+    };
+    SorobanRentFeeConfiguration rent_config = {
+        .fee_per_write_1kb = 11800,
+        .fee_per_rent_1kb = 1000,
+        .fee_per_write_entry = 10000,
+        .persistent_rent_rate_denominator = 2103,
+        .temporary_rent_rate_denominator = 4206,
+    };
+    int64_t rent_fee = 0;
+    CHECK(soroban_compute_rent_fee(changes, 3, &rent_config, 100000,
+                                   &rent_fee) == SOROBAN_STATUS_OK);
+    printf("rent_fee %" PRId64 "\n", rent_fee);
+}
+
+int main(int argc, char **argv) {
+    CHECK(argc == 2);
+    dir = argv[1];
+    SorobanLedgerInfo ledger_info = read_ledger_info();
+    SorobanBytes source_account = read_input("source_account.xdr");
+    SorobanBytes seed = read_input("prng_seed.bin");
+
+    test_upload_wasm(ledger_info, source_account, seed);
+    test_create_contract_with_module_cache(ledger_info, seed);
+    test_recording_mode(ledger_info, seed);
+    test_failures(ledger_info, source_account, seed);
+    test_fees();
+    return 0;
+}
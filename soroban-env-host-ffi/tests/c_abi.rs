@@ -0,0 +1,348 @@
+//! Drives the C round-trip test in `tests/c/ffi_test.c` against the library
+//! built for this test run and compares its outputs to the Rust API.
+#![cfg(unix)]
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    rc::Rc,
+};
+
+use sha2::{Digest, Sha256};
+use soroban_env_host::{
+    budget::Budget,
+    e2e_invoke::{
+        invoke_host_function, invoke_host_function_in_recording_mode, RecordingInvocationAuthMode,
+    },
+    e2e_testutils::{
+        default_ledger_info, get_account_id, get_wasm_key, upload_wasm_host_fn, CreateContractData,
+    },
+    fees::{
+        compute_rent_fee, compute_transaction_resource_fee, FeeConfiguration,
+        LedgerEntryRentChange, RentFeeConfiguration, TransactionResources,
+    },
+    testutils::MockSnapshotSource,
+    xdr::{
+        Hash, LedgerEntryData, LedgerFootprint, LedgerKey, ScVal, SorobanResources, TtlEntry,
+        WriteXdr,
+    },
+    DEFAULT_XDR_RW_LIMITS,
+};
+use soroban_test_wasms::ADD_I32;
+
+const PRNG_SEED: [u8; 32] = [7; 32];
+
+fn xdr(v: &impl WriteXdr) -> Vec<u8> {
+    v.to_xdr(DEFAULT_XDR_RW_LIMITS).unwrap()
+}
+
+fn resources(read_only: Vec<LedgerKey>, read_write: Vec<LedgerKey>) -> SorobanResources {
+    SorobanResources {
+        footprint: LedgerFootprint {
+            read_only: read_only.try_into().unwrap(),
+            read_write: read_write.try_into().unwrap(),
+        },
+        instructions: 10_000_000,
+        disk_read_bytes: 0,
+        write_bytes: 0,
+    }
+}
+
+// Output of the C program, keyed by the first word of every line.
+struct Report(BTreeMap<String, String>);
+
+impl Report {
+    fn get(&self, key: &str) -> &str {
+        self.0
+            .get(key)
+            .unwrap_or_else(|| panic!("missing `{key}` in the report"))
+    }
+
+    fn get_u64(&self, key: &str) -> u64 {
+        self.get(key).parse().unwrap()
+    }
+}
+
+// The library is built next to the test executable (in the `deps` directory).
+fn library_dir() -> PathBuf {
+    std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+fn compile_and_run(dir: &Path) -> Report {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let lib_dir = library_dir();
+    let exe = dir.join("ffi_test");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror"])
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg(manifest_dir.join("tests/c/ffi_test.c"))
+        .arg("-o")
+        .arg(&exe)
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lsoroban_env_host_ffi")
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to compile the C test program");
+
+    let output = Command::new(&exe).arg(dir).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        output.status.success(),
+        "C test program failed:\n{stdout}{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Report(
+        stdout
+            .lines()
+            .filter_map(|l| l.split_once(' '))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    )
+}
+
+#[test]
+fn c_abi_round_trip() {
+    let dir = std::env::temp_dir().join(format!("soroban-ffi-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, bytes: &[u8]| fs::write(dir.join(name), bytes).unwrap();
+
+    let ledger_info = default_ledger_info();
+    write(
+        "ledger_info.txt",
+        format!(
+            "{} {} {} {} {} {} {}",
+            ledger_info.protocol_version,
+            ledger_info.sequence_number,
+            ledger_info.timestamp,
+            ledger_info.base_reserve,
+            ledger_info.min_temp_entry_ttl,
+            ledger_info.min_persistent_entry_ttl,
+            ledger_info.max_entry_ttl
+        )
+        .as_bytes(),
+    );
+    write("network_id.bin", &ledger_info.network_id);
+    write("prng_seed.bin", &PRNG_SEED);
+
+    let source_account = get_account_id([123; 32]);
+    let upload_host_fn = upload_wasm_host_fn(ADD_I32);
+    let upload_resources = resources(vec![], vec![get_wasm_key(ADD_I32)]);
+    write("source_account.xdr", &xdr(&source_account));
+    write("upload_host_fn.xdr", &xdr(&upload_host_fn));
+    write("upload_resources.xdr", &xdr(&upload_resources));
+
+    let cd = CreateContractData::new([111; 32], ADD_I32);
+    let LedgerEntryData::ContractCode(code) = &cd.wasm_entry.data else {
+        unreachable!();
+    };
+    let wasm_ttl = TtlEntry {
+        key_hash: Hash(Sha256::digest(xdr(&cd.wasm_key)).into()),
+        live_until_ledger_seq: ledger_info.sequence_number + 100,
+    };
+    let create_resources = resources(vec![cd.wasm_key.clone()], vec![cd.contract_key.clone()]);
+    write("wasm_code.xdr", &xdr(code));
+    write("wasm_hash.bin", &code.hash.0);
+    write("wasm_entry.xdr", &xdr(&cd.wasm_entry));
+    write("wasm_ttl.xdr", &xdr(&wasm_ttl));
+    write("deployer.xdr", &xdr(&cd.deployer));
+    write("create_host_fn.xdr", &xdr(&cd.host_fn));
+    write("create_resources.xdr", &xdr(&create_resources));
+    write("create_auth.xdr", &xdr(&cd.auth_entry));
+
+    let report = compile_and_run(&dir);
+    let read = |name: &str| fs::read(dir.join(name)).unwrap();
+
+    // Upload matches the Rust API exactly, including the metering.
+    let budget = Budget::default();
+    let expected = invoke_host_function(
+        &budget,
+        true,
+        xdr(&upload_host_fn),
+        xdr(&upload_resources),
+        &[],
+        xdr(&source_account),
+        Vec::<Vec<u8>>::new().into_iter(),
+        ledger_info.clone(),
+        Vec::<Vec<u8>>::new().into_iter(),
+        Vec::<Vec<u8>>::new().into_iter(),
+        PRNG_SEED.to_vec(),
+        &mut vec![],
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(report.get("upload_success"), "1");
+    assert_eq!(
+        read("upload_result.xdr"),
+        expected.encoded_invoke_result.unwrap()
+    );
+    assert_eq!(
+        read("upload_change_key.xdr"),
+        expected.ledger_changes[0].encoded_key
+    );
+    assert_eq!(
+        Some(read("upload_change_value.xdr")),
+        expected.ledger_changes[0].encoded_new_value
+    );
+    assert_eq!(
+        report.get_u64("upload_cpu_insns"),
+        budget.get_cpu_insns_consumed().unwrap()
+    );
+    assert_eq!(
+        report.get_u64("upload_mem_bytes"),
+        budget.get_mem_bytes_consumed().unwrap()
+    );
+
+    // Contract creation uses the module cache, which makes it cheaper than
+    // the same invocation without the cache.
+    let budget = Budget::default();
+    let expected = invoke_host_function(
+        &budget,
+        false,
+        xdr(&cd.host_fn),
+        xdr(&create_resources),
+        &[],
+        xdr(&cd.deployer),
+        vec![xdr(&cd.auth_entry)].into_iter(),
+        ledger_info.clone(),
+        vec![xdr(&cd.wasm_entry)].into_iter(),
+        vec![xdr(&wasm_ttl)].into_iter(),
+        PRNG_SEED.to_vec(),
+        &mut vec![],
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(report.get("cache_contains"), "1");
+    assert_eq!(report.get("cache_contains_after_remove"), "0");
+    assert_eq!(report.get("create_success"), "1");
+    assert_eq!(
+        read("create_result.xdr"),
+        xdr(&ScVal::Address(cd.contract_address.clone()))
+    );
+    assert_eq!(
+        report.get_u64("create_ledger_changes"),
+        expected.ledger_changes.len() as u64
+    );
+    assert_eq!(
+        report.get_u64("create_ttl_changes"),
+        expected
+            .ledger_changes
+            .iter()
+            .filter(|c| c.ttl_change.is_some())
+            .count() as u64
+    );
+    assert!(report.get_u64("create_cpu_insns") < budget.get_cpu_insns_consumed().unwrap());
+
+    // Recording mode records the same resources and authorization.
+    let budget = Budget::default();
+    let expected = invoke_host_function_in_recording_mode(
+        &budget,
+        false,
+        &cd.host_fn,
+        &cd.deployer,
+        RecordingInvocationAuthMode::Recording(true),
+        ledger_info.clone(),
+        Rc::new(MockSnapshotSource::from_entries(vec![(
+            cd.wasm_entry.clone(),
+            Some(wasm_ttl.live_until_ledger_seq),
+        )])),
+        PRNG_SEED,
+        &mut vec![],
+    )
+    .unwrap();
+    assert_eq!(report.get("recording_success"), "1");
+    assert_eq!(read("recording_resources.xdr"), xdr(&expected.resources));
+    assert_eq!(read("recording_auth.xdr"), xdr(&expected.auth[0]));
+    assert_eq!(report.get_u64("recording_restored_entries"), 0);
+    assert_eq!(
+        report.get_u64("recording_events_and_return_value_size"),
+        expected.contract_events_and_return_value_size as u64
+    );
+    assert_eq!(
+        report.get_u64("recording_cpu_insns"),
+        budget.get_cpu_insns_consumed().unwrap()
+    );
+
+    // Malformed input fails the invocation, but still reports diagnostics.
+    assert_eq!(report.get("invalid_success"), "0");
+    assert_eq!(report.get("invalid_ledger_changes"), "0");
+    assert_ne!(report.get("invalid_error"), "0 0");
+    assert_eq!(report.get("cache_add_invalid"), "1");
+
+    let (non_refundable, refundable) = compute_transaction_resource_fee(
+        &TransactionResources {
+            instructions: 12345678,
+            disk_read_entries: 3,
+            write_entries: 2,
+            disk_read_bytes: 5000,
+            write_bytes: 3000,
+            contract_events_size_bytes: 1500,
+            transaction_size_bytes: 900,
+        },
+        &FeeConfiguration {
+            fee_per_instruction_increment: 25,
+            fee_per_disk_read_entry: 6250,
+            fee_per_write_entry: 10000,
+            fee_per_disk_read_1kb: 1786,
+            fee_per_write_1kb: 11800,
+            fee_per_historical_1kb: 16235,
+            fee_per_contract_event_1kb: 10000,
+            fee_per_transaction_size_1kb: 1624,
+        },
+    );
+    assert_eq!(
+        report.get("resource_fee"),
+        format!("{non_refundable} {refundable}")
+    );
+    let rent_fee = compute_rent_fee(
+        &[
+            LedgerEntryRentChange {
+                is_persistent: true,
+                is_code_entry: false,
+                old_size_bytes: 0,
+                new_size_bytes: 1000,
+                old_live_until_ledger: 0,
+                new_live_until_ledger: 100500,
+            },
+            LedgerEntryRentChange {
+                is_persistent: false,
+                is_code_entry: false,
+                old_size_bytes: 200,
+                new_size_bytes: 300,
+                old_live_until_ledger: 100020,
+                new_live_until_ledger: 100100,
+            },
+            LedgerEntryRentChange {
+                is_persistent: true,
+                is_code_entry: true,
+                old_size_bytes: 5000,
+                new_size_bytes: 5000,
+                old_live_until_ledger: 100010,
+                new_live_until_ledger: 200000,
+            },
+        ],
+        &RentFeeConfiguration {
+            fee_per_write_1kb: 11800,
+            fee_per_rent_1kb: 1000,
+            fee_per_write_entry: 10000,
+            persistent_rent_rate_denominator: 2103,
+            temporary_rent_rate_denominator: 4206,
+        },
+        100000,
+    );
+    assert_eq!(report.get_u64("rent_fee"), rent_fee as u64);
+
+    let _ = fs::remove_dir_all(&dir);
+}
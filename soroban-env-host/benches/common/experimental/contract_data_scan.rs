@@ -0,0 +1,68 @@
+use crate::common::HostCostMeasurement;
+use rand::rngs::StdRng;
+use soroban_env_host::{
+    budget::AsBudget,
+    cost_runner::{ContractDataKeyScanRun, ContractDataKeyScanSample},
+    xdr::{
+        ContractDataDurability, ContractDataEntry, ContractId, ExtensionPoint, Hash, LedgerEntry,
+        LedgerEntryData, LedgerEntryExt, LedgerKey, LedgerKeyContractData, ScAddress, ScSymbol,
+        ScVal,
+    },
+    Host, MeteredOrdMap,
+};
+use std::rc::Rc;
+
+pub(crate) struct ContractDataKeyScanMeasure;
+
+// Measures the costs of scanning the storage map for the contract data keys
+// with a given prefix. The input value is the number of entries in the
+// storage map. Half of the entries belong to the scanned contract, and half of
+// those match the prefix, so every kind of the entry check is exercised.
+impl HostCostMeasurement for ContractDataKeyScanMeasure {
+    type Runner = ContractDataKeyScanRun;
+
+    const STEP_SIZE: u64 = 64;
+
+    fn new_random_case(host: &Host, _rng: &mut StdRng, input: u64) -> ContractDataKeyScanSample {
+        let size = 1 + input * Self::STEP_SIZE;
+        let contract = ScAddress::Contract(ContractId(Hash([1; 32])));
+        let other_contract = ScAddress::Contract(ContractId(Hash([2; 32])));
+        let prefix = vec![ScVal::Symbol(ScSymbol("Balance".try_into().unwrap()))];
+        let other_prefix = vec![ScVal::Symbol(ScSymbol("Allowance".try_into().unwrap()))];
+        let mut entries = vec![];
+        for contract in [&contract, &other_contract] {
+            for (i, key_prefix) in [&other_prefix, &prefix].into_iter().enumerate() {
+                for j in 0..size / 4 + 1 {
+                    let mut key = key_prefix.clone();
+                    key.push(ScVal::U64(j));
+                    let key = ScVal::Vec(Some(key.try_into().unwrap()));
+                    let entry = LedgerEntry {
+                        last_modified_ledger_seq: 0,
+                        data: LedgerEntryData::ContractData(ContractDataEntry {
+                            ext: ExtensionPoint::V0,
+                            contract: contract.clone(),
+                            key: key.clone(),
+                            durability: ContractDataDurability::Persistent,
+                            val: ScVal::U32(i as u32),
+                        }),
+                        ext: LedgerEntryExt::V0,
+                    };
+                    entries.push((
+                        Rc::new(LedgerKey::ContractData(LedgerKeyContractData {
+                            contract: contract.clone(),
+                            key,
+                            durability: ContractDataDurability::Persistent,
+                        })),
+                        Some((Rc::new(entry), Some(100))),
+                    ));
+                }
+            }
+        }
+        let map = MeteredOrdMap::from_map(entries, host.as_budget()).unwrap();
+        ContractDataKeyScanSample {
+            map,
+            contract,
+            prefix,
+        }
+    }
+}
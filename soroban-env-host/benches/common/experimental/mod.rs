@@ -1,4 +1,5 @@
 mod bls12_381;
+mod contract_data_scan;
 mod decode_secp256r1_sig;
 mod ecdsa_secp256k1_verify;
 mod ecdsa_secp256r1_recover;
@@ -7,6 +8,7 @@ mod read_xdr;
 mod sec1_decode_point_compressed;
 
 pub(crate) use bls12_381::*;
+pub(crate) use contract_data_scan::*;
 pub(crate) use decode_secp256r1_sig::*;
 pub(crate) use ecdsa_secp256k1_verify::*;
 pub(crate) use ecdsa_secp256r1_recover::*;
@@ -71,6 +71,7 @@ pub(crate) fn for_each_experimental_cost_measurement<B: Benchmark>(
     call_bench::<B, Bls12381G2CheckPointOnCurveMeasure>(&mut params)?;
     call_bench::<B, Bls12381G2CheckPointInSubgroupMeasure>(&mut params)?;
     call_bench::<B, Bls12381G2ComputeYFromXMeasure>(&mut params)?;
+    call_bench::<B, ContractDataKeyScanMeasure>(&mut params)?;
 
     Ok(params)
 }
@@ -174,9 +174,12 @@ use super::xdr::Hash;
 
 #[cfg(any(test, feature = "recording_mode"))]
 use crate::{
-    builtin_contracts::{account_contract::AccountEd25519Signature, base_types::BytesN},
+    builtin_contracts::{
+        account_contract::{emulate_account_authentication, AccountEd25519Signature},
+        base_types::{BytesN, Vec as ContractVec},
+    },
     host::error::TryBorrowOrErr,
-    xdr::{ContractExecutable, PublicKey},
+    xdr::{ContractExecutable, ContractId, PublicKey, ScBytes, ScMap, ScMapEntry, ScSymbol},
 };
 #[cfg(any(test, feature = "recording_mode"))]
 use rand::Rng;
@@ -274,6 +277,68 @@ pub struct RecordedAuthPayload {
     pub invocation: xdr::SorobanAuthorizedInvocation,
 }
 
+/// Provides representative signatures for the addresses that require
+/// authorization in the recording authorization mode.
+///
+/// There are no actual signatures in the recording mode, so by default the
+/// authentication is emulated with a single signature verification for the
+/// classic accounts and without calling `__check_auth` for the custom
+/// accounts. This underestimates the resources for the multisig accounts and
+/// the custom accounts. When a signature stub is provided for an address, the
+/// recording mode authenticates the recorded authorization of that address
+/// with the stub instead: every signature of a classic account stub is
+/// verified, and `__check_auth` of a custom account is called with the stub.
+///
+/// The stubs don't need to be valid signatures, as the authentication errors
+/// are ignored in the recording mode (the budget is still charged). However,
+/// the stubs must be correctly shaped (e.g. have the expected number of
+/// signatures) for the resources to be realistic. The recorded authorization
+/// entries don't contain the stubs.
+#[cfg(any(test, feature = "recording_mode"))]
+pub trait SignatureStubProvider {
+    /// Returns the signature stub for `address`, or `None` in order to use the
+    /// default authentication emulation.
+    ///
+    /// `signature_payload` is the payload that the actual signature would have
+    /// to sign.
+    fn signature_stub(&self, address: &ScAddress, signature_payload: &[u8; 32]) -> Option<ScVal>;
+}
+
+#[cfg(any(test, feature = "recording_mode"))]
+impl SignatureStubProvider for BTreeMap<ScAddress, ScVal> {
+    fn signature_stub(&self, address: &ScAddress, _signature_payload: &[u8; 32]) -> Option<ScVal> {
+        self.get(address).cloned()
+    }
+}
+
+/// Builds a signature stub for a classic account with the given signer public
+/// keys, i.e. a vector of `AccountEd25519Signature` structures with zero
+/// signatures, ordered by the public key.
+#[cfg(any(test, feature = "recording_mode"))]
+pub fn account_signature_stub(signer_public_keys: &[[u8; 32]]) -> Result<ScVal, HostError> {
+    let mut public_keys = signer_public_keys.to_vec();
+    public_keys.sort();
+    let signatures = public_keys
+        .into_iter()
+        .map(|public_key| {
+            Ok(ScVal::Map(Some(ScMap(
+                vec![
+                    ScMapEntry {
+                        key: ScVal::Symbol(ScSymbol("public_key".try_into()?)),
+                        val: ScVal::Bytes(ScBytes(public_key.try_into()?)),
+                    },
+                    ScMapEntry {
+                        key: ScVal::Symbol(ScSymbol("signature".try_into()?)),
+                        val: ScVal::Bytes(ScBytes([0_u8; 64].try_into()?)),
+                    },
+                ]
+                .try_into()?,
+            ))))
+        })
+        .collect::<Result<std::vec::Vec<ScVal>, HostError>>()?;
+    Ok(ScVal::Vec(Some(signatures.try_into()?)))
+}
+
 // Snapshot of `AuthorizationManager` to use when performing the callstack
 // rollbacks.
 pub struct AuthorizationManagerSnapshot {
@@ -310,6 +375,8 @@ struct RecordingAuthInfo {
     // Whether to allow root authorized invocation to not match the root
     // contract invocation.
     disable_non_root_auth: bool,
+    // Provides the signature stubs for the authentication emulation.
+    signature_stub_provider: Option<Rc<dyn SignatureStubProvider>>,
 }
 
 #[derive(Clone, Hash)]
@@ -813,6 +880,7 @@ impl AuthorizationManager {
             mode: AuthorizationMode::Recording(RecordingAuthInfo {
                 tracker_by_address_handle: Default::default(),
                 disable_non_root_auth,
+                signature_stub_provider: None,
             }),
             call_stack: RefCell::new(vec![]),
             account_trackers: RefCell::new(vec![]),
@@ -1446,11 +1514,29 @@ impl AuthorizationManager {
     pub(crate) fn maybe_emulate_authentication(&self, host: &Host) -> Result<(), HostError> {
         match &self.mode {
             AuthorizationMode::Enforcing => Ok(()),
-            AuthorizationMode::Recording(_) => {
-                for tracker in self.try_borrow_account_trackers(host)?.iter() {
-                    tracker
-                        .try_borrow_mut_or_err()?
-                        .emulate_authentication(host)?;
+            AuthorizationMode::Recording(recording_info) => {
+                // `__check_auth` calls may create new trackers (when custom
+                // accounts require authorization from other addresses), so
+                // the trackers are iterated by index and must not be borrowed
+                // during the calls.
+                let mut tracker_id = 0;
+                loop {
+                    let check_auth_call = {
+                        let trackers = self.try_borrow_account_trackers(host)?;
+                        let Some(tracker) = trackers.get(tracker_id) else {
+                            break;
+                        };
+                        let check_auth_call =
+                            tracker.try_borrow_mut_or_err()?.emulate_authentication(
+                                host,
+                                recording_info.signature_stub_provider.as_ref(),
+                            )?;
+                        check_auth_call
+                    };
+                    if let Some(check_auth_call) = check_auth_call {
+                        check_auth_call.emulate(host)?;
+                    }
+                    tracker_id += 1;
                 }
                 Ok(())
             }
@@ -1467,11 +1553,27 @@ impl AuthorizationManager {
                 AuthorizationManager::new_enforcing_without_authorizations()
             }
             AuthorizationMode::Recording(rec_info) => {
-                AuthorizationManager::new_recording(rec_info.disable_non_root_auth)
+                let mut auth_manager =
+                    AuthorizationManager::new_recording(rec_info.disable_non_root_auth);
+                auth_manager.set_signature_stub_provider(rec_info.signature_stub_provider.clone());
+                auth_manager
             }
         }
     }
 
+    // Sets the signature stub provider for the authentication emulation.
+    // No-op in the enforcing mode.
+    // metering: free
+    #[cfg(any(test, feature = "recording_mode"))]
+    pub(crate) fn set_signature_stub_provider(
+        &mut self,
+        signature_stub_provider: Option<Rc<dyn SignatureStubProvider>>,
+    ) {
+        if let AuthorizationMode::Recording(rec_info) = &mut self.mode {
+            rec_info.signature_stub_provider = signature_stub_provider;
+        }
+    }
+
     // Returns all authorizations that have been authenticated for the
     // last contract invocation.
     // metering: free, testutils
@@ -1767,6 +1869,52 @@ impl InvocationTracker {
     }
 }
 
+// The `__check_auth` call of a custom account with a signature stub, see
+// `SignatureStubProvider`.
+#[cfg(any(test, feature = "recording_mode"))]
+struct CheckAuthEmulation {
+    contract_id: ContractId,
+    signature_payload: [u8; 32],
+    signature: Val,
+    invocation: AuthorizedInvocation,
+}
+
+#[cfg(any(test, feature = "recording_mode"))]
+impl CheckAuthEmulation {
+    // metering: covered
+    fn emulate(&self, host: &Host) -> Result<(), HostError> {
+        emulate_ignoring_errors(host, || {
+            check_account_contract_auth(
+                host,
+                &self.contract_id,
+                &self.signature_payload,
+                self.signature,
+                &self.invocation,
+            )
+        })
+    }
+}
+
+// Runs the authentication emulation that is expected to fail due to the
+// invalid signatures. The errors and diagnostics are suppressed, except for
+// the budget errors.
+// metering: covered
+#[cfg(any(test, feature = "recording_mode"))]
+fn emulate_ignoring_errors(
+    host: &Host,
+    f: impl FnOnce() -> Result<(), HostError>,
+) -> Result<(), HostError> {
+    let mut res = Ok(());
+    host.with_suppressed_diagnostic_events(|| {
+        res = f();
+        Ok(())
+    })?;
+    match res {
+        Err(e) if e.error.is_type(ScErrorType::Budget) => Err(e),
+        _ => Ok(()),
+    }
+}
+
 impl AccountAuthorizationTracker {
     // Metering: covered by the host and components
     fn from_authorization_entry(
@@ -2073,21 +2221,66 @@ impl AccountAuthorizationTracker {
     }
 
     // Emulates authentication for the recording mode.
+    // Returns the `__check_auth` call that has to be emulated for the custom
+    // accounts that have a signature stub. The call can't be made here, as
+    // the tracker is borrowed.
     // metering: covered
     #[cfg(any(test, feature = "recording_mode"))]
-    fn emulate_authentication(&mut self, host: &Host) -> Result<(), HostError> {
+    fn emulate_authentication(
+        &mut self,
+        host: &Host,
+        signature_stub_provider: Option<&Rc<dyn SignatureStubProvider>>,
+    ) -> Result<Option<CheckAuthEmulation>, HostError> {
         if self.is_transaction_source_account {
-            return Ok(());
+            return Ok(None);
         }
         let sc_addr = host.scaddress_from_address(self.address)?;
-        match sc_addr {
-            ScAddress::Account(acc) => {
+        let signature_stub = match signature_stub_provider {
+            Some(provider) => {
+                let signature_payload = self.get_signature_payload(host)?;
+                provider
+                    .signature_stub(&sc_addr, &signature_payload)
+                    .map(|stub| (stub, signature_payload))
+            }
+            None => None,
+        };
+        match (sc_addr, signature_stub) {
+            (ScAddress::Account(acc), Some((stub, signature_payload))) => {
+                let signatures: ContractVec =
+                    host.to_host_val(&stub)?.try_into_val(host).map_err(|_| {
+                        host.err(
+                            ScErrorType::Auth,
+                            ScErrorCode::InvalidInput,
+                            "account signature stub must be a vector of signatures",
+                            &[self.address.into()],
+                        )
+                    })?;
+                self.signature = signatures.into();
+                // Authenticate with the whole stub vector at once (like the
+                // enforcing mode does), but without stopping at the first
+                // invalid stub signature.
+                emulate_ignoring_errors(host, || {
+                    emulate_account_authentication(host, acc, &signature_payload, self.signature)
+                })?;
+            }
+            (ScAddress::Contract(contract_id), Some((stub, signature_payload))) => {
+                self.signature = host.to_host_val(&stub)?;
+                return Ok(Some(CheckAuthEmulation {
+                    contract_id,
+                    signature_payload,
+                    signature: self.signature,
+                    // metering: free for recording
+                    invocation: self.invocation_tracker.root_authorized_invocation.clone(),
+                }));
+            }
+            (ScAddress::Account(acc), None) => {
                 // Emulate verification of a single signature that belongs to this
                 // account.
                 // We could emulate more (up to 20) signature verifications, but
                 // since signature verification is a pretty expensive operation, while
                 // multisig in combination with Soroban auth is probably pretty rare,
-                // multisig users should either use enforcing auth simulation, or
+                // multisig users should either use enforcing auth simulation, provide
+                // the signature stubs (see `SignatureStubProvider`), or
                 // intentionally increase the instruction count on the recording result.
                 let key_bytes = match &acc.0 {
                     PublicKey::PublicKeyTypeEd25519(k) => k.0,
@@ -2110,7 +2303,7 @@ impl AccountAuthorizationTracker {
                 // - Return budget error in case if it was suppressed above.
                 let _ = acc.metered_clone(host.as_budget())?;
             }
-            ScAddress::Contract(contract_id) => {
+            (ScAddress::Contract(contract_id), None) => {
                 let instance_key = host.contract_instance_ledger_key(&contract_id)?;
                 let entry = host
                     .try_borrow_storage_mut()?
@@ -2125,15 +2318,15 @@ impl AccountAuthorizationTracker {
                         LedgerEntryData::ContractData(e) => match &e.val {
                             ScVal::ContractInstance(instance) => instance.metered_clone(host)?,
                             _ => {
-                                return Ok(());
+                                return Ok(None);
                             }
                         },
                         _ => {
-                            return Ok(());
+                            return Ok(None);
                         }
                     }
                 } else {
-                    return Ok(());
+                    return Ok(None);
                 };
 
                 match &instance.executable {
@@ -2155,7 +2348,7 @@ impl AccountAuthorizationTracker {
                 ));
             }
         }
-        Ok(())
+        Ok(None)
     }
 
     // metering: covered
@@ -2303,6 +2496,31 @@ impl Host {
         })
     }
 
+    /// Sets the provider of the signature stubs that are used to emulate the
+    /// authentication in the recording authorization mode, see
+    /// [`SignatureStubProvider`].
+    ///
+    /// This should only be called in the recording authorization mode, i.e.
+    /// after `switch_to_recording_auth` has been called.
+    #[cfg(any(test, feature = "recording_mode"))]
+    pub fn set_signature_stub_provider(
+        &self,
+        signature_stub_provider: Rc<dyn SignatureStubProvider>,
+    ) -> Result<(), HostError> {
+        let mut auth_manager = self.try_borrow_authorization_manager_mut()?;
+        if let AuthorizationMode::Enforcing = auth_manager.mode {
+            drop(auth_manager);
+            return Err(self.err(
+                ScErrorType::Auth,
+                ScErrorCode::InvalidAction,
+                "signature stubs can only be used in recording auth mode",
+                &[],
+            ));
+        }
+        auth_manager.set_signature_stub_provider(Some(signature_stub_provider));
+        Ok(())
+    }
+
     // Returns the recorded per-address authorization payloads that would cover the
     // top-level contract function invocation in the enforcing mode.
     // This should only be called in the recording authorization mode, i.e. only
@@ -2396,8 +2614,12 @@ impl Host {
                 recording_auth_info.disable_non_root_auth
             }
         };
-        *self.try_borrow_authorization_manager_mut()? =
-            AuthorizationManager::new_recording(disable_non_root_auth);
+        let mut auth_manager = AuthorizationManager::new_recording(disable_non_root_auth);
+        if let AuthorizationMode::Recording(recording_auth_info) = &auth_manager_snapshot.mode {
+            auth_manager
+                .set_signature_stub_provider(recording_auth_info.signature_stub_provider.clone());
+        }
+        *self.try_borrow_authorization_manager_mut()? = auth_manager;
         Ok(())
     }
 
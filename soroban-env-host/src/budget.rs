@@ -1285,6 +1285,16 @@ impl Budget {
         self.0.try_borrow_mut_or_err()?.get_wasmi_fuel_remaining()
     }
 
+    /// Returns the CPU and memory cost parameters of the budget. For the
+    /// default budget these are the calibrated cost parameters of the host.
+    pub fn get_cost_params(&self) -> Result<(ContractCostParams, ContractCostParams), HostError> {
+        let budget = self.0.try_borrow_or_err()?;
+        Ok((
+            budget.cpu_insns.to_cost_params()?,
+            budget.mem_bytes.to_cost_params()?,
+        ))
+    }
+
     pub fn reset_default(&self) -> Result<(), HostError> {
         *self.0.try_borrow_mut_or_err()? = BudgetImpl::default();
         Ok(())
@@ -1352,3 +1362,22 @@ fn test_budget_initialization() -> Result<(), HostError> {
 
     Ok(())
 }
+
+#[test]
+fn test_budget_cost_params_round_trip() -> Result<(), HostError> {
+    let (cpu_cost_params, mem_cost_params) = Budget::default().get_cost_params()?;
+    assert_eq!(cpu_cost_params.0.len(), ContractCostType::variants().len());
+    assert_eq!(mem_cost_params.0.len(), ContractCostType::variants().len());
+
+    let restored = Budget::try_from_configs(
+        u64::MAX,
+        u64::MAX,
+        cpu_cost_params.clone(),
+        mem_cost_params.clone(),
+    )?;
+    assert_eq!(
+        restored.get_cost_params()?,
+        (cpu_cost_params, mem_cost_params)
+    );
+    Ok(())
+}
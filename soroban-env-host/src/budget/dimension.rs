@@ -1,5 +1,8 @@
 use super::model::{HostCostModel, MeteredCostComponent};
-use crate::xdr::{ContractCostParams, ContractCostType, ScErrorCode, ScErrorType};
+use crate::xdr::{
+    ContractCostParamEntry, ContractCostParams, ContractCostType, ExtensionPoint, ScErrorCode,
+    ScErrorType,
+};
 use crate::{Error, HostError};
 use core::fmt::Debug;
 
@@ -93,6 +96,21 @@ impl BudgetDimension {
         Ok(bd)
     }
 
+    // Inverse of `try_from_config`: returns the cost parameters of all the
+    // cost types.
+    pub(crate) fn to_cost_params(&self) -> Result<ContractCostParams, HostError> {
+        let entries: Vec<ContractCostParamEntry> = self
+            .cost_models
+            .iter()
+            .map(|cm| ContractCostParamEntry {
+                ext: ExtensionPoint::V0,
+                const_term: cm.const_term as i64,
+                linear_term: cm.lin_term.0 as i64,
+            })
+            .collect();
+        Ok(ContractCostParams(entries.try_into()?))
+    }
+
     pub(crate) fn get_cost_model(
         &self,
         ty: ContractCostType,
@@ -6,7 +6,12 @@
 ))]
 use crate::{budget::Budget, HostError};
 
-#[cfg(any(test, feature = "testutils", feature = "bench"))]
+#[cfg(any(
+    test,
+    feature = "testutils",
+    feature = "bench",
+    feature = "recording_mode"
+))]
 use crate::host::error::TryBorrowOrErr;
 
 #[cfg(any(test, feature = "testutils"))]
@@ -213,4 +218,15 @@ impl Budget {
 
         rt
     }
+
+    /// Returns an independent budget with the same cost models and limits as
+    /// this one, but with no resources consumed.
+    pub(crate) fn fork(&self) -> Result<Budget, HostError> {
+        let mut budget = self.0.try_borrow_or_err()?.clone();
+        budget.cpu_insns.reset_count();
+        budget.mem_bytes.reset_count();
+        budget.tracker = Default::default();
+        budget.is_in_shadow_mode = false;
+        Ok(Budget(std::rc::Rc::new(std::cell::RefCell::new(budget))))
+    }
 }
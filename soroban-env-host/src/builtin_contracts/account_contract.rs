@@ -173,6 +173,33 @@ pub(crate) fn check_account_authentication(
     account_id: AccountId,
     payload: &[u8],
     signature: Val,
+) -> Result<(), HostError> {
+    check_account_authentication_impl(host, account_id, payload, signature, false)
+}
+
+// Emulates `check_account_authentication` for the recording mode: instead of
+// stopping at the first invalid signature, processes every signature in
+// `signature` (which are normally the signature stubs that are not valid)
+// exactly like the valid signatures are processed, so that the same work as
+// for the valid signatures is charged. Returns the first error encountered.
+// metering: covered
+#[cfg(any(test, feature = "recording_mode"))]
+pub(crate) fn emulate_account_authentication(
+    host: &Host,
+    account_id: AccountId,
+    payload: &[u8],
+    signature: Val,
+) -> Result<(), HostError> {
+    check_account_authentication_impl(host, account_id, payload, signature, true)
+}
+
+// metering: covered
+fn check_account_authentication_impl(
+    host: &Host,
+    account_id: AccountId,
+    payload: &[u8],
+    signature: Val,
+    process_all_signatures: bool,
 ) -> Result<(), HostError> {
     let signatures: HostVec = signature.try_into_val(host)?;
     // Check if there is too many signatures: there shouldn't be more
@@ -193,6 +220,18 @@ pub(crate) fn check_account_authentication(
             &[],
         ));
     }
+    // When all the signatures have to be processed, the signature errors are
+    // deferred until the end. Budget errors are never deferred.
+    let mut first_error: Option<HostError> = None;
+    let mut check = |res: Result<(), HostError>| -> Result<(), HostError> {
+        match res {
+            Err(e) if process_all_signatures && !e.error.is_type(ScErrorType::Budget) => {
+                first_error.get_or_insert(e);
+                Ok(())
+            }
+            res => res,
+        }
+    };
     let payload_obj = host.bytes_new_from_slice(payload)?;
     let account = host.load_account(account_id)?;
     let mut prev_pk: Option<BytesN<32>> = None;
@@ -202,20 +241,23 @@ pub(crate) fn check_account_authentication(
         // Cannot take multiple signatures from the same key
         if let Some(prev) = prev_pk {
             if prev.compare(&sig.public_key)? != Ordering::Less {
-                return Err(err!(
+                check(Err(err!(
                     host,
                     ContractError::AuthenticationError,
                     "public keys are not ordered",
                     prev,
                     sig.public_key
-                ));
+                )))?;
             }
         }
 
-        host.verify_sig_ed25519(
-            sig.public_key.clone().into(),
-            payload_obj,
-            sig.signature.into(),
+        check(
+            host.verify_sig_ed25519(
+                sig.public_key.clone().into(),
+                payload_obj,
+                sig.signature.into(),
+            )
+            .map(|_| ()),
         )?;
 
         let signer_weight =
@@ -224,12 +266,12 @@ pub(crate) fn check_account_authentication(
         // this as an error to indicate a bug in signatures, even if another
         // signers would have enough weight.
         if signer_weight == 0 {
-            return Err(err!(
+            check(Err(err!(
                 host,
                 ContractError::AuthenticationError,
                 "signer does not belong to account",
                 sig.public_key
-            ));
+            )))?;
         }
         // Overflow isn't possible here as
         // 255 * MAX_ACCOUNT_SIGNATURES is < u32::MAX,
@@ -237,6 +279,9 @@ pub(crate) fn check_account_authentication(
         weight = weight.saturating_add(signer_weight as u32);
         prev_pk = Some(sig.public_key);
     }
+    if let Some(e) = first_error {
+        return Err(e);
+    }
     // This should always work but again, we err on side
     // of future-proofing against changed assumptions.
     let Some(threshold) = account.thresholds.0.get(ThresholdIndexes::Med as usize) else {
@@ -0,0 +1,63 @@
+use std::hint::black_box;
+
+use crate::{
+    budget::CostTracker,
+    cost_runner::{CostRunner, CostType},
+    storage::StorageMap,
+    xdr::{ContractDataDurability, ScAddress, ScVal},
+};
+
+use super::ExperimentalCostType;
+
+pub struct ContractDataKeyScanRun;
+
+#[derive(Clone)]
+pub struct ContractDataKeyScanSample {
+    pub map: StorageMap,
+    pub contract: ScAddress,
+    pub prefix: Vec<ScVal>,
+}
+
+impl CostRunner for ContractDataKeyScanRun {
+    // There is no dedicated `ContractCostType` for the contract data key scan
+    // yet, so internally the scan is charged via the storage map access and
+    // the key comparison components. This measures the actual cost of the
+    // scan per storage map entry in order to calibrate the dedicated cost
+    // type.
+    const COST_TYPE: CostType = CostType::Experimental(ExperimentalCostType::ContractDataKeyScan);
+
+    type SampleType = ContractDataKeyScanSample;
+
+    type RecycledType = (Option<Vec<ScVal>>, Self::SampleType);
+
+    fn run_iter(host: &crate::Host, _iter: u64, sample: Self::SampleType) -> Self::RecycledType {
+        let keys = black_box(
+            host.scan_contract_data_keys_in_map(
+                &sample.map,
+                &sample.contract,
+                &sample.prefix,
+                ContractDataDurability::Persistent,
+                0,
+            )
+            .unwrap(),
+        );
+        (Some(keys), sample)
+    }
+
+    fn run_baseline_iter(
+        _host: &crate::Host,
+        _iter: u64,
+        sample: Self::SampleType,
+    ) -> Self::RecycledType {
+        black_box((None, sample))
+    }
+
+    fn get_tracker(_host: &crate::Host, sample: &Self::SampleType) -> CostTracker {
+        CostTracker {
+            iterations: Self::RUN_ITERATIONS,
+            inputs: Some((sample.map.len() as u64).saturating_mul(Self::RUN_ITERATIONS)),
+            cpu: 0,
+            mem: 0,
+        }
+    }
+}
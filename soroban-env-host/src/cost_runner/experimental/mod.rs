@@ -1,4 +1,5 @@
 mod bls12_381;
+mod contract_data_scan;
 mod decode_secp256r1_sig;
 mod ecdsa_secp256k1_verify;
 mod ecdsa_secp256r1_recover;
@@ -7,6 +8,7 @@ mod read_xdr;
 mod sec1_decode_point_compressed;
 
 pub use bls12_381::*;
+pub use contract_data_scan::*;
 pub use decode_secp256r1_sig::*;
 pub use ecdsa_secp256k1_verify::*;
 pub use ecdsa_secp256r1_recover::*;
@@ -32,6 +34,7 @@ pub enum ExperimentalCostType {
     Bls12381Fp2DeserializeUncompressed,
     Bls12381G1ComputeYFromX,
     Bls12381G2ComputeYFromX,
+    ContractDataKeyScan,
 }
 
 impl Name for ExperimentalCostType {
@@ -62,6 +65,7 @@ impl Name for ExperimentalCostType {
             }
             ExperimentalCostType::Bls12381G1ComputeYFromX => "Bls12381G1ComputeYFromX",
             ExperimentalCostType::Bls12381G2ComputeYFromX => "Bls12381G2ComputeYFromX",
+            ExperimentalCostType::ContractDataKeyScan => "ContractDataKeyScan",
         }
     }
 }
@@ -6,10 +6,10 @@ use std::{cmp::max, rc::Rc};
 
 #[cfg(any(test, feature = "recording_mode"))]
 use crate::{
-    auth::RecordedAuthPayload,
+    auth::{RecordedAuthPayload, SignatureStubProvider},
+    host::call_tree::CallTreeNode,
     storage::is_persistent_key,
-    xdr::{ContractEvent, ReadXdr, ScVal, SorobanAddressCredentials, SorobanCredentials, WriteXdr},
-    DEFAULT_XDR_RW_LIMITS,
+    xdr::{SorobanAddressCredentials, SorobanCredentials},
 };
 use crate::{
     budget::{AsBudget, Budget},
@@ -23,21 +23,112 @@ use crate::{
     },
     storage::{AccessType, Footprint, FootprintMap, SnapshotSource, Storage, StorageMap},
     xdr::{
-        AccountId, ContractDataDurability, ContractEventType, DiagnosticEvent, HostFunction,
-        LedgerEntry, LedgerEntryData, LedgerEntryType, LedgerFootprint, LedgerKey,
-        LedgerKeyAccount, LedgerKeyContractCode, LedgerKeyContractData, LedgerKeyTrustLine,
-        ScErrorCode, ScErrorType, SorobanAuthorizationEntry, SorobanResources, TtlEntry,
+        self, AccountId, ContractCostType, ContractDataDurability, ContractEvent,
+        ContractEventType, DiagnosticEvent, HostFunction, LedgerEntry, LedgerEntryData,
+        LedgerEntryType, LedgerFootprint, LedgerKey, LedgerKeyAccount, LedgerKeyContractCode,
+        LedgerKeyContractData, LedgerKeyTrustLine, Limited, ReadXdr, ScErrorCode, ScErrorType,
+        ScVal, SorobanAuthorizationEntry, SorobanResources, TtlEntry, WriteXdr,
     },
-    DiagnosticLevel, Error, Host, HostError, LedgerInfo, MeteredOrdMap,
+    DiagnosticLevel, Error, ErrorHandler, Host, HostError, LedgerInfo, MeteredOrdMap,
+    DEFAULT_XDR_RW_LIMITS,
 };
 use crate::{ledger_info::get_key_durability, ModuleCache};
 use crate::{storage::EntryWithLiveUntil, vm::wasm_module_memory_cost};
-#[cfg(any(test, feature = "recording_mode"))]
 use sha2::{Digest, Sha256};
 
+mod meta;
+mod receipt;
+#[cfg(any(test, feature = "recording_mode"))]
+mod unchanged_entries;
+pub use meta::{build_transaction_meta, TransactionMetaFeeConfig};
+pub use receipt::{ExecutionReceipt, ReceiptComponent};
+#[cfg(any(test, feature = "recording_mode"))]
+pub use unchanged_entries::{find_unchanged_read_write_entries, UnchangedReadWriteEntries};
+
 type TtlEntryMap = MeteredOrdMap<Rc<LedgerKey>, Rc<TtlEntry>, Budget>;
 type RestoredKeySet = MeteredOrdMap<Rc<LedgerKey>, (), Budget>;
 
+// Input of the host function invocation that is either encoded as XDR or has
+// already been decoded by the embedder.
+// Typed inputs are charged exactly as if they were decoded from their XDR
+// representation, which allows both `invoke_host_function` and
+// `invoke_host_function_typed` to share the same code path and have identical
+// metering.
+trait MeteredXdrInput<T> {
+    fn metered_decode(self, budget: &Budget) -> Result<T, HostError>;
+    fn metered_decode_with_host(self, host: &Host) -> Result<T, HostError>;
+}
+
+struct EncodedXdr<B: AsRef<[u8]>>(B);
+
+impl<T: ReadXdr, B: AsRef<[u8]>> MeteredXdrInput<T> for EncodedXdr<B> {
+    fn metered_decode(self, budget: &Budget) -> Result<T, HostError> {
+        metered_from_xdr_with_budget(self.0.as_ref(), budget)
+    }
+
+    fn metered_decode_with_host(self, host: &Host) -> Result<T, HostError> {
+        host.metered_from_xdr(self.0.as_ref())
+    }
+}
+
+struct DecodedXdr<T: WriteXdr>(T);
+
+impl<T: WriteXdr> DecodedXdr<T> {
+    // Computes the size of the XDR representation of the value without
+    // allocating it.
+    fn encoded_len(&self) -> Result<u64, xdr::Error> {
+        struct LenCounter(u64);
+        impl std::io::Write for LenCounter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0 = self.0.saturating_add(buf.len() as u64);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut w = Limited::new(LenCounter(0), DEFAULT_XDR_RW_LIMITS);
+        self.0.write_xdr(&mut w)?;
+        Ok(w.inner.0)
+    }
+}
+
+impl<T: WriteXdr> MeteredXdrInput<T> for DecodedXdr<T> {
+    fn metered_decode(self, budget: &Budget) -> Result<T, HostError> {
+        budget.charge(ContractCostType::ValDeser, Some(self.encoded_len()?))?;
+        Ok(self.0)
+    }
+
+    fn metered_decode_with_host(self, host: &Host) -> Result<T, HostError> {
+        let len = host.map_err(self.encoded_len())?;
+        host.charge_budget(ContractCostType::ValDeser, Some(len))?;
+        Ok(self.0)
+    }
+}
+
+// Pairs the encoded ledger entries with their respective encoded TTL entries
+// (an empty buffer means that the entry has no TTL).
+fn encoded_ledger_entry_inputs<T: AsRef<[u8]>, I: ExactSizeIterator<Item = T>>(
+    encoded_ledger_entries: I,
+    encoded_ttl_entries: I,
+) -> Result<impl Iterator<Item = (EncodedXdr<T>, Option<EncodedXdr<T>>)>, HostError> {
+    if encoded_ledger_entries.len() != encoded_ttl_entries.len() {
+        return Err(
+            Error::from_type_and_code(ScErrorType::Storage, ScErrorCode::InternalError).into(),
+        );
+    }
+    Ok(encoded_ledger_entries
+        .zip(encoded_ttl_entries)
+        .map(|(entry_buf, ttl_buf)| {
+            let ttl_input = if ttl_buf.as_ref().is_empty() {
+                None
+            } else {
+                Some(EncodedXdr(ttl_buf))
+            };
+            (EncodedXdr(entry_buf), ttl_input)
+        }))
+}
+
 /// Result of invoking a single host function prepared for embedder consumption.
 pub struct InvokeHostFunctionResult {
     /// Result value of the function, encoded `ScVal` XDR on success, or error.
@@ -90,6 +181,12 @@ pub struct InvokeHostFunctionRecordingModeResult {
     /// Size of the encoded contract events and the return value.
     /// Non-zero only when invocation has succeeded.
     pub contract_events_and_return_value_size: u32,
+    /// Resources consumed by every contract call made during the invocation,
+    /// organized as a tree rooted at the host function.
+    ///
+    /// Only populated when `RecordingInvocationOptions::enable_call_tree` is
+    /// set.
+    pub call_tree: Option<CallTreeNode>,
 }
 
 /// Represents a change of the ledger entry from 'old' value to the 'new' one.
@@ -133,6 +230,104 @@ pub struct LedgerEntryLiveUntilChange {
     pub new_live_until_ledger: u32,
 }
 
+/// Result of invoking a single host function via `invoke_host_function_typed`.
+///
+/// This is the same as `InvokeHostFunctionResult`, but with all the values
+/// decoded from XDR.
+pub struct InvokeHostFunctionTypedResult {
+    /// Result value of the function on success, or error.
+    pub invoke_result: Result<ScVal, HostError>,
+    /// All the ledger changes caused by this invocation, including no-ops.
+    /// This contains an entry for *every* item in the input footprint, even if
+    /// it wasn't modified at all.
+    ///
+    /// Empty when invocation fails.
+    pub ledger_changes: Vec<TypedLedgerEntryChange>,
+    /// All the events that contracts emitted during invocation.
+    ///
+    /// Empty when invocation fails.
+    pub contract_events: Vec<ContractEvent>,
+}
+
+/// Decoded version of `LedgerEntryChange`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TypedLedgerEntryChange {
+    /// Whether the ledger entry is read-only, as defined by the footprint.
+    pub read_only: bool,
+    /// Entry key.
+    pub key: LedgerKey,
+    /// Size of the 'old' entry to use in the rent computations.
+    pub old_entry_size_bytes_for_rent: u32,
+    /// New value of the ledger entry.
+    /// Only set for non-removed, non-readonly values, otherwise `None`.
+    pub new_value: Option<LedgerEntry>,
+    /// Size of the 'new' entry to use in the rent computations.
+    pub new_entry_size_bytes_for_rent: u32,
+    /// Change of the live until state of the entry.
+    /// Only set for entries that have a TTL, otherwise `None`.
+    pub ttl_change: Option<LedgerEntryLiveUntilChange>,
+}
+
+impl TypedLedgerEntryChange {
+    /// Decodes the `LedgerEntryChange` produced by `invoke_host_function`.
+    ///
+    /// Decoding is not metered.
+    pub fn from_encoded(change: &LedgerEntryChange) -> Result<Self, HostError> {
+        Ok(Self {
+            read_only: change.read_only,
+            key: LedgerKey::from_xdr(&change.encoded_key, DEFAULT_XDR_RW_LIMITS)?,
+            old_entry_size_bytes_for_rent: change.old_entry_size_bytes_for_rent,
+            new_value: change
+                .encoded_new_value
+                .as_ref()
+                .map(|v| LedgerEntry::from_xdr(v, DEFAULT_XDR_RW_LIMITS))
+                .transpose()?,
+            new_entry_size_bytes_for_rent: change.new_entry_size_bytes_for_rent,
+            ttl_change: change.ttl_change.clone(),
+        })
+    }
+
+    /// Returns the key and the state of the entry after applying this change
+    /// to `snapshot`, which has to be the state the change has been produced
+    /// for. The state is `None` when the entry has been removed.
+    ///
+    /// Read-write changes overwrite or remove the entry. Read-only changes
+    /// may only extend the live until ledger of the entry, so `None` is
+    /// returned for the read-only changes that don't extend it. Extending an
+    /// entry that is missing from `snapshot` is an error, as it means that
+    /// the change has been produced for a different state.
+    ///
+    /// This is not metered.
+    #[allow(clippy::type_complexity)]
+    pub fn updated_entry(
+        &self,
+        snapshot: &(impl SnapshotSource + ?Sized),
+    ) -> Result<Option<(Rc<LedgerKey>, Option<EntryWithLiveUntil>)>, HostError> {
+        let key = Rc::new(self.key.clone());
+        let new_live_until = self
+            .ttl_change
+            .as_ref()
+            .map(|ttl_change| ttl_change.new_live_until_ledger);
+        if !self.read_only {
+            let new_entry = self
+                .new_value
+                .as_ref()
+                .map(|entry| (Rc::new(entry.clone()), new_live_until));
+            return Ok(Some((key, new_entry)));
+        }
+        let Some(ttl_change) = &self.ttl_change else {
+            return Ok(None);
+        };
+        if ttl_change.new_live_until_ledger <= ttl_change.old_live_until_ledger {
+            return Ok(None);
+        }
+        let Some((entry, _)) = snapshot.get(&key)? else {
+            return Err((ScErrorType::Storage, ScErrorCode::InvalidInput).into());
+        };
+        Ok(Some((key, Some((entry, new_live_until)))))
+    }
+}
+
 // Builds a set for metered lookups of keys for entries that were restored from
 // the archived state.
 // This returns an `Option` instead of an empty set because most of the
@@ -319,41 +514,66 @@ pub fn extract_rent_changes(ledger_changes: &[LedgerEntryChange]) -> Vec<LedgerE
     ledger_changes
         .iter()
         .filter_map(|entry_change| {
-            // Rent changes are only relevant to non-removed entries with
-            // a ttl.
-            if let (Some(ttl_change), optional_encoded_new_value) =
-                (&entry_change.ttl_change, &entry_change.encoded_new_value)
-            {
-                let new_size_bytes_for_rent = if optional_encoded_new_value.is_some() {
-                    entry_change.new_entry_size_bytes_for_rent
-                } else {
-                    entry_change.old_entry_size_bytes_for_rent
-                };
-
-                // Skip the entry if 1. it is not extended and 2. the entry size has not increased
-                if ttl_change.old_live_until_ledger >= ttl_change.new_live_until_ledger
-                    && entry_change.old_entry_size_bytes_for_rent >= new_size_bytes_for_rent
-                {
-                    return None;
-                }
-                Some(LedgerEntryRentChange {
-                    is_persistent: matches!(
-                        ttl_change.durability,
-                        ContractDataDurability::Persistent
-                    ),
-                    is_code_entry: matches!(ttl_change.entry_type, LedgerEntryType::ContractCode),
-                    old_size_bytes: entry_change.old_entry_size_bytes_for_rent,
-                    new_size_bytes: new_size_bytes_for_rent,
-                    old_live_until_ledger: ttl_change.old_live_until_ledger,
-                    new_live_until_ledger: ttl_change.new_live_until_ledger,
-                })
-            } else {
-                None
-            }
+            rent_change(
+                &entry_change.ttl_change,
+                entry_change.encoded_new_value.is_some(),
+                entry_change.old_entry_size_bytes_for_rent,
+                entry_change.new_entry_size_bytes_for_rent,
+            )
+        })
+        .collect()
+}
+
+/// Extracts the rent-related changes from the provided typed ledger changes.
+///
+/// This is equivalent to `extract_rent_changes`.
+pub fn extract_rent_changes_from_typed(
+    ledger_changes: &[TypedLedgerEntryChange],
+) -> Vec<LedgerEntryRentChange> {
+    ledger_changes
+        .iter()
+        .filter_map(|entry_change| {
+            rent_change(
+                &entry_change.ttl_change,
+                entry_change.new_value.is_some(),
+                entry_change.old_entry_size_bytes_for_rent,
+                entry_change.new_entry_size_bytes_for_rent,
+            )
         })
         .collect()
 }
 
+fn rent_change(
+    ttl_change: &Option<LedgerEntryLiveUntilChange>,
+    has_new_value: bool,
+    old_entry_size_bytes_for_rent: u32,
+    new_entry_size_bytes_for_rent: u32,
+) -> Option<LedgerEntryRentChange> {
+    // Rent changes are only relevant to non-removed entries with
+    // a ttl.
+    let ttl_change = ttl_change.as_ref()?;
+    let new_size_bytes_for_rent = if has_new_value {
+        new_entry_size_bytes_for_rent
+    } else {
+        old_entry_size_bytes_for_rent
+    };
+
+    // Skip the entry if 1. it is not extended and 2. the entry size has not increased
+    if ttl_change.old_live_until_ledger >= ttl_change.new_live_until_ledger
+        && old_entry_size_bytes_for_rent >= new_size_bytes_for_rent
+    {
+        return None;
+    }
+    Some(LedgerEntryRentChange {
+        is_persistent: matches!(ttl_change.durability, ContractDataDurability::Persistent),
+        is_code_entry: matches!(ttl_change.entry_type, LedgerEntryType::ContractCode),
+        old_size_bytes: old_entry_size_bytes_for_rent,
+        new_size_bytes: new_size_bytes_for_rent,
+        old_live_until_ledger: ttl_change.old_live_until_ledger,
+        new_live_until_ledger: ttl_change.new_live_until_ledger,
+    })
+}
+
 /// Helper for computing the size of the ledger entry to be used in rent
 /// computations.
 ///
@@ -411,9 +631,194 @@ pub fn invoke_host_function<T: AsRef<[u8]>, I: ExactSizeIterator<Item = T>>(
     module_cache: Option<ModuleCache>,
 ) -> Result<InvokeHostFunctionResult, HostError> {
     let _span0 = tracy_span!("invoke_host_function");
+    let output = invoke_host_function_impl(
+        budget,
+        enable_diagnostics,
+        EncodedXdr(encoded_host_fn),
+        EncodedXdr(encoded_resources),
+        restored_rw_entry_indices,
+        EncodedXdr(encoded_source_account),
+        encoded_auth_entries.map(EncodedXdr),
+        ledger_info,
+        encoded_ledger_entry_inputs(encoded_ledger_entries, encoded_ttl_entries)?,
+        base_prng_seed.as_ref(),
+        diagnostic_events,
+        trace_hook,
+        module_cache,
+        None,
+    )?;
+    Ok(InvokeHostFunctionResult {
+        encoded_invoke_result: output.invoke_result.map(|(_, encoded)| encoded),
+        ledger_changes: output.ledger_changes,
+        encoded_contract_events: output.encoded_contract_events,
+    })
+}
+
+/// Invokes a host function within the provided reusable `host`.
+///
+/// This is equivalent to `invoke_host_function`, but instead of constructing
+/// a fresh host instance the `host` is reset via `Host::try_reset` and reused.
+/// This avoids the cost of reconstructing the host for every invocation, while
+/// producing exactly the same results and budget consumption. The `host`
+/// reference must be unique.
+///
+/// The reset clears the module cache of the `host`, so a long-lived
+/// `module_cache` has to be passed for every invocation. If `module_cache` is
+/// `None`, the contracts are parsed from scratch, like in a fresh host.
+#[allow(clippy::too_many_arguments)]
+pub fn invoke_host_function_with_host<T: AsRef<[u8]>, I: ExactSizeIterator<Item = T>>(
+    host: &mut Host,
+    budget: &Budget,
+    enable_diagnostics: bool,
+    encoded_host_fn: T,
+    encoded_resources: T,
+    restored_rw_entry_indices: &[u32],
+    encoded_source_account: T,
+    encoded_auth_entries: I,
+    ledger_info: LedgerInfo,
+    encoded_ledger_entries: I,
+    encoded_ttl_entries: I,
+    base_prng_seed: T,
+    diagnostic_events: &mut Vec<DiagnosticEvent>,
+    trace_hook: Option<TraceHook>,
+    module_cache: Option<ModuleCache>,
+) -> Result<InvokeHostFunctionResult, HostError> {
+    let _span0 = tracy_span!("invoke_host_function_with_host");
+    let output = invoke_host_function_impl(
+        budget,
+        enable_diagnostics,
+        EncodedXdr(encoded_host_fn),
+        EncodedXdr(encoded_resources),
+        restored_rw_entry_indices,
+        EncodedXdr(encoded_source_account),
+        encoded_auth_entries.map(EncodedXdr),
+        ledger_info,
+        encoded_ledger_entry_inputs(encoded_ledger_entries, encoded_ttl_entries)?,
+        base_prng_seed.as_ref(),
+        diagnostic_events,
+        trace_hook,
+        module_cache,
+        Some(host),
+    )?;
+    Ok(InvokeHostFunctionResult {
+        encoded_invoke_result: output.invoke_result.map(|(_, encoded)| encoded),
+        ledger_changes: output.ledger_changes,
+        encoded_contract_events: output.encoded_contract_events,
+    })
+}
 
-    let resources: SorobanResources =
-        metered_from_xdr_with_budget(encoded_resources.as_ref(), &budget)?;
+/// Invokes a host function within a fresh host instance using the decoded
+/// inputs.
+///
+/// This is equivalent to `invoke_host_function`, but allows to skip
+/// encoding the inputs to XDR (and decoding the outputs from XDR) for the
+/// embedders that already have the typed values. The metering is identical to
+/// `invoke_host_function` called with the encoded version of the same inputs,
+/// i.e. the inputs are charged as if they have been decoded from XDR.
+///
+/// `ledger_entries` contain the entries for the footprint keys together with
+/// their live until ledgers (that must be set for the contract data and code
+/// entries and only for them).
+#[allow(clippy::too_many_arguments)]
+pub fn invoke_host_function_typed(
+    budget: &Budget,
+    enable_diagnostics: bool,
+    host_fn: HostFunction,
+    resources: SorobanResources,
+    restored_rw_entry_indices: &[u32],
+    source_account: AccountId,
+    auth_entries: Vec<SorobanAuthorizationEntry>,
+    ledger_info: LedgerInfo,
+    ledger_entries: Vec<(LedgerEntry, Option<u32>)>,
+    base_prng_seed: [u8; 32],
+    diagnostic_events: &mut Vec<DiagnosticEvent>,
+    trace_hook: Option<TraceHook>,
+    module_cache: Option<ModuleCache>,
+) -> Result<InvokeHostFunctionTypedResult, HostError> {
+    let _span0 = tracy_span!("invoke_host_function_typed");
+    // Build the TTL entries outside of the metered path, in the same fashion
+    // as embedder would do for `invoke_host_function`.
+    let ledger_entry_inputs = ledger_entries
+        .into_iter()
+        .map(|(entry, live_until)| {
+            let ttl_input = match live_until {
+                Some(live_until_ledger_seq) => {
+                    let key = ledger_entry_to_ledger_key(&entry, &Budget::default())?;
+                    let key_hash: [u8; 32] =
+                        Sha256::digest(key.to_xdr(DEFAULT_XDR_RW_LIMITS)?).into();
+                    Some(DecodedXdr(TtlEntry {
+                        key_hash: key_hash.into(),
+                        live_until_ledger_seq,
+                    }))
+                }
+                None => None,
+            };
+            Ok((DecodedXdr(entry), ttl_input))
+        })
+        .collect::<Result<Vec<_>, HostError>>()?;
+    let output = invoke_host_function_impl(
+        budget,
+        enable_diagnostics,
+        DecodedXdr(host_fn),
+        DecodedXdr(resources),
+        restored_rw_entry_indices,
+        DecodedXdr(source_account),
+        auth_entries.into_iter().map(DecodedXdr),
+        ledger_info,
+        ledger_entry_inputs.into_iter(),
+        &base_prng_seed,
+        diagnostic_events,
+        trace_hook,
+        module_cache,
+        None,
+    )?;
+    // Outputs are decoded outside of the metered path as well.
+    Ok(InvokeHostFunctionTypedResult {
+        invoke_result: output.invoke_result.map(|(res, _)| res),
+        ledger_changes: output
+            .ledger_changes
+            .iter()
+            .map(TypedLedgerEntryChange::from_encoded)
+            .collect::<Result<Vec<_>, HostError>>()?,
+        contract_events: output
+            .encoded_contract_events
+            .iter()
+            .map(|e| ContractEvent::from_xdr(e, DEFAULT_XDR_RW_LIMITS))
+            .collect::<Result<Vec<_>, _>>()?,
+    })
+}
+
+struct InvokeHostFunctionOutput {
+    invoke_result: Result<(ScVal, Vec<u8>), HostError>,
+    ledger_changes: Vec<LedgerEntryChange>,
+    encoded_contract_events: Vec<Vec<u8>>,
+}
+
+// Shared implementation of `invoke_host_function`,
+// `invoke_host_function_with_host` and `invoke_host_function_typed`. A fresh
+// host is created unless `reusable_host` is provided.
+#[allow(clippy::too_many_arguments)]
+fn invoke_host_function_impl<E, T>(
+    budget: &Budget,
+    enable_diagnostics: bool,
+    host_fn_input: impl MeteredXdrInput<HostFunction>,
+    resources_input: impl MeteredXdrInput<SorobanResources>,
+    restored_rw_entry_indices: &[u32],
+    source_account_input: impl MeteredXdrInput<AccountId>,
+    auth_entry_inputs: impl ExactSizeIterator<Item = impl MeteredXdrInput<SorobanAuthorizationEntry>>,
+    ledger_info: LedgerInfo,
+    ledger_entry_inputs: impl Iterator<Item = (E, Option<T>)>,
+    base_prng_seed: &[u8],
+    diagnostic_events: &mut Vec<DiagnosticEvent>,
+    trace_hook: Option<TraceHook>,
+    module_cache: Option<ModuleCache>,
+    reusable_host: Option<&mut Host>,
+) -> Result<InvokeHostFunctionOutput, HostError>
+where
+    E: MeteredXdrInput<LedgerEntry>,
+    T: MeteredXdrInput<TtlEntry>,
+{
+    let resources: SorobanResources = resources_input.metered_decode(budget)?;
     let restored_keys = build_restored_key_set(&budget, &resources, &restored_rw_entry_indices)?;
     let footprint = build_storage_footprint_from_xdr(&budget, resources.footprint)?;
     let current_ledger_seq = ledger_info.sequence_number;
@@ -425,11 +830,10 @@ pub fn invoke_host_function<T: AsRef<[u8]>, I: ExactSizeIterator<Item = T>>(
                 ScErrorCode::InternalError,
             ))
         })?;
-    let (storage_map, init_ttl_map) = build_storage_map_from_xdr_ledger_entries(
+    let (storage_map, init_ttl_map) = build_storage_map_from_ledger_entries(
         &budget,
         &footprint,
-        encoded_ledger_entries,
-        encoded_ttl_entries,
+        ledger_entry_inputs,
         current_ledger_seq,
         #[cfg(any(test, feature = "recording_mode"))]
         false,
@@ -438,18 +842,28 @@ pub fn invoke_host_function<T: AsRef<[u8]>, I: ExactSizeIterator<Item = T>>(
     let init_storage_map = storage_map.metered_clone(budget)?;
 
     let storage = Storage::with_enforcing_footprint_and_map(footprint, storage_map);
-    let host = Host::with_storage_and_budget(storage, budget.clone());
+    let mut fresh_host;
+    let host = match reusable_host {
+        Some(host) => {
+            host.try_reset(storage, budget.clone())?;
+            host
+        }
+        None => {
+            fresh_host = Host::with_storage_and_budget(storage, budget.clone());
+            &mut fresh_host
+        }
+    };
     let have_trace_hook = trace_hook.is_some();
     if let Some(th) = trace_hook {
         host.set_trace_hook(Some(th))?;
     }
-    let auth_entries = host.build_auth_entries_from_xdr(encoded_auth_entries)?;
-    let host_function: HostFunction = host.metered_from_xdr(encoded_host_fn.as_ref())?;
-    let source_account: AccountId = host.metered_from_xdr(encoded_source_account.as_ref())?;
+    let auth_entries = host.build_auth_entries(auth_entry_inputs)?;
+    let host_function: HostFunction = host_fn_input.metered_decode_with_host(&host)?;
+    let source_account: AccountId = source_account_input.metered_decode_with_host(&host)?;
     host.set_source_account(source_account)?;
     host.set_ledger_info(ledger_info)?;
     host.set_authorization_entries(auth_entries)?;
-    let seed32: [u8; 32] = base_prng_seed.as_ref().try_into().map_err(|_| {
+    let seed32: [u8; 32] = base_prng_seed.try_into().map_err(|_| {
         host.err(
             ScErrorType::Context,
             ScErrorCode::InternalError,
@@ -471,15 +885,16 @@ pub fn invoke_host_function<T: AsRef<[u8]>, I: ExactSizeIterator<Item = T>>(
     if have_trace_hook {
         host.set_trace_hook(None)?;
     }
-    let (storage, events) = host.try_finish()?;
+    let (storage, events) = host.try_finish_for_reuse()?;
     if enable_diagnostics {
         extract_diagnostic_events(&events, diagnostic_events);
     }
-    let encoded_invoke_result = result.and_then(|res| {
+    let invoke_result = result.and_then(|res| {
         let mut encoded_result_sc_val = vec![];
-        metered_write_xdr(&budget, &res, &mut encoded_result_sc_val).map(|_| encoded_result_sc_val)
+        metered_write_xdr(&budget, &res, &mut encoded_result_sc_val)
+            .map(|_| (res, encoded_result_sc_val))
     });
-    if encoded_invoke_result.is_ok() {
+    if invoke_result.is_ok() {
         let init_storage_snapshot = StorageMapSnapshotSource {
             budget: &budget,
             map: &init_storage_map,
@@ -495,14 +910,14 @@ pub fn invoke_host_function<T: AsRef<[u8]>, I: ExactSizeIterator<Item = T>>(
             current_ledger_seq,
         )?;
         let encoded_contract_events = encode_contract_events(budget, &events)?;
-        Ok(InvokeHostFunctionResult {
-            encoded_invoke_result,
+        Ok(InvokeHostFunctionOutput {
+            invoke_result,
             ledger_changes,
             encoded_contract_events,
         })
     } else {
-        Ok(InvokeHostFunctionResult {
-            encoded_invoke_result,
+        Ok(InvokeHostFunctionOutput {
+            invoke_result,
             ledger_changes: vec![],
             encoded_contract_events: vec![],
         })
@@ -599,6 +1014,11 @@ pub enum RecordingInvocationAuthMode {
     /// disabled (i.e. non-root auth is not allowed when `true` is passed to
     /// the enum).
     Recording(bool),
+    /// Same as `Recording`, but emulate the authentication of the recorded
+    /// authorizations with the signatures provided by the
+    /// `SignatureStubProvider`, in order to estimate the resources of the
+    /// multisig accounts and custom accounts more precisely.
+    RecordingWithSignatureStubs(bool, Rc<dyn SignatureStubProvider>),
 }
 
 /// Invokes a host function within a fresh host instance in 'recording' mode.
@@ -622,8 +1042,9 @@ pub enum RecordingInvocationAuthMode {
 /// call. Typically this difference should be within 1% from the correct
 /// value, but in scenarios where recording auth is used it might be
 /// significantly higher (e.g. if the user uses multisig with classic
-/// accounts or custom accounts - there is currently no way to emulate that
-/// in recording auth mode).
+/// accounts or custom accounts). Use
+/// `RecordingInvocationAuthMode::RecordingWithSignatureStubs` to emulate the
+/// authentication of such accounts with the representative signatures.
 ///
 /// The input `Budget` should normally be configured to match the network
 /// limits. Exceeding the budget is the only error condition for this
@@ -646,9 +1067,71 @@ pub fn invoke_host_function_in_recording_mode(
     base_prng_seed: [u8; 32],
     diagnostic_events: &mut Vec<DiagnosticEvent>,
 ) -> Result<InvokeHostFunctionRecordingModeResult, HostError> {
+    invoke_host_function_in_recording_mode_with_options(
+        budget,
+        enable_diagnostics,
+        host_fn,
+        source_account,
+        auth_mode,
+        ledger_info,
+        ledger_snapshot,
+        base_prng_seed,
+        RecordingInvocationOptions::default(),
+        diagnostic_events,
+    )
+}
+
+/// Optional features of the recording mode invocation, see
+/// `invoke_host_function_in_recording_mode_with_options`.
+///
+/// All the options can be combined with each other and none of them affects
+/// the metered resources of the invocation.
+#[cfg(any(test, feature = "recording_mode"))]
+#[derive(Clone, Default)]
+pub struct RecordingInvocationOptions {
+    /// Meter the resources consumed by every contract call and return them
+    /// in `InvokeHostFunctionRecordingModeResult::call_tree`.
+    pub enable_call_tree: bool,
+    /// Long-lived module cache that is reused across the invocations in order
+    /// to avoid re-parsing the same contracts.
+    ///
+    /// The parsing is charged to the budget as if the modules weren't cached.
+    /// All the contracts loaded by the invocation are added to the cache
+    /// after the invocation. The cache must be created with the same cost
+    /// parameters as the invocation budget and for the same protocol version
+    /// as the invocation ledger info, and it should be rebuilt when either of
+    /// these changes.
+    ///
+    /// Nothing is ever evicted from the cache by the invocations. The caller
+    /// owns the cache's lifetime and has to bound its size, e.g. by replacing
+    /// it with a new cache periodically (removing the modules from the cache
+    /// doesn't release the code compiled into its engine).
+    pub module_cache: Option<ModuleCache>,
+}
+
+/// Same as `invoke_host_function_in_recording_mode`, but additionally
+/// enables the optional features specified by `options`.
+#[cfg(any(test, feature = "recording_mode"))]
+#[allow(clippy::too_many_arguments)]
+pub fn invoke_host_function_in_recording_mode_with_options(
+    budget: &Budget,
+    enable_diagnostics: bool,
+    host_fn: &HostFunction,
+    source_account: &AccountId,
+    auth_mode: RecordingInvocationAuthMode,
+    ledger_info: LedgerInfo,
+    ledger_snapshot: Rc<dyn SnapshotSource>,
+    base_prng_seed: [u8; 32],
+    options: RecordingInvocationOptions,
+    diagnostic_events: &mut Vec<DiagnosticEvent>,
+) -> Result<InvokeHostFunctionRecordingModeResult, HostError> {
+    let RecordingInvocationOptions {
+        enable_call_tree,
+        module_cache,
+    } = options;
     let storage = Storage::with_recording_footprint(ledger_snapshot.clone());
     let host = Host::with_storage_and_budget(storage, budget.clone());
-    let is_recording_auth = matches!(auth_mode, RecordingInvocationAuthMode::Recording(_));
+    let is_recording_auth = !matches!(auth_mode, RecordingInvocationAuthMode::Enforcing(_));
     let ledger_seq = ledger_info.sequence_number;
     let min_live_until_ledger = ledger_info
         .min_live_until_ledger_checked(ContractDataDurability::Persistent)
@@ -671,12 +1154,37 @@ pub fn invoke_host_function_in_recording_mode(
         RecordingInvocationAuthMode::Recording(disable_non_root_auth) => {
             host.switch_to_recording_auth(*disable_non_root_auth)?;
         }
+        RecordingInvocationAuthMode::RecordingWithSignatureStubs(
+            disable_non_root_auth,
+            signature_stub_provider,
+        ) => {
+            host.switch_to_recording_auth(*disable_non_root_auth)?;
+            host.set_signature_stub_provider(signature_stub_provider.clone())?;
+        }
     }
 
     if enable_diagnostics {
         host.set_diagnostic_level(DiagnosticLevel::Debug)?;
     }
+    if enable_call_tree {
+        host.enable_call_tree_metering()?;
+    }
+    if let Some(module_cache) = &module_cache {
+        host.set_recording_mode_module_cache(module_cache.clone())?;
+    }
     let invoke_result = host.invoke_function(host_function);
+    if let Some(module_cache) = &module_cache {
+        // Emulate the write-back to the module cache of the contracts loaded
+        // by the invocation. This doesn't resemble anything in the enforcing
+        // mode, so use the shadow budget for this. Failure to cache a module
+        // is not an error, the module will just be parsed again next time.
+        budget.with_shadow_mode(|| module_cache.add_stored_contracts(&host));
+    }
+    let call_tree = if enable_call_tree {
+        host.take_call_tree()?.into_iter().next()
+    } else {
+        None
+    };
     let mut contract_events_and_return_value_size = 0_u32;
     if let Ok(res) = &invoke_result {
         let mut encoded_result_sc_val = vec![];
@@ -699,7 +1207,8 @@ pub fn invoke_host_function_in_recording_mode(
         .iter()
         .map(|e| host.to_xdr_non_metered(e))
         .collect::<Result<Vec<Vec<u8>>, HostError>>()?;
-    let decoded_auth_entries = host.build_auth_entries_from_xdr(encoded_auth_entries.iter())?;
+    let decoded_auth_entries =
+        host.build_auth_entries(encoded_auth_entries.iter().map(EncodedXdr))?;
     if is_recording_auth {
         host.set_authorization_entries(decoded_auth_entries)?;
         for auth_entry in &mut output_auth {
@@ -771,11 +1280,13 @@ pub fn invoke_host_function_in_recording_mode(
                     current_rw_id += 1;
                 }
             }
-            let (init_storage, init_ttl_map) = build_storage_map_from_xdr_ledger_entries(
+            let (init_storage, init_ttl_map) = build_storage_map_from_ledger_entries(
                 &budget,
                 &storage.footprint,
-                encoded_ledger_entries.iter(),
-                encoded_ttl_entries.iter(),
+                encoded_ledger_entry_inputs(
+                    encoded_ledger_entries.iter(),
+                    encoded_ttl_entries.iter(),
+                )?,
                 ledger_seq,
                 true,
             )?;
@@ -856,6 +1367,7 @@ pub fn invoke_host_function_in_recording_mode(
         ledger_changes,
         contract_events,
         contract_events_and_return_value_size,
+        call_tree,
     })
 }
 
@@ -944,36 +1456,27 @@ fn build_storage_footprint_from_xdr(
     Ok(Footprint(footprint_map))
 }
 
-fn build_storage_map_from_xdr_ledger_entries<T: AsRef<[u8]>, I: ExactSizeIterator<Item = T>>(
+fn build_storage_map_from_ledger_entries<E, T>(
     budget: &Budget,
     footprint: &Footprint,
-    encoded_ledger_entries: I,
-    encoded_ttl_entries: I,
+    ledger_entry_inputs: impl Iterator<Item = (E, Option<T>)>,
     ledger_num: u32,
     #[cfg(any(test, feature = "recording_mode"))] is_recording_mode: bool,
-) -> Result<(StorageMap, TtlEntryMap), HostError> {
+) -> Result<(StorageMap, TtlEntryMap), HostError>
+where
+    E: MeteredXdrInput<LedgerEntry>,
+    T: MeteredXdrInput<TtlEntry>,
+{
     let mut storage_map = StorageMap::new();
     let mut ttl_map = TtlEntryMap::new();
 
-    if encoded_ledger_entries.len() != encoded_ttl_entries.len() {
-        return Err(
-            Error::from_type_and_code(ScErrorType::Storage, ScErrorCode::InternalError).into(),
-        );
-    }
-
-    for (entry_buf, ttl_buf) in encoded_ledger_entries.zip(encoded_ttl_entries) {
+    for (entry_input, ttl_input) in ledger_entry_inputs {
         let mut live_until_ledger: Option<u32> = None;
 
-        let le = Rc::metered_new(
-            metered_from_xdr_with_budget::<LedgerEntry>(entry_buf.as_ref(), budget)?,
-            budget,
-        )?;
+        let le = Rc::metered_new(entry_input.metered_decode(budget)?, budget)?;
         let key = Rc::metered_new(ledger_entry_to_ledger_key(&le, budget)?, budget)?;
-        if !ttl_buf.as_ref().is_empty() {
-            let ttl_entry = Rc::metered_new(
-                metered_from_xdr_with_budget::<TtlEntry>(ttl_buf.as_ref(), budget)?,
-                budget,
-            )?;
+        if let Some(ttl_input) = ttl_input {
+            let ttl_entry = Rc::metered_new(ttl_input.metered_decode(budget)?, budget)?;
             // In the default host flow (i.e. enforcing storage only) we don't
             // expect expired entries to ever appear in the storage map, so
             // that's always an internal error.
@@ -1041,12 +1544,14 @@ fn build_storage_map_from_xdr_ledger_entries<T: AsRef<[u8]>, I: ExactSizeIterato
 }
 
 impl Host {
-    fn build_auth_entries_from_xdr<T: AsRef<[u8]>, I: ExactSizeIterator<Item = T>>(
+    fn build_auth_entries(
         &self,
-        encoded_contract_auth_entries: I,
+        auth_entry_inputs: impl ExactSizeIterator<
+            Item = impl MeteredXdrInput<SorobanAuthorizationEntry>,
+        >,
     ) -> Result<Vec<SorobanAuthorizationEntry>, HostError> {
-        encoded_contract_auth_entries
-            .map(|buf| self.metered_from_xdr::<SorobanAuthorizationEntry>(buf.as_ref()))
+        auth_entry_inputs
+            .map(|input| input.metered_decode_with_host(self))
             .metered_collect::<Result<Vec<SorobanAuthorizationEntry>, HostError>>(
                 self.as_budget(),
             )?
@@ -0,0 +1,285 @@
+//! Transaction metadata for the host function invocations.
+//!
+//! [build_transaction_meta] converts the result of a single
+//! `invoke_host_function_typed` call into the `TransactionMeta` that the
+//! network would emit for an `InvokeHostFunctionOp` transaction with the same
+//! inputs. This allows testing the metadata consumers (such as indexers)
+//! directly against the host.
+//!
+//! Only the parts of the metadata that are produced by the operation itself
+//! are populated. Fee and sequence number related changes of the source
+//! account (`tx_changes_before`/`tx_changes_after` and the fee events) are up
+//! to the embedder and are left empty.
+//!
+//! The ledger entry changes follow the conventions of the network:
+//!
+//! - Every modified entry is preceded by its `State`, created entries are
+//!   `Created`, deleted entries are `Removed`.
+//! - TTL changes are emitted as changes of the respective `Ttl` entries.
+//! - Auto-restored entries are emitted as `Restored` (followed by `Updated`
+//!   if the invocation has modified them further).
+//! - Changes are ordered by the ledger key, thus all the TTL entries follow
+//!   the contract data and code entries.
+//!
+//! Building the metadata is not metered.
+use std::collections::BTreeMap;
+
+use super::{
+    extract_rent_changes_from_typed, InvokeHostFunctionTypedResult, TypedLedgerEntryChange,
+};
+use crate::{
+    fees::{
+        compute_rent_fee, compute_transaction_resource_fee, FeeConfiguration, RentFeeConfiguration,
+        TransactionResources,
+    },
+    xdr::{
+        DiagnosticEvent, ExtensionPoint, Hash, LedgerEntry, LedgerEntryChange, LedgerEntryChanges,
+        LedgerEntryData, LedgerEntryExt, LedgerKey, LedgerKeyTtl, OperationMetaV2,
+        SorobanResources, SorobanTransactionMetaExt, SorobanTransactionMetaExtV1,
+        SorobanTransactionMetaV2, TransactionMeta, TransactionMetaV4, TtlEntry, WriteXdr,
+    },
+    HostError, LedgerInfo, DEFAULT_XDR_RW_LIMITS,
+};
+
+/// Fee settings for populating the fee information of the metadata.
+pub struct TransactionMetaFeeConfig {
+    /// Resources declared by the transaction. `contract_events_size_bytes` is
+    /// ignored, as the refundable fee is charged for the actual size of the
+    /// contract events and the return value.
+    pub resources: TransactionResources,
+    pub fee_config: FeeConfiguration,
+    pub rent_fee_config: RentFeeConfiguration,
+}
+
+/// Builds the `TransactionMeta` for the result of `invoke_host_function_typed`.
+///
+/// `resources`, `restored_rw_entry_indices` and `ledger_entries` must be the
+/// inputs of the invocation, they define the state of the entries before the
+/// invocation. As the inputs don't contain the last modified ledger of the
+/// TTL entries, the pre-invocation TTL entries use the last modified ledger of
+/// the entries they belong to.
+///
+/// When `fee_config` is provided, the resource and rent fees charged for the
+/// invocation are reported in the Soroban metadata extension.
+pub fn build_transaction_meta(
+    ledger_info: &LedgerInfo,
+    resources: &SorobanResources,
+    restored_rw_entry_indices: &[u32],
+    ledger_entries: &[(LedgerEntry, Option<u32>)],
+    result: &InvokeHostFunctionTypedResult,
+    diagnostic_events: &[DiagnosticEvent],
+    fee_config: Option<&TransactionMetaFeeConfig>,
+) -> Result<TransactionMeta, HostError> {
+    let ledger_seq = ledger_info.sequence_number;
+    let return_value = result.invoke_result.as_ref().ok();
+    let ext = match fee_config {
+        Some(fee_config) => {
+            SorobanTransactionMetaExt::V1(charged_fees(ledger_seq, result, fee_config)?)
+        }
+        None => SorobanTransactionMetaExt::V0,
+    };
+    let operations = if return_value.is_some() {
+        let restored_keys: Vec<&LedgerKey> = restored_rw_entry_indices
+            .iter()
+            .filter_map(|i| resources.footprint.read_write.get(*i as usize))
+            .collect();
+        let old_entries: BTreeMap<LedgerKey, &(LedgerEntry, Option<u32>)> =
+            ledger_entries.iter().map(|e| (e.0.to_key(), e)).collect();
+        let mut changes: BTreeMap<LedgerKey, Vec<LedgerEntryChange>> = BTreeMap::new();
+        for change in &result.ledger_changes {
+            let old_entry = old_entries.get(&change.key).copied();
+            let is_restored = restored_keys.contains(&&change.key);
+            add_entry_changes(&mut changes, change, old_entry, is_restored, ledger_seq)?;
+        }
+        vec![OperationMetaV2 {
+            ext: ExtensionPoint::V0,
+            changes: LedgerEntryChanges(
+                changes
+                    .into_values()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .try_into()?,
+            ),
+            events: result.contract_events.clone().try_into()?,
+        }]
+    } else {
+        vec![]
+    };
+    Ok(TransactionMeta::V4(TransactionMetaV4 {
+        ext: ExtensionPoint::V0,
+        tx_changes_before: LedgerEntryChanges(Default::default()),
+        operations: operations.try_into()?,
+        tx_changes_after: LedgerEntryChanges(Default::default()),
+        soroban_meta: Some(SorobanTransactionMetaV2 {
+            ext,
+            return_value: return_value.cloned(),
+        }),
+        events: Default::default(),
+        diagnostic_events: diagnostic_events.to_vec().try_into()?,
+    }))
+}
+
+fn charged_fees(
+    ledger_seq: u32,
+    result: &InvokeHostFunctionTypedResult,
+    fee_config: &TransactionMetaFeeConfig,
+) -> Result<SorobanTransactionMetaExtV1, HostError> {
+    let mut contract_events_and_return_value_size = 0_u32;
+    if let Ok(return_value) = &result.invoke_result {
+        let mut size = return_value.to_xdr(DEFAULT_XDR_RW_LIMITS)?.len();
+        for event in &result.contract_events {
+            size = size.saturating_add(event.to_xdr(DEFAULT_XDR_RW_LIMITS)?.len());
+        }
+        contract_events_and_return_value_size = u32::try_from(size).unwrap_or(u32::MAX);
+    }
+    let declared = &fee_config.resources;
+    let resources = TransactionResources {
+        instructions: declared.instructions,
+        disk_read_entries: declared.disk_read_entries,
+        write_entries: declared.write_entries,
+        disk_read_bytes: declared.disk_read_bytes,
+        write_bytes: declared.write_bytes,
+        contract_events_size_bytes: contract_events_and_return_value_size,
+        transaction_size_bytes: declared.transaction_size_bytes,
+    };
+    let (non_refundable_fee, refundable_fee) =
+        compute_transaction_resource_fee(&resources, &fee_config.fee_config);
+    let rent_fee = if result.invoke_result.is_ok() {
+        compute_rent_fee(
+            &extract_rent_changes_from_typed(&result.ledger_changes),
+            &fee_config.rent_fee_config,
+            ledger_seq,
+        )
+    } else {
+        0
+    };
+    Ok(SorobanTransactionMetaExtV1 {
+        ext: ExtensionPoint::V0,
+        total_non_refundable_resource_fee_charged: non_refundable_fee,
+        total_refundable_resource_fee_charged: refundable_fee.saturating_add(rent_fee),
+        rent_fee_charged: rent_fee,
+    })
+}
+
+fn add_entry_changes(
+    changes: &mut BTreeMap<LedgerKey, Vec<LedgerEntryChange>>,
+    change: &TypedLedgerEntryChange,
+    old_entry: Option<&(LedgerEntry, Option<u32>)>,
+    is_restored: bool,
+    ledger_seq: u32,
+) -> Result<(), HostError> {
+    let updated = |entry: &LedgerEntry| LedgerEntry {
+        last_modified_ledger_seq: ledger_seq,
+        ..entry.clone()
+    };
+    let mut entry_changes = vec![];
+    if !change.read_only {
+        match (old_entry, &change.new_value, is_restored) {
+            (Some((old_entry, _)), new_value, true) => {
+                entry_changes.push(LedgerEntryChange::Restored(old_entry.clone()));
+                match new_value {
+                    Some(new_value) if !same_entry_data(old_entry, new_value) => {
+                        entry_changes.push(LedgerEntryChange::Updated(updated(new_value)));
+                    }
+                    Some(_) => (),
+                    None => entry_changes.push(LedgerEntryChange::Removed(change.key.clone())),
+                }
+            }
+            (Some((old_entry, _)), Some(new_value), false) => {
+                if !same_entry_data(old_entry, new_value) {
+                    entry_changes.push(LedgerEntryChange::State(old_entry.clone()));
+                    entry_changes.push(LedgerEntryChange::Updated(updated(new_value)));
+                }
+            }
+            (Some((old_entry, _)), None, false) => {
+                entry_changes.push(LedgerEntryChange::State(old_entry.clone()));
+                entry_changes.push(LedgerEntryChange::Removed(change.key.clone()));
+            }
+            (None, Some(new_value), _) => {
+                entry_changes.push(LedgerEntryChange::Created(updated(new_value)));
+            }
+            (None, None, _) => (),
+        }
+    }
+    if !entry_changes.is_empty() {
+        changes.insert(change.key.clone(), entry_changes);
+    }
+
+    let Some(ttl_change) = &change.ttl_change else {
+        return Ok(());
+    };
+    let key_hash = Hash(
+        ttl_change
+            .key_hash
+            .clone()
+            .try_into()
+            .map_err(|_| HostError::from(crate::xdr::Error::Invalid))?,
+    );
+    let ttl_key = LedgerKey::Ttl(LedgerKeyTtl {
+        key_hash: key_hash.clone(),
+    });
+    let ttl_entry = |live_until_ledger_seq: u32, last_modified_ledger_seq: u32| LedgerEntry {
+        last_modified_ledger_seq,
+        data: LedgerEntryData::Ttl(TtlEntry {
+            key_hash: key_hash.clone(),
+            live_until_ledger_seq,
+        }),
+        ext: LedgerEntryExt::V0,
+    };
+    let old_ttl = old_entry.and_then(|(entry, live_until)| {
+        live_until.map(|live_until| (live_until, entry.last_modified_ledger_seq))
+    });
+    let new_live_until = ttl_change.new_live_until_ledger;
+    let is_removed = !change.read_only && change.new_value.is_none();
+    let mut ttl_changes = vec![];
+    match (old_ttl, is_removed, is_restored) {
+        (Some((old_live_until, last_modified)), _, true) => {
+            ttl_changes.push(LedgerEntryChange::Restored(ttl_entry(
+                old_live_until,
+                last_modified,
+            )));
+            if is_removed {
+                ttl_changes.push(LedgerEntryChange::Removed(ttl_key.clone()));
+            } else if new_live_until != old_live_until {
+                ttl_changes.push(LedgerEntryChange::Updated(ttl_entry(
+                    new_live_until,
+                    ledger_seq,
+                )));
+            }
+        }
+        (Some((old_live_until, last_modified)), true, false) => {
+            ttl_changes.push(LedgerEntryChange::State(ttl_entry(
+                old_live_until,
+                last_modified,
+            )));
+            ttl_changes.push(LedgerEntryChange::Removed(ttl_key.clone()));
+        }
+        (Some((old_live_until, last_modified)), false, false) => {
+            if new_live_until != old_live_until {
+                ttl_changes.push(LedgerEntryChange::State(ttl_entry(
+                    old_live_until,
+                    last_modified,
+                )));
+                ttl_changes.push(LedgerEntryChange::Updated(ttl_entry(
+                    new_live_until,
+                    ledger_seq,
+                )));
+            }
+        }
+        (None, false, _) if change.new_value.is_some() => {
+            ttl_changes.push(LedgerEntryChange::Created(ttl_entry(
+                new_live_until,
+                ledger_seq,
+            )));
+        }
+        (None, _, _) => (),
+    }
+    if !ttl_changes.is_empty() {
+        changes.insert(ttl_key, ttl_changes);
+    }
+    Ok(())
+}
+
+fn same_entry_data(a: &LedgerEntry, b: &LedgerEntry) -> bool {
+    a.data == b.data && a.ext == b.ext
+}
@@ -0,0 +1,334 @@
+//! Deterministic receipts of the host function invocations.
+//!
+//! An [ExecutionReceipt] commits to every observable output of a single
+//! `invoke_host_function` call, as well as to the PRNG seed it has been
+//! executed with. Every component is hashed separately and the component
+//! hashes are then combined into a hash chain. Thus two nodes that disagree
+//! on the invocation outcome can exchange the receipts (which are just a few
+//! hashes) and find out which exact component has diverged.
+//!
+//! Unlike [TraceState](crate::TraceState), which uses Rust's default hasher
+//! that is only stable within a single build, the receipts use SHA-256 over
+//! canonical encodings and thus can be compared across different builds and
+//! platforms.
+//!
+//! Receipts are built after the invocation is done and computing them is not
+//! metered.
+use sha2::{Digest, Sha256};
+
+use super::{
+    InvokeHostFunctionResult, InvokeHostFunctionTypedResult, LedgerEntryChange,
+    LedgerEntryLiveUntilChange,
+};
+use crate::{
+    budget::Budget,
+    xdr::{ContractCostType, ScError, ScErrorCode, ScErrorType, WriteXdr},
+    HostError, DEFAULT_XDR_RW_LIMITS,
+};
+
+const RECEIPT_DOMAIN: &[u8] = b"soroban-execution-receipt-v1";
+
+/// Component of the [ExecutionReceipt], in the order of the hash chain.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReceiptComponent {
+    /// Return value of the host function, or the error it has failed with.
+    ReturnValue,
+    /// Ordered ledger changes.
+    LedgerChanges,
+    /// Ordered contract events.
+    ContractEvents,
+    /// Final budget consumption for every `ContractCostType`.
+    BudgetConsumption,
+    /// Base PRNG seed of the invocation.
+    PrngSeed,
+}
+
+impl ReceiptComponent {
+    /// All the components in the order of the hash chain.
+    pub const ALL: [ReceiptComponent; 5] = [
+        ReceiptComponent::ReturnValue,
+        ReceiptComponent::LedgerChanges,
+        ReceiptComponent::ContractEvents,
+        ReceiptComponent::BudgetConsumption,
+        ReceiptComponent::PrngSeed,
+    ];
+}
+
+/// Hash commitment to the outcome of a single host function invocation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExecutionReceipt {
+    /// Hashes of the individual components, indexed in the order of
+    /// [ReceiptComponent::ALL].
+    pub component_hashes: [[u8; 32]; 5],
+    /// Final hash of the chain over all the component hashes.
+    pub receipt_hash: [u8; 32],
+}
+
+// Byte sink that hashes everything written to it, including the XDR values.
+struct ReceiptHasher(Sha256);
+
+impl ReceiptHasher {
+    fn new(component: ReceiptComponent) -> Self {
+        let mut hasher = Self(Sha256::new());
+        hasher.write_bytes(RECEIPT_DOMAIN);
+        hasher.write_u32(component as u32);
+        hasher
+    }
+
+    fn write_u32(&mut self, v: u32) {
+        self.0.update(v.to_be_bytes());
+    }
+
+    fn write_u64(&mut self, v: u64) {
+        self.0.update(v.to_be_bytes());
+    }
+
+    // Writes the length-prefixed bytes.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u64(bytes.len() as u64);
+        self.0.update(bytes);
+    }
+
+    fn write_optional_bytes(&mut self, bytes: Option<&[u8]>) {
+        match bytes {
+            Some(bytes) => {
+                self.write_u32(1);
+                self.write_bytes(bytes);
+            }
+            None => self.write_u32(0),
+        }
+    }
+
+    fn write_xdr(&mut self, v: &impl WriteXdr) -> Result<(), HostError> {
+        self.write_bytes(&v.to_xdr(DEFAULT_XDR_RW_LIMITS)?);
+        Ok(())
+    }
+
+    fn finish(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+}
+
+impl ExecutionReceipt {
+    /// Builds the receipt for the result of `invoke_host_function`.
+    ///
+    /// `budget` must be the budget used for the invocation and
+    /// `base_prng_seed` must be the seed passed to it.
+    pub fn from_invoke_result(
+        budget: &Budget,
+        base_prng_seed: &[u8],
+        result: &InvokeHostFunctionResult,
+    ) -> Result<Self, HostError> {
+        let encoded_invoke_result = match &result.encoded_invoke_result {
+            Ok(v) => Ok(v.as_slice()),
+            Err(e) => Err(e),
+        };
+        Self::build(
+            budget,
+            base_prng_seed,
+            encoded_invoke_result,
+            &result.ledger_changes,
+            &result.encoded_contract_events,
+        )
+    }
+
+    /// Builds the receipt for the result of `invoke_host_function_typed`.
+    ///
+    /// The receipt is identical to the one built by
+    /// [ExecutionReceipt::from_invoke_result] for the same invocation
+    /// performed via `invoke_host_function`.
+    pub fn from_typed_invoke_result(
+        budget: &Budget,
+        base_prng_seed: &[u8],
+        result: &InvokeHostFunctionTypedResult,
+    ) -> Result<Self, HostError> {
+        let encoded_result = match &result.invoke_result {
+            Ok(v) => Ok(v.to_xdr(DEFAULT_XDR_RW_LIMITS)?),
+            Err(e) => Err(e),
+        };
+        let ledger_changes = result
+            .ledger_changes
+            .iter()
+            .map(|c| {
+                Ok(LedgerEntryChange {
+                    read_only: c.read_only,
+                    encoded_key: c.key.to_xdr(DEFAULT_XDR_RW_LIMITS)?,
+                    old_entry_size_bytes_for_rent: c.old_entry_size_bytes_for_rent,
+                    encoded_new_value: c
+                        .new_value
+                        .as_ref()
+                        .map(|v| v.to_xdr(DEFAULT_XDR_RW_LIMITS))
+                        .transpose()?,
+                    new_entry_size_bytes_for_rent: c.new_entry_size_bytes_for_rent,
+                    ttl_change: c.ttl_change.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, HostError>>()?;
+        let encoded_contract_events = result
+            .contract_events
+            .iter()
+            .map(|e| e.to_xdr(DEFAULT_XDR_RW_LIMITS))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::build(
+            budget,
+            base_prng_seed,
+            encoded_result
+                .as_ref()
+                .map(|v| v.as_slice())
+                .map_err(|e| *e),
+            &ledger_changes,
+            &encoded_contract_events,
+        )
+    }
+
+    fn build(
+        budget: &Budget,
+        base_prng_seed: &[u8],
+        encoded_invoke_result: Result<&[u8], &HostError>,
+        ledger_changes: &[LedgerEntryChange],
+        encoded_contract_events: &[Vec<u8>],
+    ) -> Result<Self, HostError> {
+        let mut component_hashes = [[0; 32]; 5];
+        for (i, component) in ReceiptComponent::ALL.into_iter().enumerate() {
+            let mut hasher = ReceiptHasher::new(component);
+            match component {
+                ReceiptComponent::ReturnValue => match encoded_invoke_result {
+                    Ok(encoded_value) => {
+                        hasher.write_u32(0);
+                        hasher.write_bytes(encoded_value);
+                    }
+                    Err(e) => {
+                        hasher.write_u32(1);
+                        hasher.write_xdr(&ScError::try_from(e.error)?)?;
+                    }
+                },
+                ReceiptComponent::LedgerChanges => {
+                    hasher.write_u64(ledger_changes.len() as u64);
+                    for change in ledger_changes {
+                        hash_ledger_change(&mut hasher, change);
+                    }
+                }
+                ReceiptComponent::ContractEvents => {
+                    hasher.write_u64(encoded_contract_events.len() as u64);
+                    for event in encoded_contract_events {
+                        hasher.write_bytes(event);
+                    }
+                }
+                ReceiptComponent::BudgetConsumption => {
+                    let cost_types = ContractCostType::variants();
+                    hasher.write_u64(cost_types.len() as u64);
+                    for ty in cost_types {
+                        let tracker = budget.get_tracker(ty)?;
+                        hasher.write_u32(ty as u32);
+                        hasher.write_u64(tracker.iterations);
+                        match tracker.inputs {
+                            Some(inputs) => {
+                                hasher.write_u32(1);
+                                hasher.write_u64(inputs);
+                            }
+                            None => hasher.write_u32(0),
+                        }
+                        hasher.write_u64(tracker.cpu);
+                        hasher.write_u64(tracker.mem);
+                    }
+                    hasher.write_u64(budget.get_cpu_insns_consumed()?);
+                    hasher.write_u64(budget.get_mem_bytes_consumed()?);
+                }
+                ReceiptComponent::PrngSeed => hasher.write_bytes(base_prng_seed),
+            }
+            component_hashes[i] = hasher.finish();
+        }
+        Ok(Self {
+            receipt_hash: chain_hash(&component_hashes),
+            component_hashes,
+        })
+    }
+
+    /// Returns the hash of the given component.
+    pub fn component_hash(&self, component: ReceiptComponent) -> &[u8; 32] {
+        &self.component_hashes[component as usize]
+    }
+
+    /// Returns the components that differ between this and `other` receipts,
+    /// in the order of the hash chain.
+    ///
+    /// The result is empty if and only if the receipts are identical.
+    pub fn diverging_components(&self, other: &ExecutionReceipt) -> Vec<ReceiptComponent> {
+        ReceiptComponent::ALL
+            .into_iter()
+            .filter(|c| self.component_hash(*c) != other.component_hash(*c))
+            .collect()
+    }
+
+    /// Serializes the receipt as the concatenation of the component hashes
+    /// followed by the receipt hash.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 * (self.component_hashes.len() + 1));
+        for hash in &self.component_hashes {
+            bytes.extend_from_slice(hash);
+        }
+        bytes.extend_from_slice(&self.receipt_hash);
+        bytes
+    }
+
+    /// Deserializes the receipt produced by [ExecutionReceipt::to_bytes].
+    ///
+    /// Fails if the receipt hash doesn't match the component hashes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HostError> {
+        let invalid_input = || HostError::from((ScErrorType::Value, ScErrorCode::InvalidInput));
+        let mut chunks = bytes.chunks_exact(32);
+        if chunks.len() != ReceiptComponent::ALL.len() + 1 || !chunks.remainder().is_empty() {
+            return Err(invalid_input());
+        }
+        let mut component_hashes = [[0; 32]; 5];
+        for hash in component_hashes.iter_mut() {
+            hash.copy_from_slice(chunks.next().ok_or_else(invalid_input)?);
+        }
+        let mut receipt_hash = [0; 32];
+        receipt_hash.copy_from_slice(chunks.next().ok_or_else(invalid_input)?);
+        if receipt_hash != chain_hash(&component_hashes) {
+            return Err(invalid_input());
+        }
+        Ok(Self {
+            component_hashes,
+            receipt_hash,
+        })
+    }
+}
+
+fn hash_ledger_change(hasher: &mut ReceiptHasher, change: &LedgerEntryChange) {
+    hasher.write_u32(change.read_only as u32);
+    hasher.write_bytes(&change.encoded_key);
+    hasher.write_u32(change.old_entry_size_bytes_for_rent);
+    hasher.write_optional_bytes(change.encoded_new_value.as_deref());
+    hasher.write_u32(change.new_entry_size_bytes_for_rent);
+    match &change.ttl_change {
+        Some(LedgerEntryLiveUntilChange {
+            key_hash,
+            durability,
+            entry_type,
+            old_live_until_ledger,
+            new_live_until_ledger,
+        }) => {
+            hasher.write_u32(1);
+            hasher.write_bytes(key_hash);
+            hasher.write_u32(*durability as u32);
+            hasher.write_u32(*entry_type as u32);
+            hasher.write_u32(*old_live_until_ledger);
+            hasher.write_u32(*new_live_until_ledger);
+        }
+        None => hasher.write_u32(0),
+    }
+}
+
+// Each link of the chain commits to all the preceding components.
+fn chain_hash(component_hashes: &[[u8; 32]; 5]) -> [u8; 32] {
+    let mut chain: [u8; 32] = Sha256::digest(RECEIPT_DOMAIN).into();
+    for hash in component_hashes {
+        let mut hasher = Sha256::new();
+        hasher.update(chain);
+        hasher.update(hash);
+        chain = hasher.finalize().into();
+    }
+    chain
+}
@@ -0,0 +1,97 @@
+use std::{collections::BTreeSet, rc::Rc};
+
+use super::InvokeHostFunctionRecordingModeResult;
+use crate::{
+    fees::{compute_transaction_resource_fee, FeeConfiguration, TransactionResources},
+    storage::SnapshotSource,
+    xdr::{LedgerKey, ReadXdr, ScErrorCode, ScErrorType, WriteXdr},
+    HostError, DEFAULT_XDR_RW_LIMITS,
+};
+
+/// The read-write footprint entries of a recording mode invocation that end
+/// up with exactly the same value as they had before the invocation, see
+/// [`find_unchanged_read_write_entries`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UnchangedReadWriteEntries {
+    /// Keys of the unchanged entries, ordered by key (like the footprint).
+    pub keys: Vec<LedgerKey>,
+    /// Part of the recorded write bytes that is due to the unchanged entries.
+    pub write_bytes: u32,
+}
+
+impl UnchangedReadWriteEntries {
+    /// Returns the part of the non-refundable resource fee of a transaction
+    /// with the provided resources that is due to the unchanged entries, i.e.
+    /// the fee that would be saved if the unchanged entries were not written.
+    pub fn fee_savings(
+        &self,
+        tx_resources: &TransactionResources,
+        fee_config: &FeeConfiguration,
+    ) -> i64 {
+        let (fee, _) = compute_transaction_resource_fee(tx_resources, fee_config);
+        let minimized_resources = TransactionResources {
+            instructions: tx_resources.instructions,
+            disk_read_entries: tx_resources.disk_read_entries,
+            write_entries: tx_resources
+                .write_entries
+                .saturating_sub(self.keys.len() as u32),
+            disk_read_bytes: tx_resources.disk_read_bytes,
+            write_bytes: tx_resources.write_bytes.saturating_sub(self.write_bytes),
+            contract_events_size_bytes: tx_resources.contract_events_size_bytes,
+            transaction_size_bytes: tx_resources.transaction_size_bytes,
+        };
+        let (minimized_fee, _) = compute_transaction_resource_fee(&minimized_resources, fee_config);
+        fee.saturating_sub(minimized_fee)
+    }
+}
+
+/// Finds the entries that the recording mode invocation has recorded in the
+/// read-write footprint, but whose final value is the same as the value in
+/// `ledger_snapshot` (including the entries that neither existed before nor
+/// exist after the invocation). The auto-restored entries are never considered
+/// unchanged.
+///
+/// Note, that these entries can not be simply moved to the read-only
+/// footprint: the footprint is enforced for every write, even if the write
+/// doesn't change the entry value. Instead, this is meant to point the
+/// contract authors at the redundant writes and estimate the savings from
+/// avoiding them.
+///
+/// `ledger_snapshot` must be the snapshot the invocation has been recorded
+/// with. The invocation must have succeeded, as there are no ledger changes
+/// for the failed invocations.
+pub fn find_unchanged_read_write_entries(
+    result: &InvokeHostFunctionRecordingModeResult,
+    ledger_snapshot: &dyn SnapshotSource,
+) -> Result<UnchangedReadWriteEntries, HostError> {
+    if result.invoke_result.is_err() {
+        return Err((ScErrorType::Context, ScErrorCode::InvalidAction).into());
+    }
+    let restored_keys: BTreeSet<&LedgerKey> = result
+        .restored_rw_entry_indices
+        .iter()
+        .filter_map(|i| result.resources.footprint.read_write.get(*i as usize))
+        .collect();
+    let mut unchanged = UnchangedReadWriteEntries::default();
+    for change in &result.ledger_changes {
+        if change.read_only {
+            continue;
+        }
+        let key = LedgerKey::from_xdr(&change.encoded_key, DEFAULT_XDR_RW_LIMITS)?;
+        if restored_keys.contains(&key) {
+            continue;
+        }
+        let encoded_old_value = match ledger_snapshot.get(&Rc::new(key.clone()))? {
+            Some((entry, _)) => Some(entry.to_xdr(DEFAULT_XDR_RW_LIMITS)?),
+            None => None,
+        };
+        if encoded_old_value == change.encoded_new_value {
+            if let Some(new_value) = &change.encoded_new_value {
+                unchanged.write_bytes =
+                    unchanged.write_bytes.saturating_add(new_value.len() as u32);
+            }
+            unchanged.keys.push(key);
+        }
+    }
+    Ok(unchanged)
+}
@@ -124,6 +124,27 @@ fn display_scval(scv: &ScVal, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Resu
     }
 }
 
+/// Wrapper for displaying an `ScAddress` as a strkey, the same way as the
+/// addresses are displayed in the host events.
+pub struct DisplayScAddress<'a>(pub &'a ScAddress);
+
+impl core::fmt::Display for DisplayScAddress<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        display_address(self.0, f)
+    }
+}
+
+/// Wrapper for displaying an `ScVal` the same way as the values are
+/// displayed in the host events (e.g. with the addresses rendered as
+/// strkeys).
+pub struct DisplayScVal<'a>(pub &'a ScVal);
+
+impl core::fmt::Display for DisplayScVal<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        display_scval(self.0, f)
+    }
+}
+
 impl core::fmt::Display for HostEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.failed_call {
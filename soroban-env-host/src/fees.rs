@@ -48,6 +48,7 @@ pub struct TransactionResources {
 /// function.
 
 #[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FeeConfiguration {
     /// Fee per `INSTRUCTIONS_INCREMENT=10000` instructions.
     pub fee_per_instruction_increment: i64,
@@ -73,6 +74,7 @@ pub struct FeeConfiguration {
 ///
 /// This should be normally loaded from the ledger.
 #[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RentWriteFeeConfiguration {
     // Write fee grows linearly until the Soroban state reaches this size.
     pub state_target_size_bytes: i64,
@@ -117,6 +119,7 @@ pub struct LedgerEntryRentChange {
 /// `compute_rent_write_fee_per_1kb` function.
 
 #[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RentFeeConfiguration {
     // Fee per 1KB written to the ledger.
     // This is the same value as `fee_per_write_1kb` in `FeeConfiguration`.
@@ -13,16 +13,19 @@ use crate::{
     storage::Storage,
     vm::ModuleCache,
     xdr::{
-        int128_helpers, AccountId, Asset, ContractCostType, ContractEventType, ContractExecutable,
-        ContractId, ContractIdPreimage, ContractIdPreimageFromAddress, CreateContractArgsV2,
-        Duration, Hash, LedgerEntryData, PublicKey, ScAddress, ScBytes, ScErrorCode, ScErrorType,
-        ScString, ScSymbol, ScVal, TimePoint, Uint256,
+        int128_helpers, AccountId, Asset, ContractCostType, ContractDataDurability,
+        ContractEventType, ContractExecutable, ContractId, ContractIdPreimage,
+        ContractIdPreimageFromAddress, CreateContractArgsV2, Duration, Hash, LedgerEntryData,
+        PublicKey, ScAddress, ScBytes, ScErrorCode, ScErrorType, ScString, ScSymbol, ScVal,
+        TimePoint, Uint256,
     },
     AddressObject, Bool, BytesObject, Compare, ConversionError, EnvBase, Error, LedgerInfo,
     MapObject, Object, StorageType, StringObject, Symbol, SymbolObject, SymbolSmall, TryFromVal,
     TryIntoVal, Val, VecObject, VmCaller, VmCallerEnv, Void,
 };
 
+#[cfg(any(test, feature = "recording_mode"))]
+pub mod call_tree;
 mod comparison;
 mod conversion;
 mod data_helper;
@@ -41,6 +44,8 @@ pub(crate) mod metered_vector;
 pub(crate) mod metered_xdr;
 mod num;
 pub(crate) mod prng;
+#[cfg(any(test, feature = "recording_mode"))]
+pub mod storage_access_log;
 pub(crate) mod trace;
 mod validity;
 
@@ -65,8 +70,12 @@ pub(crate) use frame::Frame;
 #[cfg(any(test, feature = "recording_mode"))]
 use rand_chacha::ChaCha20Rng;
 
+#[cfg(any(test, feature = "recording_mode"))]
+use call_tree::CallTreeMeter;
 #[cfg(any(test, feature = "testutils"))]
 use invocation_metering::InvocationMeter;
+#[cfg(any(test, feature = "recording_mode"))]
+use storage_access_log::StorageAccessLog;
 
 #[cfg(any(test, feature = "testutils"))]
 #[derive(Clone, Copy)]
@@ -171,6 +180,15 @@ struct HostImpl {
 
     #[cfg(any(test, feature = "testutils"))]
     pub(crate) invocation_meter: RefCell<InvocationMeter>,
+
+    #[cfg(any(test, feature = "recording_mode"))]
+    storage_access_log: RefCell<StorageAccessLog>,
+
+    #[cfg(any(test, feature = "recording_mode"))]
+    call_tree_meter: RefCell<CallTreeMeter>,
+
+    #[cfg(any(test, feature = "recording_mode"))]
+    recording_mode_module_cache: RefCell<Option<ModuleCache>>,
 }
 
 // Host is a newtype on Rc<HostImpl> so we can impl Env for it below.
@@ -329,6 +347,30 @@ impl_checked_borrow_helpers!(
     try_borrow_suppress_diagnostic_events_mut
 );
 
+#[cfg(any(test, feature = "recording_mode"))]
+impl_checked_borrow_helpers!(
+    storage_access_log,
+    StorageAccessLog,
+    try_borrow_storage_access_log,
+    try_borrow_storage_access_log_mut
+);
+
+#[cfg(any(test, feature = "recording_mode"))]
+impl_checked_borrow_helpers!(
+    call_tree_meter,
+    CallTreeMeter,
+    try_borrow_call_tree_meter,
+    try_borrow_call_tree_meter_mut
+);
+
+#[cfg(any(test, feature = "recording_mode"))]
+impl_checked_borrow_helpers!(
+    recording_mode_module_cache,
+    Option<ModuleCache>,
+    try_borrow_recording_mode_module_cache,
+    try_borrow_recording_mode_module_cache_mut
+);
+
 impl Debug for HostImpl {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "HostImpl(...)")
@@ -381,6 +423,12 @@ impl Host {
             suppress_diagnostic_events: RefCell::new(false),
             #[cfg(any(test, feature = "testutils"))]
             invocation_meter: Default::default(),
+            #[cfg(any(test, feature = "recording_mode"))]
+            storage_access_log: Default::default(),
+            #[cfg(any(test, feature = "recording_mode"))]
+            call_tree_meter: Default::default(),
+            #[cfg(any(test, feature = "recording_mode"))]
+            recording_mode_module_cache: RefCell::new(None),
         }))
     }
 
@@ -439,6 +487,20 @@ impl Host {
         Ok(())
     }
 
+    // Install a long-lived module cache to be used in recording mode. Unlike
+    // the regular module cache, this cache is only used to avoid re-parsing
+    // the contracts: the parsing is still charged to the budget exactly as if
+    // the module wasn't cached, so that the recorded resources don't depend
+    // on the cache contents. The cache has to be created with the same
+    // budget cost parameters as the ones used by this host. The host never
+    // evicts anything from the cache, so bounding its size is up to the
+    // caller that owns it.
+    #[cfg(any(test, feature = "recording_mode"))]
+    pub fn set_recording_mode_module_cache(&self, cache: ModuleCache) -> Result<(), HostError> {
+        *self.try_borrow_recording_mode_module_cache_mut()? = Some(cache);
+        Ok(())
+    }
+
     pub fn set_source_account(&self, source_account: AccountId) -> Result<(), HostError> {
         *self.try_borrow_source_account_mut()? = Some(source_account);
         Ok(())
@@ -749,6 +811,139 @@ impl Host {
             })
     }
 
+    /// Like [`Host::try_finish`], but keeps the _unique_ (refcount = 1) host
+    /// reference alive, so that it can be reused for another invocation after
+    /// calling [`Host::try_reset`].
+    pub fn try_finish_for_reuse(&mut self) -> Result<(Storage, Events), HostError> {
+        let events = self.try_borrow_events()?.externalize(self)?;
+        let host_impl = Rc::get_mut(&mut self.0).ok_or_else(|| {
+            HostError::from(Error::from_type_and_code(
+                ScErrorType::Context,
+                ScErrorCode::InternalError,
+            ))
+        })?;
+        let storage = std::mem::take(host_impl.storage.get_mut());
+        Ok((storage, events))
+    }
+
+    /// Resets a _unique_ (refcount = 1) host reference to the state of a host
+    /// created via [`Host::with_storage_and_budget`] with the provided
+    /// `storage` and `budget`.
+    ///
+    /// This allows reusing the host for many invocations without the cost of
+    /// reconstructing it: the allocations of the object table and the other
+    /// internal buffers are kept. Everything else is reset, including the
+    /// installed module caches, so the invocations in the reset host are
+    /// indistinguishable from the invocations in a fresh host, including the
+    /// budget consumption. A long-lived module cache has to be installed again
+    /// after every reset.
+    pub fn try_reset(&mut self, storage: Storage, budget: Budget) -> Result<(), HostError> {
+        let host_impl = Rc::get_mut(&mut self.0).ok_or_else(|| {
+            HostError::from(Error::from_type_and_code(
+                ScErrorType::Context,
+                ScErrorCode::InternalError,
+            ))
+        })?;
+        *host_impl.module_cache.get_mut() = None;
+        *host_impl.source_account.get_mut() = None;
+        *host_impl.ledger.get_mut() = None;
+        host_impl.objects.get_mut().clear();
+        *host_impl.storage.get_mut() = storage;
+        host_impl.context_stack.get_mut().clear();
+        host_impl.budget = budget;
+        host_impl.events.get_mut().vec.clear();
+        *host_impl.authorization_manager.get_mut() =
+            AuthorizationManager::new_enforcing_without_authorizations();
+        *host_impl.diagnostic_level.get_mut() = Default::default();
+        *host_impl.base_prng.get_mut() = None;
+        *host_impl.storage_key_conversion_active.get_mut() = false;
+        #[cfg(any(test, feature = "recording_mode"))]
+        {
+            *host_impl.recording_auth_nonce_prng.get_mut() = None;
+            *host_impl.suppress_diagnostic_events.get_mut() = false;
+            *host_impl.storage_access_log.get_mut() = Default::default();
+            *host_impl.call_tree_meter.get_mut() = Default::default();
+            *host_impl.recording_mode_module_cache.get_mut() = None;
+        }
+        #[cfg(any(test, feature = "testutils"))]
+        {
+            *host_impl.test_prng.get_mut() = None;
+            host_impl.contracts.get_mut().clear();
+            *host_impl.previous_authorization_manager.get_mut() = None;
+            *host_impl.top_contract_invocation_hook.get_mut() = None;
+            *host_impl.coverage_scoreboard.get_mut() = Default::default();
+            *host_impl.invocation_meter.get_mut() = Default::default();
+        }
+        *host_impl.trace_hook.get_mut() = None;
+        *host_impl.disable_tracing.get_mut() = false;
+        Ok(())
+    }
+
+    /// Creates an independent copy of this host, e.g. for exploring several
+    /// alternative invocations starting from the same state.
+    ///
+    /// The copy gets its own storage, object table, events, authorization
+    /// manager, ledger info and PRNG state, so changes made by invocations in
+    /// one host are not observable in the other. The budget of the copy has
+    /// the same cost models and limits, but doesn't have any resources
+    /// consumed. The module cache, registered test contracts and hooks are
+    /// shared between the hosts.
+    ///
+    /// Forking is only possible when no invocation is in progress.
+    #[cfg(any(test, feature = "recording_mode"))]
+    pub fn fork(&self) -> Result<Host, HostError> {
+        if !self.try_borrow_context_stack()?.is_empty() {
+            return Err(self.err(
+                ScErrorType::Context,
+                ScErrorCode::InvalidAction,
+                "host can't be forked while an invocation is in progress",
+                &[],
+            ));
+        }
+        let host = &self.0;
+        Ok(Self(Rc::new(HostImpl {
+            module_cache: RefCell::new(self.try_borrow_module_cache()?.clone()),
+            source_account: RefCell::new(self.try_borrow_source_account()?.clone()),
+            ledger: RefCell::new(self.try_borrow_ledger()?.clone()),
+            objects: RefCell::new(self.try_borrow_objects()?.clone()),
+            storage: RefCell::new(self.try_borrow_storage()?.clone()),
+            context_stack: Default::default(),
+            budget: host.budget.fork()?,
+            events: RefCell::new(self.try_borrow_events()?.clone()),
+            authorization_manager: RefCell::new(self.try_borrow_authorization_manager()?.clone()),
+            diagnostic_level: RefCell::new(host.diagnostic_level.try_borrow_or_err()?.clone()),
+            base_prng: RefCell::new(self.try_borrow_base_prng()?.clone()),
+            storage_key_conversion_active: RefCell::new(false),
+            recording_auth_nonce_prng: RefCell::new(
+                self.try_borrow_recording_auth_nonce_prng()?.clone(),
+            ),
+            #[cfg(any(test, feature = "testutils"))]
+            test_prng: RefCell::new(self.try_borrow_test_prng()?.clone()),
+            #[cfg(any(test, feature = "testutils"))]
+            contracts: RefCell::new(self.try_borrow_contracts()?.clone()),
+            #[cfg(any(test, feature = "testutils"))]
+            previous_authorization_manager: RefCell::new(
+                self.try_borrow_previous_authorization_manager()?.clone(),
+            ),
+            trace_hook: RefCell::new(self.try_borrow_trace_hook()?.clone()),
+            disable_tracing: RefCell::new(false),
+            #[cfg(any(test, feature = "testutils"))]
+            top_contract_invocation_hook: RefCell::new(
+                self.try_borrow_top_contract_invocation_hook()?.clone(),
+            ),
+            #[cfg(any(test, feature = "testutils"))]
+            coverage_scoreboard: Default::default(),
+            suppress_diagnostic_events: RefCell::new(false),
+            #[cfg(any(test, feature = "testutils"))]
+            invocation_meter: RefCell::new(host.invocation_meter.try_borrow_or_err()?.clone()),
+            storage_access_log: RefCell::new(self.try_borrow_storage_access_log()?.clone()),
+            call_tree_meter: RefCell::new(self.try_borrow_call_tree_meter()?.clone()),
+            recording_mode_module_cache: RefCell::new(
+                self.try_borrow_recording_mode_module_cache()?.clone(),
+            ),
+        })))
+    }
+
     fn create_contract_impl(
         &self,
         deployer: AddressObject,
@@ -2409,6 +2604,33 @@ impl VmCallerEnv for Host {
         Ok(Val::VOID)
     }
 
+    // Notes on metering: covered by the components.
+    fn scan_contract_data_keys(
+        &self,
+        _vmcaller: &mut VmCaller<Host>,
+        prefix: VecObject,
+        t: StorageType,
+    ) -> Result<VecObject, HostError> {
+        let durability: ContractDataDurability = match t {
+            StorageType::Temporary | StorageType::Persistent => t.try_into()?,
+            StorageType::Instance => {
+                return Err(self.err(
+                    ScErrorType::Storage,
+                    ScErrorCode::InvalidAction,
+                    "instance storage can't be scanned",
+                    &[],
+                ))
+            }
+        };
+        let prefix = self.vecobject_to_scval_vec(prefix)?;
+        let keys = self.scan_contract_data_keys_internal(prefix.as_slice(), durability)?;
+        let mut vals = Vec::<Val>::with_metered_capacity(keys.len(), self)?;
+        for key in keys.iter() {
+            vals.push(self.to_valid_host_val(key)?);
+        }
+        self.add_host_object(HostVec::from_vec(vals)?)
+    }
+
     // endregion: "ledger" module functions
     // region: "call" module functions
 
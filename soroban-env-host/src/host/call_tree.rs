@@ -0,0 +1,384 @@
+use std::rc::Rc;
+
+use crate::{
+    e2e_invoke::entry_size_for_rent,
+    events::{EventError, InternalEvent},
+    host::Frame,
+    ledger_info::get_key_durability,
+    storage::{AccessType, EntryWithLiveUntil, FootprintMode},
+    xdr::{ContractDataDurability, ContractEventType, ContractId, LedgerKey, WriteXdr},
+    Host, HostError, Symbol, SymbolStr, TryFromVal, DEFAULT_XDR_RW_LIMITS,
+};
+
+/// Resources attributed to a node of the call tree, see
+/// [`Host::enable_call_tree_metering`].
+///
+/// The ledger entries are attributed to the call that has accessed them
+/// first, i.e. an entry that is read by both a contract and the contract it
+/// calls only counts towards the call that has read it first. The rent is
+/// attributed to the calls that have extended the TTL of the entry or
+/// increased its size.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CallResources {
+    /// Number of modelled CPU instructions.
+    pub instructions: u64,
+    /// Size of modelled memory in bytes.
+    pub mem_bytes: u64,
+    /// Number of ledger entries added to the footprint.
+    pub read_entries: u32,
+    /// Number of ledger entries added to the read-write footprint (including
+    /// the entries that have been previously read).
+    pub write_entries: u32,
+    /// Total size of the contract events emitted.
+    pub contract_events_size_bytes: u32,
+    /// Cumulative rent bump of the persistent entries in 'ledger-bytes', see
+    /// `InvocationResources`.
+    pub persistent_rent_ledger_bytes: i64,
+    /// Number of rent bumps of the persistent entries.
+    pub persistent_entry_rent_bumps: u32,
+    /// Cumulative rent bump of the temporary entries in 'ledger-bytes'.
+    pub temporary_rent_ledger_bytes: i64,
+    /// Number of rent bumps of the temporary entries.
+    pub temporary_entry_rent_bumps: u32,
+}
+
+/// A single frame of the invocation (a contract call or a host function)
+/// with the resources it has consumed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CallTreeNode {
+    /// Called contract, or `None` for the host function frames (such as the
+    /// contract creation).
+    pub contract_id: Option<ContractId>,
+    /// Name of the called contract function, or the type of the host
+    /// function.
+    pub function_name: String,
+    /// Whether the call has failed. The storage changes and the events of
+    /// the failed calls are rolled back, so these don't contribute to the
+    /// write bytes, rent and the event sizes of the call.
+    pub failed: bool,
+    /// Resources consumed by the call, including the nested calls.
+    pub inclusive: CallResources,
+    /// Resources consumed by the call itself, excluding the nested calls.
+    pub exclusive: CallResources,
+    /// Nested calls in the order they have been made.
+    pub children: Vec<CallTreeNode>,
+}
+
+impl CallResources {
+    fn add(&mut self, other: &CallResources) {
+        self.instructions = self.instructions.saturating_add(other.instructions);
+        self.mem_bytes = self.mem_bytes.saturating_add(other.mem_bytes);
+        self.read_entries = self.read_entries.saturating_add(other.read_entries);
+        self.write_entries = self.write_entries.saturating_add(other.write_entries);
+        self.contract_events_size_bytes = self
+            .contract_events_size_bytes
+            .saturating_add(other.contract_events_size_bytes);
+        self.persistent_rent_ledger_bytes = self
+            .persistent_rent_ledger_bytes
+            .saturating_add(other.persistent_rent_ledger_bytes);
+        self.persistent_entry_rent_bumps = self
+            .persistent_entry_rent_bumps
+            .saturating_add(other.persistent_entry_rent_bumps);
+        self.temporary_rent_ledger_bytes = self
+            .temporary_rent_ledger_bytes
+            .saturating_add(other.temporary_rent_ledger_bytes);
+        self.temporary_entry_rent_bumps = self
+            .temporary_entry_rent_bumps
+            .saturating_add(other.temporary_entry_rent_bumps);
+    }
+
+    fn clear_rolled_back(&mut self) {
+        self.contract_events_size_bytes = 0;
+        self.persistent_rent_ledger_bytes = 0;
+        self.persistent_entry_rent_bumps = 0;
+        self.temporary_rent_ledger_bytes = 0;
+        self.temporary_entry_rent_bumps = 0;
+    }
+}
+
+impl CallTreeNode {
+    fn finalize(&mut self) {
+        let mut inclusive = self.exclusive.clone();
+        for child in &mut self.children {
+            child.finalize();
+            inclusive.add(&child.inclusive);
+        }
+        self.inclusive = inclusive;
+    }
+
+    fn clear_rolled_back(&mut self) {
+        self.exclusive.clear_rolled_back();
+        for child in &mut self.children {
+            child.clear_rolled_back();
+        }
+    }
+}
+
+// The meter attributes the resources consumed between every two consecutive
+// frame boundaries (push or pop) to the frame on top of the stack. The state
+// observed at the previous boundary is stored in order to compute the
+// difference.
+#[derive(Clone, Default)]
+pub(crate) struct CallTreeMeter {
+    enabled: bool,
+    open_nodes: Vec<CallTreeNode>,
+    roots: Vec<CallTreeNode>,
+    cpu_insns: u64,
+    mem_bytes: u64,
+    events: usize,
+    footprint: Vec<(Rc<LedgerKey>, AccessType)>,
+    storage: Vec<(Rc<LedgerKey>, Option<EntryWithLiveUntil>)>,
+}
+
+// Returns the size for rent and the live until ledger of the entry, or `None`
+// if there is no entry or it doesn't have TTL.
+//
+// Call tree metering is a debugging facility, so neither this nor the other
+// helpers are metered (contract code sizes are computed in the shadow mode).
+fn rent_state(host: &Host, entry: &Option<EntryWithLiveUntil>) -> Option<(u32, u32)> {
+    let (entry, live_until) = entry.as_ref()?;
+    let live_until = (*live_until)?;
+    let xdr_size = entry.as_ref().to_xdr(DEFAULT_XDR_RW_LIMITS).ok()?.len() as u32;
+    let mut size_for_rent = xdr_size;
+    host.budget_ref().with_shadow_mode(|| {
+        size_for_rent = entry_size_for_rent(host.budget_ref(), entry, xdr_size)?;
+        Ok(())
+    });
+    Some((size_for_rent, live_until))
+}
+
+// Computes the rent bump of the entry in 'ledger-bytes' in the same fashion
+// as `InvocationMeter`.
+fn rent_ledger_bytes(curr_ledger_seq: u32, before: Option<(u32, u32)>, after: (u32, u32)) -> i64 {
+    let (new_size, new_live_until) = after;
+    let (init_size, init_live_until) = match before {
+        Some((size, live_until)) => (size, live_until.max(curr_ledger_seq)),
+        None => (0, curr_ledger_seq),
+    };
+    let extension_ledgers = new_live_until.saturating_sub(init_live_until) as i64;
+    let existing_ledgers = init_live_until.saturating_sub(curr_ledger_seq) as i64;
+    let rent_size_delta = new_size.saturating_sub(init_size) as i64;
+    existing_ledgers
+        .saturating_mul(rent_size_delta)
+        .saturating_add(extension_ledgers.saturating_mul(new_size as i64))
+}
+
+impl CallTreeMeter {
+    fn observe_state(&mut self, host: &Host) -> Result<(), HostError> {
+        let storage = host.try_borrow_storage()?;
+        self.footprint = storage.footprint.0.map.clone();
+        self.storage = storage.map.map.clone();
+        self.events = host.try_borrow_events()?.vec.len();
+        self.cpu_insns = host.budget_ref().get_cpu_insns_consumed()?;
+        self.mem_bytes = host.budget_ref().get_mem_bytes_consumed()?;
+        Ok(())
+    }
+
+    // Attributes the resources consumed since the previous boundary to the
+    // frame on top of the stack.
+    fn attribute_to_top(&mut self, host: &Host) -> Result<(), HostError> {
+        let Some(node) = self.open_nodes.last_mut() else {
+            return self.observe_state(host);
+        };
+        let resources = &mut node.exclusive;
+        resources.instructions = resources.instructions.saturating_add(
+            host.budget_ref()
+                .get_cpu_insns_consumed()?
+                .saturating_sub(self.cpu_insns),
+        );
+        resources.mem_bytes = resources.mem_bytes.saturating_add(
+            host.budget_ref()
+                .get_mem_bytes_consumed()?
+                .saturating_sub(self.mem_bytes),
+        );
+
+        let storage = host.try_borrow_storage()?;
+        for (key, access_type) in &storage.footprint.0.map {
+            let prev_access_type = self
+                .footprint
+                .binary_search_by(|(k, _)| k.cmp(key))
+                .ok()
+                .map(|i| self.footprint[i].1);
+            match (prev_access_type, access_type) {
+                (None, AccessType::ReadOnly) => {
+                    resources.read_entries = resources.read_entries.saturating_add(1);
+                }
+                (None, AccessType::ReadWrite) => {
+                    resources.read_entries = resources.read_entries.saturating_add(1);
+                    resources.write_entries = resources.write_entries.saturating_add(1);
+                }
+                (Some(AccessType::ReadOnly), AccessType::ReadWrite) => {
+                    resources.write_entries = resources.write_entries.saturating_add(1);
+                }
+                _ => (),
+            }
+        }
+
+        // Rent can't be computed without the ledger info (which is only
+        // missing in tests).
+        let curr_ledger_seq = host.with_ledger_info(|li| Ok(li.sequence_number)).ok();
+        for (key, entry) in &storage.map.map {
+            let Some(curr_ledger_seq) = curr_ledger_seq else {
+                break;
+            };
+            let prev_entry = match self.storage.binary_search_by(|(k, _)| k.cmp(key)) {
+                Ok(i) => self.storage[i].1.clone(),
+                // Entries are loaded into the storage map lazily in the
+                // recording mode, so the missing entries are the unmodified
+                // snapshot entries.
+                Err(_) => match &storage.mode {
+                    FootprintMode::Recording(src) => src.get(key)?,
+                    FootprintMode::Enforcing => None,
+                },
+            };
+            let unchanged = match (&prev_entry, entry) {
+                (Some((prev, prev_live_until)), Some((curr, curr_live_until))) => {
+                    Rc::ptr_eq(prev, curr) && prev_live_until == curr_live_until
+                }
+                (None, None) => true,
+                _ => false,
+            };
+            if unchanged {
+                continue;
+            }
+            let Some(after) = rent_state(host, entry) else {
+                continue;
+            };
+            let rent = rent_ledger_bytes(curr_ledger_seq, rent_state(host, &prev_entry), after);
+            if rent <= 0 {
+                continue;
+            }
+            match get_key_durability(key.as_ref()) {
+                Some(ContractDataDurability::Persistent) => {
+                    resources.persistent_rent_ledger_bytes =
+                        resources.persistent_rent_ledger_bytes.saturating_add(rent);
+                    resources.persistent_entry_rent_bumps =
+                        resources.persistent_entry_rent_bumps.saturating_add(1);
+                }
+                Some(ContractDataDurability::Temporary) => {
+                    resources.temporary_rent_ledger_bytes =
+                        resources.temporary_rent_ledger_bytes.saturating_add(rent);
+                    resources.temporary_entry_rent_bumps =
+                        resources.temporary_entry_rent_bumps.saturating_add(1);
+                }
+                None => (),
+            }
+        }
+        drop(storage);
+
+        let events = host.try_borrow_events()?;
+        let mut events_size = 0_u32;
+        host.budget_ref().with_shadow_mode(|| {
+            for (event, status) in events.vec.iter().skip(self.events) {
+                if let (InternalEvent::Contract(event), EventError::FromSuccessfulCall) =
+                    (event, status)
+                {
+                    if event.type_ == ContractEventType::Diagnostic {
+                        continue;
+                    }
+                    let size = event.to_xdr(host)?.to_xdr(DEFAULT_XDR_RW_LIMITS)?.len() as u32;
+                    events_size = events_size.saturating_add(size);
+                }
+            }
+            Ok(())
+        });
+        drop(events);
+        resources.contract_events_size_bytes = resources
+            .contract_events_size_bytes
+            .saturating_add(events_size);
+
+        self.observe_state(host)
+    }
+}
+
+impl Host {
+    /// Enables metering the resources consumed by every contract call (and
+    /// host function) performed by this host. The resources are organized
+    /// into a tree that follows the call stack. Any previously recorded calls
+    /// are discarded.
+    ///
+    /// This is meant for explaining which nested calls are responsible for
+    /// the resource consumption of an invocation.
+    pub fn enable_call_tree_metering(&self) -> Result<(), HostError> {
+        *self.try_borrow_call_tree_meter_mut()? = CallTreeMeter {
+            enabled: true,
+            ..Default::default()
+        };
+        Ok(())
+    }
+
+    /// Returns the trees of the top-level calls that have been finished
+    /// since the call tree metering has been enabled or since the previous
+    /// call of this function.
+    pub fn take_call_tree(&self) -> Result<Vec<CallTreeNode>, HostError> {
+        let mut roots = std::mem::take(&mut self.try_borrow_call_tree_meter_mut()?.roots);
+        for root in &mut roots {
+            root.finalize();
+        }
+        Ok(roots)
+    }
+
+    // Starts a call tree node for the frame that has just been pushed.
+    pub(crate) fn call_tree_push_frame(&self) -> Result<(), HostError> {
+        if !self.try_borrow_call_tree_meter()?.enabled {
+            return Ok(());
+        }
+        let (contract_id, function_name) = self.with_current_frame(|frame| {
+            Ok(match frame {
+                Frame::ContractVM { vm, fn_name, .. } => (
+                    Some(vm.contract_id.clone()),
+                    symbol_to_string(self, *fn_name),
+                ),
+                Frame::StellarAssetContract(id, fn_name, ..) => {
+                    (Some(id.clone()), symbol_to_string(self, *fn_name))
+                }
+                #[cfg(any(test, feature = "testutils"))]
+                Frame::TestContract(tc) => (Some(tc.id.clone()), symbol_to_string(self, tc.func)),
+                Frame::HostFunction(hf_type) => (None, hf_type.name().to_string()),
+            })
+        })?;
+        // The resources consumed before the push (including the push itself)
+        // belong to the caller.
+        let mut meter = self.try_borrow_call_tree_meter_mut()?;
+        meter.attribute_to_top(self)?;
+        meter.open_nodes.push(CallTreeNode {
+            contract_id,
+            function_name,
+            failed: false,
+            inclusive: Default::default(),
+            exclusive: Default::default(),
+            children: vec![],
+        });
+        Ok(())
+    }
+
+    // Finishes the call tree node of the frame that has just been popped.
+    pub(crate) fn call_tree_pop_frame(&self, failed: bool) -> Result<(), HostError> {
+        let mut meter = self.try_borrow_call_tree_meter_mut()?;
+        if !meter.enabled {
+            return Ok(());
+        }
+        meter.attribute_to_top(self)?;
+        let Some(mut node) = meter.open_nodes.pop() else {
+            return Ok(());
+        };
+        if failed {
+            node.failed = true;
+            node.clear_rolled_back();
+        }
+        match meter.open_nodes.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => meter.roots.push(node),
+        }
+        Ok(())
+    }
+}
+
+fn symbol_to_string(host: &Host, symbol: Symbol) -> String {
+    let mut name = String::new();
+    host.budget_ref().with_shadow_mode(|| {
+        name = SymbolStr::try_from_val(host, &symbol)?.to_string();
+        Ok(())
+    });
+    name
+}
@@ -1,11 +1,11 @@
-use core::cmp::min;
+use core::cmp::{min, Ordering};
 use std::rc::Rc;
 
 use crate::{
     budget::AsBudget,
     err,
     host::metered_clone::{MeteredAlloc, MeteredClone},
-    storage::{InstanceStorageMap, Storage},
+    storage::{InstanceStorageMap, Storage, StorageMap},
     vm::VersionedContractCodeCostInputs,
     xdr::{
         AccountEntry, AccountId, Asset, BytesM, ContractCodeEntry, ContractDataDurability,
@@ -15,7 +15,7 @@ use crate::{
         LedgerKeyTrustLine, PublicKey, ScAddress, ScContractInstance, ScErrorCode, ScErrorType,
         ScMap, ScVal, Signer, SignerKey, ThresholdIndexes, TrustLineAsset, Uint256,
     },
-    AddressObject, Env, ErrorHandler, Host, HostError, StorageType, U32Val, Val,
+    AddressObject, Compare, Env, ErrorHandler, Host, HostError, StorageType, U32Val, Val,
 };
 
 impl Host {
@@ -280,6 +280,104 @@ impl Host {
         Ok(())
     }
 
+    /// Returns the keys of the live `durability` contract data entries of the
+    /// currently running contract that start with `prefix`, in the storage
+    /// order. An empty `prefix` matches every key, otherwise only `ScVec`
+    /// keys whose leading elements are equal to `prefix` are matched.
+    ///
+    /// Only the entries present in the storage map are visible, i.e. the
+    /// footprint entries in the enforcing mode. In the recording mode all the
+    /// live matching entries of the snapshot are recorded in the read-only
+    /// footprint before the scan, so the recorded footprint reproduces the
+    /// scan result in the enforcing mode.
+    // Notes on metering: the storage scan, key comparisons and clones are
+    // covered by the components.
+    pub(crate) fn scan_contract_data_keys_internal(
+        &self,
+        prefix: &[ScVal],
+        durability: ContractDataDurability,
+    ) -> Result<Vec<ScVal>, HostError> {
+        let contract = ScAddress::Contract(self.get_current_contract_id_internal()?);
+        let ledger_seq = self.with_ledger_info(|li| Ok(li.sequence_number))?;
+        #[cfg(any(test, feature = "recording_mode"))]
+        self.try_borrow_storage_mut()?.record_contract_data_scan(
+            self,
+            &contract,
+            durability,
+            |key| self.contract_data_key_is_scanned(key, &contract, prefix, durability),
+        )?;
+        self.scan_contract_data_keys_in_map(
+            &self.try_borrow_storage()?.map,
+            &contract,
+            prefix,
+            durability,
+            ledger_seq,
+        )
+    }
+
+    // Notes on metering: covered by components.
+    pub(crate) fn scan_contract_data_keys_in_map(
+        &self,
+        map: &StorageMap,
+        contract: &ScAddress,
+        prefix: &[ScVal],
+        durability: ContractDataDurability,
+        ledger_seq: u32,
+    ) -> Result<Vec<ScVal>, HostError> {
+        let mut keys = vec![];
+        for (key, entry) in map.iter(self.budget_ref())? {
+            let LedgerKey::ContractData(key) = key.as_ref() else {
+                continue;
+            };
+            // Entries that don't exist or have expired are not visible.
+            match entry {
+                Some((_, Some(live_until))) if *live_until >= ledger_seq => (),
+                _ => continue,
+            }
+            if self.contract_data_key_is_scanned(key, contract, prefix, durability)? {
+                keys.push(key.key.metered_clone(self)?);
+            }
+        }
+        Ok(keys)
+    }
+
+    // Notes on metering: covered by components.
+    fn contract_data_key_is_scanned(
+        &self,
+        key: &LedgerKeyContractData,
+        contract: &ScAddress,
+        prefix: &[ScVal],
+        durability: ContractDataDurability,
+    ) -> Result<bool, HostError> {
+        // The contract instance entry is not a contract data entry from the
+        // contract's perspective.
+        if key.durability != durability
+            || matches!(key.key, ScVal::LedgerKeyContractInstance)
+            || self.as_budget().compare(&key.contract, contract)? != Ordering::Equal
+        {
+            return Ok(false);
+        }
+        self.scval_has_prefix(&key.key, prefix)
+    }
+
+    fn scval_has_prefix(&self, val: &ScVal, prefix: &[ScVal]) -> Result<bool, HostError> {
+        if prefix.is_empty() {
+            return Ok(true);
+        }
+        let ScVal::Vec(Some(elements)) = val else {
+            return Ok(false);
+        };
+        if elements.len() < prefix.len() {
+            return Ok(false);
+        }
+        for (element, prefix_element) in elements.iter().zip(prefix.iter()) {
+            if self.as_budget().compare(element, prefix_element)? != Ordering::Equal {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     // metering: covered by components
     pub(crate) fn get_full_contract_id_preimage(
         &self,
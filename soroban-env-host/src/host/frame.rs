@@ -210,23 +210,38 @@ impl Host {
     pub(super) fn pop_context(&self, orp: Option<RollbackPoint>) -> Result<Context, HostError> {
         let _span = tracy_span!("pop context");
 
-        let ctx = self.try_borrow_context_stack_mut()?.pop();
-
+        // The storage is rolled back before the authentication emulation
+        // below, so that the `__check_auth` calls made by the emulation don't
+        // observe the changes made by a failed invocation.
+        let rollback = if let Some(rp) = orp {
+            self.try_borrow_storage_mut()?.map = rp.storage;
+            Some((rp.events, rp.auth))
+        } else {
+            None
+        };
         #[cfg(any(test, feature = "recording_mode"))]
-        if self.try_borrow_context_stack()?.is_empty() {
-            // When there are no contexts left, emulate authentication for the
-            // recording auth mode. This is a no-op for the enforcing mode.
+        let emulation_res = if self.try_borrow_context_stack()?.len() == 1 {
+            // When the last context is about to be popped, emulate
+            // authentication for the recording auth mode. This is a no-op for
+            // the enforcing mode. The context is popped afterwards, so that
+            // the `__check_auth` calls made by the emulation are nested into
+            // it. The emulation error is only propagated after the context
+            // has been popped in order to keep the context stack balanced.
             self.try_borrow_authorization_manager()?
-                .maybe_emulate_authentication(self)?;
-        }
+                .maybe_emulate_authentication(self)
+        } else {
+            Ok(())
+        };
+        let ctx = self.try_borrow_context_stack_mut()?.pop();
         let mut auth_snapshot = None;
-        if let Some(rp) = orp {
-            self.try_borrow_storage_mut()?.map = rp.storage;
-            self.try_borrow_events_mut()?.rollback(rp.events)?;
-            auth_snapshot = Some(rp.auth);
+        if let Some((events, auth)) = rollback {
+            self.try_borrow_events_mut()?.rollback(events)?;
+            auth_snapshot = Some(auth);
         }
         self.try_borrow_authorization_manager()?
             .pop_frame(self, auth_snapshot)?;
+        #[cfg(any(test, feature = "recording_mode"))]
+        emulation_res?;
         ctx.ok_or_else(|| {
             self.err(
                 ScErrorType::Context,
@@ -436,6 +451,8 @@ impl Host {
                 self.call_any_lifecycle_hook(crate::host::TraceEvent::PushCtx(ctx))?;
             }
         }
+        #[cfg(any(test, feature = "recording_mode"))]
+        self.call_tree_push_frame()?;
         #[cfg(any(test, feature = "testutils"))]
         let mut is_top_contract_invocation = false;
         #[cfg(any(test, feature = "testutils"))]
@@ -546,6 +563,11 @@ impl Host {
             // Just pop on success.
             self.pop_context(None)?
         };
+        // This is done after the pop in order to attribute the work done
+        // during the pop (such as the authentication emulation in the
+        // recording mode) to the popped frame.
+        #[cfg(any(test, feature = "recording_mode"))]
+        self.call_tree_pop_frame(res.is_err())?;
         // Every push and pop should be matched; if not there is a bug.
         let end_depth = self.try_borrow_context_stack()?.len();
         if start_depth != end_depth {
@@ -763,6 +785,11 @@ impl Host {
         //     - If the module is _not expired_ we assume it'll be survive until
         //       execution, simulate a hit, and risk undercharging.
         if self.in_storage_recording_mode()? {
+            if let Some(vm) =
+                self.instantiate_vm_from_recording_mode_module_cache(&contract_id, wasm_hash)?
+            {
+                return Ok(vm);
+            }
             if let Some((parsed_module, wasmi_linker)) =
                 self.budget_ref().with_observable_shadow_mode(|| {
                     use crate::vm::ParsedModule;
@@ -794,6 +821,53 @@ impl Host {
         Vm::new_with_cost_inputs(self, contract_id, code.as_slice(), costs)
     }
 
+    // Instantiates the VM from the module cached in the recording mode module
+    // cache (if any). The budget is charged in exactly the same way as for
+    // the uncached module in `instantiate_vm`, i.e. the parsing is charged to
+    // the shadow budget for the modules that are live in the snapshot, and
+    // to the real budget otherwise.
+    #[cfg(any(test, feature = "recording_mode"))]
+    fn instantiate_vm_from_recording_mode_module_cache(
+        &self,
+        contract_id: &ContractId,
+        wasm_hash: &Hash,
+    ) -> Result<Option<Rc<Vm>>, HostError> {
+        let cache = self.try_borrow_recording_mode_module_cache()?;
+        let Some(cache) = &*cache else {
+            return Ok(None);
+        };
+        let Some(parsed_module) = cache.get_module(wasm_hash)? else {
+            return Ok(None);
+        };
+        let charge_for_parsing = || -> Result<(), HostError> {
+            let (_code, costs) = self.retrieve_wasm_from_storage(wasm_hash)?;
+            costs.charge_for_parsing(self)?;
+            // Charge for building the linker for the module imports.
+            parsed_module.with_import_symbols(self, |_| Ok(()))
+        };
+        let charged_in_shadow_mode = self.budget_ref().with_observable_shadow_mode(|| {
+            let wasm_key = self.contract_code_ledger_key(wasm_hash)?;
+            let is_key_live_in_snapshot = self
+                .try_borrow_storage_mut()?
+                .is_key_live_in_snapshot(self, &wasm_key)?;
+            if is_key_live_in_snapshot {
+                charge_for_parsing()?;
+            }
+            Ok(is_key_live_in_snapshot)
+        })?;
+        if !charged_in_shadow_mode {
+            charge_for_parsing()?;
+        }
+        Vm::from_parsed_module_and_wasmi_linker(
+            self,
+            // The clone has already been charged for in `instantiate_vm`.
+            contract_id.clone(),
+            parsed_module,
+            &cache.wasmi_linker,
+        )
+        .map(Some)
+    }
+
     pub(crate) fn get_contract_protocol_version(
         &self,
         contract_id: &ContractId,
@@ -0,0 +1,186 @@
+use std::rc::Rc;
+
+use crate::{
+    host::Frame,
+    storage::{FootprintMode, Storage, StorageAccessType},
+    xdr::{ContractId, LedgerKey, WriteXdr},
+    Host, HostError, Symbol, SymbolStr, TryFromVal, DEFAULT_XDR_RW_LIMITS,
+};
+
+/// A single storage operation recorded in the storage access log, see
+/// [`Host::enable_storage_access_log`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StorageAccess {
+    /// Contract that has performed the access, or `None` if the access has
+    /// been performed outside of a contract frame (e.g. when uploading Wasm).
+    pub contract_id: Option<ContractId>,
+    /// Name of the contract function that has performed the access.
+    pub function_name: Option<String>,
+    pub key: Rc<LedgerKey>,
+    pub access_type: StorageAccessType,
+    /// XDR size of the entry before the access, `None` if the entry didn't
+    /// exist.
+    pub entry_size_before: Option<u32>,
+    /// XDR size of the entry after the access, `None` if the entry doesn't
+    /// exist.
+    pub entry_size_after: Option<u32>,
+    /// Live until ledger of the entry before the access.
+    pub live_until_ledger_before: Option<u32>,
+    /// Live until ledger of the entry after the access.
+    pub live_until_ledger_after: Option<u32>,
+    /// CPU instructions consumed by the access.
+    pub cpu_insns: u64,
+    /// Memory bytes consumed by the access.
+    pub mem_bytes: u64,
+    /// Whether the access has succeeded.
+    pub succeeded: bool,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct StorageAccessLog {
+    enabled: bool,
+    // Set while an access is being recorded, so that the nested storage
+    // operations (such as `get` performed by `has`) are not recorded
+    // separately.
+    access_in_progress: bool,
+    accesses: Vec<StorageAccess>,
+}
+
+pub(crate) struct PendingStorageAccess {
+    contract_id: Option<ContractId>,
+    function_name: Option<Symbol>,
+    entry_before: (Option<u32>, Option<u32>),
+    cpu_insns: u64,
+    mem_bytes: u64,
+}
+
+// Returns the XDR size and the live until ledger of the entry currently
+// visible to `storage`.
+//
+// The log is a debugging facility, so neither this nor the other log helpers
+// are metered and the snapshot reads are not recorded in the footprint.
+fn entry_state(
+    storage: &Storage,
+    key: &Rc<LedgerKey>,
+) -> Result<(Option<u32>, Option<u32>), HostError> {
+    let entry = match storage.map.map.binary_search_by(|(k, _)| k.cmp(key)) {
+        Ok(index) => storage.map.map[index].1.clone(),
+        Err(_) => match &storage.mode {
+            FootprintMode::Recording(src) => src.get(key)?,
+            FootprintMode::Enforcing => None,
+        },
+    };
+    match entry {
+        Some((entry, live_until)) => {
+            let size = entry
+                .to_xdr(DEFAULT_XDR_RW_LIMITS)
+                .map(|xdr| xdr.len() as u32)
+                .ok();
+            Ok((size, live_until))
+        }
+        None => Ok((None, None)),
+    }
+}
+
+impl Host {
+    /// Enables recording every storage access (`get`, `has`, `put`, `del`
+    /// and TTL extension) performed by this host, along with the contract
+    /// function that has performed it, the entry sizes and the consumed
+    /// budget. Any previously recorded accesses are discarded.
+    ///
+    /// This is meant for explaining the footprint and the I/O fees of an
+    /// invocation entry by entry.
+    pub fn enable_storage_access_log(&self) -> Result<(), HostError> {
+        *self.try_borrow_storage_access_log_mut()? = StorageAccessLog {
+            enabled: true,
+            ..Default::default()
+        };
+        Ok(())
+    }
+
+    /// Returns the storage accesses recorded since the log has been enabled
+    /// or since the previous call of this function.
+    pub fn take_storage_access_log(&self) -> Result<Vec<StorageAccess>, HostError> {
+        Ok(std::mem::take(
+            &mut self.try_borrow_storage_access_log_mut()?.accesses,
+        ))
+    }
+
+    pub(crate) fn begin_storage_access(
+        &self,
+        storage: &Storage,
+        key: &Rc<LedgerKey>,
+    ) -> Result<Option<PendingStorageAccess>, HostError> {
+        {
+            let log = self.try_borrow_storage_access_log()?;
+            if !log.enabled || log.access_in_progress {
+                return Ok(None);
+            }
+        }
+        let (contract_id, function_name) = self.with_current_frame_opt(|frame| {
+            Ok(match frame {
+                Some(Frame::ContractVM { vm, fn_name, .. }) => {
+                    (Some(vm.contract_id.clone()), Some(*fn_name))
+                }
+                Some(Frame::StellarAssetContract(id, fn_name, ..)) => {
+                    (Some(id.clone()), Some(*fn_name))
+                }
+                #[cfg(any(test, feature = "testutils"))]
+                Some(Frame::TestContract(tc)) => (Some(tc.id.clone()), Some(tc.func)),
+                Some(Frame::HostFunction(_)) | None => (None, None),
+            })
+        })?;
+        let entry_before = entry_state(storage, key)?;
+        self.try_borrow_storage_access_log_mut()?.access_in_progress = true;
+        Ok(Some(PendingStorageAccess {
+            contract_id,
+            function_name,
+            entry_before,
+            cpu_insns: self.budget_ref().get_cpu_insns_consumed()?,
+            mem_bytes: self.budget_ref().get_mem_bytes_consumed()?,
+        }))
+    }
+
+    pub(crate) fn end_storage_access(
+        &self,
+        storage: &Storage,
+        key: &Rc<LedgerKey>,
+        access_type: StorageAccessType,
+        pending: PendingStorageAccess,
+        succeeded: bool,
+    ) -> Result<(), HostError> {
+        self.try_borrow_storage_access_log_mut()?.access_in_progress = false;
+        let cpu_insns = self
+            .budget_ref()
+            .get_cpu_insns_consumed()?
+            .saturating_sub(pending.cpu_insns);
+        let mem_bytes = self
+            .budget_ref()
+            .get_mem_bytes_consumed()?
+            .saturating_sub(pending.mem_bytes);
+        let (entry_size_after, live_until_ledger_after) = entry_state(storage, key)?;
+        let mut function_name = None;
+        if let Some(fn_name) = pending.function_name {
+            self.budget_ref().with_shadow_mode(|| {
+                function_name = Some(SymbolStr::try_from_val(self, &fn_name)?.to_string());
+                Ok(())
+            });
+        }
+        self.try_borrow_storage_access_log_mut()?
+            .accesses
+            .push(StorageAccess {
+                contract_id: pending.contract_id,
+                function_name,
+                key: Rc::clone(key),
+                access_type,
+                entry_size_before: pending.entry_before.0,
+                entry_size_after,
+                live_until_ledger_before: pending.entry_before.1,
+                live_until_ledger_after,
+                cpu_insns,
+                mem_bytes,
+                succeeded,
+            });
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+//! This module contains [LedgerEmulator], a helper for emulating the
+//! evolution of the ledger state over many ledger closes.
+//!
+//! The emulator owns the ledger state and applies the transactions via the
+//! [TransactionSetExecutor]. When the ledger is advanced, the entries whose
+//! TTL has run out are processed following the network rules: expired
+//! temporary entries are deleted, and expired persistent entries are archived.
+//! Archived entries can't be accessed by the transactions until they have been
+//! restored, either via `RestoreFootprintOp` or via the automatic restoration
+//! of `InvokeHostFunctionOp`.
+//!
+//! Unlike the network, which evicts the expired entries incrementally, the
+//! emulator processes all of them as soon as the ledger is advanced past their
+//! live until ledger. This makes the emulation deterministic and doesn't
+//! change the observable behavior, as an expired entry can't be accessed
+//! without being restored no matter whether it has been evicted.
+use std::{collections::BTreeMap, rc::Rc};
+
+use crate::{
+    ledger_info::get_key_durability,
+    storage::{EntryWithLiveUntil, SnapshotSource},
+    tx_set::{
+        SorobanTransaction, TransactionBudgetConfig, TransactionResult, TransactionSetExecutor,
+    },
+    xdr::{ContractDataDurability, LedgerEntry, LedgerKey, ScAddress, ScErrorCode, ScErrorType},
+    HostError, LedgerInfo,
+};
+
+/// Default time between the ledger closes, matching the network target.
+pub const DEFAULT_LEDGER_CLOSE_TIME_SECONDS: u64 = 5;
+
+// Live entries and archived persistent entries. Archived entries are stored
+// with their (expired) live until ledger, so that the executor treats them
+// exactly like the network treats the archived entries.
+#[derive(Clone, Default)]
+struct LedgerState {
+    live: BTreeMap<Rc<LedgerKey>, EntryWithLiveUntil>,
+    archived: BTreeMap<Rc<LedgerKey>, EntryWithLiveUntil>,
+}
+
+impl SnapshotSource for LedgerState {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        Ok(self
+            .live
+            .get(key)
+            .or_else(|| self.archived.get(key))
+            .cloned())
+    }
+
+    fn contract_data_keys(
+        &self,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+    ) -> Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        Ok(Some(
+            self.live
+                .keys()
+                .chain(self.archived.keys())
+                .filter(|key| {
+                    matches!(key.as_ref(), LedgerKey::ContractData(cd)
+                        if cd.contract == *contract && cd.durability == durability)
+                })
+                .cloned()
+                .collect(),
+        ))
+    }
+}
+
+/// State of a ledger entry in the [LedgerEmulator].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LedgerEntryState {
+    /// The entry can be accessed by the transactions.
+    Live(EntryWithLiveUntil),
+    /// The persistent entry has expired and has to be restored before it can
+    /// be accessed.
+    Archived(Rc<LedgerEntry>),
+    /// The entry doesn't exist, which includes the expired temporary entries.
+    Missing,
+}
+
+/// Entries that have expired when the ledger has been advanced.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExpirationResult {
+    /// Expired temporary entries that have been deleted, ordered by key.
+    pub deleted_temporary_entries: Vec<Rc<LedgerKey>>,
+    /// Expired persistent entries that have been archived, ordered by key.
+    pub archived_persistent_entries: Vec<Rc<LedgerKey>>,
+}
+
+/// Result of [LedgerEmulator::close_ledger].
+pub struct LedgerCloseResult {
+    /// Per-transaction results, in the order of the input transactions.
+    pub transaction_results: Vec<TransactionResult>,
+    /// Entries that have expired after the ledger has been closed.
+    pub expiration: ExpirationResult,
+}
+
+/// Emulates the ledger closes on top of a mutable ledger state.
+///
+/// [LedgerEmulator::ledger_info] always describes the ledger that is going to
+/// be closed next, i.e. the ledger the next transactions are applied in.
+pub struct LedgerEmulator {
+    ledger_info: LedgerInfo,
+    ledger_close_time_seconds: u64,
+    budget_config: Option<TransactionBudgetConfig>,
+    enable_diagnostics: bool,
+    state: Rc<LedgerState>,
+}
+
+impl LedgerEmulator {
+    /// Creates an emulator with an empty ledger state.
+    ///
+    /// `budget_config` is used for the transaction budgets in the same
+    /// fashion as in [TransactionSetExecutor::new].
+    pub fn new(
+        ledger_info: LedgerInfo,
+        budget_config: Option<TransactionBudgetConfig>,
+        enable_diagnostics: bool,
+    ) -> Self {
+        Self {
+            ledger_info,
+            ledger_close_time_seconds: DEFAULT_LEDGER_CLOSE_TIME_SECONDS,
+            budget_config,
+            enable_diagnostics,
+            state: Rc::new(LedgerState::default()),
+        }
+    }
+
+    /// Sets the time between the ledger closes, which is used for advancing
+    /// the ledger timestamp.
+    pub fn set_ledger_close_time_seconds(&mut self, seconds: u64) {
+        self.ledger_close_time_seconds = seconds;
+    }
+
+    /// Returns the info of the ledger that is going to be closed next.
+    pub fn ledger_info(&self) -> &LedgerInfo {
+        &self.ledger_info
+    }
+
+    /// Sets the ledger entry, overwriting the existing one.
+    ///
+    /// Contract data and code entries must have the live until ledger, while
+    /// the other entries must not. Entries that have already expired at the
+    /// current ledger are processed immediately, i.e. temporary entries are
+    /// dropped and persistent entries are archived.
+    pub fn set_entry(
+        &mut self,
+        entry: LedgerEntry,
+        live_until: Option<u32>,
+    ) -> Result<(), HostError> {
+        let key = Rc::new(entry.to_key());
+        let durability = get_key_durability(&key);
+        if durability.is_some() != live_until.is_some() {
+            return Err(HostError::from((
+                ScErrorType::Storage,
+                ScErrorCode::InvalidInput,
+            )));
+        }
+        let state = Rc::make_mut(&mut self.state);
+        state.live.remove(&key);
+        state.archived.remove(&key);
+        let is_expired = live_until.is_some_and(|l| l < self.ledger_info.sequence_number);
+        match (durability, is_expired) {
+            (Some(ContractDataDurability::Temporary), true) => (),
+            (Some(ContractDataDurability::Persistent), true) => {
+                state.archived.insert(key, (Rc::new(entry), live_until));
+            }
+            (_, _) => {
+                state.live.insert(key, (Rc::new(entry), live_until));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the current state of the entry with the given key.
+    pub fn entry_state(&self, key: &LedgerKey) -> LedgerEntryState {
+        if let Some(entry) = self.state.live.get(key) {
+            LedgerEntryState::Live(entry.clone())
+        } else if let Some((entry, _)) = self.state.archived.get(key) {
+            LedgerEntryState::Archived(entry.clone())
+        } else {
+            LedgerEntryState::Missing
+        }
+    }
+
+    /// Returns a snapshot of the current ledger state, e.g. for simulating
+    /// the transactions against it.
+    ///
+    /// Archived entries are included in the snapshot with their expired live
+    /// until ledgers, which is how the network exposes them to the
+    /// simulation.
+    pub fn snapshot(&self) -> Rc<dyn SnapshotSource> {
+        self.state.clone()
+    }
+
+    /// Applies `transactions` in order at the current ledger, commits their
+    /// changes and advances to the next ledger.
+    ///
+    /// Transaction failures are reported in the respective
+    /// `TransactionResult` and don't stop the execution of the remaining
+    /// transactions.
+    pub fn close_ledger(
+        &mut self,
+        transactions: &[SorobanTransaction],
+    ) -> Result<LedgerCloseResult, HostError> {
+        let executor = TransactionSetExecutor::new(
+            self.ledger_info.clone(),
+            self.budget_config.clone(),
+            None,
+            self.enable_diagnostics,
+        );
+        let res = executor.execute(self.state.clone(), transactions)?;
+        let state = Rc::make_mut(&mut self.state);
+        for diff in res.state_diff {
+            state.archived.remove(&diff.key);
+            match diff.state_after {
+                Some(entry) => {
+                    state.live.insert(diff.key, entry);
+                }
+                None => {
+                    state.live.remove(&diff.key);
+                }
+            }
+        }
+        Ok(LedgerCloseResult {
+            transaction_results: res.transaction_results,
+            expiration: self.advance_ledgers(1)?,
+        })
+    }
+
+    /// Advances the ledger by `ledgers` ledgers without applying any
+    /// transactions, and processes the entries that have expired.
+    pub fn advance_ledgers(&mut self, ledgers: u32) -> Result<ExpirationResult, HostError> {
+        let invalid_input = || HostError::from((ScErrorType::Context, ScErrorCode::InvalidInput));
+        // Compute the new ledger info first so that the ledger is left
+        // untouched when either of the values overflows.
+        let mut ledger_info = self.ledger_info.clone();
+        ledger_info.sequence_number = ledger_info
+            .sequence_number
+            .checked_add(ledgers)
+            .ok_or_else(invalid_input)?;
+        ledger_info.timestamp = self
+            .ledger_close_time_seconds
+            .checked_mul(ledgers as u64)
+            .and_then(|t| t.checked_add(ledger_info.timestamp))
+            .ok_or_else(invalid_input)?;
+        self.ledger_info = ledger_info;
+        Ok(self.expire_entries())
+    }
+
+    /// Advances the ledger by at least `seconds` seconds, rounding up to the
+    /// whole ledgers.
+    pub fn advance_time(&mut self, seconds: u64) -> Result<ExpirationResult, HostError> {
+        let ledgers = seconds.div_ceil(self.ledger_close_time_seconds.max(1));
+        let ledgers = u32::try_from(ledgers)
+            .map_err(|_| HostError::from((ScErrorType::Context, ScErrorCode::InvalidInput)))?;
+        self.advance_ledgers(ledgers)
+    }
+
+    fn expire_entries(&mut self) -> ExpirationResult {
+        let ledger_seq = self.ledger_info.sequence_number;
+        let expired_keys: Vec<Rc<LedgerKey>> = self
+            .state
+            .live
+            .iter()
+            .filter(|(_, (_, live_until))| live_until.is_some_and(|l| l < ledger_seq))
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut res = ExpirationResult::default();
+        if expired_keys.is_empty() {
+            return res;
+        }
+        let state = Rc::make_mut(&mut self.state);
+        for key in expired_keys {
+            let Some(entry) = state.live.remove(&key) else {
+                continue;
+            };
+            if get_key_durability(&key) == Some(ContractDataDurability::Persistent) {
+                state.archived.insert(key.clone(), entry);
+                res.archived_persistent_entries.push(key);
+            } else {
+                res.deleted_temporary_entries.push(key);
+            }
+        }
+        res
+    }
+}
@@ -42,6 +42,8 @@ pub use host::{
 };
 pub use soroban_env_common::*;
 
+#[cfg(any(test, feature = "recording_mode"))]
+pub use host::call_tree::{CallResources, CallTreeNode};
 #[cfg(any(test, feature = "testutils"))]
 pub use host::invocation_metering::{FeeEstimate, InvocationResources};
 
@@ -50,6 +52,8 @@ pub use ledger_info::LedgerInfo;
 
 pub mod e2e_invoke;
 pub mod fees;
+pub mod ledger_emulator;
+pub mod tx_set;
 
 #[doc(hidden)]
 pub use host::{TraceEvent, TraceHook, TraceRecord, TraceState};
@@ -11,11 +11,16 @@ use std::rc::Rc;
 
 use crate::budget::AsBudget;
 use crate::host::metered_clone::MeteredClone;
+#[cfg(any(test, feature = "recording_mode"))]
+pub use crate::host::storage_access_log::StorageAccess;
 use crate::{
     budget::Budget,
     host::metered_map::MeteredOrdMap,
     ledger_info::get_key_durability,
-    xdr::{ContractDataDurability, LedgerEntry, LedgerKey, ScErrorCode, ScErrorType, ScVal},
+    xdr::{
+        ContractDataDurability, LedgerEntry, LedgerKey, LedgerKeyContractData, ScAddress,
+        ScErrorCode, ScErrorType, ScVal,
+    },
     Env, Error, Host, HostError, Val,
 };
 
@@ -53,6 +58,17 @@ pub enum AccessType {
     /// When in [FootprintMode::Enforcing], indicates that the [LedgerKey] is _allowed_ to be written (and also allowed to be read).
     ReadWrite,
 }
+
+/// The kind of a storage operation recorded in the storage access log.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum StorageAccessType {
+    Get,
+    Has,
+    Put,
+    Del,
+    ExtendTtl,
+}
+
 /// A helper type used by [FootprintMode::Recording] to provide access
 /// to a stable read-snapshot of a ledger.
 /// The snapshot is expected to have access to all the persistent entries,
@@ -62,6 +78,21 @@ pub trait SnapshotSource {
     /// Returns the ledger entry for the key and its live_until ledger if entry
     /// exists, or `None` otherwise.
     fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError>;
+
+    /// Returns the keys of all the `durability` contract data entries of
+    /// `contract` in the snapshot (possibly including the expired entries), or
+    /// `None` if the snapshot doesn't support enumerating the entries.
+    ///
+    /// This is only used for recording the footprint of the contract data
+    /// scans (see `scan_contract_data_keys` host function), which fail in
+    /// [FootprintMode::Recording] when the entries can't be enumerated.
+    fn contract_data_keys(
+        &self,
+        _contract: &ScAddress,
+        _durability: ContractDataDurability,
+    ) -> Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        Ok(None)
+    }
 }
 
 /// Describes the total set of [LedgerKey]s that a given transaction
@@ -232,6 +263,20 @@ impl Storage {
         key: &Rc<LedgerKey>,
         host: &Host,
         key_val: Option<Val>,
+    ) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        self.with_access_log(key, host, StorageAccessType::Get, |storage| {
+            storage.try_get_full_without_access_log(key, host, key_val)
+        })
+    }
+
+    // Like `try_get_full`, but is not recorded in the storage access log.
+    // This is used for the host-internal reads that are not a part of the
+    // contract logic.
+    pub(crate) fn try_get_full_without_access_log(
+        &mut self,
+        key: &Rc<LedgerKey>,
+        host: &Host,
+        key_val: Option<Val>,
     ) -> Result<Option<EntryWithLiveUntil>, HostError> {
         let res = self
             .try_get_full_helper(key, host)
@@ -239,6 +284,26 @@ impl Storage {
         Ok(res)
     }
 
+    // Records the storage operation performed by `f` in the host's storage
+    // access log, if the log is enabled. The nested operations are not
+    // recorded.
+    #[cfg_attr(not(any(test, feature = "recording_mode")), allow(unused_variables))]
+    fn with_access_log<T>(
+        &mut self,
+        key: &Rc<LedgerKey>,
+        host: &Host,
+        access_type: StorageAccessType,
+        f: impl FnOnce(&mut Self) -> Result<T, HostError>,
+    ) -> Result<T, HostError> {
+        #[cfg(any(test, feature = "recording_mode"))]
+        if let Some(pending) = host.begin_storage_access(self, key)? {
+            let res = f(self);
+            host.end_storage_access(self, key, access_type, pending, res.is_ok())?;
+            return res;
+        }
+        f(self)
+    }
+
     pub(crate) fn get(
         &mut self,
         key: &Rc<LedgerKey>,
@@ -347,7 +412,9 @@ impl Storage {
         key_val: Option<Val>,
     ) -> Result<(), HostError> {
         let _span = tracy_span!("storage put");
-        self.put_opt(key, Some((val.clone(), live_until_ledger)), host, key_val)
+        self.with_access_log(key, host, StorageAccessType::Put, |storage| {
+            storage.put_opt(key, Some((val.clone(), live_until_ledger)), host, key_val)
+        })
     }
 
     /// Attempts to delete the [LedgerEntry] associated with a given [LedgerKey]
@@ -366,8 +433,11 @@ impl Storage {
         key_val: Option<Val>,
     ) -> Result<(), HostError> {
         let _span = tracy_span!("storage del");
-        self.put_opt(key, None, host, key_val)
-            .map_err(|e| host.decorate_storage_error(e, key.as_ref(), key_val))
+        self.with_access_log(key, host, StorageAccessType::Del, |storage| {
+            storage
+                .put_opt(key, None, host, key_val)
+                .map_err(|e| host.decorate_storage_error(e, key.as_ref(), key_val))
+        })
     }
 
     /// Attempts to determine the presence of a [LedgerEntry] associated with a
@@ -386,7 +456,9 @@ impl Storage {
         key_val: Option<Val>,
     ) -> Result<bool, HostError> {
         let _span = tracy_span!("storage has");
-        Ok(self.try_get_full(key, host, key_val)?.is_some())
+        self.with_access_log(key, host, StorageAccessType::Has, |storage| {
+            Ok(storage.try_get_full(key, host, key_val)?.is_some())
+        })
     }
 
     /// Extends `key` to live `extend_to` ledgers from now (not counting the
@@ -412,6 +484,22 @@ impl Storage {
         key_val: Option<Val>,
     ) -> Result<(), HostError> {
         let _span = tracy_span!("extend key");
+        self.with_access_log(
+            &key.clone(),
+            host,
+            StorageAccessType::ExtendTtl,
+            |storage| storage.extend_ttl_helper(host, key, threshold, extend_to, key_val),
+        )
+    }
+
+    fn extend_ttl_helper(
+        &mut self,
+        host: &Host,
+        key: Rc<LedgerKey>,
+        threshold: u32,
+        extend_to: u32,
+        key_val: Option<Val>,
+    ) -> Result<(), HostError> {
         Self::check_supported_ledger_key_type(&key)?;
 
         if threshold > extend_to {
@@ -532,6 +620,58 @@ impl Storage {
         }
     }
 
+    #[cfg(any(test, feature = "recording_mode"))]
+    /// In [FootprintMode::Recording] mode, records the live `durability`
+    /// contract data entries of `contract` in the underlying [SnapshotSource]
+    /// for which `is_scanned` returns `true` in the [Footprint] as
+    /// [AccessType::ReadOnly] (unless already recorded), so that the scan of
+    /// the storage map observes the same entries as the scan in
+    /// [FootprintMode::Enforcing] mode with the recorded footprint.
+    ///
+    /// Fails if the [SnapshotSource] doesn't support enumerating the contract
+    /// data entries. Does nothing in [FootprintMode::Enforcing] mode.
+    pub(crate) fn record_contract_data_scan(
+        &mut self,
+        host: &Host,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+        mut is_scanned: impl FnMut(&LedgerKeyContractData) -> Result<bool, HostError>,
+    ) -> Result<(), HostError> {
+        let FootprintMode::Recording(snapshot) = &self.mode else {
+            return Ok(());
+        };
+        let keys = snapshot
+            .contract_data_keys(contract, durability)?
+            .ok_or_else(|| {
+                host.err(
+                    ScErrorType::Storage,
+                    ScErrorCode::InternalError,
+                    "snapshot source doesn't support enumerating contract data entries",
+                    &[],
+                )
+            })?;
+        for key in keys {
+            let LedgerKey::ContractData(data_key) = key.as_ref() else {
+                continue;
+            };
+            if !is_scanned(data_key)? {
+                continue;
+            }
+            // The entries that have already been loaded are observed by the
+            // scan as is, and the entries that are not live wouldn't be
+            // observed even if they were in the footprint.
+            if self
+                .map
+                .contains_key::<Rc<LedgerKey>>(&key, host.budget_ref())?
+                || !self.is_key_live_in_snapshot(host, &key)?
+            {
+                continue;
+            }
+            self.try_get_full_without_access_log(&key, host, None)?;
+        }
+        Ok(())
+    }
+
     // Test-only helper for getting the value directly from the storage map,
     // without the footprint management and autorestoration.
     #[cfg(any(test, feature = "testutils"))]
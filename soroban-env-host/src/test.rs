@@ -6,6 +6,7 @@ mod basic;
 mod bls12_381;
 mod budget_metering;
 mod bytes;
+mod call_tree;
 mod complex;
 mod crypto;
 mod depth_limit;
@@ -21,6 +22,7 @@ mod hostile_opt;
 mod invocation;
 mod invoker_auth;
 mod ledger;
+mod ledger_emulator;
 mod lifecycle;
 mod lifetime_extension;
 mod linear_memory;
@@ -36,4 +38,5 @@ mod storage;
 mod str;
 mod symbol;
 mod tuple;
+mod tx_set;
 mod vec;
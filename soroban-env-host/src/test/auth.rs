@@ -2,26 +2,29 @@ use ed25519_dalek::SigningKey;
 use rand::Rng;
 use soroban_builtin_sdk_macros::contracttype;
 use soroban_env_common::xdr::{
-    AccountId, ContractDataDurability, HashIdPreimage, HashIdPreimageSorobanAuthorization,
-    InvokeContractArgs, PublicKey, ScAddress, ScBytes, ScErrorCode, ScErrorType, ScNonceKey,
-    ScSymbol, ScVal, SorobanAddressCredentials, SorobanAuthorizationEntry,
-    SorobanAuthorizedFunction, SorobanAuthorizedInvocation, SorobanCredentials, Uint256, VecM,
+    AccountId, ContractCostType, ContractDataDurability, HashIdPreimage,
+    HashIdPreimageSorobanAuthorization, InvokeContractArgs, PublicKey, ScAddress, ScBytes,
+    ScErrorCode, ScErrorType, ScNonceKey, ScSymbol, ScVal, SorobanAddressCredentials,
+    SorobanAuthorizationEntry, SorobanAuthorizedFunction, SorobanAuthorizedInvocation,
+    SorobanCredentials, Uint256, VecM,
 };
 use soroban_test_wasms::{
     AUTH_TEST_CONTRACT, CONDITIONAL_ACCOUNT_TEST_CONTRACT, DELEGATED_ACCOUNT_TEST_CONTRACT,
 };
 
-use crate::auth::RecordedAuthPayload;
+use crate::auth::{account_signature_stub, RecordedAuthPayload};
 use crate::builtin_contracts::base_types::Address;
 use crate::builtin_contracts::testutils::{
     create_account, generate_signing_key, sign_payload_for_account, signing_key_to_account_id,
 };
-use crate::{Host, LedgerInfo};
+use crate::{Host, HostError, LedgerInfo};
 use soroban_env_common::{AddressObject, Env, Symbol, SymbolStr, TryFromVal, TryIntoVal};
 
 use crate::builtin_contracts::base_types::Vec as HostVec;
 
 use pretty_assertions::assert_eq;
+use std::collections::BTreeMap;
+use std::rc::Rc;
 
 #[derive(Clone)]
 #[contracttype]
@@ -3207,3 +3210,229 @@ fn test_rollback_with_conditional_custom_account_auth() {
     // Third call still can't succeed and won't consume nonce.
     assert_eq!(test.read_nonce_live_until(&account, 666), None);
 }
+
+#[test]
+fn test_recording_auth_with_account_signature_stubs() {
+    let signers: Vec<SigningKey> = (1..=3).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+    let account_id = signing_key_to_account_id(&signers[0]);
+    let run_recording = |signature_stubs: Option<BTreeMap<ScAddress, ScVal>>| {
+        let test = AuthTest::setup(0, 1);
+        create_account(
+            &test.host,
+            &account_id,
+            signers.iter().map(|k| (k, 1)).collect(),
+            100_000_000,
+            2,
+            [1, 0, 0, 0],
+            None,
+            None,
+            0,
+        );
+        let address = test
+            .host
+            .add_host_object(ScAddress::Account(account_id.clone()))
+            .unwrap();
+        let tree = test.convert_setup_tree(&SetupNode::new(&test.contracts[0], vec![true], vec![]));
+        test.host.switch_to_recording_auth(true).unwrap();
+        if let Some(signature_stubs) = signature_stubs {
+            test.host
+                .set_signature_stub_provider(Rc::new(signature_stubs))
+                .unwrap();
+        }
+        test.host
+            .call(
+                test.contracts[0].clone().into(),
+                Symbol::try_from_small_str("tree_fn").unwrap(),
+                test_vec![&test.host, test_vec![&test.host, address], tree].into(),
+            )
+            .unwrap();
+        let budget = test.host.budget_cloned();
+        let verifications = budget
+            .get_tracker(ContractCostType::VerifyEd25519Sig)
+            .unwrap()
+            .iterations;
+        let hashes = budget
+            .get_tracker(ContractCostType::ComputeSha256Hash)
+            .unwrap()
+            .iterations;
+        let payloads: Vec<_> = test
+            .host
+            .get_recorded_auth_payloads()
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.address, p.nonce, p.invocation))
+            .collect();
+        (payloads, verifications, hashes)
+    };
+
+    // By default only a single signature verification is emulated.
+    let (payloads, verifications, hashes) = run_recording(None);
+    assert_eq!(verifications, 1);
+
+    let signature_stub = account_signature_stub(
+        &signers
+            .iter()
+            .map(|k| k.verifying_key().to_bytes())
+            .collect::<Vec<_>>(),
+    )
+    .unwrap();
+    let (stub_payloads, stub_verifications, stub_hashes) = run_recording(Some(
+        [(ScAddress::Account(account_id.clone()), signature_stub)].into(),
+    ));
+    assert_eq!(stub_verifications, 3);
+    // The whole stub vector is authenticated at once, so the signature
+    // payload is still computed just once.
+    assert_eq!(stub_hashes, hashes);
+    assert_eq!(stub_payloads, payloads);
+
+    // Stubs for the addresses that don't require authorization are ignored.
+    let (_, verifications, _) = run_recording(Some(
+        [(
+            ScAddress::Account(signing_key_to_account_id(&signers[1])),
+            account_signature_stub(&[]).unwrap(),
+        )]
+        .into(),
+    ));
+    assert_eq!(verifications, 1);
+}
+
+#[test]
+fn test_recording_auth_emulation_after_failed_invocation() {
+    let test = AuthTest::setup(0, 1);
+    let account_obj = test
+        .host
+        .register_test_contract_wasm(CONDITIONAL_ACCOUNT_TEST_CONTRACT);
+    let account = Address::try_from_val(&test.host, &account_obj).unwrap();
+    let ScAddress::Contract(contract_id) = test.contracts[0].to_sc_address().unwrap() else {
+        unreachable!();
+    };
+    test.host.switch_to_recording_auth(true).unwrap();
+    test.host
+        .set_signature_stub_provider(Rc::new(BTreeMap::from([(
+            account.to_sc_address().unwrap(),
+            ScVal::Void,
+        )])))
+        .unwrap();
+    test.host.enable_storage_access_log().unwrap();
+
+    // Allow the account authentication and then fail the invocation.
+    let res = test.host.with_test_contract_frame(
+        contract_id,
+        Symbol::try_from_small_str("test").unwrap(),
+        || {
+            test.host.call(
+                account_obj,
+                Symbol::try_from_small_str("allow").unwrap(),
+                test_vec![&test.host].into(),
+            )?;
+            test.host.require_auth(account_obj)?;
+            Err(test.host.err(
+                ScErrorType::Context,
+                ScErrorCode::InvalidAction,
+                "invocation failure",
+                &[],
+            ))
+        },
+    );
+    assert!(res.is_err());
+
+    // `__check_auth` must be emulated against the rolled back storage, i.e.
+    // it must not observe the permission stored by the failed invocation.
+    let check_auth_accesses: Vec<_> = test
+        .host
+        .take_storage_access_log()
+        .unwrap()
+        .into_iter()
+        .filter(|access| access.function_name.as_deref() == Some("__check_auth"))
+        .collect();
+    assert!(!check_auth_accesses.is_empty());
+    for access in check_auth_accesses {
+        assert_eq!(access.entry_size_before, None);
+    }
+}
+
+#[test]
+fn test_recording_auth_emulation_failure_pops_context() {
+    let signers: Vec<SigningKey> = (1..=3).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+    let account_id = signing_key_to_account_id(&signers[0]);
+    let signature_stub = account_signature_stub(
+        &signers
+            .iter()
+            .map(|k| k.verifying_key().to_bytes())
+            .collect::<Vec<_>>(),
+    )
+    .unwrap();
+    let run_recording = |cpu_limit: u64| {
+        let test = AuthTest::setup(0, 1);
+        create_account(
+            &test.host,
+            &account_id,
+            signers.iter().map(|k| (k, 1)).collect(),
+            100_000_000,
+            2,
+            [1, 0, 0, 0],
+            None,
+            None,
+            0,
+        );
+        let address = test
+            .host
+            .add_host_object(ScAddress::Account(account_id.clone()))
+            .unwrap();
+        let tree = test.convert_setup_tree(&SetupNode::new(&test.contracts[0], vec![true], vec![]));
+        test.host.switch_to_recording_auth(true).unwrap();
+        test.host
+            .set_signature_stub_provider(Rc::new(BTreeMap::from([(
+                ScAddress::Account(account_id.clone()),
+                signature_stub.clone(),
+            )])))
+            .unwrap();
+        test.host
+            .budget_cloned()
+            .reset_limits(cpu_limit, u64::MAX)
+            .unwrap();
+        let res = test.host.call(
+            test.contracts[0].clone().into(),
+            Symbol::try_from_small_str("tree_fn").unwrap(),
+            test_vec![&test.host, test_vec![&test.host, address], tree].into(),
+        );
+        let budget = test.host.budget_cloned();
+        let verification_cpu = budget
+            .get_tracker(ContractCostType::VerifyEd25519Sig)
+            .unwrap()
+            .cpu;
+        let cpu_consumed = budget.get_cpu_insns_consumed().unwrap();
+        (
+            res,
+            cpu_consumed,
+            verification_cpu,
+            test.host.has_frame().unwrap(),
+        )
+    };
+
+    let (res, cpu_consumed, verification_cpu, has_frame) = run_recording(u64::MAX);
+    assert!(res.is_ok());
+    assert!(!has_frame);
+
+    // Run out of budget while verifying the stub signatures, i.e. after the
+    // contract call itself has succeeded. The last context must still be
+    // popped.
+    let (res, _, _, has_frame) = run_recording(cpu_consumed - verification_cpu / 3);
+    assert!(HostError::result_matches_err(
+        res,
+        (ScErrorType::Budget, ScErrorCode::ExceededLimit)
+    ));
+    assert!(!has_frame);
+}
+
+#[test]
+fn test_signature_stub_provider_requires_recording_mode() {
+    let test = AuthTest::setup(0, 0);
+    test.host.set_authorization_entries(vec![]).unwrap();
+    let err = test
+        .host
+        .set_signature_stub_provider(Rc::new(BTreeMap::<ScAddress, ScVal>::new()))
+        .err()
+        .unwrap();
+    assert!(err.error.is_code(ScErrorCode::InvalidAction));
+}
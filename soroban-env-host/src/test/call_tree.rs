@@ -0,0 +1,145 @@
+use crate::{budget::AsBudget, host::call_tree::CallTreeNode, Host, HostError, Symbol, TryFromVal};
+use soroban_env_common::{Env, TryIntoVal, VecObject};
+use soroban_test_wasms::{ADD_I32, CONTRACT_STORAGE, INVOKE_CONTRACT};
+
+fn check_inclusive_resources(node: &CallTreeNode) {
+    let mut expected = node.exclusive.clone();
+    for child in &node.children {
+        check_inclusive_resources(child);
+        expected.instructions += child.inclusive.instructions;
+        expected.mem_bytes += child.inclusive.mem_bytes;
+        expected.read_entries += child.inclusive.read_entries;
+        expected.write_entries += child.inclusive.write_entries;
+        expected.contract_events_size_bytes += child.inclusive.contract_events_size_bytes;
+        expected.persistent_rent_ledger_bytes += child.inclusive.persistent_rent_ledger_bytes;
+        expected.persistent_entry_rent_bumps += child.inclusive.persistent_entry_rent_bumps;
+        expected.temporary_rent_ledger_bytes += child.inclusive.temporary_rent_ledger_bytes;
+        expected.temporary_entry_rent_bumps += child.inclusive.temporary_entry_rent_bumps;
+    }
+    assert_eq!(node.inclusive, expected);
+}
+
+#[test]
+fn test_call_tree_follows_nested_calls() -> Result<(), HostError> {
+    let host = Host::test_host_with_recording_footprint();
+    let caller = host.register_test_contract_wasm(INVOKE_CONTRACT);
+    let callee = host.register_test_contract_wasm(ADD_I32);
+    let fn_name = Symbol::try_from_small_str("add_with")?;
+    let args = host.test_vec_obj::<i32>(&[5, 6])?;
+    let args = host.vec_push_back(args, callee.to_val())?;
+
+    // Nothing is recorded until the metering is enabled.
+    host.call(caller, fn_name, args)?;
+    assert!(host.take_call_tree()?.is_empty());
+
+    host.enable_call_tree_metering()?;
+    host.as_budget().reset_default()?;
+    host.call(caller, fn_name, args)?;
+    let consumed_insns = host.as_budget().get_cpu_insns_consumed()?;
+    let roots = host.take_call_tree()?;
+    assert_eq!(roots.len(), 1);
+    let root = &roots[0];
+    check_inclusive_resources(root);
+    assert_eq!(
+        root.contract_id,
+        Some(host.contract_id_from_address(caller)?)
+    );
+    assert_eq!(root.function_name, "add_with");
+    assert!(!root.failed);
+    assert!(root.inclusive.instructions <= consumed_insns);
+    assert!(root.exclusive.instructions > 0);
+    assert!(root.exclusive.contract_events_size_bytes > 0);
+
+    assert_eq!(root.children.len(), 1);
+    let child = &root.children[0];
+    assert_eq!(
+        child.contract_id,
+        Some(host.contract_id_from_address(callee)?)
+    );
+    assert_eq!(child.function_name, "add");
+    assert!(!child.failed);
+    assert!(child.children.is_empty());
+    assert!(child.exclusive.instructions > 0);
+    assert!(child.exclusive.contract_events_size_bytes > 0);
+
+    // The tree is only returned once.
+    assert!(host.take_call_tree()?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_call_tree_marks_failed_calls() -> Result<(), HostError> {
+    let host = Host::test_host_with_recording_footprint();
+    let caller = host.register_test_contract_wasm(INVOKE_CONTRACT);
+    let callee = host.register_test_contract_wasm(ADD_I32);
+    host.enable_call_tree_metering()?;
+    // `add` overflows and traps, which `add_with_try` ignores.
+    let args = host.test_vec_obj::<i32>(&[i32::MAX, 1])?;
+    let args = host.vec_push_back(args, callee.to_val())?;
+    host.call(caller, Symbol::try_from_val(&host, &"add_with_try")?, args)?;
+
+    let roots = host.take_call_tree()?;
+    assert_eq!(roots.len(), 1);
+    let root = &roots[0];
+    check_inclusive_resources(root);
+    assert!(!root.failed);
+    assert_eq!(root.children.len(), 1);
+    let child = &root.children[0];
+    assert_eq!(child.function_name, "add");
+    assert!(child.failed);
+    assert!(child.exclusive.instructions > 0);
+    // The event of the failed call is rolled back.
+    assert_eq!(child.exclusive.contract_events_size_bytes, 0);
+    assert_eq!(
+        root.inclusive.contract_events_size_bytes,
+        root.exclusive.contract_events_size_bytes
+    );
+    Ok(())
+}
+
+#[test]
+fn test_call_tree_attributes_storage_accesses() -> Result<(), HostError> {
+    let host = Host::test_host_with_recording_footprint();
+    let contract = host.register_test_contract_wasm(CONTRACT_STORAGE);
+    host.enable_call_tree_metering()?;
+    let key = Symbol::try_from_small_str("key")?;
+    let call = |fn_name: &str, args: VecObject| -> Result<CallTreeNode, HostError> {
+        let fn_name = Symbol::try_from_val(&host, &fn_name)?;
+        host.call(contract, fn_name, args)?;
+        let mut roots = host.take_call_tree()?;
+        assert_eq!(roots.len(), 1);
+        let root = roots.pop().unwrap();
+        check_inclusive_resources(&root);
+        Ok(root)
+    };
+
+    let put = call("put_persistent", test_vec![&host, key, 1_u64].into())?;
+    assert!(put.exclusive.read_entries > 0);
+    assert_eq!(put.exclusive.write_entries, 1);
+    // The new entry pays rent.
+    assert_eq!(put.exclusive.persistent_entry_rent_bumps, 1);
+    assert!(put.exclusive.persistent_rent_ledger_bytes > 0);
+    assert_eq!(put.exclusive.temporary_entry_rent_bumps, 0);
+
+    // All the entries are already in the footprint and the entry doesn't
+    // change.
+    let get = call("get_persistent", test_vec![&host, key].into())?;
+    assert_eq!(get.exclusive.read_entries, 0);
+    assert_eq!(get.exclusive.write_entries, 0);
+    assert_eq!(get.exclusive.persistent_entry_rent_bumps, 0);
+
+    let put_temp = call("put_temporary", test_vec![&host, key, 1_u64].into())?;
+    assert_eq!(put_temp.exclusive.read_entries, 1);
+    assert_eq!(put_temp.exclusive.write_entries, 1);
+    assert_eq!(put_temp.exclusive.temporary_entry_rent_bumps, 1);
+    assert!(put_temp.exclusive.temporary_rent_ledger_bytes > 0);
+    assert_eq!(put_temp.exclusive.persistent_entry_rent_bumps, 0);
+
+    let extend = call(
+        "extend_persistent",
+        test_vec![&host, key, 10_000_u32, 10_000_u32].into(),
+    )?;
+    assert_eq!(extend.exclusive.persistent_entry_rent_bumps, 1);
+    assert!(extend.exclusive.persistent_rent_ledger_bytes > 0);
+    Ok(())
+}
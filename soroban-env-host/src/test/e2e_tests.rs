@@ -10,25 +10,30 @@ use crate::{
     budget::{AsBudget, Budget},
     builtin_contracts::testutils::TestSigner,
     e2e_invoke::{
-        entry_size_for_rent, invoke_host_function, invoke_host_function_in_recording_mode,
-        ledger_entry_to_ledger_key, LedgerEntryChange, LedgerEntryLiveUntilChange,
-        RecordingInvocationAuthMode,
+        build_transaction_meta, entry_size_for_rent, find_unchanged_read_write_entries,
+        invoke_host_function, invoke_host_function_in_recording_mode,
+        invoke_host_function_in_recording_mode_with_options, invoke_host_function_typed,
+        invoke_host_function_with_host, ledger_entry_to_ledger_key, ExecutionReceipt,
+        LedgerEntryChange, LedgerEntryLiveUntilChange, ReceiptComponent,
+        RecordingInvocationAuthMode, RecordingInvocationOptions, TransactionMetaFeeConfig,
     },
     e2e_testutils::{
         auth_contract_invocation, create_contract_auth, default_ledger_info, get_account_id,
         get_contract_id_preimage, get_wasm_hash, get_wasm_key, ledger_entry, wasm_entry,
         AuthContractInvocationNode, CreateContractData,
     },
+    fees::{FeeConfiguration, RentFeeConfiguration, TransactionResources},
     testutils::MockSnapshotSource,
     xdr::{
-        AccountId, ContractCodeEntryExt, ContractDataDurability, ContractDataEntry, ContractEvent,
-        ContractExecutable, ContractId, ContractIdPreimage, ContractIdPreimageFromAddress,
-        CreateContractArgs, DiagnosticEvent, ExtensionPoint, Hash, HashIdPreimage,
-        HashIdPreimageSorobanAuthorization, HostFunction, InvokeContractArgs, LedgerEntry,
-        LedgerEntryData, LedgerEntryType, LedgerFootprint, LedgerKey, LedgerKeyContractCode,
-        LedgerKeyContractData, Limits, ReadXdr, ScAddress, ScContractInstance, ScErrorCode,
-        ScErrorType, ScMap, ScNonceKey, ScVal, ScVec, SorobanAuthorizationEntry,
-        SorobanCredentials, SorobanResources, TtlEntry, Uint256, WriteXdr,
+        self, AccountId, ContractCodeEntryExt, ContractCostType, ContractDataDurability,
+        ContractDataEntry, ContractEvent, ContractExecutable, ContractId, ContractIdPreimage,
+        ContractIdPreimageFromAddress, CreateContractArgs, DiagnosticEvent, ExtensionPoint, Hash,
+        HashIdPreimage, HashIdPreimageSorobanAuthorization, HostFunction, InvokeContractArgs,
+        LedgerEntry, LedgerEntryData, LedgerEntryType, LedgerFootprint, LedgerKey,
+        LedgerKeyContractCode, LedgerKeyContractData, Limits, ReadXdr, ScAddress,
+        ScContractInstance, ScErrorCode, ScErrorType, ScMap, ScNonceKey, ScVal, ScVec,
+        SorobanAuthorizationEntry, SorobanCredentials, SorobanResources, SorobanTransactionMetaExt,
+        TransactionMeta, TtlEntry, Uint256, WriteXdr,
     },
     Host, HostError, LedgerInfo,
 };
@@ -44,7 +49,7 @@ use soroban_test_wasms::{
     NO_ARGUMENT_CONSTRUCTOR_TEST_CONTRACT_P22, SIMPLE_ACCOUNT_CONTRACT, SUM_I32,
     UPDATEABLE_CONTRACT,
 };
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::rc::Rc;
 
 // It's tricky to get exactly the same instruction consumption
@@ -211,6 +216,7 @@ struct InvokeHostFunctionHelperResult {
     contract_events: Vec<ContractEvent>,
     diagnostic_events: Vec<DiagnosticEvent>,
     budget: Budget,
+    receipt: ExecutionReceipt,
 }
 
 struct InvokeHostFunctionRecordingHelperResult {
@@ -259,6 +265,7 @@ impl AsBudget for E2eTestCompilationContext {
 }
 impl crate::CompilationContext for E2eTestCompilationContext {}
 
+#[allow(clippy::too_many_arguments)]
 fn invoke_host_function_helper_with_restored_entries(
     enable_diagnostics: bool,
     host_fn: &HostFunction,
@@ -269,6 +276,38 @@ fn invoke_host_function_helper_with_restored_entries(
     ledger_entries_with_ttl: Vec<(LedgerEntry, Option<u32>)>,
     prng_seed: &[u8; 32],
     restored_entry_ids: &[u32],
+) -> Result<InvokeHostFunctionHelperResult, HostError> {
+    invoke_host_function_helper_impl(
+        enable_diagnostics,
+        host_fn,
+        resources,
+        source_account,
+        auth_entries,
+        ledger_info,
+        ledger_entries_with_ttl,
+        prng_seed,
+        restored_entry_ids,
+        true,
+        None,
+    )
+}
+
+// Invokes the host function in a fresh host, or in `reusable_host` when it's
+// provided. When `with_module_cache` is not set, no module cache is passed to
+// the invocation, so the host parses the contracts on its own.
+#[allow(clippy::too_many_arguments)]
+fn invoke_host_function_helper_impl(
+    enable_diagnostics: bool,
+    host_fn: &HostFunction,
+    resources: &SorobanResources,
+    source_account: &AccountId,
+    auth_entries: Vec<SorobanAuthorizationEntry>,
+    ledger_info: &LedgerInfo,
+    ledger_entries_with_ttl: Vec<(LedgerEntry, Option<u32>)>,
+    prng_seed: &[u8; 32],
+    restored_entry_ids: &[u32],
+    with_module_cache: bool,
+    reusable_host: Option<&mut Host>,
 ) -> Result<InvokeHostFunctionHelperResult, HostError> {
     let limits = Limits::none();
     let encoded_host_fn = host_fn.to_xdr(limits.clone()).unwrap();
@@ -315,30 +354,57 @@ fn invoke_host_function_helper_with_restored_entries(
             restored_contracts.insert(code.hash.clone());
         }
     }
-    let module_cache =
-        build_module_cache_for_entries(ledger_info, ledger_entries_with_ttl, &restored_contracts)?;
+    let module_cache = if with_module_cache {
+        Some(build_module_cache_for_entries(
+            ledger_info,
+            ledger_entries_with_ttl,
+            &restored_contracts,
+        )?)
+    } else {
+        None
+    };
 
     let budget = Budget::default();
     budget
         .reset_cpu_limit(resources.instructions as u64)
         .unwrap();
     let mut diagnostic_events = Vec::<DiagnosticEvent>::new();
-    let res = invoke_host_function(
-        &budget,
-        enable_diagnostics,
-        encoded_host_fn,
-        encoded_resources,
-        restored_entry_ids,
-        encoded_source_account,
-        encoded_auth_entries.into_iter(),
-        ledger_info.clone(),
-        encoded_ledger_entries.into_iter(),
-        encoded_ttl_entries.into_iter(),
-        prng_seed.to_vec(),
-        &mut diagnostic_events,
-        None,
-        Some(module_cache),
-    )?;
+    let res = match reusable_host {
+        Some(host) => invoke_host_function_with_host(
+            host,
+            &budget,
+            enable_diagnostics,
+            encoded_host_fn,
+            encoded_resources,
+            restored_entry_ids,
+            encoded_source_account,
+            encoded_auth_entries.into_iter(),
+            ledger_info.clone(),
+            encoded_ledger_entries.into_iter(),
+            encoded_ttl_entries.into_iter(),
+            prng_seed.to_vec(),
+            &mut diagnostic_events,
+            None,
+            module_cache,
+        )?,
+        None => invoke_host_function(
+            &budget,
+            enable_diagnostics,
+            encoded_host_fn,
+            encoded_resources,
+            restored_entry_ids,
+            encoded_source_account,
+            encoded_auth_entries.into_iter(),
+            ledger_info.clone(),
+            encoded_ledger_entries.into_iter(),
+            encoded_ttl_entries.into_iter(),
+            prng_seed.to_vec(),
+            &mut diagnostic_events,
+            None,
+            module_cache,
+        )?,
+    };
+    let receipt = ExecutionReceipt::from_invoke_result(&budget, prng_seed, &res)?;
     Ok(InvokeHostFunctionHelperResult {
         invoke_result: res
             .encoded_invoke_result
@@ -351,6 +417,7 @@ fn invoke_host_function_helper_with_restored_entries(
             .collect(),
         diagnostic_events,
         budget,
+        receipt,
     })
 }
 
@@ -377,6 +444,63 @@ fn invoke_host_function_helper(
     )
 }
 
+#[allow(clippy::too_many_arguments)]
+fn invoke_host_function_typed_helper(
+    enable_diagnostics: bool,
+    host_fn: &HostFunction,
+    resources: &SorobanResources,
+    source_account: &AccountId,
+    auth_entries: Vec<SorobanAuthorizationEntry>,
+    ledger_info: &LedgerInfo,
+    ledger_entries_with_ttl: Vec<(LedgerEntry, Option<u32>)>,
+    prng_seed: &[u8; 32],
+) -> Result<InvokeHostFunctionHelperResult, HostError> {
+    let module_cache = build_module_cache_for_entries(
+        ledger_info,
+        ledger_entries_with_ttl.clone(),
+        &HashSet::new(),
+    )?;
+    let budget = Budget::default();
+    budget
+        .reset_cpu_limit(resources.instructions as u64)
+        .unwrap();
+    let mut diagnostic_events = Vec::<DiagnosticEvent>::new();
+    let res = invoke_host_function_typed(
+        &budget,
+        enable_diagnostics,
+        host_fn.clone(),
+        resources.clone(),
+        &[],
+        source_account.clone(),
+        auth_entries,
+        ledger_info.clone(),
+        ledger_entries_with_ttl,
+        *prng_seed,
+        &mut diagnostic_events,
+        None,
+        Some(module_cache),
+    )?;
+    let receipt = ExecutionReceipt::from_typed_invoke_result(&budget, prng_seed, &res)?;
+    Ok(InvokeHostFunctionHelperResult {
+        invoke_result: res.invoke_result,
+        ledger_changes: res
+            .ledger_changes
+            .into_iter()
+            .map(|c| LedgerEntryChangeHelper {
+                read_only: c.read_only,
+                key: c.key,
+                old_entry_size_bytes_for_rent: c.old_entry_size_bytes_for_rent,
+                new_value: c.new_value,
+                ttl_change: c.ttl_change,
+            })
+            .collect(),
+        contract_events: res.contract_events,
+        diagnostic_events,
+        budget,
+        receipt,
+    })
+}
+
 fn build_module_cache_for_entries(
     ledger_info: &LedgerInfo,
     ledger_entries_with_ttl: Vec<(LedgerEntry, Option<u32>)>,
@@ -674,6 +798,90 @@ fn contract_data_entry(
     }))
 }
 
+// `CONTRACT_STORAGE` contract with its `put_persistent` invocation, shared
+// by the tests that compare the results of different invocation paths on the
+// same inputs.
+struct StorageContractFixture {
+    cd: CreateContractData,
+    ledger_info: LedgerInfo,
+    key: ScVal,
+    data_key: LedgerKey,
+}
+
+impl StorageContractFixture {
+    fn new() -> Self {
+        let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+        let key = symbol_sc_val("key");
+        let data_key = contract_data_key(
+            &cd.contract_address,
+            &key,
+            ContractDataDurability::Persistent,
+        );
+        Self {
+            cd,
+            ledger_info: default_ledger_info(),
+            key,
+            data_key,
+        }
+    }
+
+    fn wasm_entry_with_ttl(&self) -> (LedgerEntry, Option<u32>) {
+        (
+            self.cd.wasm_entry.clone(),
+            Some(self.ledger_info.sequence_number + 100),
+        )
+    }
+
+    // Ledger entries of the deployed contract.
+    fn contract_entries(&self) -> Vec<(LedgerEntry, Option<u32>)> {
+        vec![
+            self.wasm_entry_with_ttl(),
+            (
+                self.cd.contract_entry.clone(),
+                Some(self.ledger_info.sequence_number + 1000),
+            ),
+        ]
+    }
+
+    // Host function, resources, auth entries and ledger entries for creating
+    // the contract.
+    fn create_contract_inputs(
+        &self,
+    ) -> (
+        HostFunction,
+        SorobanResources,
+        Vec<SorobanAuthorizationEntry>,
+        Vec<(LedgerEntry, Option<u32>)>,
+    ) {
+        (
+            self.cd.host_fn.clone(),
+            resources(
+                10_000_000,
+                vec![self.cd.wasm_key.clone()],
+                vec![self.cd.contract_key.clone()],
+            ),
+            vec![self.cd.auth_entry.clone()],
+            vec![self.wasm_entry_with_ttl()],
+        )
+    }
+
+    fn put_host_fn(&self, val: u64) -> HostFunction {
+        invoke_contract_host_fn(
+            &self.cd.contract_address,
+            "put_persistent",
+            vec![self.key.clone(), u64_sc_val(val)],
+        )
+    }
+
+    fn put_resources(&self, instructions: u32) -> SorobanResources {
+        resources(
+            instructions,
+            vec![self.cd.contract_key.clone(), self.cd.wasm_key.clone()],
+            vec![self.data_key.clone()],
+        )
+    }
+}
+
 #[test]
 fn test_run_out_of_budget_before_calling_host() {
     let res = invoke_host_function_helper(
@@ -1188,6 +1396,405 @@ fn test_create_contract_success() {
     assert!(res.budget.get_mem_bytes_consumed().unwrap() > 0);
 }
 
+#[test]
+fn test_typed_invoke_host_function_matches_encoded_metering() {
+    let f = StorageContractFixture::new();
+    let mut put_entries = f.contract_entries();
+    put_entries.push((
+        contract_data_entry(
+            &f.cd.contract_address,
+            &f.key,
+            &u64_sc_val(1),
+            ContractDataDurability::Persistent,
+        ),
+        Some(f.ledger_info.sequence_number + 10),
+    ));
+    let cases = vec![
+        // Contract creation with an authorization entry.
+        f.create_contract_inputs(),
+        // Contract call that modifies an existing entry.
+        (
+            f.put_host_fn(123),
+            f.put_resources(10_000_000),
+            vec![],
+            put_entries.clone(),
+        ),
+        // Contract call that runs out of budget.
+        (
+            f.put_host_fn(123),
+            f.put_resources(900_000),
+            vec![],
+            put_entries,
+        ),
+    ];
+    for (host_fn, resources, auth_entries, ledger_entries) in cases {
+        let encoded_res = invoke_host_function_helper(
+            true,
+            &host_fn,
+            &resources,
+            &f.cd.deployer,
+            auth_entries.clone(),
+            &f.ledger_info,
+            ledger_entries.clone(),
+            &prng_seed(),
+        )
+        .unwrap();
+        let typed_res = invoke_host_function_typed_helper(
+            true,
+            &host_fn,
+            &resources,
+            &f.cd.deployer,
+            auth_entries,
+            &f.ledger_info,
+            ledger_entries,
+            &prng_seed(),
+        )
+        .unwrap();
+        assert_eq!(encoded_res.invoke_result, typed_res.invoke_result);
+        assert_eq!(encoded_res.ledger_changes, typed_res.ledger_changes);
+        assert_eq!(encoded_res.contract_events, typed_res.contract_events);
+        assert_eq!(encoded_res.diagnostic_events, typed_res.diagnostic_events);
+        for ty in ContractCostType::variants() {
+            assert_eq!(
+                encoded_res.budget.get_tracker(ty).unwrap(),
+                typed_res.budget.get_tracker(ty).unwrap(),
+                "{ty:?}"
+            );
+        }
+        assert_eq!(
+            encoded_res.budget.get_cpu_insns_consumed().unwrap(),
+            typed_res.budget.get_cpu_insns_consumed().unwrap()
+        );
+        assert_eq!(
+            encoded_res.budget.get_mem_bytes_consumed().unwrap(),
+            typed_res.budget.get_mem_bytes_consumed().unwrap()
+        );
+        assert_eq!(encoded_res.receipt, typed_res.receipt);
+    }
+}
+
+#[test]
+fn test_reused_host_matches_fresh_host() {
+    let f = StorageContractFixture::new();
+    let cases = [
+        // Wasm upload.
+        (
+            upload_wasm_host_fn(CONTRACT_STORAGE),
+            resources(10_000_000, vec![], vec![f.cd.wasm_key.clone()]),
+            vec![],
+            vec![],
+        ),
+        // Contract creation with an authorization entry.
+        f.create_contract_inputs(),
+        // Contract call that runs out of budget.
+        (
+            f.put_host_fn(123),
+            f.put_resources(900_000),
+            vec![],
+            f.contract_entries(),
+        ),
+        // Contract call that creates a new entry.
+        (
+            f.put_host_fn(123),
+            f.put_resources(10_000_000),
+            vec![],
+            f.contract_entries(),
+        ),
+    ];
+    let mut host = Host::default();
+    // Every case is executed twice in order to make sure that the reused host
+    // doesn't carry over any state from the previous invocations, both with
+    // and without the module cache passed to the invocation. The latter
+    // ensures that the reset host doesn't reuse the module cache installed by
+    // a previous invocation.
+    for with_module_cache in [true, false] {
+        for (host_fn, resources, auth_entries, ledger_entries) in
+            cases.iter().chain(cases.iter()).cloned()
+        {
+            let fresh_res = invoke_host_function_helper_impl(
+                true,
+                &host_fn,
+                &resources,
+                &f.cd.deployer,
+                auth_entries.clone(),
+                &f.ledger_info,
+                ledger_entries.clone(),
+                &prng_seed(),
+                &[],
+                with_module_cache,
+                None,
+            )
+            .unwrap();
+            let reused_res = invoke_host_function_helper_impl(
+                true,
+                &host_fn,
+                &resources,
+                &f.cd.deployer,
+                auth_entries,
+                &f.ledger_info,
+                ledger_entries,
+                &prng_seed(),
+                &[],
+                with_module_cache,
+                Some(&mut host),
+            )
+            .unwrap();
+            assert_eq!(fresh_res.invoke_result, reused_res.invoke_result);
+            assert_eq!(fresh_res.ledger_changes, reused_res.ledger_changes);
+            assert_eq!(fresh_res.contract_events, reused_res.contract_events);
+            assert_eq!(fresh_res.diagnostic_events, reused_res.diagnostic_events);
+            for ty in ContractCostType::variants() {
+                assert_eq!(
+                    fresh_res.budget.get_tracker(ty).unwrap(),
+                    reused_res.budget.get_tracker(ty).unwrap(),
+                    "{ty:?}"
+                );
+            }
+            assert_eq!(fresh_res.receipt, reused_res.receipt);
+        }
+    }
+}
+
+#[test]
+fn test_execution_receipt_divergence() {
+    let f = StorageContractFixture::new();
+    let receipt = |val: u64, instructions: u32, prng_seed: [u8; 32]| {
+        invoke_host_function_helper(
+            false,
+            &f.put_host_fn(val),
+            &f.put_resources(instructions),
+            &f.cd.deployer,
+            vec![],
+            &f.ledger_info,
+            f.contract_entries(),
+            &prng_seed,
+        )
+        .unwrap()
+        .receipt
+    };
+
+    let base_receipt = receipt(1, 10_000_000, [0; 32]);
+    assert_eq!(base_receipt, receipt(1, 10_000_000, [0; 32]));
+    assert!(base_receipt
+        .diverging_components(&receipt(1, 10_000_000, [0; 32]))
+        .is_empty());
+    // Instruction limit is not a part of the receipt as long as it's not
+    // exceeded.
+    assert_eq!(base_receipt, receipt(1, 20_000_000, [0; 32]));
+
+    let other_seed_receipt = receipt(1, 10_000_000, [1; 32]);
+    assert_ne!(base_receipt.receipt_hash, other_seed_receipt.receipt_hash);
+    assert_eq!(
+        base_receipt.diverging_components(&other_seed_receipt),
+        vec![ReceiptComponent::PrngSeed]
+    );
+
+    let other_value_receipt = receipt(2, 10_000_000, [0; 32]);
+    assert_eq!(
+        base_receipt.diverging_components(&other_value_receipt)[0],
+        ReceiptComponent::LedgerChanges
+    );
+
+    let failed_receipt = receipt(1, 850_000, [0; 32]);
+    assert_eq!(
+        base_receipt.diverging_components(&failed_receipt),
+        vec![
+            ReceiptComponent::ReturnValue,
+            ReceiptComponent::LedgerChanges,
+            ReceiptComponent::BudgetConsumption,
+        ]
+    );
+
+    let bytes = base_receipt.to_bytes();
+    assert_eq!(ExecutionReceipt::from_bytes(&bytes).unwrap(), base_receipt);
+    let mut tampered_bytes = bytes.clone();
+    tampered_bytes[0] ^= 1;
+    assert!(HostError::result_matches_err(
+        ExecutionReceipt::from_bytes(&tampered_bytes),
+        (ScErrorType::Value, ScErrorCode::InvalidInput)
+    ));
+    assert!(ExecutionReceipt::from_bytes(&bytes[1..]).is_err());
+}
+
+#[test]
+fn test_build_transaction_meta() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let key = symbol_sc_val("key");
+    let data_key = contract_data_key(
+        &cd.contract_address,
+        &key,
+        ContractDataDurability::Persistent,
+    );
+    let old_data_entry = contract_data_entry(
+        &cd.contract_address,
+        &key,
+        &u64_sc_val(1),
+        ContractDataDurability::Persistent,
+    );
+    let host_fn = |value: ScVal| {
+        invoke_contract_host_fn(
+            &cd.contract_address,
+            "put_persistent",
+            vec![key.clone(), value],
+        )
+    };
+    let contract_entries = vec![
+        (
+            cd.wasm_entry.clone(),
+            Some(ledger_info.sequence_number + 100),
+        ),
+        (
+            cd.contract_entry.clone(),
+            Some(ledger_info.sequence_number + 1000),
+        ),
+    ];
+    let fee_config = TransactionMetaFeeConfig {
+        resources: TransactionResources {
+            instructions: 10_000_000,
+            disk_read_entries: 1,
+            write_entries: 1,
+            disk_read_bytes: 1000,
+            write_bytes: 1000,
+            contract_events_size_bytes: 0,
+            transaction_size_bytes: 500,
+        },
+        fee_config: FeeConfiguration {
+            fee_per_instruction_increment: 25,
+            fee_per_disk_read_entry: 6250,
+            fee_per_write_entry: 10000,
+            fee_per_disk_read_1kb: 1786,
+            fee_per_write_1kb: 11800,
+            fee_per_historical_1kb: 16235,
+            fee_per_contract_event_1kb: 10000,
+            fee_per_transaction_size_1kb: 1624,
+        },
+        rent_fee_config: RentFeeConfiguration {
+            fee_per_write_1kb: 11800,
+            fee_per_rent_1kb: 1000,
+            fee_per_write_entry: 10000,
+            persistent_rent_rate_denominator: 2103,
+            temporary_rent_rate_denominator: 4206,
+        },
+    };
+    let meta = |host_fn: HostFunction, ledger_entries: Vec<(LedgerEntry, Option<u32>)>| {
+        let resources = resources(
+            10_000_000,
+            vec![cd.contract_key.clone(), cd.wasm_key.clone()],
+            vec![data_key.clone()],
+        );
+        let budget = Budget::default();
+        budget
+            .reset_cpu_limit(resources.instructions as u64)
+            .unwrap();
+        let mut diagnostic_events = vec![];
+        let res = invoke_host_function_typed(
+            &budget,
+            true,
+            host_fn,
+            resources.clone(),
+            &[],
+            cd.deployer.clone(),
+            vec![],
+            ledger_info.clone(),
+            ledger_entries.clone(),
+            prng_seed(),
+            &mut diagnostic_events,
+            None,
+            None,
+        )
+        .unwrap();
+        let meta = build_transaction_meta(
+            &ledger_info,
+            &resources,
+            &[],
+            &ledger_entries,
+            &res,
+            &diagnostic_events,
+            Some(&fee_config),
+        )
+        .unwrap();
+        let TransactionMeta::V4(meta) = meta else {
+            panic!("unexpected meta version");
+        };
+        assert_eq!(meta.diagnostic_events.as_slice(), diagnostic_events);
+        meta
+    };
+    let updated = |entry: &LedgerEntry| LedgerEntry {
+        last_modified_ledger_seq: ledger_info.sequence_number,
+        ..entry.clone()
+    };
+    let new_data_entry = updated(&contract_data_entry(
+        &cd.contract_address,
+        &key,
+        &u64_sc_val(123),
+        ContractDataDurability::Persistent,
+    ));
+
+    // Modifying an existing entry without changing its TTL.
+    let mut ledger_entries = contract_entries.clone();
+    ledger_entries.push((
+        old_data_entry.clone(),
+        Some(ledger_info.sequence_number + 10),
+    ));
+    let update_meta = meta(host_fn(u64_sc_val(123)), ledger_entries);
+    assert_eq!(update_meta.operations.len(), 1);
+    assert_eq!(
+        update_meta.operations[0].changes.0.as_slice(),
+        &[
+            xdr::LedgerEntryChange::State(old_data_entry.clone()),
+            xdr::LedgerEntryChange::Updated(new_data_entry.clone()),
+        ]
+    );
+    let soroban_meta = update_meta.soroban_meta.unwrap();
+    assert_eq!(soroban_meta.return_value, Some(ScVal::Void));
+    let SorobanTransactionMetaExt::V1(update_fees) = soroban_meta.ext else {
+        panic!("missing fee info");
+    };
+    assert_eq!(update_fees.rent_fee_charged, 0);
+    assert!(update_fees.total_non_refundable_resource_fee_charged > 0);
+
+    // Creating a new entry creates its TTL entry as well.
+    let create_meta = meta(host_fn(u64_sc_val(123)), contract_entries.clone());
+    let ttl_key_hash = Hash(compute_key_hash(&data_key).try_into().unwrap());
+    assert_eq!(
+        create_meta.operations[0].changes.0.as_slice(),
+        &[
+            xdr::LedgerEntryChange::Created(new_data_entry),
+            xdr::LedgerEntryChange::Created(updated(&ledger_entry(LedgerEntryData::Ttl(
+                TtlEntry {
+                    key_hash: ttl_key_hash,
+                    live_until_ledger_seq: ledger_info.sequence_number
+                        + ledger_info.min_persistent_entry_ttl
+                        - 1,
+                }
+            )))),
+        ]
+    );
+    let SorobanTransactionMetaExt::V1(create_fees) = create_meta.soroban_meta.unwrap().ext else {
+        panic!("missing fee info");
+    };
+    assert!(create_fees.rent_fee_charged > 0);
+    assert_eq!(
+        create_fees.total_refundable_resource_fee_charged,
+        update_fees.total_refundable_resource_fee_charged + create_fees.rent_fee_charged
+    );
+
+    // Failed invocations don't produce any ledger changes.
+    let failed_meta = meta(host_fn(ScVal::Void), contract_entries);
+    assert!(failed_meta.operations.is_empty());
+    let soroban_meta = failed_meta.soroban_meta.unwrap();
+    assert_eq!(soroban_meta.return_value, None);
+    let SorobanTransactionMetaExt::V1(failed_fees) = soroban_meta.ext else {
+        panic!("missing fee info");
+    };
+    assert_eq!(failed_fees.rent_fee_charged, 0);
+    assert_eq!(
+        failed_fees.total_non_refundable_resource_fee_charged,
+        update_fees.total_non_refundable_resource_fee_charged
+    );
+}
+
 #[test]
 fn test_create_contract_with_no_argument_constructor_success() {
     let cd = CreateContractData::new([111; 32], NO_ARGUMENT_CONSTRUCTOR_TEST_CONTRACT_P22);
@@ -2083,6 +2690,128 @@ fn test_invoke_contract_with_storage_ops_success_in_recording_mode() {
     );
 }
 
+#[test]
+fn test_find_unchanged_read_write_entries() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let key = symbol_sc_val("key");
+    let val = u64_sc_val(123);
+    let data_key = contract_data_key(
+        &cd.contract_address,
+        &key,
+        ContractDataDurability::Persistent,
+    );
+    let data_entry = contract_data_entry(
+        &cd.contract_address,
+        &key,
+        &val,
+        ContractDataDurability::Persistent,
+    );
+    let ledger_entries = vec![
+        (
+            cd.wasm_entry.clone(),
+            Some(ledger_info.sequence_number + 100),
+        ),
+        (
+            cd.contract_entry.clone(),
+            Some(ledger_info.sequence_number + 1000),
+        ),
+        (data_entry.clone(), Some(ledger_info.sequence_number + 100)),
+    ];
+    let snapshot = Rc::new(MockSnapshotSource::from_entries(ledger_entries.clone()));
+    let record = |val: &ScVal| {
+        let host_fn = invoke_contract_host_fn(
+            &cd.contract_address,
+            "put_persistent",
+            vec![key.clone(), val.clone()],
+        );
+        let res = invoke_host_function_in_recording_mode(
+            &Budget::default(),
+            false,
+            &host_fn,
+            &cd.deployer,
+            RecordingInvocationAuthMode::Recording(true),
+            ledger_info.clone(),
+            snapshot.clone(),
+            prng_seed(),
+            &mut vec![],
+        )
+        .unwrap();
+        assert!(res.invoke_result.is_ok());
+        (host_fn, res)
+    };
+
+    // Writing a different value modifies the entry.
+    let (_, res) = record(&u64_sc_val(456));
+    let unchanged = find_unchanged_read_write_entries(&res, snapshot.as_ref()).unwrap();
+    assert!(unchanged.keys.is_empty());
+    assert_eq!(unchanged.write_bytes, 0);
+
+    // Writing the same value doesn't.
+    let (host_fn, res) = record(&val);
+    assert_eq!(
+        res.resources.footprint.read_write.to_vec(),
+        vec![data_key.clone()]
+    );
+    let unchanged = find_unchanged_read_write_entries(&res, snapshot.as_ref()).unwrap();
+    let entry_size = data_entry.to_xdr(Limits::none()).unwrap().len() as u32;
+    assert_eq!(unchanged.keys, vec![data_key.clone()]);
+    assert_eq!(unchanged.write_bytes, entry_size);
+    assert_eq!(res.resources.write_bytes, entry_size);
+
+    let tx_resources = TransactionResources {
+        instructions: res.resources.instructions,
+        disk_read_entries: 0,
+        write_entries: 1,
+        disk_read_bytes: 0,
+        write_bytes: res.resources.write_bytes,
+        contract_events_size_bytes: 0,
+        transaction_size_bytes: 1000,
+    };
+    let fee_config = FeeConfiguration {
+        fee_per_write_entry: 100,
+        fee_per_write_1kb: 2048,
+        ..Default::default()
+    };
+    assert_eq!(
+        unchanged.fee_savings(&tx_resources, &fee_config),
+        100 + (entry_size as i64 * 2048 + 1023) / 1024
+    );
+
+    // The recorded footprint is enforced for every write, so the unchanged
+    // entry can't be moved to the read-only footprint.
+    let mut ro_footprint = res.resources.footprint.read_only.to_vec();
+    let enforcing_res = invoke_host_function_helper(
+        false,
+        &host_fn,
+        &resources(10_000_000, ro_footprint.clone(), vec![data_key.clone()]),
+        &cd.deployer,
+        vec![],
+        &ledger_info,
+        ledger_entries.clone(),
+        &prng_seed(),
+    )
+    .unwrap();
+    assert!(enforcing_res.invoke_result.is_ok());
+    ro_footprint.push(data_key);
+    ro_footprint.sort();
+    let enforcing_res = invoke_host_function_helper(
+        false,
+        &host_fn,
+        &resources(10_000_000, ro_footprint, vec![]),
+        &cd.deployer,
+        vec![],
+        &ledger_info,
+        ledger_entries,
+        &prng_seed(),
+    )
+    .unwrap();
+    assert!(HostError::result_matches_err(
+        enforcing_res.invoke_result,
+        (ScErrorType::Storage, ScErrorCode::ExceededLimit)
+    ));
+}
+
 #[test]
 fn test_create_contract_success_with_autorestore() {
     let cd = CreateContractData::new([111; 32], ADD_I32);
@@ -3169,6 +3898,97 @@ fn test_cap_54_55_56_module_cache_recording_fidelity() {
     }
 }
 
+// Test that reusing the module cache across the recording mode invocations
+// doesn't affect the recorded resources, both for the live modules and for the
+// modules that have to be restored.
+#[test]
+fn test_module_cache_reuse_in_recording_mode() {
+    for refined_cost_inputs in [false, true] {
+        let add_cd = CreateContractData::new_with_refined_contract_cost_inputs(
+            [111; 32],
+            ADD_I32,
+            refined_cost_inputs,
+        );
+        let sum_cd = CreateContractData::new_with_refined_contract_cost_inputs(
+            [222; 32],
+            SUM_I32,
+            refined_cost_inputs,
+        );
+        let ledger_info = default_ledger_info();
+        let host_fn = invoke_contract_host_fn(
+            &sum_cd.contract_address,
+            "sum",
+            vec![
+                ScVal::Address(add_cd.contract_address.clone()),
+                ScVal::Vec(Some(ScVec(
+                    vec![ScVal::I32(1), ScVal::I32(2), ScVal::I32(3)]
+                        .try_into()
+                        .unwrap(),
+                ))),
+            ],
+        );
+        let snapshot = Rc::new(MockSnapshotSource::from_entries(vec![
+            // Archived Wasm is parsed at the expense of the real budget.
+            (
+                add_cd.wasm_entry.clone(),
+                Some(ledger_info.sequence_number - 1),
+            ),
+            (
+                add_cd.contract_entry.clone(),
+                Some(ledger_info.sequence_number + 1000),
+            ),
+            (
+                sum_cd.wasm_entry.clone(),
+                Some(ledger_info.sequence_number + 100),
+            ),
+            (
+                sum_cd.contract_entry.clone(),
+                Some(ledger_info.sequence_number + 1000),
+            ),
+        ]));
+        let invoke = |module_cache: Option<ModuleCache>| {
+            let budget = Budget::default();
+            let res = invoke_host_function_in_recording_mode_with_options(
+                &budget,
+                false,
+                &host_fn,
+                &sum_cd.deployer,
+                RecordingInvocationAuthMode::Recording(true),
+                ledger_info.clone(),
+                snapshot.clone(),
+                prng_seed(),
+                RecordingInvocationOptions {
+                    module_cache,
+                    ..Default::default()
+                },
+                &mut vec![],
+            )
+            .unwrap();
+            assert_eq!(res.invoke_result.as_ref().unwrap(), &ScVal::I32(6));
+            (
+                budget.get_cpu_insns_consumed().unwrap(),
+                budget.get_mem_bytes_consumed().unwrap(),
+                res.resources,
+                res.restored_rw_entry_indices,
+            )
+        };
+
+        let uncached = invoke(None);
+        let module_cache = ModuleCache::new(&E2eTestCompilationContext::new().unwrap()).unwrap();
+        // The first invocation populates the cache.
+        assert_eq!(invoke(Some(module_cache.clone())), uncached);
+        for wasm in [ADD_I32, SUM_I32] {
+            assert!(module_cache
+                .contains_module(&Hash(get_wasm_hash(wasm)))
+                .unwrap());
+        }
+        // The subsequent invocations use the cached modules, but are charged
+        // in the same way.
+        assert_eq!(invoke(Some(module_cache.clone())), uncached);
+        assert_eq!(invoke(Some(module_cache)), uncached);
+    }
+}
+
 #[test]
 fn test_deployer_operations_using_simulation() {
     let deployer_contract = CreateContractData::new([1; 32], DEPLOYER_TEST_CONTRACT);
@@ -3277,3 +4097,138 @@ fn test_create_contract_authorized_by_custom_account() {
     .unwrap();
     assert!(res.invoke_result.is_ok());
 }
+
+#[test]
+fn test_create_contract_in_recording_mode_with_custom_account_signature_stub() {
+    let ledger_info = default_ledger_info();
+    let account_contract = CreateContractData::new([1; 32], SIMPLE_ACCOUNT_CONTRACT);
+    let mut prng = StdRng::from_seed(prng_seed());
+    let account_key = SigningKey::generate(&mut prng);
+    let dummy_host = Host::test_host();
+    let signers = vec![TestSigner::AccountContract(AccountContractSigner {
+        address: dummy_host
+            .add_host_object(account_contract.contract_address.clone())
+            .unwrap()
+            .try_into_val(&dummy_host)
+            .unwrap(),
+        sign: simple_account_sign_fn(&dummy_host, &account_key),
+    })];
+
+    let source_account = get_account_id([123; 32]);
+    let host_fn = HostFunction::CreateContract(CreateContractArgs {
+        contract_id_preimage: ContractIdPreimage::Address(ContractIdPreimageFromAddress {
+            address: account_contract.contract_address.clone(),
+            salt: Uint256([2; 32]),
+        }),
+        executable: ContractExecutable::Wasm(get_wasm_hash(ADD_I32).try_into().unwrap()),
+    });
+    let account_key_entry = ledger_entry(LedgerEntryData::ContractData(ContractDataEntry {
+        ext: ExtensionPoint::V0,
+        contract: account_contract.contract_address.clone(),
+        key: ScVal::Vec(Some(
+            vec![ScVal::Symbol("Owner".try_into().unwrap())]
+                .try_into()
+                .unwrap(),
+        )),
+        durability: ContractDataDurability::Persistent,
+        val: ScVal::Bytes(
+            account_key
+                .verifying_key()
+                .as_bytes()
+                .to_vec()
+                .try_into()
+                .unwrap(),
+        ),
+    }));
+    let ledger_entries = vec![
+        (
+            account_contract.wasm_entry.clone(),
+            Some(ledger_info.sequence_number + 100),
+        ),
+        (
+            account_contract.contract_entry.clone(),
+            Some(ledger_info.sequence_number + 1000),
+        ),
+        (
+            wasm_entry(ADD_I32),
+            Some(ledger_info.sequence_number + 1000),
+        ),
+        (
+            account_key_entry.clone(),
+            Some(ledger_info.sequence_number + 100),
+        ),
+    ];
+    let run_recording = |auth_mode| {
+        invoke_host_function_recording_helper(
+            true,
+            &host_fn,
+            &source_account,
+            auth_mode,
+            &ledger_info,
+            ledger_entries.clone(),
+            &prng_seed(),
+            None,
+        )
+        .unwrap()
+    };
+
+    let recording_result = run_recording(RecordingInvocationAuthMode::Recording(true));
+    let signed_auth: Vec<_> = recording_result
+        .auth
+        .iter()
+        .map(|a| sign_auth_entry(&dummy_host, &ledger_info, &signers, a.clone()))
+        .collect();
+    let enforcing_result = run_recording(RecordingInvocationAuthMode::Enforcing(signed_auth));
+    // The signature doesn't need to be valid, only the shape matters.
+    let signature_stubs: BTreeMap<ScAddress, ScVal> = [(
+        account_contract.contract_address.clone(),
+        ScVal::Bytes(vec![0; 64].try_into().unwrap()),
+    )]
+    .into();
+    let stub_result = run_recording(RecordingInvocationAuthMode::RecordingWithSignatureStubs(
+        true,
+        Rc::new(signature_stubs),
+    ));
+    assert!(recording_result.invoke_result.is_ok());
+    assert_eq!(stub_result.invoke_result, recording_result.invoke_result);
+    assert!(enforcing_result.invoke_result.is_ok());
+
+    // The recorded entries are still unsigned.
+    assert_eq!(stub_result.auth, recording_result.auth);
+
+    // `__check_auth` reads the owner key, which is only observed when the
+    // signature stub is provided.
+    let account_key_key =
+        ledger_entry_to_ledger_key(&account_key_entry, &Budget::default()).unwrap();
+    assert!(!recording_result
+        .resources
+        .footprint
+        .read_only
+        .contains(&account_key_key));
+    assert!(stub_result
+        .resources
+        .footprint
+        .read_only
+        .contains(&account_key_key));
+    assert_eq!(
+        stub_result.resources.footprint,
+        enforcing_result.resources.footprint
+    );
+    assert_eq!(
+        stub_result.resources.disk_read_bytes,
+        enforcing_result.resources.disk_read_bytes
+    );
+
+    assert!(recording_result.resources.instructions < enforcing_result.resources.instructions);
+    let instructions_range = |instructions: u32| {
+        (instructions as f64 * (1.0 + RECORDING_MODE_INSTRUCTIONS_RANGE)) as u32
+    };
+    assert!(
+        instructions_range(stub_result.resources.instructions)
+            >= enforcing_result.resources.instructions
+    );
+    assert!(
+        stub_result.resources.instructions
+            <= instructions_range(enforcing_result.resources.instructions)
+    );
+}
@@ -1,8 +1,9 @@
 use soroban_env_common::xdr::{ScBytes, ScErrorCode, ScErrorType};
-use soroban_env_common::Val;
+use soroban_env_common::{AddressObject, Symbol, TryFromVal, TryIntoVal, Val};
+use soroban_test_wasms::CONTRACT_STORAGE;
 
 use crate::{
-    budget::Budget,
+    budget::{AsBudget, Budget},
     storage::{Footprint, Storage, StorageMap},
     Env, Host, HostError,
 };
@@ -58,3 +59,44 @@ fn invalid_val() -> Result<(), HostError> {
 
     Ok(())
 }
+
+#[test]
+fn forked_hosts_are_independent() -> Result<(), HostError> {
+    let host = Host::test_host_with_recording_footprint();
+    host.set_base_prng_seed([5; 32])?;
+    let contract_id = host.register_test_contract_wasm(CONTRACT_STORAGE);
+    let key = Symbol::try_from_small_str("key")?;
+    let put = |host: &Host, contract_id: AddressObject, value: u64| -> Result<(), HostError> {
+        let fn_name = Symbol::try_from_val(host, &"put_persistent")?;
+        host.call(contract_id, fn_name, test_vec![host, key, value].into())?;
+        Ok(())
+    };
+    let get = |host: &Host, contract_id: AddressObject| -> Result<u64, HostError> {
+        let fn_name = Symbol::try_from_val(host, &"get_persistent")?;
+        let res = host.call(contract_id, fn_name, test_vec![host, key].into())?;
+        Ok(u64::try_from_val(host, &res)?)
+    };
+    put(&host, contract_id, 1)?;
+    let consumed_before_fork = host.as_budget().get_cpu_insns_consumed()?;
+
+    // Objects created before forking are valid in both hosts.
+    let fork = host.fork()?;
+    assert!(!fork.is_same(&host));
+    assert_eq!(fork.as_budget().get_cpu_insns_consumed()?, 0);
+    assert_eq!(get(&fork, contract_id)?, 1);
+
+    put(&fork, contract_id, 2)?;
+    assert_eq!(get(&fork, contract_id)?, 2);
+    assert_eq!(get(&host, contract_id)?, 1);
+    put(&host, contract_id, 3)?;
+    assert_eq!(get(&fork, contract_id)?, 2);
+    assert!(host.as_budget().get_cpu_insns_consumed()? > consumed_before_fork);
+
+    // The fork continues from the same PRNG state.
+    let fork = host.fork()?;
+    assert_eq!(
+        host.try_borrow_base_prng()?.as_ref().unwrap().0,
+        fork.try_borrow_base_prng()?.as_ref().unwrap().0
+    );
+    Ok(())
+}
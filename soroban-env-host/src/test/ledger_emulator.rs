@@ -0,0 +1,253 @@
+use std::rc::Rc;
+
+use crate::{
+    e2e_testutils::{default_ledger_info, CreateContractData},
+    ledger_emulator::{
+        ExpirationResult, LedgerEmulator, LedgerEntryState, DEFAULT_LEDGER_CLOSE_TIME_SECONDS,
+    },
+    test::tx_set::{call_op, data_entry, data_key, sym, tx},
+    tx_set::SorobanOperation,
+    xdr::{
+        ContractDataDurability, ExtensionPoint, RestoreFootprintOp, ScErrorCode, ScErrorType, ScVal,
+    },
+    HostError,
+};
+use soroban_test_wasms::CONTRACT_STORAGE;
+
+fn deployed_contract_emulator(cd: &CreateContractData, live_until: u32) -> LedgerEmulator {
+    let mut emulator = LedgerEmulator::new(default_ledger_info(), None, false);
+    emulator
+        .set_entry(cd.wasm_entry.clone(), Some(live_until))
+        .unwrap();
+    emulator
+        .set_entry(cd.contract_entry.clone(), Some(live_until))
+        .unwrap();
+    emulator
+}
+
+#[test]
+fn test_ledger_emulator_expiration_and_restoration() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let mut emulator = deployed_contract_emulator(&cd, ledger_info.sequence_number + 5_000_000);
+    let contract_keys = vec![cd.wasm_key.clone(), cd.contract_key.clone()];
+    let persistent_key = data_key(
+        &cd.contract_address,
+        "p",
+        ContractDataDurability::Persistent,
+    );
+    let temp_key = data_key(&cd.contract_address, "t", ContractDataDurability::Temporary);
+
+    let res = emulator
+        .close_ledger(&[
+            tx(
+                &cd,
+                call_op(&cd, "put_persistent", vec![sym("p"), ScVal::U64(42)]),
+                contract_keys.clone(),
+                vec![persistent_key.clone()],
+                vec![],
+            ),
+            tx(
+                &cd,
+                call_op(&cd, "put_temporary", vec![sym("t"), ScVal::U64(7)]),
+                contract_keys.clone(),
+                vec![temp_key.clone()],
+                vec![],
+            ),
+        ])
+        .unwrap();
+    for tx_res in &res.transaction_results {
+        assert!(tx_res.result.is_ok());
+    }
+    assert_eq!(res.expiration, ExpirationResult::default());
+    assert_eq!(
+        emulator.ledger_info().sequence_number,
+        ledger_info.sequence_number + 1
+    );
+    assert_eq!(
+        emulator.ledger_info().timestamp,
+        ledger_info.timestamp + DEFAULT_LEDGER_CLOSE_TIME_SECONDS
+    );
+    assert!(matches!(
+        emulator.entry_state(&temp_key),
+        LedgerEntryState::Live(_)
+    ));
+
+    // Temporary entry lives for the minimum temporary TTL and is then deleted.
+    let expiration = emulator
+        .advance_ledgers(ledger_info.min_temp_entry_ttl)
+        .unwrap();
+    assert_eq!(
+        expiration,
+        ExpirationResult {
+            deleted_temporary_entries: vec![Rc::new(temp_key.clone())],
+            archived_persistent_entries: vec![],
+        }
+    );
+    assert_eq!(emulator.entry_state(&temp_key), LedgerEntryState::Missing);
+
+    // About three months later the persistent entry gets archived, while the
+    // contract itself is still live.
+    let expiration = emulator.advance_time(90 * 24 * 3600).unwrap();
+    assert_eq!(
+        expiration,
+        ExpirationResult {
+            deleted_temporary_entries: vec![],
+            archived_persistent_entries: vec![Rc::new(persistent_key.clone())],
+        }
+    );
+    assert_eq!(
+        emulator.ledger_info().sequence_number,
+        ledger_info.sequence_number + 1 + ledger_info.min_temp_entry_ttl + 1_555_200
+    );
+    assert_eq!(
+        emulator.entry_state(&persistent_key),
+        LedgerEntryState::Archived(Rc::new(data_entry(
+            &cd.contract_address,
+            "p",
+            42,
+            ContractDataDurability::Persistent
+        )))
+    );
+    assert!(matches!(
+        emulator.entry_state(&cd.contract_key),
+        LedgerEntryState::Live(_)
+    ));
+
+    // Archived entry can't be accessed until it's restored.
+    let mut read_only_keys = contract_keys.clone();
+    read_only_keys.push(persistent_key.clone());
+    let get_tx = tx(
+        &cd,
+        call_op(&cd, "get_persistent", vec![sym("p")]),
+        read_only_keys,
+        vec![],
+        vec![],
+    );
+    let res = emulator
+        .close_ledger(std::slice::from_ref(&get_tx))
+        .unwrap();
+    assert!(HostError::result_matches_err(
+        res.transaction_results[0].result.clone(),
+        (ScErrorType::Storage, ScErrorCode::InvalidInput)
+    ));
+    let restore_seq = emulator.ledger_info().sequence_number;
+    let res = emulator
+        .close_ledger(&[
+            tx(
+                &cd,
+                SorobanOperation::RestoreFootprint(RestoreFootprintOp {
+                    ext: ExtensionPoint::V0,
+                }),
+                vec![],
+                vec![persistent_key.clone()],
+                vec![],
+            ),
+            get_tx,
+        ])
+        .unwrap();
+    assert!(res.transaction_results[0].result.is_ok());
+    assert_eq!(
+        res.transaction_results[1].result.as_ref().unwrap(),
+        &Some(ScVal::U64(42))
+    );
+    let LedgerEntryState::Live((_, live_until)) = emulator.entry_state(&persistent_key) else {
+        panic!("entry has not been restored");
+    };
+    assert_eq!(
+        live_until,
+        Some(restore_seq + ledger_info.min_persistent_entry_ttl - 1)
+    );
+
+    // Eventually the contract instance and code expire as well.
+    let expiration = emulator.advance_ledgers(5_000_000).unwrap();
+    let mut archived = vec![
+        Rc::new(cd.wasm_key.clone()),
+        Rc::new(cd.contract_key.clone()),
+        Rc::new(persistent_key.clone()),
+    ];
+    archived.sort();
+    assert_eq!(expiration.archived_persistent_entries, archived);
+}
+
+#[test]
+fn test_ledger_emulator_set_entry() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let mut emulator = deployed_contract_emulator(&cd, ledger_info.sequence_number + 1000);
+    let entry = |key, durability| data_entry(&cd.contract_address, key, 1, durability);
+
+    // Contract data must have TTL.
+    assert!(HostError::result_matches_err(
+        emulator.set_entry(entry("a", ContractDataDurability::Persistent), None),
+        (ScErrorType::Storage, ScErrorCode::InvalidInput)
+    ));
+
+    // Already expired entries are archived or dropped right away.
+    let expired = Some(ledger_info.sequence_number - 1);
+    emulator
+        .set_entry(entry("p", ContractDataDurability::Persistent), expired)
+        .unwrap();
+    emulator
+        .set_entry(entry("t", ContractDataDurability::Temporary), expired)
+        .unwrap();
+    assert_eq!(
+        emulator.entry_state(&data_key(
+            &cd.contract_address,
+            "p",
+            ContractDataDurability::Persistent
+        )),
+        LedgerEntryState::Archived(Rc::new(entry("p", ContractDataDurability::Persistent)))
+    );
+    assert_eq!(
+        emulator.entry_state(&data_key(
+            &cd.contract_address,
+            "t",
+            ContractDataDurability::Temporary
+        )),
+        LedgerEntryState::Missing
+    );
+
+    // Archived entries are visible in the snapshot with their expired TTL.
+    let snapshot_entry = emulator
+        .snapshot()
+        .get(&Rc::new(data_key(
+            &cd.contract_address,
+            "p",
+            ContractDataDurability::Persistent,
+        )))
+        .unwrap();
+    assert_eq!(snapshot_entry.unwrap().1, expired);
+}
+
+#[test]
+fn test_ledger_emulator_advance_overflow_keeps_ledger_info() {
+    let ledger_info = default_ledger_info();
+    let mut emulator = LedgerEmulator::new(ledger_info.clone(), None, false);
+
+    // The sequence number fits, but the timestamp overflows.
+    emulator.set_ledger_close_time_seconds(u64::MAX);
+    assert!(HostError::result_matches_err(
+        emulator.advance_ledgers(2),
+        (ScErrorType::Context, ScErrorCode::InvalidInput)
+    ));
+    assert_eq!(emulator.ledger_info(), &ledger_info);
+
+    // The sequence number overflows.
+    emulator.set_ledger_close_time_seconds(DEFAULT_LEDGER_CLOSE_TIME_SECONDS);
+    assert!(HostError::result_matches_err(
+        emulator.advance_ledgers(u32::MAX),
+        (ScErrorType::Context, ScErrorCode::InvalidInput)
+    ));
+    assert_eq!(emulator.ledger_info(), &ledger_info);
+
+    emulator.advance_ledgers(2).unwrap();
+    assert_eq!(
+        emulator.ledger_info().sequence_number,
+        ledger_info.sequence_number + 2
+    );
+    assert_eq!(
+        emulator.ledger_info().timestamp,
+        ledger_info.timestamp + 2 * DEFAULT_LEDGER_CLOSE_TIME_SECONDS
+    );
+}
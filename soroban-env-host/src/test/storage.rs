@@ -2,13 +2,18 @@ use std::rc::Rc;
 
 use crate::budget::{AsBudget, Budget};
 use crate::host_object::MuxedScAddress;
-use crate::storage::{AccessType, Footprint, Storage};
+use crate::storage::{AccessType, Footprint, Storage, StorageAccess, StorageAccessType};
+use crate::storage::{EntryWithLiveUntil, SnapshotSource};
+use crate::testutils::MockSnapshotSource;
 use crate::xdr::{
-    ContractDataDurability, ContractId, LedgerKey, LedgerKeyContractData, MuxedEd25519Account,
-    ScAddress, ScErrorCode, ScErrorType, ScVal, Uint256,
+    ContractDataDurability, ContractDataEntry, ContractExecutable, ContractId, ExtensionPoint,
+    LedgerEntry, LedgerEntryData, LedgerEntryExt, LedgerKey, LedgerKeyContractData,
+    MuxedEd25519Account, ScAddress, ScContractInstance, ScErrorCode, ScErrorType, ScVal, Uint256,
+};
+use crate::{Host, HostError, MeteredOrdMap, StorageType};
+use soroban_env_common::{
+    AddressObject, Env, MuxedAddressObject, Symbol, TryFromVal, TryIntoVal, VecObject,
 };
-use crate::{Host, HostError, MeteredOrdMap};
-use soroban_env_common::{AddressObject, Env, MuxedAddressObject, Symbol, TryFromVal, TryIntoVal};
 use soroban_test_wasms::{CONTRACT_STORAGE, CONTRACT_STORAGE_WITH_VALS, INVOKE_CONTRACT};
 
 #[test]
@@ -540,3 +545,360 @@ fn test_large_instance_key() {
         test_vec![&*host, key, 1_u64].into(),
     );
 }
+
+#[test]
+fn test_storage_access_log() -> Result<(), HostError> {
+    let host = Host::test_host_with_recording_footprint();
+    let contract_id = host.register_test_contract_wasm(CONTRACT_STORAGE);
+    let contract_id_hash = host.contract_id_from_address(contract_id)?;
+    let key = Symbol::try_from_small_str("key")?;
+    let data_key = Rc::new(LedgerKey::ContractData(LedgerKeyContractData {
+        contract: ScAddress::Contract(contract_id_hash.clone()),
+        key: ScVal::Symbol("key".try_into().unwrap()),
+        durability: ContractDataDurability::Persistent,
+    }));
+    let call = |fn_name: &str, args: VecObject| -> Result<Vec<StorageAccess>, HostError> {
+        let fn_name = Symbol::try_from_val(&host, &fn_name)?;
+        host.call(contract_id, fn_name, args)?;
+        // Only keep the accesses to the data entry, the contract instance and
+        // code are accessed as well.
+        Ok(host
+            .take_storage_access_log()?
+            .into_iter()
+            .filter(|access| access.key == data_key)
+            .collect())
+    };
+
+    // Nothing is recorded until the log is enabled.
+    call("put_persistent", test_vec![&host, key, 1_u64].into())?;
+    assert!(host.take_storage_access_log()?.is_empty());
+
+    host.enable_storage_access_log()?;
+    // Host reads the existing entry before overwriting it.
+    let accesses = call("put_persistent", test_vec![&host, key, 2_u64].into())?;
+    assert_eq!(
+        accesses.iter().map(|a| a.access_type).collect::<Vec<_>>(),
+        vec![
+            StorageAccessType::Has,
+            StorageAccessType::Get,
+            StorageAccessType::Put
+        ]
+    );
+    let put = &accesses[2];
+    assert_eq!(put.access_type, StorageAccessType::Put);
+    assert_eq!(put.contract_id, Some(contract_id_hash.clone()));
+    assert_eq!(put.function_name.as_deref(), Some("put_persistent"));
+    assert!(put.succeeded);
+    assert!(put.entry_size_before.is_some());
+    assert_eq!(put.entry_size_before, put.entry_size_after);
+    assert!(put.cpu_insns > 0);
+    let live_until = put.live_until_ledger_after.unwrap();
+
+    let accesses = call("has_persistent", test_vec![&host, key].into())?;
+    assert!(!accesses.is_empty());
+    for access in &accesses {
+        assert_eq!(access.function_name.as_deref(), Some("has_persistent"));
+        assert_eq!(access.entry_size_before, put.entry_size_after);
+        assert_eq!(access.entry_size_after, put.entry_size_after);
+    }
+    // `has` is recorded as a single access, even though it reads the entry.
+    assert!(accesses
+        .iter()
+        .all(|access| access.access_type == StorageAccessType::Has));
+
+    let accesses = call(
+        "extend_persistent",
+        test_vec![&host, key, 10_000_u32, 10_000_u32].into(),
+    )?;
+    assert_eq!(accesses.len(), 1);
+    assert_eq!(accesses[0].access_type, StorageAccessType::ExtendTtl);
+    assert_eq!(accesses[0].live_until_ledger_before, Some(live_until));
+    assert!(accesses[0].live_until_ledger_after.unwrap() > live_until);
+
+    let accesses = call("del_persistent", test_vec![&host, key].into())?;
+    assert_eq!(accesses.len(), 1);
+    assert_eq!(accesses[0].access_type, StorageAccessType::Del);
+    assert_eq!(accesses[0].entry_size_before, put.entry_size_after);
+    assert_eq!(accesses[0].entry_size_after, None);
+
+    // Accesses outside of the contract frames are recorded too.
+    assert!(host
+        .with_mut_storage(|s: &mut Storage| s.get(&data_key, &host, None))
+        .is_err());
+    let accesses = host.take_storage_access_log()?;
+    assert_eq!(accesses.len(), 1);
+    assert_eq!(accesses[0].access_type, StorageAccessType::Get);
+    assert_eq!(accesses[0].contract_id, None);
+    assert_eq!(accesses[0].function_name, None);
+    assert_eq!(accesses[0].entry_size_before, None);
+
+    // Failed accesses are recorded too.
+    call("put_persistent", test_vec![&host, key, 3_u64].into())?;
+    assert!(call(
+        "extend_persistent",
+        test_vec![&host, key, 10_u32, 5_u32].into()
+    )
+    .is_err());
+    let accesses = host.take_storage_access_log()?;
+    let extend = accesses.last().unwrap();
+    assert_eq!(extend.access_type, StorageAccessType::ExtendTtl);
+    assert!(!extend.succeeded);
+    Ok(())
+}
+
+#[test]
+fn test_scan_contract_data_keys() -> Result<(), HostError> {
+    let host = Host::test_host();
+    host.with_mut_ledger_info(|li| li.sequence_number = 100)?;
+    let contract_id = ContractId([1; 32].into());
+    let other_contract_id = ContractId([2; 32].into());
+    let sym = |s: &str| ScVal::Symbol(s.try_into().unwrap());
+    let balance = |i: u32| {
+        ScVal::Vec(Some(
+            vec![sym("Balance"), ScVal::U32(i)].try_into().unwrap(),
+        ))
+    };
+    let allowance = ScVal::Vec(Some(
+        vec![sym("Allowance"), ScVal::U32(1)].try_into().unwrap(),
+    ));
+    let setup_entry = |contract_id: &ContractId,
+                       key: ScVal,
+                       durability: ContractDataDurability,
+                       live_until: Option<u32>|
+     -> Result<(), HostError> {
+        let contract = ScAddress::Contract(contract_id.clone());
+        let ledger_key = Rc::new(LedgerKey::ContractData(LedgerKeyContractData {
+            contract: contract.clone(),
+            key: key.clone(),
+            durability,
+        }));
+        let val = match key {
+            ScVal::LedgerKeyContractInstance => ScVal::ContractInstance(ScContractInstance {
+                executable: ContractExecutable::StellarAsset,
+                storage: None,
+            }),
+            _ => ScVal::Void,
+        };
+        let entry = live_until.map(|live_until| {
+            let entry = LedgerEntry {
+                last_modified_ledger_seq: 0,
+                data: LedgerEntryData::ContractData(ContractDataEntry {
+                    ext: ExtensionPoint::V0,
+                    contract,
+                    key,
+                    durability,
+                    val,
+                }),
+                ext: LedgerEntryExt::V0,
+            };
+            (Rc::new(entry), Some(live_until))
+        });
+        host.setup_storage_entry(ledger_key, entry, AccessType::ReadOnly)
+    };
+    let persistent = ContractDataDurability::Persistent;
+    let temporary = ContractDataDurability::Temporary;
+    setup_entry(
+        &contract_id,
+        ScVal::LedgerKeyContractInstance,
+        persistent,
+        Some(200),
+    )?;
+    setup_entry(&contract_id, balance(2), persistent, Some(200))?;
+    setup_entry(&contract_id, balance(1), persistent, Some(200))?;
+    setup_entry(&contract_id, allowance.clone(), persistent, Some(200))?;
+    setup_entry(&contract_id, sym("Admin"), persistent, Some(200))?;
+    // Footprint entries that don't exist are not visible.
+    setup_entry(&contract_id, balance(3), persistent, None)?;
+    // Expired entries are not visible.
+    setup_entry(&contract_id, balance(4), temporary, Some(99))?;
+    setup_entry(&contract_id, balance(5), temporary, Some(100))?;
+    // Entries of other contracts are not visible.
+    setup_entry(&other_contract_id, balance(6), persistent, Some(200))?;
+
+    let in_contract_frame = |f: &mut dyn FnMut() -> Result<(), HostError>| {
+        host.with_test_contract_frame(
+            contract_id.clone(),
+            Symbol::try_from_small_str("scan")?,
+            || {
+                f()?;
+                Ok(().into())
+            },
+        )
+    };
+    let scan = |prefix: Vec<ScVal>, durability| -> Result<Vec<ScVal>, HostError> {
+        let mut keys = vec![];
+        in_contract_frame(&mut || {
+            keys = host.scan_contract_data_keys_internal(&prefix, durability)?;
+            Ok(())
+        })?;
+        Ok(keys)
+    };
+
+    assert_eq!(
+        scan(vec![], persistent)?,
+        vec![sym("Admin"), allowance, balance(1), balance(2)]
+    );
+    assert_eq!(
+        scan(vec![sym("Balance")], persistent)?,
+        vec![balance(1), balance(2)]
+    );
+    assert_eq!(
+        scan(vec![sym("Balance"), ScVal::U32(2)], persistent)?,
+        vec![balance(2)]
+    );
+    assert_eq!(
+        scan(
+            vec![sym("Balance"), ScVal::U32(2), ScVal::U32(0)],
+            persistent
+        )?,
+        vec![]
+    );
+    assert_eq!(scan(vec![sym("Admin")], persistent)?, vec![]);
+    assert_eq!(scan(vec![sym("Balance")], temporary)?, vec![balance(5)]);
+
+    // The host function is only available starting from protocol 24.
+    let call_host_fn = |t: StorageType| -> Result<Vec<ScVal>, HostError> {
+        let mut keys = vec![];
+        in_contract_frame(&mut || {
+            let prefix = test_vec![&host, Symbol::try_from_small_str("Balance")?];
+            let res = host.scan_contract_data_keys(prefix.into(), t)?;
+            keys = host.vecobject_to_scval_vec(res)?.to_vec();
+            Ok(())
+        })?;
+        Ok(keys)
+    };
+    if host.get_ledger_protocol_version()? < 24 {
+        assert!(HostError::result_matches_err(
+            call_host_fn(StorageType::Persistent),
+            (ScErrorType::Context, ScErrorCode::IndexBounds)
+        ));
+    } else {
+        assert_eq!(
+            call_host_fn(StorageType::Persistent)?,
+            vec![balance(1), balance(2)]
+        );
+        assert!(HostError::result_matches_err(
+            call_host_fn(StorageType::Instance),
+            (ScErrorType::Storage, ScErrorCode::InvalidAction)
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_scan_contract_data_keys_recording_enforcing_round_trip() -> Result<(), HostError> {
+    let contract_id = ContractId([1; 32].into());
+    let contract = ScAddress::Contract(contract_id.clone());
+    let balance = |i: u32| {
+        ScVal::Vec(Some(
+            vec![ScVal::Symbol("Balance".try_into().unwrap()), ScVal::U32(i)]
+                .try_into()
+                .unwrap(),
+        ))
+    };
+    let key = |i: u32| {
+        Rc::new(LedgerKey::ContractData(LedgerKeyContractData {
+            contract: contract.clone(),
+            key: balance(i),
+            durability: ContractDataDurability::Persistent,
+        }))
+    };
+    let entry = |key: ScVal, val: ScVal| LedgerEntry {
+        last_modified_ledger_seq: 0,
+        data: LedgerEntryData::ContractData(ContractDataEntry {
+            ext: ExtensionPoint::V0,
+            contract: contract.clone(),
+            key,
+            durability: ContractDataDurability::Persistent,
+            val,
+        }),
+        ext: LedgerEntryExt::V0,
+    };
+    let instance = ScVal::ContractInstance(ScContractInstance {
+        executable: ContractExecutable::StellarAsset,
+        storage: None,
+    });
+    let mut entries = vec![(entry(ScVal::LedgerKeyContractInstance, instance), Some(200))];
+    for i in 1..=3 {
+        entries.push((entry(balance(i), ScVal::Void), Some(200)));
+    }
+    // Archived entries are not visible to the scan.
+    entries.push((entry(balance(4), ScVal::Void), Some(50)));
+    // Neither are the entries that don't match the prefix.
+    entries.push((entry(ScVal::U32(1), ScVal::Void), Some(200)));
+    let snapshot = Rc::new(MockSnapshotSource::from_entries(entries));
+    // Scans the keys after accessing the `accessed` keys.
+    let scan = |host: &Host, accessed: &[u32]| -> Result<Vec<ScVal>, HostError> {
+        let mut keys = vec![];
+        host.with_test_contract_frame(
+            contract_id.clone(),
+            Symbol::try_from_small_str("scan")?,
+            || {
+                for i in accessed {
+                    host.with_mut_storage(|s| s.get(&key(*i), host, None))?;
+                }
+                keys = host.scan_contract_data_keys_internal(
+                    &[ScVal::Symbol("Balance".try_into().unwrap())],
+                    ContractDataDurability::Persistent,
+                )?;
+                Ok(().into())
+            },
+        )?;
+        Ok(keys)
+    };
+    let recording_host = |snapshot: Rc<dyn SnapshotSource>| -> Result<Host, HostError> {
+        let host = Host::with_storage_and_budget(
+            Storage::with_recording_footprint(snapshot),
+            Budget::default(),
+        );
+        host.set_test_ledger_info_with_current_test_protocol();
+        host.with_mut_ledger_info(|li| li.sequence_number = 100)?;
+        Ok(host)
+    };
+
+    // Recording mode observes all the live matching entries of the snapshot,
+    // including the ones that haven't been accessed before the scan, and
+    // records them in the read-only footprint.
+    let host = recording_host(snapshot.clone())?;
+    let recorded_keys = scan(&host, &[2])?;
+    assert_eq!(recorded_keys, vec![balance(1), balance(2), balance(3)]);
+    let footprint = host.with_mut_storage(|s| Ok(s.footprint.clone()))?;
+    let mut footprint_keys = vec![];
+    for (key, access_type) in footprint.0.iter(host.as_budget())? {
+        assert_eq!(*access_type, AccessType::ReadOnly);
+        footprint_keys.push(key.as_ref().clone());
+    }
+    let instance_key = LedgerKey::ContractData(LedgerKeyContractData {
+        contract: contract.clone(),
+        key: ScVal::LedgerKeyContractInstance,
+        durability: ContractDataDurability::Persistent,
+    });
+    let mut expected_footprint_keys = vec![instance_key];
+    expected_footprint_keys.extend((1..=3).map(|i| key(i).as_ref().clone()));
+    expected_footprint_keys.sort();
+    assert_eq!(footprint_keys, expected_footprint_keys);
+
+    // The recorded footprint produces the same scan result in the enforcing
+    // mode.
+    let enforcing_host = Host::test_host();
+    enforcing_host.with_mut_ledger_info(|li| li.sequence_number = 100)?;
+    for (key, access_type) in footprint.0.iter(enforcing_host.as_budget())? {
+        enforcing_host.setup_storage_entry(key.clone(), snapshot.get(key)?, *access_type)?;
+    }
+    assert_eq!(scan(&enforcing_host, &[2])?, recorded_keys);
+
+    // The scan can't be recorded when the snapshot can't enumerate the
+    // entries, as the recorded footprint wouldn't reproduce its result.
+    struct NonEnumerableSnapshotSource(Rc<MockSnapshotSource>);
+    impl SnapshotSource for NonEnumerableSnapshotSource {
+        fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+            self.0.get(key)
+        }
+    }
+    let host = recording_host(Rc::new(NonEnumerableSnapshotSource(snapshot)))?;
+    assert!(HostError::result_matches_err(
+        scan(&host, &[2]),
+        (ScErrorType::Storage, ScErrorCode::InternalError)
+    ));
+    Ok(())
+}
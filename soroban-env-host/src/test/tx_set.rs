@@ -0,0 +1,751 @@
+use std::rc::Rc;
+
+use crate::{
+    budget::AsBudget,
+    e2e_invoke::{LedgerEntryLiveUntilChange, TypedLedgerEntryChange},
+    e2e_testutils::{
+        default_ledger_info, get_wasm_hash, ledger_entry, upload_wasm_host_fn, CreateContractData,
+    },
+    fees::RentFeeConfiguration,
+    storage::SnapshotSource,
+    testutils::MockSnapshotSource,
+    tx_set::{
+        LedgerEntryStateDiff, LedgerOverlay, SorobanOperation, SorobanTransaction,
+        TransactionBudgetConfig, TransactionSetExecutor, TransactionSetResult,
+    },
+    vm::VersionedContractCodeCostInputs,
+    xdr::{
+        ContractCodeEntryExt, ContractCostParamEntry, ContractCostParams, ContractCostType,
+        ContractDataDurability, ContractDataEntry, ExtendFootprintTtlOp, ExtensionPoint,
+        HostFunction, InvokeContractArgs, InvokeHostFunctionOp, LedgerEntry, LedgerEntryData,
+        LedgerEntryType, LedgerFootprint, LedgerKey, LedgerKeyContractData, RestoreFootprintOp,
+        ScAddress, ScErrorCode, ScErrorType, ScVal, SorobanResources, SorobanResourcesExtV0,
+        SorobanTransactionData, SorobanTransactionDataExt,
+    },
+    Host, HostError, ModuleCache,
+};
+use soroban_test_wasms::CONTRACT_STORAGE;
+
+pub(crate) fn tx(
+    cd: &CreateContractData,
+    operation: SorobanOperation,
+    read_only: Vec<LedgerKey>,
+    read_write: Vec<LedgerKey>,
+    archived_entries: Vec<u32>,
+) -> SorobanTransaction {
+    let ext = if archived_entries.is_empty() {
+        SorobanTransactionDataExt::V0
+    } else {
+        SorobanTransactionDataExt::V1(SorobanResourcesExtV0 {
+            archived_soroban_entries: archived_entries.try_into().unwrap(),
+        })
+    };
+    SorobanTransaction {
+        source_account: cd.deployer.clone(),
+        operation,
+        transaction_data: SorobanTransactionData {
+            ext,
+            resources: SorobanResources {
+                footprint: LedgerFootprint {
+                    read_only: read_only.try_into().unwrap(),
+                    read_write: read_write.try_into().unwrap(),
+                },
+                instructions: 100_000_000,
+                disk_read_bytes: 0,
+                write_bytes: 0,
+            },
+            resource_fee: 0,
+        },
+        base_prng_seed: [0; 32],
+    }
+}
+
+pub(crate) fn invoke_op(host_fn: HostFunction) -> SorobanOperation {
+    SorobanOperation::InvokeHostFunction(InvokeHostFunctionOp {
+        host_function: host_fn,
+        auth: Default::default(),
+    })
+}
+
+pub(crate) fn call_op(
+    cd: &CreateContractData,
+    fn_name: &str,
+    args: Vec<ScVal>,
+) -> SorobanOperation {
+    invoke_op(HostFunction::InvokeContract(InvokeContractArgs {
+        contract_address: cd.contract_address.clone(),
+        function_name: fn_name.try_into().unwrap(),
+        args: args.try_into().unwrap(),
+    }))
+}
+
+pub(crate) fn data_key(
+    contract: &ScAddress,
+    key: &str,
+    durability: ContractDataDurability,
+) -> LedgerKey {
+    LedgerKey::ContractData(LedgerKeyContractData {
+        contract: contract.clone(),
+        key: ScVal::Symbol(key.try_into().unwrap()),
+        durability,
+    })
+}
+
+pub(crate) fn data_entry(
+    contract: &ScAddress,
+    key: &str,
+    val: u64,
+    durability: ContractDataDurability,
+) -> LedgerEntry {
+    ledger_entry(LedgerEntryData::ContractData(ContractDataEntry {
+        ext: ExtensionPoint::V0,
+        contract: contract.clone(),
+        key: ScVal::Symbol(key.try_into().unwrap()),
+        durability,
+        val: ScVal::U64(val),
+    }))
+}
+
+pub(crate) fn sym(s: &str) -> ScVal {
+    ScVal::Symbol(s.try_into().unwrap())
+}
+
+fn deployed_contract_snapshot(
+    cd: &CreateContractData,
+    extra_entries: Vec<(LedgerEntry, Option<u32>)>,
+) -> Rc<MockSnapshotSource> {
+    let live_until = default_ledger_info().sequence_number + 1000;
+    let mut entries = vec![
+        (cd.wasm_entry.clone(), Some(live_until)),
+        (cd.contract_entry.clone(), Some(live_until)),
+    ];
+    entries.extend(extra_entries);
+    Rc::new(MockSnapshotSource::from_entries(entries))
+}
+
+#[test]
+fn test_tx_set_deploy_and_use_contract() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let key = data_key(
+        &cd.contract_address,
+        "k",
+        ContractDataDurability::Persistent,
+    );
+    let mut create_tx = tx(
+        &cd,
+        invoke_op(cd.host_fn.clone()),
+        vec![cd.wasm_key.clone()],
+        vec![cd.contract_key.clone()],
+        vec![],
+    );
+    if let SorobanOperation::InvokeHostFunction(op) = &mut create_tx.operation {
+        op.auth = vec![cd.auth_entry.clone()].try_into().unwrap();
+    }
+    let txs = vec![
+        tx(
+            &cd,
+            invoke_op(upload_wasm_host_fn(CONTRACT_STORAGE)),
+            vec![],
+            vec![cd.wasm_key.clone()],
+            vec![],
+        ),
+        create_tx,
+        tx(
+            &cd,
+            call_op(&cd, "put_persistent", vec![sym("k"), ScVal::U64(42)]),
+            vec![cd.wasm_key.clone(), cd.contract_key.clone()],
+            vec![key.clone()],
+            vec![],
+        ),
+        tx(
+            &cd,
+            call_op(&cd, "get_persistent", vec![sym("k")]),
+            vec![cd.wasm_key.clone(), cd.contract_key.clone(), key.clone()],
+            vec![],
+            vec![],
+        ),
+    ];
+    let executor = TransactionSetExecutor::new(ledger_info.clone(), None, None, false);
+    let res = executor
+        .execute(Rc::new(MockSnapshotSource::new()), &txs)
+        .unwrap();
+
+    assert_eq!(res.transaction_results.len(), 4);
+    for tx_res in &res.transaction_results {
+        assert!(tx_res.result.is_ok());
+        assert!(tx_res.cpu_insns_consumed > 0);
+    }
+    assert_eq!(
+        res.transaction_results[1].result.as_ref().unwrap(),
+        &Some(ScVal::Address(cd.contract_address.clone()))
+    );
+    assert_eq!(
+        res.transaction_results[3].result.as_ref().unwrap(),
+        &Some(ScVal::U64(42))
+    );
+    let diff_keys: Vec<LedgerKey> = res
+        .state_diff
+        .iter()
+        .map(|d| d.key.as_ref().clone())
+        .collect();
+    let mut expected_keys = vec![cd.wasm_key.clone(), cd.contract_key.clone(), key.clone()];
+    expected_keys.sort();
+    assert_eq!(diff_keys, expected_keys);
+    for diff in &res.state_diff {
+        assert!(diff.state_before.is_none());
+        assert!(diff.state_after.is_some());
+    }
+}
+
+#[test]
+fn test_tx_set_failed_tx_does_not_modify_state() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let key = data_key(
+        &cd.contract_address,
+        "k",
+        ContractDataDurability::Persistent,
+    );
+    let contract_keys = vec![cd.wasm_key.clone(), cd.contract_key.clone()];
+    let mut out_of_budget_tx = tx(
+        &cd,
+        call_op(&cd, "put_persistent", vec![sym("k"), ScVal::U64(1)]),
+        contract_keys.clone(),
+        vec![key.clone()],
+        vec![],
+    );
+    out_of_budget_tx.transaction_data.resources.instructions = 1000;
+    let txs = vec![
+        // Entry doesn't exist yet.
+        tx(
+            &cd,
+            call_op(&cd, "get_persistent", vec![sym("k")]),
+            vec![cd.wasm_key.clone(), cd.contract_key.clone(), key.clone()],
+            vec![],
+            vec![],
+        ),
+        out_of_budget_tx,
+        tx(
+            &cd,
+            call_op(&cd, "put_persistent", vec![sym("k"), ScVal::U64(2)]),
+            contract_keys.clone(),
+            vec![key.clone()],
+            vec![],
+        ),
+    ];
+    let cost_params = |const_term: i64| {
+        ContractCostParams(
+            vec![
+                ContractCostParamEntry {
+                    ext: ExtensionPoint::V0,
+                    const_term,
+                    linear_term: 1,
+                };
+                ContractCostType::variants().len()
+            ]
+            .try_into()
+            .unwrap(),
+        )
+    };
+    let budget_config = TransactionBudgetConfig {
+        tx_memory_limit: 100_000_000,
+        cpu_cost_params: cost_params(100),
+        memory_cost_params: cost_params(10),
+    };
+    let executor =
+        TransactionSetExecutor::new(ledger_info.clone(), Some(budget_config), None, true);
+    let res = executor
+        .execute(deployed_contract_snapshot(&cd, vec![]), &txs)
+        .unwrap();
+
+    assert!(res.transaction_results[0].result.is_err());
+    assert!(res.transaction_results[0].ledger_changes.is_empty());
+    assert!(!res.transaction_results[0].diagnostic_events.is_empty());
+    assert!(HostError::result_matches_err(
+        res.transaction_results[1].result.clone(),
+        (ScErrorType::Budget, ScErrorCode::ExceededLimit)
+    ));
+    assert!(res.transaction_results[2].result.is_ok());
+
+    assert_eq!(res.state_diff.len(), 1);
+    let diff = &res.state_diff[0];
+    assert_eq!(diff.key.as_ref(), &key);
+    assert!(diff.state_before.is_none());
+    let (entry, live_until) = diff.state_after.clone().unwrap();
+    assert_eq!(
+        entry.as_ref(),
+        &data_entry(
+            &cd.contract_address,
+            "k",
+            2,
+            ContractDataDurability::Persistent
+        )
+    );
+    assert_eq!(
+        live_until,
+        ledger_info.min_live_until_ledger_checked(ContractDataDurability::Persistent)
+    );
+}
+
+#[test]
+fn test_tx_set_expired_entries() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let expired = ledger_info.sequence_number - 1;
+    let persistent_key = data_key(
+        &cd.contract_address,
+        "p",
+        ContractDataDurability::Persistent,
+    );
+    let temp_key = data_key(&cd.contract_address, "t", ContractDataDurability::Temporary);
+    let persistent_entry = data_entry(
+        &cd.contract_address,
+        "p",
+        10,
+        ContractDataDurability::Persistent,
+    );
+    let snapshot = deployed_contract_snapshot(
+        &cd,
+        vec![
+            (persistent_entry.clone(), Some(expired)),
+            (
+                data_entry(
+                    &cd.contract_address,
+                    "t",
+                    20,
+                    ContractDataDurability::Temporary,
+                ),
+                Some(expired),
+            ),
+        ],
+    );
+    let contract_keys = vec![cd.wasm_key.clone(), cd.contract_key.clone()];
+    let with_keys = |keys: &[&LedgerKey]| {
+        let mut v = contract_keys.clone();
+        v.extend(keys.iter().map(|k| (*k).clone()));
+        v
+    };
+    let txs = vec![
+        // Expired temporary entry is treated as non-existent.
+        tx(
+            &cd,
+            call_op(&cd, "has_temporary", vec![sym("t")]),
+            with_keys(&[&temp_key]),
+            vec![],
+            vec![],
+        ),
+        // Archived entry can't be accessed without being restored.
+        tx(
+            &cd,
+            call_op(&cd, "get_persistent", vec![sym("p")]),
+            with_keys(&[&persistent_key]),
+            vec![],
+            vec![],
+        ),
+        // Auto-restore the entry.
+        tx(
+            &cd,
+            call_op(&cd, "get_persistent", vec![sym("p")]),
+            contract_keys.clone(),
+            vec![persistent_key.clone()],
+            vec![0],
+        ),
+    ];
+    let mut executor = TransactionSetExecutor::new(ledger_info.clone(), None, None, false);
+    let res = executor.execute(snapshot.clone(), &txs).unwrap();
+    assert_eq!(
+        res.transaction_results[0].result.as_ref().unwrap(),
+        &Some(ScVal::Bool(false))
+    );
+    // Rent fees are not computed without the rent fee configuration.
+    assert_eq!(res.transaction_results[2].rent_fee, None);
+    assert!(HostError::result_matches_err(
+        res.transaction_results[1].result.clone(),
+        (ScErrorType::Storage, ScErrorCode::InvalidInput)
+    ));
+    assert_eq!(
+        res.transaction_results[2].result.as_ref().unwrap(),
+        &Some(ScVal::U64(10))
+    );
+    let min_persistent_live_until = ledger_info
+        .min_live_until_ledger_checked(ContractDataDurability::Persistent)
+        .unwrap();
+    assert_eq!(
+        res.state_diff,
+        vec![LedgerEntryStateDiff {
+            key: Rc::new(persistent_key.clone()),
+            state_before: Some((Rc::new(persistent_entry.clone()), Some(expired))),
+            state_after: Some((
+                Rc::new(persistent_entry.clone()),
+                Some(min_persistent_live_until)
+            )),
+        }]
+    );
+
+    // Restore and extend via the dedicated operations.
+    let txs = vec![
+        tx(
+            &cd,
+            SorobanOperation::RestoreFootprint(RestoreFootprintOp {
+                ext: ExtensionPoint::V0,
+            }),
+            vec![],
+            vec![persistent_key.clone(), temp_key.clone()],
+            vec![],
+        ),
+        tx(
+            &cd,
+            SorobanOperation::ExtendFootprintTtl(ExtendFootprintTtlOp {
+                ext: ExtensionPoint::V0,
+                extend_to: 200_000,
+            }),
+            with_keys(&[&persistent_key, &temp_key]),
+            vec![],
+            vec![],
+        ),
+        tx(
+            &cd,
+            call_op(&cd, "get_persistent", vec![sym("p")]),
+            with_keys(&[&persistent_key]),
+            vec![],
+            vec![],
+        ),
+    ];
+    executor.set_rent_fee_config(RentFeeConfiguration {
+        fee_per_write_1kb: 1000,
+        fee_per_rent_1kb: 1000,
+        fee_per_write_entry: 100,
+        persistent_rent_rate_denominator: 1000,
+        temporary_rent_rate_denominator: 10000,
+    });
+    let res = executor.execute(snapshot, &txs).unwrap();
+    for tx_res in &res.transaction_results {
+        assert!(tx_res.result.is_ok());
+    }
+    // The restoration and the extension are charged for rent, while the
+    // read-only invocation doesn't change any entries.
+    assert!(res.transaction_results[0].rent_fee.unwrap() > 0);
+    assert!(res.transaction_results[1].rent_fee.unwrap() > 0);
+    assert_eq!(res.transaction_results[2].rent_fee, Some(0));
+    let restore_changes = &res.transaction_results[0].ledger_changes;
+    assert_eq!(restore_changes.len(), 1);
+    let ttl_change = restore_changes[0].ttl_change.as_ref().unwrap();
+    assert_eq!(ttl_change.old_live_until_ledger, 0);
+    assert_eq!(ttl_change.new_live_until_ledger, min_persistent_live_until);
+    // Wasm, instance and the restored entry have been extended.
+    let extend_changes = &res.transaction_results[1].ledger_changes;
+    assert_eq!(extend_changes.len(), 3);
+    for change in extend_changes {
+        assert!(change.read_only);
+        assert_eq!(
+            change.ttl_change.as_ref().unwrap().new_live_until_ledger,
+            ledger_info.sequence_number + 200_000
+        );
+    }
+    assert_eq!(
+        res.transaction_results[2].result.as_ref().unwrap(),
+        &Some(ScVal::U64(10))
+    );
+    assert_eq!(res.state_diff.len(), 3);
+    for diff in &res.state_diff {
+        assert_eq!(
+            diff.state_after.as_ref().unwrap().1,
+            Some(ledger_info.sequence_number + 200_000)
+        );
+    }
+}
+
+#[test]
+fn test_tx_set_extend_beyond_max_entry_ttl_fails() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let snapshot = deployed_contract_snapshot(&cd, vec![]);
+    let extend_tx = |extend_to: u32| {
+        tx(
+            &cd,
+            SorobanOperation::ExtendFootprintTtl(ExtendFootprintTtlOp {
+                ext: ExtensionPoint::V0,
+                extend_to,
+            }),
+            vec![cd.wasm_key.clone(), cd.contract_key.clone()],
+            vec![],
+            vec![],
+        )
+    };
+    let txs = vec![
+        extend_tx(ledger_info.max_entry_ttl),
+        extend_tx(ledger_info.max_entry_ttl - 1),
+    ];
+    let executor = TransactionSetExecutor::new(ledger_info.clone(), None, None, false);
+    let res = executor.execute(snapshot, &txs).unwrap();
+    assert!(HostError::result_matches_err(
+        res.transaction_results[0].result.clone(),
+        (ScErrorType::Storage, ScErrorCode::InvalidInput)
+    ));
+    assert!(res.transaction_results[0].ledger_changes.is_empty());
+    assert!(res.transaction_results[1].result.is_ok());
+    for change in &res.transaction_results[1].ledger_changes {
+        assert_eq!(
+            change.ttl_change.as_ref().unwrap().new_live_until_ledger,
+            ledger_info.sequence_number + ledger_info.max_entry_ttl - 1
+        );
+    }
+    assert_eq!(res.transaction_results[1].ledger_changes.len(), 2);
+}
+
+#[test]
+fn test_tx_set_with_module_cache() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let host = Host::default();
+    host.as_budget().reset_unlimited().unwrap();
+    let module_cache = ModuleCache::new(&host).unwrap();
+    let LedgerEntryData::ContractCode(code) = &cd.wasm_entry.data else {
+        panic!("expected contract code entry");
+    };
+    let ContractCodeEntryExt::V1(ext) = &code.ext else {
+        panic!("expected refined cost inputs");
+    };
+    module_cache
+        .parse_and_cache_module(
+            &host,
+            ledger_info.protocol_version,
+            &get_wasm_hash(CONTRACT_STORAGE).into(),
+            CONTRACT_STORAGE,
+            VersionedContractCodeCostInputs::V1(ext.cost_inputs.clone()),
+        )
+        .unwrap();
+
+    let key = data_key(
+        &cd.contract_address,
+        "k",
+        ContractDataDurability::Persistent,
+    );
+    let put_tx = |val: u64| {
+        tx(
+            &cd,
+            call_op(&cd, "put_persistent", vec![sym("k"), ScVal::U64(val)]),
+            vec![cd.wasm_key.clone(), cd.contract_key.clone()],
+            vec![key.clone()],
+            vec![],
+        )
+    };
+    let txs = vec![put_tx(1), put_tx(2)];
+    let snapshot = deployed_contract_snapshot(&cd, vec![]);
+
+    let cached_res =
+        TransactionSetExecutor::new(ledger_info.clone(), None, Some(module_cache.clone()), false)
+            .execute(snapshot.clone(), &txs)
+            .unwrap();
+    let uncached_res = TransactionSetExecutor::new(ledger_info.clone(), None, None, false)
+        .execute(snapshot.clone(), &txs)
+        .unwrap();
+    assert_eq!(cached_res.state_diff, uncached_res.state_diff);
+    for (cached, uncached) in cached_res
+        .transaction_results
+        .iter()
+        .zip(uncached_res.transaction_results.iter())
+    {
+        assert!(cached.result.is_ok());
+        // Parsing the module is not necessary with the cache.
+        assert!(cached.cpu_insns_consumed < uncached.cpu_insns_consumed);
+    }
+
+    // The overlay can also be used directly for applying the transactions
+    // one by one.
+    let executor = TransactionSetExecutor::new(ledger_info, None, Some(module_cache), false);
+    let mut overlay = LedgerOverlay::new(snapshot);
+    for tx in &txs {
+        assert!(executor
+            .apply_transaction(&mut overlay, tx)
+            .unwrap()
+            .result
+            .is_ok());
+    }
+    assert_eq!(overlay.state_diff().unwrap(), cached_res.state_diff);
+}
+
+fn assert_tx_set_results_eq(a: &TransactionSetResult, b: &TransactionSetResult) {
+    assert_eq!(a.state_diff, b.state_diff);
+    assert_eq!(a.transaction_results.len(), b.transaction_results.len());
+    for (a, b) in a
+        .transaction_results
+        .iter()
+        .zip(b.transaction_results.iter())
+    {
+        assert_eq!(
+            a.result.as_ref().map_err(|e| e.error),
+            b.result.as_ref().map_err(|e| e.error)
+        );
+        assert_eq!(a.contract_events, b.contract_events);
+        assert_eq!(a.cpu_insns_consumed, b.cpu_insns_consumed);
+        assert_eq!(a.mem_bytes_consumed, b.mem_bytes_consumed);
+        assert_eq!(a.rent_fee, b.rent_fee);
+        assert_eq!(a.ledger_changes, b.ledger_changes);
+    }
+}
+
+#[test]
+fn test_tx_set_parallel_execution_matches_sequential() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let ledger_info = default_ledger_info();
+    let contract_keys = vec![cd.wasm_key.clone(), cd.contract_key.clone()];
+    let key = |k: &str| data_key(&cd.contract_address, k, ContractDataDurability::Persistent);
+    let with_keys = |keys: Vec<LedgerKey>| {
+        let mut v = contract_keys.clone();
+        v.extend(keys);
+        v
+    };
+    let put_tx = |k: &str, val: u64| {
+        tx(
+            &cd,
+            call_op(&cd, "put_persistent", vec![sym(k), ScVal::U64(val)]),
+            contract_keys.clone(),
+            vec![key(k)],
+            vec![],
+        )
+    };
+    let get_tx = |k: &str| {
+        tx(
+            &cd,
+            call_op(&cd, "get_persistent", vec![sym(k)]),
+            with_keys(vec![key(k)]),
+            vec![],
+            vec![],
+        )
+    };
+    let txs = vec![
+        put_tx("a", 1),
+        put_tx("b", 2),
+        put_tx("a", 3),
+        get_tx("c"),
+        // Extends the TTL of the read-only entry that is also read by the
+        // independent transactions, which requires merging their clusters.
+        tx(
+            &cd,
+            call_op(
+                &cd,
+                "extend_persistent",
+                vec![sym("c"), ScVal::U32(5000), ScVal::U32(10_000)],
+            ),
+            with_keys(vec![key("c")]),
+            vec![],
+            vec![],
+        ),
+        get_tx("c"),
+        get_tx("b"),
+        put_tx("d", 4),
+    ];
+    let snapshot = deployed_contract_snapshot(
+        &cd,
+        vec![(
+            data_entry(
+                &cd.contract_address,
+                "c",
+                5,
+                ContractDataDurability::Persistent,
+            ),
+            Some(ledger_info.sequence_number + 1000),
+        )],
+    );
+    let executor = TransactionSetExecutor::new(ledger_info.clone(), None, None, false);
+    let sequential_res = executor.execute(snapshot.clone(), &txs).unwrap();
+    for tx_res in &sequential_res.transaction_results {
+        assert!(tx_res.result.is_ok());
+    }
+    // The last read observes the TTL extended by the previous transaction.
+    let c_change = sequential_res.transaction_results[5]
+        .ledger_changes
+        .iter()
+        .find_map(|c| (c.key == key("c")).then_some(c.ttl_change.clone().unwrap()))
+        .unwrap();
+    assert_eq!(
+        c_change.old_live_until_ledger,
+        ledger_info.sequence_number + 10_000
+    );
+
+    for num_threads in [1, 2, 4, 16] {
+        let parallel_res = executor
+            .execute_parallel(snapshot.clone(), &txs, num_threads)
+            .unwrap();
+        assert_tx_set_results_eq(&sequential_res, &parallel_res);
+    }
+}
+
+#[test]
+fn test_ledger_overlay_apply_ledger_changes() {
+    let cd = CreateContractData::new([111; 32], CONTRACT_STORAGE);
+    let mut overlay = LedgerOverlay::new(deployed_contract_snapshot(&cd, vec![]));
+    let live_until = default_ledger_info().sequence_number + 1000;
+    let key = data_key(
+        &cd.contract_address,
+        "k",
+        ContractDataDurability::Persistent,
+    );
+    let entry = data_entry(
+        &cd.contract_address,
+        "k",
+        1,
+        ContractDataDurability::Persistent,
+    );
+    let ttl_change = |old_live_until_ledger, new_live_until_ledger| {
+        Some(LedgerEntryLiveUntilChange {
+            key_hash: vec![0; 32],
+            durability: ContractDataDurability::Persistent,
+            entry_type: LedgerEntryType::ContractData,
+            old_live_until_ledger,
+            new_live_until_ledger,
+        })
+    };
+    let change = |key: &LedgerKey, read_only, new_value, ttl_change| TypedLedgerEntryChange {
+        read_only,
+        key: key.clone(),
+        old_entry_size_bytes_for_rent: 0,
+        new_value,
+        new_entry_size_bytes_for_rent: 0,
+        ttl_change,
+    };
+    let get = |overlay: &LedgerOverlay, key: &LedgerKey| {
+        overlay
+            .get(&Rc::new(key.clone()))
+            .unwrap()
+            .map(|(e, live_until)| (e.as_ref().clone(), live_until))
+    };
+
+    overlay
+        .apply_ledger_changes(&[
+            change(&key, false, Some(entry.clone()), ttl_change(0, 100)),
+            // Read-only change that extends the TTL.
+            change(
+                &cd.wasm_key,
+                true,
+                None,
+                ttl_change(live_until, live_until + 5000),
+            ),
+            // Read-only change that doesn't extend the TTL is ignored.
+            change(&cd.contract_key, true, None, ttl_change(live_until, 10)),
+        ])
+        .unwrap();
+    assert_eq!(get(&overlay, &key), Some((entry, Some(100))));
+    assert_eq!(
+        get(&overlay, &cd.wasm_key),
+        Some((cd.wasm_entry.clone(), Some(live_until + 5000)))
+    );
+    assert_eq!(
+        get(&overlay, &cd.contract_key),
+        Some((cd.contract_entry.clone(), Some(live_until)))
+    );
+
+    // Removal.
+    overlay
+        .apply_ledger_changes(&[change(&key, false, None, None)])
+        .unwrap();
+    assert_eq!(get(&overlay, &key), None);
+
+    // Extending the TTL of a missing entry is an error.
+    assert!(HostError::result_matches_err(
+        overlay.apply_ledger_changes(&[change(&key, true, None, ttl_change(0, 100))]),
+        (ScErrorType::Storage, ScErrorCode::InvalidInput)
+    ));
+}
@@ -6,8 +6,8 @@ use crate::{
     builtin_contracts::testutils::create_account,
     storage::{SnapshotSource, Storage},
     xdr::{
-        AccountId, ContractCostType, LedgerEntry, LedgerKey, PublicKey, ScAddress, ScVal, ScVec,
-        Uint256,
+        AccountId, ContractCostType, ContractDataDurability, LedgerEntry, LedgerKey, PublicKey,
+        ScAddress, ScVal, ScVec, Uint256,
     },
     AddressObject, BytesObject, Env, EnvBase, Host, HostError, LedgerInfo, MeteredOrdMap,
     StorageType, SymbolSmall, Val, VecObject,
@@ -143,6 +143,23 @@ impl SnapshotSource for MockSnapshotSource {
             Ok(None)
         }
     }
+
+    fn contract_data_keys(
+        &self,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+    ) -> Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        Ok(Some(
+            self.0
+                .keys()
+                .filter(|key| {
+                    matches!(key.as_ref(), LedgerKey::ContractData(cd)
+                        if cd.contract == *contract && cd.durability == durability)
+                })
+                .cloned()
+                .collect(),
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,548 @@
+//! This module contains [TransactionSetExecutor], a helper for applying an
+//! ordered sequence of Soroban transactions on top of a ledger snapshot.
+//!
+//! Every transaction is executed within a fresh host instance via
+//! [invoke_host_function_typed](crate::e2e_invoke::invoke_host_function_typed), and the
+//! resulting ledger changes are applied to a [LedgerOverlay] that serves as
+//! the input state of the next transaction. This mirrors what the embedder
+//! (i.e. Core) does when applying a transaction set, with the exception of
+//! the transaction-level validation (fees, resource limits etc.) that is not
+//! performed here.
+//!
+//! Transactions with non-conflicting footprints may also be applied
+//! concurrently via [TransactionSetExecutor::execute_parallel].
+use std::{collections::BTreeMap, rc::Rc};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    budget::Budget,
+    e2e_invoke::{
+        entry_size_for_rent, extract_rent_changes_from_typed, invoke_host_function_typed,
+        LedgerEntryLiveUntilChange, TypedLedgerEntryChange,
+    },
+    fees::{compute_rent_fee, RentFeeConfiguration},
+    ledger_info::get_key_durability,
+    storage::{EntryWithLiveUntil, SnapshotSource},
+    xdr::{
+        AccountId, ContractCostParams, ContractDataDurability, ContractEvent, DiagnosticEvent,
+        ExtendFootprintTtlOp, InvokeHostFunctionOp, LedgerEntry, LedgerKey, RestoreFootprintOp,
+        ScErrorCode, ScErrorType, ScVal, SorobanResources, SorobanTransactionData,
+        SorobanTransactionDataExt, WriteXdr,
+    },
+    HostError, LedgerInfo, ModuleCache, DEFAULT_XDR_RW_LIMITS,
+};
+
+mod parallel;
+
+/// Soroban operation to be applied by the [TransactionSetExecutor].
+#[derive(Clone, Debug)]
+pub enum SorobanOperation {
+    InvokeHostFunction(InvokeHostFunctionOp),
+    ExtendFootprintTtl(ExtendFootprintTtlOp),
+    RestoreFootprint(RestoreFootprintOp),
+}
+
+/// A single Soroban transaction to be applied by the [TransactionSetExecutor].
+#[derive(Clone, Debug)]
+pub struct SorobanTransaction {
+    /// Source account of the transaction (or of the operation, if overridden).
+    pub source_account: AccountId,
+    /// The only operation of the transaction.
+    pub operation: SorobanOperation,
+    /// Soroban-specific transaction data, i.e. the resources and the indices
+    /// of the archived entries to be restored.
+    pub transaction_data: SorobanTransactionData,
+    /// Seed for the host PRNG, only used for `InvokeHostFunction` operations.
+    pub base_prng_seed: [u8; 32],
+}
+
+/// Network settings used for building the per-transaction budgets.
+#[derive(Clone, Debug)]
+pub struct TransactionBudgetConfig {
+    /// Memory limit for a single transaction.
+    pub tx_memory_limit: u64,
+    /// CPU cost model parameters.
+    pub cpu_cost_params: ContractCostParams,
+    /// Memory cost model parameters.
+    pub memory_cost_params: ContractCostParams,
+}
+
+/// Result of applying a single transaction.
+pub struct TransactionResult {
+    /// Result of the operation. Contains the return value of the host function
+    /// for `InvokeHostFunction` operations and `None` for the other
+    /// operations.
+    ///
+    /// When the transaction fails, none of its changes are applied to the
+    /// ledger state.
+    pub result: Result<Option<ScVal>, HostError>,
+    /// Ledger changes caused by the transaction, in the same format as
+    /// produced by `invoke_host_function_typed`.
+    ///
+    /// For `ExtendFootprintTtl` operations this only contains the extended
+    /// entries and for `RestoreFootprint` operations this only contains
+    /// the restored entries.
+    ///
+    /// Empty when the transaction fails.
+    pub ledger_changes: Vec<TypedLedgerEntryChange>,
+    /// Contract events emitted by the transaction.
+    ///
+    /// Empty when the transaction fails.
+    pub contract_events: Vec<ContractEvent>,
+    /// Diagnostic events emitted by the transaction. Only populated when
+    /// diagnostics are enabled.
+    pub diagnostic_events: Vec<DiagnosticEvent>,
+    /// Rent fee for the ledger changes of the transaction, as computed by
+    /// `compute_rent_fee`. This is computed for every operation type, but
+    /// only when the rent fee configuration has been provided via
+    /// [TransactionSetExecutor::set_rent_fee_config].
+    ///
+    /// `None` when the transaction fails.
+    pub rent_fee: Option<i64>,
+    /// CPU instructions consumed by the host.
+    pub cpu_insns_consumed: u64,
+    /// Memory bytes consumed by the host.
+    pub mem_bytes_consumed: u64,
+}
+
+/// Change of a single ledger entry between the base snapshot and the final
+/// state of the [LedgerOverlay].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LedgerEntryStateDiff {
+    pub key: Rc<LedgerKey>,
+    /// Entry and its live until ledger in the base snapshot, `None` if the
+    /// entry did not exist.
+    pub state_before: Option<EntryWithLiveUntil>,
+    /// Entry and its live until ledger after all the transactions have been
+    /// applied, `None` if the entry has been removed.
+    pub state_after: Option<EntryWithLiveUntil>,
+}
+
+/// Result of applying a transaction set.
+pub struct TransactionSetResult {
+    /// Per-transaction results, in the order of the input transactions.
+    pub transaction_results: Vec<TransactionResult>,
+    /// All the entries that differ between the base snapshot and the final
+    /// state, ordered by key.
+    pub state_diff: Vec<LedgerEntryStateDiff>,
+}
+
+/// [SnapshotSource] that applies the modifications on top of a base snapshot
+/// without modifying it.
+pub struct LedgerOverlay {
+    base: Rc<dyn SnapshotSource>,
+    // `None` value means that the entry has been removed.
+    modified: BTreeMap<Rc<LedgerKey>, Option<EntryWithLiveUntil>>,
+}
+
+impl LedgerOverlay {
+    pub fn new(base: Rc<dyn SnapshotSource>) -> Self {
+        Self {
+            base,
+            modified: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the entry for the given key, or removes it if `entry` is `None`.
+    pub fn set(&mut self, key: Rc<LedgerKey>, entry: Option<EntryWithLiveUntil>) {
+        self.modified.insert(key, entry);
+    }
+
+    /// Applies the changes produced by `invoke_host_function_typed` (or the
+    /// [TransactionSetExecutor]) to this overlay.
+    ///
+    /// See [TypedLedgerEntryChange::updated_entry] for the details on how
+    /// the changes are applied.
+    pub fn apply_ledger_changes(
+        &mut self,
+        changes: &[TypedLedgerEntryChange],
+    ) -> Result<(), HostError> {
+        for change in changes {
+            if let Some((key, entry)) = change.updated_entry(self)? {
+                self.set(key, entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns all the entries that differ from the base snapshot, ordered by
+    /// key.
+    pub fn state_diff(&self) -> Result<Vec<LedgerEntryStateDiff>, HostError> {
+        let mut diff = vec![];
+        for (key, state_after) in &self.modified {
+            let state_before = self.base.get(key)?;
+            if state_before != *state_after {
+                diff.push(LedgerEntryStateDiff {
+                    key: key.clone(),
+                    state_before,
+                    state_after: state_after.clone(),
+                });
+            }
+        }
+        Ok(diff)
+    }
+}
+
+impl SnapshotSource for LedgerOverlay {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        match self.modified.get(key) {
+            Some(entry) => Ok(entry.clone()),
+            None => self.base.get(key),
+        }
+    }
+}
+
+/// Applies ordered sequences of Soroban transactions on top of a ledger
+/// snapshot.
+///
+/// All the transactions are executed at the same ledger (as defined by the
+/// provided `LedgerInfo`) and share the same `ModuleCache`. Note, that just
+/// like on the network, the modules uploaded by the transactions are not
+/// added to the cache.
+pub struct TransactionSetExecutor {
+    ledger_info: LedgerInfo,
+    budget_config: Option<TransactionBudgetConfig>,
+    rent_fee_config: Option<RentFeeConfiguration>,
+    module_cache: Option<ModuleCache>,
+    enable_diagnostics: bool,
+}
+
+impl TransactionSetExecutor {
+    /// Creates a new executor.
+    ///
+    /// When `budget_config` is `None`, every transaction gets the default
+    /// budget that is only suitable for local testing (in particular, the
+    /// instructions declared in the transaction resources are not enforced).
+    pub fn new(
+        ledger_info: LedgerInfo,
+        budget_config: Option<TransactionBudgetConfig>,
+        module_cache: Option<ModuleCache>,
+        enable_diagnostics: bool,
+    ) -> Self {
+        Self {
+            ledger_info,
+            budget_config,
+            rent_fee_config: None,
+            module_cache,
+            enable_diagnostics,
+        }
+    }
+
+    /// Sets the rent fee configuration used for computing
+    /// `TransactionResult::rent_fee` of the applied transactions.
+    pub fn set_rent_fee_config(&mut self, rent_fee_config: RentFeeConfiguration) {
+        self.rent_fee_config = Some(rent_fee_config);
+    }
+
+    /// Applies `transactions` in order on top of `snapshot`.
+    ///
+    /// Transaction failures are reported in the respective
+    /// `TransactionResult` and don't stop the execution of the remaining
+    /// transactions. The only errors returned from this function are the
+    /// errors coming from the `snapshot` itself (including the malformed
+    /// snapshot entries, such as contract data entries without a TTL) and
+    /// the errors caused by an invalid `budget_config`.
+    pub fn execute(
+        &self,
+        snapshot: Rc<dyn SnapshotSource>,
+        transactions: &[SorobanTransaction],
+    ) -> Result<TransactionSetResult, HostError> {
+        let mut overlay = LedgerOverlay::new(snapshot);
+        let mut transaction_results = Vec::with_capacity(transactions.len());
+        for tx in transactions {
+            transaction_results.push(self.apply_transaction(&mut overlay, tx)?);
+        }
+        Ok(TransactionSetResult {
+            transaction_results,
+            state_diff: overlay.state_diff()?,
+        })
+    }
+
+    /// Applies a single transaction to `overlay`.
+    ///
+    /// The changes are only applied when the transaction succeeds.
+    pub fn apply_transaction(
+        &self,
+        overlay: &mut LedgerOverlay,
+        tx: &SorobanTransaction,
+    ) -> Result<TransactionResult, HostError> {
+        let budget = self.build_budget(&tx.transaction_data.resources)?;
+        let mut res = TransactionResult {
+            result: Ok(None),
+            ledger_changes: vec![],
+            contract_events: vec![],
+            diagnostic_events: vec![],
+            rent_fee: None,
+            cpu_insns_consumed: 0,
+            mem_bytes_consumed: 0,
+        };
+        let changes = match &tx.operation {
+            SorobanOperation::InvokeHostFunction(op) => {
+                self.invoke_host_function(overlay, tx, op, &budget, &mut res)
+            }
+            SorobanOperation::ExtendFootprintTtl(op) => {
+                self.extend_footprint_ttl(overlay, &tx.transaction_data.resources, op, &budget)
+            }
+            SorobanOperation::RestoreFootprint(_) => {
+                self.restore_footprint(overlay, &tx.transaction_data.resources, &budget)
+            }
+        }?;
+        res.cpu_insns_consumed = budget.get_cpu_insns_consumed()?;
+        res.mem_bytes_consumed = budget.get_mem_bytes_consumed()?;
+        match changes {
+            Ok(changes) => {
+                overlay.apply_ledger_changes(&changes)?;
+                res.rent_fee = self.rent_fee_config.as_ref().map(|rent_fee_config| {
+                    compute_rent_fee(
+                        &extract_rent_changes_from_typed(&changes),
+                        rent_fee_config,
+                        self.ledger_info.sequence_number,
+                    )
+                });
+                res.ledger_changes = changes;
+            }
+            Err(e) => {
+                res.result = Err(e);
+                res.contract_events.clear();
+            }
+        }
+        Ok(res)
+    }
+
+    fn build_budget(&self, resources: &SorobanResources) -> Result<Budget, HostError> {
+        match &self.budget_config {
+            Some(config) => Budget::try_from_configs(
+                resources.instructions as u64,
+                config.tx_memory_limit,
+                config.cpu_cost_params.clone(),
+                config.memory_cost_params.clone(),
+            ),
+            None => Ok(Budget::default()),
+        }
+    }
+
+    // Returns the error for the transaction in the outer `Ok` and the
+    // snapshot errors in the outer `Err`.
+    fn invoke_host_function(
+        &self,
+        overlay: &LedgerOverlay,
+        tx: &SorobanTransaction,
+        op: &InvokeHostFunctionOp,
+        budget: &Budget,
+        res: &mut TransactionResult,
+    ) -> Result<Result<Vec<TypedLedgerEntryChange>, HostError>, HostError> {
+        let resources = &tx.transaction_data.resources;
+        let restored_rw_entry_indices: &[u32] = match &tx.transaction_data.ext {
+            SorobanTransactionDataExt::V0 => &[],
+            SorobanTransactionDataExt::V1(ext) => ext.archived_soroban_entries.as_slice(),
+        };
+        let mut ledger_entries = vec![];
+        let read_write_keys = resources.footprint.read_write.iter().enumerate();
+        let read_only_keys = resources
+            .footprint
+            .read_only
+            .iter()
+            .map(|k| (usize::MAX, k));
+        for (rw_index, key) in read_write_keys.chain(read_only_keys) {
+            let key = Rc::new(key.clone());
+            let is_restored = restored_rw_entry_indices.contains(&(rw_index as u32));
+            let Some((entry, live_until)) = self.load_entry(overlay, &key, is_restored)? else {
+                continue;
+            };
+            let live_until = match live_until {
+                Ok(live_until) => live_until,
+                Err(e) => return Ok(Err(e)),
+            };
+            ledger_entries.push((entry.as_ref().clone(), live_until));
+        }
+        let invoke_res = invoke_host_function_typed(
+            budget,
+            self.enable_diagnostics,
+            op.host_function.clone(),
+            resources.clone(),
+            restored_rw_entry_indices,
+            tx.source_account.clone(),
+            op.auth.to_vec(),
+            self.ledger_info.clone(),
+            ledger_entries,
+            tx.base_prng_seed,
+            &mut res.diagnostic_events,
+            None,
+            self.module_cache.clone(),
+        );
+        let invoke_res = match invoke_res {
+            Ok(invoke_res) => invoke_res,
+            Err(e) => return Ok(Err(e)),
+        };
+        match invoke_res.invoke_result {
+            Ok(result) => res.result = Ok(Some(result)),
+            Err(e) => return Ok(Err(e)),
+        }
+        res.contract_events = invoke_res.contract_events;
+        Ok(Ok(invoke_res.ledger_changes))
+    }
+
+    // Loads the entry to be passed to the host, following the network rules:
+    // expired temporary entries are treated as non-existent, archived
+    // persistent entries have to be restored (in which case they get the
+    // minimum persistent TTL). Errors for the entries that can't be accessed
+    // are returned in the inner `Result`.
+    #[allow(clippy::type_complexity)]
+    fn load_entry(
+        &self,
+        overlay: &LedgerOverlay,
+        key: &Rc<LedgerKey>,
+        is_restored: bool,
+    ) -> Result<Option<(Rc<LedgerEntry>, Result<Option<u32>, HostError>)>, HostError> {
+        let Some((entry, live_until)) = overlay.get(key)? else {
+            return Ok(None);
+        };
+        let Some(durability) = get_key_durability(key) else {
+            return Ok(Some((entry, Ok(live_until))));
+        };
+        let live_until = live_until.ok_or_else(internal_error)?;
+        let is_live = live_until >= self.ledger_info.sequence_number;
+        let live_until = match (durability, is_live, is_restored) {
+            (_, true, false) => Ok(Some(live_until)),
+            (ContractDataDurability::Temporary, false, false) => return Ok(None),
+            (ContractDataDurability::Persistent, false, true) => {
+                self.min_persistent_live_until_ledger().map(Some)
+            }
+            (_, _, _) => Err(HostError::from((
+                ScErrorType::Storage,
+                ScErrorCode::InvalidInput,
+            ))),
+        };
+        Ok(Some((entry, live_until)))
+    }
+
+    fn extend_footprint_ttl(
+        &self,
+        overlay: &LedgerOverlay,
+        resources: &SorobanResources,
+        op: &ExtendFootprintTtlOp,
+        budget: &Budget,
+    ) -> Result<Result<Vec<TypedLedgerEntryChange>, HostError>, HostError> {
+        // Same as in Core: the operation is malformed when it attempts to
+        // extend the entries beyond the maximum entry TTL.
+        if op.extend_to > self.ledger_info.max_entry_ttl.saturating_sub(1) {
+            return Ok(Err(HostError::from((
+                ScErrorType::Storage,
+                ScErrorCode::InvalidInput,
+            ))));
+        }
+        let Some(new_live_until) = self.ledger_info.sequence_number.checked_add(op.extend_to)
+        else {
+            return Ok(Err(internal_error()));
+        };
+        let mut changes = vec![];
+        for key in resources.footprint.read_only.iter() {
+            let key = Rc::new(key.clone());
+            let Some(durability) = get_key_durability(&key) else {
+                continue;
+            };
+            let Some((entry, live_until)) = overlay.get(&key)? else {
+                continue;
+            };
+            let old_live_until = live_until.ok_or_else(internal_error)?;
+            if old_live_until < self.ledger_info.sequence_number || old_live_until >= new_live_until
+            {
+                continue;
+            }
+            let size_for_rent = match size_for_rent(budget, &entry) {
+                Ok(size_for_rent) => size_for_rent,
+                Err(e) => return Ok(Err(e)),
+            };
+            let key_hash = match ledger_key_hash(&key) {
+                Ok(key_hash) => key_hash,
+                Err(e) => return Ok(Err(e)),
+            };
+            changes.push(TypedLedgerEntryChange {
+                read_only: true,
+                key: key.as_ref().clone(),
+                old_entry_size_bytes_for_rent: size_for_rent,
+                new_value: None,
+                new_entry_size_bytes_for_rent: size_for_rent,
+                ttl_change: Some(LedgerEntryLiveUntilChange {
+                    key_hash: key_hash.to_vec(),
+                    durability,
+                    entry_type: key.discriminant(),
+                    old_live_until_ledger: old_live_until,
+                    new_live_until_ledger: new_live_until,
+                }),
+            });
+        }
+        Ok(Ok(changes))
+    }
+
+    fn restore_footprint(
+        &self,
+        overlay: &LedgerOverlay,
+        resources: &SorobanResources,
+        budget: &Budget,
+    ) -> Result<Result<Vec<TypedLedgerEntryChange>, HostError>, HostError> {
+        let new_live_until = match self.min_persistent_live_until_ledger() {
+            Ok(new_live_until) => new_live_until,
+            Err(e) => return Ok(Err(e)),
+        };
+        let mut changes = vec![];
+        for key in resources.footprint.read_write.iter() {
+            let key = Rc::new(key.clone());
+            if !matches!(
+                get_key_durability(&key),
+                Some(ContractDataDurability::Persistent)
+            ) {
+                continue;
+            }
+            let Some((entry, live_until)) = overlay.get(&key)? else {
+                continue;
+            };
+            if live_until.ok_or_else(internal_error)? >= self.ledger_info.sequence_number {
+                continue;
+            }
+            let size_for_rent = match size_for_rent(budget, &entry) {
+                Ok(size_for_rent) => size_for_rent,
+                Err(e) => return Ok(Err(e)),
+            };
+            let key_hash = match ledger_key_hash(&key) {
+                Ok(key_hash) => key_hash,
+                Err(e) => return Ok(Err(e)),
+            };
+            changes.push(TypedLedgerEntryChange {
+                read_only: false,
+                key: key.as_ref().clone(),
+                old_entry_size_bytes_for_rent: 0,
+                new_value: Some(entry.as_ref().clone()),
+                new_entry_size_bytes_for_rent: size_for_rent,
+                ttl_change: Some(LedgerEntryLiveUntilChange {
+                    key_hash: key_hash.to_vec(),
+                    durability: ContractDataDurability::Persistent,
+                    entry_type: key.discriminant(),
+                    old_live_until_ledger: 0,
+                    new_live_until_ledger: new_live_until,
+                }),
+            });
+        }
+        Ok(Ok(changes))
+    }
+
+    fn min_persistent_live_until_ledger(&self) -> Result<u32, HostError> {
+        self.ledger_info
+            .min_live_until_ledger_checked(ContractDataDurability::Persistent)
+            .ok_or_else(internal_error)
+    }
+}
+
+fn internal_error() -> HostError {
+    HostError::from((ScErrorType::Context, ScErrorCode::InternalError))
+}
+
+fn ledger_key_hash(key: &LedgerKey) -> Result<[u8; 32], HostError> {
+    Ok(Sha256::digest(key.to_xdr(DEFAULT_XDR_RW_LIMITS)?).into())
+}
+
+fn size_for_rent(budget: &Budget, entry: &LedgerEntry) -> Result<u32, HostError> {
+    let encoded_entry = entry.to_xdr(DEFAULT_XDR_RW_LIMITS)?;
+    entry_size_for_rent(budget, entry, encoded_entry.len() as u32)
+}
@@ -0,0 +1,292 @@
+//! Parallel execution of the transaction sets.
+//!
+//! Transactions are partitioned into clusters using the footprints declared in
+//! their `SorobanResources`: two transactions end up in the same cluster if
+//! they both access some key and at least one of them may write it. Clusters
+//! don't observe each other's changes and thus may be executed concurrently,
+//! while transactions within a cluster are applied sequentially in their
+//! original order.
+//!
+//! The only modification that isn't declared by the footprint is the TTL
+//! extension of the read-only entries. After execution we check if any
+//! read-only entry has been extended in one cluster while being accessed by
+//! another one; if so, the clusters are merged and re-executed. This makes
+//! the results identical to the sequential application.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use super::{
+    LedgerOverlay, SorobanOperation, SorobanTransaction, TransactionResult, TransactionSetExecutor,
+    TransactionSetResult,
+};
+use crate::{
+    storage::{EntryWithLiveUntil, SnapshotSource},
+    xdr::{LedgerEntry, LedgerKey, ScErrorCode, ScErrorType},
+    HostError,
+};
+
+// Owned (and thus `Send`) representation of the ledger entries passed to and
+// from the worker threads.
+type OwnedEntries = Vec<(LedgerKey, Option<(LedgerEntry, Option<u32>)>)>;
+
+struct ClusterResult {
+    transaction_results: Vec<TransactionResult>,
+    modified_entries: OwnedEntries,
+}
+
+struct PreloadedSnapshotSource(BTreeMap<Rc<LedgerKey>, EntryWithLiveUntil>);
+
+impl SnapshotSource for PreloadedSnapshotSource {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        Ok(self.0.get(key).cloned())
+    }
+}
+
+struct DisjointSets(Vec<usize>);
+
+impl DisjointSets {
+    fn new(size: usize) -> Self {
+        Self((0..size).collect())
+    }
+
+    fn find(&mut self, mut id: usize) -> usize {
+        while self.0[id] != id {
+            self.0[id] = self.0[self.0[id]];
+            id = self.0[id];
+        }
+        id
+    }
+
+    // Returns `true` if `a` and `b` were in different sets.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        // Keep the smallest id as a root in order to make the cluster order
+        // independent of the union order.
+        self.0[a.max(b)] = a.min(b);
+        true
+    }
+
+    // Returns the sets ordered by their smallest element, each set is ordered
+    // as well.
+    fn sets(&mut self) -> Vec<Vec<usize>> {
+        let mut sets: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for id in 0..self.0.len() {
+            let root = self.find(id);
+            sets.entry(root).or_default().push(id);
+        }
+        sets.into_values().collect()
+    }
+}
+
+// Returns the keys of the transaction footprint and whether the key may be
+// written to.
+fn footprint_keys(tx: &SorobanTransaction) -> impl Iterator<Item = (&LedgerKey, bool)> + '_ {
+    let footprint = &tx.transaction_data.resources.footprint;
+    // `ExtendFootprintTtl` is the only operation that modifies the read-only
+    // footprint by design.
+    let ro_is_written = matches!(tx.operation, SorobanOperation::ExtendFootprintTtl(_));
+    footprint
+        .read_write
+        .iter()
+        .map(|k| (k, true))
+        .chain(footprint.read_only.iter().map(move |k| (k, ro_is_written)))
+}
+
+fn to_owned_entry(entry: Option<EntryWithLiveUntil>) -> Option<(LedgerEntry, Option<u32>)> {
+    entry.map(|(entry, live_until)| (entry.as_ref().clone(), live_until))
+}
+
+fn thread_error() -> HostError {
+    HostError::from((ScErrorType::Context, ScErrorCode::InternalError))
+}
+
+impl TransactionSetExecutor {
+    /// Applies `transactions` on top of `snapshot` using up to `num_threads`
+    /// worker threads.
+    ///
+    /// The result is identical to the result of [TransactionSetExecutor::execute]
+    /// for the same inputs.
+    pub fn execute_parallel(
+        &self,
+        snapshot: Rc<dyn SnapshotSource>,
+        transactions: &[SorobanTransaction],
+        num_threads: usize,
+    ) -> Result<TransactionSetResult, HostError> {
+        let mut clusters = DisjointSets::new(transactions.len());
+        let mut key_accesses: BTreeMap<&LedgerKey, (Vec<usize>, bool)> = BTreeMap::new();
+        for (tx_id, tx) in transactions.iter().enumerate() {
+            for (key, is_write) in footprint_keys(tx) {
+                let (tx_ids, written) = key_accesses.entry(key).or_default();
+                tx_ids.push(tx_id);
+                *written |= is_write;
+            }
+        }
+        for (tx_ids, written) in key_accesses.values() {
+            if *written {
+                for tx_id in &tx_ids[1..] {
+                    clusters.union(tx_ids[0], *tx_id);
+                }
+            }
+        }
+
+        let mut cluster_results: HashMap<Vec<usize>, ClusterResult> = HashMap::new();
+        loop {
+            let cluster_sets = clusters.sets();
+            let pending: Vec<&Vec<usize>> = cluster_sets
+                .iter()
+                .filter(|c| !cluster_results.contains_key(*c))
+                .collect();
+            let new_results =
+                self.execute_clusters(&snapshot, transactions, &pending, num_threads)?;
+            for (cluster, res) in pending.into_iter().zip(new_results) {
+                cluster_results.insert(cluster.clone(), res);
+            }
+            // Merge the clusters where a read-only entry has been extended in
+            // one cluster and accessed in another.
+            let mut merged = false;
+            for cluster in &cluster_sets {
+                let res = &cluster_results[cluster];
+                for (tx_id, tx_res) in cluster.iter().zip(res.transaction_results.iter()) {
+                    for change in &tx_res.ledger_changes {
+                        let Some(ttl_change) = &change.ttl_change else {
+                            continue;
+                        };
+                        if !change.read_only
+                            || ttl_change.new_live_until_ledger <= ttl_change.old_live_until_ledger
+                        {
+                            continue;
+                        }
+                        if let Some((tx_ids, _)) = key_accesses.get(&change.key) {
+                            for other_tx_id in tx_ids {
+                                merged |= clusters.union(*tx_id, *other_tx_id);
+                            }
+                        }
+                    }
+                }
+            }
+            if !merged {
+                break;
+            }
+        }
+
+        let mut transaction_results: Vec<Option<TransactionResult>> =
+            (0..transactions.len()).map(|_| None).collect();
+        let mut overlay = LedgerOverlay::new(snapshot);
+        for cluster in clusters.sets() {
+            let res = cluster_results.remove(&cluster).ok_or_else(thread_error)?;
+            for (tx_id, tx_res) in cluster.into_iter().zip(res.transaction_results) {
+                transaction_results[tx_id] = Some(tx_res);
+            }
+            for (key, entry) in res.modified_entries {
+                overlay.set(
+                    Rc::new(key),
+                    entry.map(|(entry, live_until)| (Rc::new(entry), live_until)),
+                );
+            }
+        }
+        Ok(TransactionSetResult {
+            transaction_results: transaction_results
+                .into_iter()
+                .map(|res| res.ok_or_else(thread_error))
+                .collect::<Result<Vec<_>, _>>()?,
+            state_diff: overlay.state_diff()?,
+        })
+    }
+
+    fn execute_clusters(
+        &self,
+        snapshot: &Rc<dyn SnapshotSource>,
+        transactions: &[SorobanTransaction],
+        clusters: &[&Vec<usize>],
+        num_threads: usize,
+    ) -> Result<Vec<ClusterResult>, HostError> {
+        // Snapshot source is not thread-safe, so every cluster gets a copy of
+        // all the entries it may need.
+        let mut inputs = Vec::with_capacity(clusters.len());
+        for cluster in clusters {
+            let mut keys = BTreeSet::new();
+            for tx_id in cluster.iter() {
+                keys.extend(footprint_keys(&transactions[*tx_id]).map(|(k, _)| k));
+            }
+            let mut entries = OwnedEntries::with_capacity(keys.len());
+            for key in keys {
+                let key = Rc::new(key.clone());
+                let entry = to_owned_entry(snapshot.get(&key)?);
+                entries.push((key.as_ref().clone(), entry));
+            }
+            inputs.push(Mutex::new(Some(entries)));
+        }
+
+        let results: Vec<Mutex<Option<Result<ClusterResult, HostError>>>> =
+            clusters.iter().map(|_| Mutex::new(None)).collect();
+        let next_cluster = AtomicUsize::new(0);
+        let worker = || loop {
+            let cluster_id = next_cluster.fetch_add(1, Ordering::Relaxed);
+            if cluster_id >= clusters.len() {
+                break;
+            }
+            let res = inputs[cluster_id]
+                .lock()
+                .map_err(|_| thread_error())
+                .and_then(|mut input| input.take().ok_or_else(thread_error))
+                .and_then(|entries| {
+                    self.execute_cluster(transactions, clusters[cluster_id], entries)
+                });
+            if let Ok(mut out) = results[cluster_id].lock() {
+                *out = Some(res);
+            }
+        };
+        std::thread::scope(|s| {
+            for _ in 1..num_threads.min(clusters.len()) {
+                s.spawn(worker);
+            }
+            worker();
+        });
+        results
+            .into_iter()
+            .map(|res| {
+                res.into_inner()
+                    .map_err(|_| thread_error())?
+                    .ok_or_else(thread_error)?
+            })
+            .collect()
+    }
+
+    fn execute_cluster(
+        &self,
+        transactions: &[SorobanTransaction],
+        cluster: &[usize],
+        entries: OwnedEntries,
+    ) -> Result<ClusterResult, HostError> {
+        let snapshot = PreloadedSnapshotSource(
+            entries
+                .into_iter()
+                .filter_map(|(key, entry)| {
+                    entry.map(|(entry, live_until)| (Rc::new(key), (Rc::new(entry), live_until)))
+                })
+                .collect(),
+        );
+        let mut overlay = LedgerOverlay::new(Rc::new(snapshot));
+        let mut transaction_results = Vec::with_capacity(cluster.len());
+        for tx_id in cluster {
+            transaction_results.push(self.apply_transaction(&mut overlay, &transactions[*tx_id])?);
+        }
+        Ok(ClusterResult {
+            transaction_results,
+            modified_entries: overlay
+                .modified
+                .into_iter()
+                .map(|(key, entry)| (key.as_ref().clone(), to_owned_entry(entry)))
+                .collect(),
+        })
+    }
+}
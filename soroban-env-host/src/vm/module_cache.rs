@@ -1,5 +1,5 @@
 use super::parsed_module::{CompilationContext, ParsedModule, VersionedContractCodeCostInputs};
-#[cfg(any(test, feature = "testutils"))]
+#[cfg(any(test, feature = "testutils", feature = "recording_mode"))]
 use crate::budget::AsBudget;
 use crate::{
     budget::get_wasmi_config,
@@ -95,7 +95,7 @@ impl ModuleCache {
         })
     }
 
-    #[cfg(any(test, feature = "testutils"))]
+    #[cfg(any(test, feature = "testutils", feature = "recording_mode"))]
     pub fn add_stored_contracts(&self, host: &Host) -> Result<(), HostError> {
         use crate::xdr::{ContractCodeEntry, ContractCodeEntryExt, LedgerEntryData, LedgerKey};
         let storage = host.try_borrow_storage()?;
@@ -0,0 +1,599 @@
+// This is a command-line tool for replaying the recorded host function
+// invocations, e.g. for reproducing the failures observed on the network.
+//
+// Every invocation is described by a text file that contains one
+// `<key> <value>...` pair per line. Empty lines and lines starting with `#`
+// are ignored. XDR values are base64-encoded, byte arrays are hex-encoded.
+//
+// Invocation inputs:
+//   host_fn <HostFunction>
+//   resources <SorobanResources>
+//   source_account <AccountId>
+//   auth_entry <SorobanAuthorizationEntry>           (repeated)
+//   ledger_entry <LedgerEntry> [<TtlEntry>]          (repeated)
+//   restored_rw_entry_index <u32>                    (repeated)
+//   prng_seed <32 bytes hex>
+//   protocol_version, sequence_number, timestamp, base_reserve,
+//   min_temp_entry_ttl, min_persistent_entry_ttl, max_entry_ttl <number>
+//   network_id <32 bytes hex>
+//
+// Budget configuration (the default local testing budget is used when the cost
+// parameters are not provided):
+//   cpu_cost_params <ContractCostParams>
+//   memory_cost_params <ContractCostParams>
+//   memory_limit <u64>
+//
+// Optional expected outputs to compare the replay against:
+//   expected_result <ScVal>
+//   expected_error <ScError>
+//   expected_event <ContractEvent>                   (repeated)
+//   expected_cpu_insns <u64>
+//   expected_mem_bytes <u64>
+//   expected_receipt <ExecutionReceipt bytes hex>
+
+use std::{
+    fmt::{self, Debug, Display},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use soroban_env_host::{
+    budget::{AsBudget, Budget},
+    e2e_invoke::{invoke_host_function, ExecutionReceipt, TypedLedgerEntryChange},
+    events::HostEvent,
+    vm::VersionedContractCodeCostInputs,
+    xdr::{
+        AccountId, ContractCodeEntryExt, ContractCostParams, ContractEvent, DiagnosticEvent,
+        HostFunction, LedgerEntry, LedgerEntryData, LedgerKey, ReadXdr, ScError, ScVal,
+        SorobanAuthorizationEntry, SorobanResources, TtlEntry, WriteXdr,
+    },
+    CompilationContext, Error, ErrorHandler, HostError, LedgerInfo, ModuleCache, Val,
+    DEFAULT_XDR_RW_LIMITS,
+};
+
+const USAGE: &str = "\
+Usage: soroban-env-replay [OPTIONS] <PATH>
+
+Replays recorded host function invocations. PATH is either a single
+invocation file, or a directory, in which case every `*.invocation` file in
+it is replayed in lexicographical order.
+
+Options:
+  --no-diagnostics    Don't collect the diagnostic events
+  --no-module-cache   Don't pre-compile the contracts from the ledger entries
+  -h, --help          Print this message";
+
+// `main` reports the errors via `Debug`, so make it print just the message.
+struct ReplayError(String);
+
+impl Debug for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+struct Options {
+    enable_diagnostics: bool,
+    use_module_cache: bool,
+    path: PathBuf,
+}
+
+struct BudgetConfig {
+    cpu_cost_params: ContractCostParams,
+    memory_cost_params: ContractCostParams,
+    memory_limit: u64,
+}
+
+#[derive(Default)]
+struct Expectations {
+    result: Option<Result<ScVal, ScError>>,
+    events: Option<Vec<ContractEvent>>,
+    cpu_insns: Option<u64>,
+    mem_bytes: Option<u64>,
+    receipt: Option<ExecutionReceipt>,
+}
+
+struct Invocation {
+    host_fn: HostFunction,
+    resources: SorobanResources,
+    restored_rw_entry_indices: Vec<u32>,
+    source_account: AccountId,
+    auth_entries: Vec<SorobanAuthorizationEntry>,
+    ledger_info: LedgerInfo,
+    ledger_entries: Vec<(LedgerEntry, Option<TtlEntry>)>,
+    prng_seed: [u8; 32],
+    budget_config: Option<BudgetConfig>,
+    expected: Expectations,
+}
+
+// Compilation of the cached modules happens outside of the invocation budget,
+// just like in the embedder.
+#[derive(Clone)]
+struct ReplayCompilationContext(Budget);
+
+impl ErrorHandler for ReplayCompilationContext {
+    fn map_err<T, E>(&self, res: Result<T, E>) -> Result<T, HostError>
+    where
+        Error: From<E>,
+        E: Debug,
+    {
+        res.map_err(HostError::from)
+    }
+
+    fn error(&self, error: Error, _msg: &str, _args: &[Val]) -> HostError {
+        HostError::from(error)
+    }
+}
+
+impl AsBudget for ReplayCompilationContext {
+    fn as_budget(&self) -> &Budget {
+        &self.0
+    }
+}
+
+impl CompilationContext for ReplayCompilationContext {}
+
+fn main() -> Result<(), ReplayError> {
+    let options = parse_args(std::env::args().skip(1)).map_err(ReplayError)?;
+    let paths = invocation_paths(&options.path).map_err(ReplayError)?;
+    let mut mismatches = 0;
+    for path in &paths {
+        println!("== {}", path.display());
+        let matched = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse_invocation(&text))
+            .and_then(|invocation| replay(&invocation, &options))
+            .map_err(|e| ReplayError(format!("{}: {e}", path.display())))?;
+        if !matched {
+            mismatches += 1;
+        }
+    }
+    println!(
+        "replayed {} invocation(s), {mismatches} with mismatches",
+        paths.len()
+    );
+    if mismatches > 0 {
+        return Err(ReplayError(format!(
+            "{mismatches} invocation(s) did not match the expected outputs"
+        )));
+    }
+    Ok(())
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut enable_diagnostics = true;
+    let mut use_module_cache = true;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--no-diagnostics" => enable_diagnostics = false,
+            "--no-module-cache" => use_module_cache = false,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}\n\n{USAGE}")),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {arg}\n\n{USAGE}")),
+        }
+    }
+    Ok(Options {
+        enable_diagnostics,
+        use_module_cache,
+        path: path.ok_or_else(|| USAGE.to_string())?,
+    })
+}
+
+fn invocation_paths(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut paths = vec![];
+    for entry in fs::read_dir(path).map_err(|e| format!("{}: {e}", path.display()))? {
+        let entry_path = entry.map_err(|e| e.to_string())?.path();
+        if entry_path.is_file()
+            && entry_path.extension().and_then(|e| e.to_str()) == Some("invocation")
+        {
+            paths.push(entry_path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn parse_invocation(text: &str) -> Result<Invocation, String> {
+    let mut host_fn = None;
+    let mut resources = None;
+    let mut source_account = None;
+    let mut prng_seed = None;
+    let mut restored_rw_entry_indices = vec![];
+    let mut auth_entries = vec![];
+    let mut ledger_entries = vec![];
+    let mut ledger_info = LedgerInfo::default();
+    let mut cpu_cost_params = None;
+    let mut memory_cost_params = None;
+    let mut memory_limit = None;
+    let mut expected = Expectations::default();
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let key = parts.next().unwrap_or_default();
+        let values: Vec<&str> = parts.collect();
+        let res: Result<(), String> = match key {
+            "host_fn" => single(&values).and_then(xdr).map(|v| host_fn = Some(v)),
+            "resources" => single(&values).and_then(xdr).map(|v| resources = Some(v)),
+            "source_account" => single(&values)
+                .and_then(xdr)
+                .map(|v| source_account = Some(v)),
+            "auth_entry" => single(&values).and_then(xdr).map(|v| auth_entries.push(v)),
+            "ledger_entry" => match values.as_slice() {
+                [entry] => xdr(entry).map(|e| ledger_entries.push((e, None))),
+                [entry, ttl] => {
+                    xdr(entry).and_then(|e| xdr(ttl).map(|t| ledger_entries.push((e, Some(t)))))
+                }
+                _ => Err("expected an entry and an optional TTL entry".to_string()),
+            },
+            "restored_rw_entry_index" => single(&values)
+                .and_then(number)
+                .map(|v| restored_rw_entry_indices.push(v)),
+            "prng_seed" => single(&values).and_then(hex32).map(|v| prng_seed = Some(v)),
+            "protocol_version" => single(&values)
+                .and_then(number)
+                .map(|v| ledger_info.protocol_version = v),
+            "sequence_number" => single(&values)
+                .and_then(number)
+                .map(|v| ledger_info.sequence_number = v),
+            "timestamp" => single(&values)
+                .and_then(number)
+                .map(|v| ledger_info.timestamp = v),
+            "network_id" => single(&values)
+                .and_then(hex32)
+                .map(|v| ledger_info.network_id = v),
+            "base_reserve" => single(&values)
+                .and_then(number)
+                .map(|v| ledger_info.base_reserve = v),
+            "min_temp_entry_ttl" => single(&values)
+                .and_then(number)
+                .map(|v| ledger_info.min_temp_entry_ttl = v),
+            "min_persistent_entry_ttl" => single(&values)
+                .and_then(number)
+                .map(|v| ledger_info.min_persistent_entry_ttl = v),
+            "max_entry_ttl" => single(&values)
+                .and_then(number)
+                .map(|v| ledger_info.max_entry_ttl = v),
+            "cpu_cost_params" => single(&values)
+                .and_then(xdr)
+                .map(|v| cpu_cost_params = Some(v)),
+            "memory_cost_params" => single(&values)
+                .and_then(xdr)
+                .map(|v| memory_cost_params = Some(v)),
+            "memory_limit" => single(&values)
+                .and_then(number)
+                .map(|v| memory_limit = Some(v)),
+            "expected_result" => single(&values)
+                .and_then(xdr)
+                .map(|v| expected.result = Some(Ok(v))),
+            "expected_error" => single(&values)
+                .and_then(xdr)
+                .map(|v| expected.result = Some(Err(v))),
+            "expected_event" => single(&values)
+                .and_then(xdr)
+                .map(|v| expected.events.get_or_insert_with(Vec::new).push(v)),
+            "expected_cpu_insns" => single(&values)
+                .and_then(number)
+                .map(|v| expected.cpu_insns = Some(v)),
+            "expected_mem_bytes" => single(&values)
+                .and_then(number)
+                .map(|v| expected.mem_bytes = Some(v)),
+            "expected_receipt" => single(&values).and_then(|v| {
+                let receipt = ExecutionReceipt::from_bytes(&decode_hex(v)?)
+                    .map_err(|e| format!("invalid receipt: {e:?}"))?;
+                expected.receipt = Some(receipt);
+                Ok(())
+            }),
+            _ => Err(format!("unknown key `{key}`")),
+        };
+        res.map_err(|e| format!("line {}: {e}", line_no + 1))?;
+    }
+    let budget_config = match (cpu_cost_params, memory_cost_params, memory_limit) {
+        (Some(cpu_cost_params), Some(memory_cost_params), Some(memory_limit)) => {
+            Some(BudgetConfig {
+                cpu_cost_params,
+                memory_cost_params,
+                memory_limit,
+            })
+        }
+        (None, None, None) => None,
+        _ => {
+            return Err(
+                "cpu_cost_params, memory_cost_params and memory_limit must be set together"
+                    .to_string(),
+            )
+        }
+    };
+    Ok(Invocation {
+        host_fn: host_fn.ok_or("missing host_fn")?,
+        resources: resources.ok_or("missing resources")?,
+        restored_rw_entry_indices,
+        source_account: source_account.ok_or("missing source_account")?,
+        auth_entries,
+        ledger_info,
+        ledger_entries,
+        prng_seed: prng_seed.ok_or("missing prng_seed")?,
+        budget_config,
+        expected,
+    })
+}
+
+fn single<'a>(values: &[&'a str]) -> Result<&'a str, String> {
+    match values {
+        [value] => Ok(value),
+        _ => Err(format!("expected a single value, got {}", values.len())),
+    }
+}
+
+fn xdr<T: ReadXdr>(value: &str) -> Result<T, String> {
+    T::from_xdr_base64(value, DEFAULT_XDR_RW_LIMITS).map_err(|e| format!("invalid XDR: {e}"))
+}
+
+fn number<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("invalid number `{value}`: {e}"))
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, String> {
+    if value.len() % 2 != 0 || !value.is_ascii() {
+        return Err(format!("invalid hex `{value}`"));
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&value[i..i + 2], 16).map_err(|_| format!("invalid hex `{value}`"))
+        })
+        .collect()
+}
+
+fn hex32(value: &str) -> Result<[u8; 32], String> {
+    decode_hex(value)?
+        .try_into()
+        .map_err(|_| format!("expected 32 bytes, got `{value}`"))
+}
+
+fn to_xdr(value: &impl WriteXdr) -> Result<Vec<u8>, String> {
+    value
+        .to_xdr(DEFAULT_XDR_RW_LIMITS)
+        .map_err(|e| e.to_string())
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn build_budget(invocation: &Invocation) -> Result<Budget, HostError> {
+    match &invocation.budget_config {
+        Some(config) => Budget::try_from_configs(
+            invocation.resources.instructions as u64,
+            config.memory_limit,
+            config.cpu_cost_params.clone(),
+            config.memory_cost_params.clone(),
+        ),
+        None => Ok(Budget::default()),
+    }
+}
+
+// Builds the module cache for all the contracts in the ledger entries besides
+// the ones that are being restored, in the same fashion as the embedder does.
+fn build_module_cache(invocation: &Invocation) -> Result<ModuleCache, HostError> {
+    let no_costs = || ContractCostParams(Default::default());
+    let ctx = ReplayCompilationContext(Budget::try_from_configs(
+        u64::MAX,
+        u64::MAX,
+        no_costs(),
+        no_costs(),
+    )?);
+    let cache = ModuleCache::new(&ctx)?;
+    let restored_keys: Vec<&LedgerKey> = invocation
+        .restored_rw_entry_indices
+        .iter()
+        .filter_map(|i| invocation.resources.footprint.read_write.get(*i as usize))
+        .collect();
+    for (entry, _) in &invocation.ledger_entries {
+        let LedgerEntryData::ContractCode(code) = &entry.data else {
+            continue;
+        };
+        if restored_keys
+            .iter()
+            .any(|k| matches!(k, LedgerKey::ContractCode(c) if c.hash == code.hash))
+        {
+            continue;
+        }
+        let cost_inputs = match &code.ext {
+            ContractCodeEntryExt::V0 => VersionedContractCodeCostInputs::V0 {
+                wasm_bytes: code.code.len(),
+            },
+            ContractCodeEntryExt::V1(v1) => {
+                VersionedContractCodeCostInputs::V1(v1.cost_inputs.clone())
+            }
+        };
+        cache.parse_and_cache_module(
+            &ctx,
+            invocation.ledger_info.protocol_version,
+            &code.hash,
+            &code.code,
+            cost_inputs,
+        )?;
+    }
+    Ok(cache)
+}
+
+// Replays the invocation and prints its outputs. Returns `false` if any of the
+// outputs doesn't match the expectations.
+fn replay(invocation: &Invocation, options: &Options) -> Result<bool, String> {
+    let host_err = |e: HostError| format!("{e:?}");
+    let budget = build_budget(invocation).map_err(host_err)?;
+    if invocation.budget_config.is_none() {
+        println!("note: no cost parameters provided, using the default budget");
+    }
+    let module_cache = if options.use_module_cache {
+        Some(build_module_cache(invocation).map_err(host_err)?)
+    } else {
+        None
+    };
+    let encoded_auth_entries = invocation
+        .auth_entries
+        .iter()
+        .map(to_xdr)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut encoded_ledger_entries = vec![];
+    let mut encoded_ttl_entries = vec![];
+    for (entry, ttl) in &invocation.ledger_entries {
+        encoded_ledger_entries.push(to_xdr(entry)?);
+        encoded_ttl_entries.push(match ttl {
+            Some(ttl) => to_xdr(ttl)?,
+            None => vec![],
+        });
+    }
+    let mut diagnostic_events: Vec<DiagnosticEvent> = vec![];
+    let res = invoke_host_function(
+        &budget,
+        options.enable_diagnostics,
+        to_xdr(&invocation.host_fn)?,
+        to_xdr(&invocation.resources)?,
+        &invocation.restored_rw_entry_indices,
+        to_xdr(&invocation.source_account)?,
+        encoded_auth_entries.into_iter(),
+        invocation.ledger_info.clone(),
+        encoded_ledger_entries.into_iter(),
+        encoded_ttl_entries.into_iter(),
+        invocation.prng_seed.to_vec(),
+        &mut diagnostic_events,
+        None,
+        module_cache,
+    );
+
+    // Errors that happen before the host function is invoked are reported in
+    // the same fashion as the invocation errors.
+    let (result, ledger_changes, contract_events, receipt) = match &res {
+        Ok(res) => {
+            let result = match &res.encoded_invoke_result {
+                Ok(v) => Ok(ScVal::from_xdr(v, DEFAULT_XDR_RW_LIMITS).map_err(|e| e.to_string())?),
+                Err(e) => Err(e.error),
+            };
+            let ledger_changes = res
+                .ledger_changes
+                .iter()
+                .map(TypedLedgerEntryChange::from_encoded)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(host_err)?;
+            let contract_events = res
+                .encoded_contract_events
+                .iter()
+                .map(|e| ContractEvent::from_xdr(e, DEFAULT_XDR_RW_LIMITS))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?;
+            let receipt = ExecutionReceipt::from_invoke_result(&budget, &invocation.prng_seed, res)
+                .map_err(host_err)?;
+            (result, ledger_changes, contract_events, Some(receipt))
+        }
+        Err(e) => (Err(e.error), vec![], vec![], None),
+    };
+
+    match &result {
+        Ok(v) => println!("result: {v:?}"),
+        Err(e) => println!("error: {e:?}"),
+    }
+    println!("ledger changes:");
+    for change in &ledger_changes {
+        print_ledger_change(change);
+    }
+    println!("contract events:");
+    for event in &contract_events {
+        let event = HostEvent {
+            event: event.clone(),
+            failed_call: false,
+        };
+        println!("  {event}");
+    }
+    println!("diagnostic events:");
+    for event in &diagnostic_events {
+        let event = HostEvent {
+            event: event.event.clone(),
+            failed_call: !event.in_successful_contract_call,
+        };
+        println!("  {event}");
+    }
+    print!("budget:\n{budget}");
+    let cpu_insns = budget.get_cpu_insns_consumed().map_err(host_err)?;
+    let mem_bytes = budget.get_mem_bytes_consumed().map_err(host_err)?;
+    if let Some(receipt) = &receipt {
+        println!("receipt: {}", encode_hex(&receipt.to_bytes()));
+    }
+
+    let expected = &invocation.expected;
+    let mut matched = true;
+    let mut mismatch = |what: &str, expected: &dyn Debug, actual: &dyn Debug| {
+        println!("MISMATCH {what}: expected {expected:?}, got {actual:?}");
+        matched = false;
+    };
+    if let Some(expected_result) = &expected.result {
+        let actual_result = match &result {
+            Ok(v) => Ok(v.clone()),
+            Err(e) => Err(ScError::try_from(*e).map_err(|e| e.to_string())?),
+        };
+        if *expected_result != actual_result {
+            mismatch("result", expected_result, &actual_result);
+        }
+    }
+    if let Some(expected_events) = &expected.events {
+        if *expected_events != contract_events {
+            mismatch("contract events", expected_events, &contract_events);
+        }
+    }
+    if let Some(expected_cpu_insns) = expected.cpu_insns {
+        if expected_cpu_insns != cpu_insns {
+            mismatch("cpu instructions", &expected_cpu_insns, &cpu_insns);
+        }
+    }
+    if let Some(expected_mem_bytes) = expected.mem_bytes {
+        if expected_mem_bytes != mem_bytes {
+            mismatch("memory bytes", &expected_mem_bytes, &mem_bytes);
+        }
+    }
+    if let Some(expected_receipt) = &expected.receipt {
+        match &receipt {
+            Some(receipt) if receipt == expected_receipt => (),
+            Some(receipt) => mismatch(
+                "receipt components",
+                &"no divergence",
+                &expected_receipt.diverging_components(receipt),
+            ),
+            None => mismatch("receipt", &"a receipt", &"invocation failed"),
+        }
+    }
+    Ok(matched)
+}
+
+fn print_ledger_change(change: &TypedLedgerEntryChange) {
+    let access = if change.read_only { "ro" } else { "rw" };
+    let new_value = match (&change.new_value, change.read_only) {
+        (_, true) => "read".to_string(),
+        (Some(_), false) => format!(
+            "written ({} -> {} bytes for rent)",
+            change.old_entry_size_bytes_for_rent, change.new_entry_size_bytes_for_rent
+        ),
+        (None, false) => "deleted".to_string(),
+    };
+    let ttl = match &change.ttl_change {
+        Some(ttl) => format!(
+            ", live until {} -> {}",
+            ttl.old_live_until_ledger, ttl.new_live_until_ledger
+        ),
+        None => String::new(),
+    };
+    println!("  [{access}] {:?}: {new_value}{ttl}", change.key);
+}
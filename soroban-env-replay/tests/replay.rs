@@ -0,0 +1,161 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use sha2::{Digest, Sha256};
+use soroban_env_host::{
+    e2e_testutils::{
+        bytes_sc_val, default_ledger_info, get_account_id, get_wasm_hash, get_wasm_key,
+        upload_wasm_host_fn, CreateContractData,
+    },
+    xdr::{Hash, LedgerFootprint, LedgerKey, ScVal, SorobanResources, TtlEntry, WriteXdr},
+    LedgerInfo, DEFAULT_XDR_RW_LIMITS,
+};
+use soroban_test_wasms::ADD_I32;
+
+fn b64(v: &impl WriteXdr) -> String {
+    v.to_xdr_base64(DEFAULT_XDR_RW_LIMITS).unwrap()
+}
+
+fn resources(read_only: Vec<LedgerKey>, read_write: Vec<LedgerKey>) -> SorobanResources {
+    SorobanResources {
+        footprint: LedgerFootprint {
+            read_only: read_only.try_into().unwrap(),
+            read_write: read_write.try_into().unwrap(),
+        },
+        instructions: 10_000_000,
+        disk_read_bytes: 0,
+        write_bytes: 0,
+    }
+}
+
+fn ledger_info_lines(ledger_info: &LedgerInfo) -> String {
+    format!(
+        "protocol_version {}\nsequence_number {}\ntimestamp {}\nnetwork_id {}\n\
+         base_reserve {}\nmin_temp_entry_ttl {}\nmin_persistent_entry_ttl {}\n\
+         max_entry_ttl {}\nprng_seed {}\n",
+        ledger_info.protocol_version,
+        ledger_info.sequence_number,
+        ledger_info.timestamp,
+        hex::encode(ledger_info.network_id),
+        ledger_info.base_reserve,
+        ledger_info.min_temp_entry_ttl,
+        ledger_info.min_persistent_entry_ttl,
+        ledger_info.max_entry_ttl,
+        hex::encode([7; 32]),
+    )
+}
+
+fn create_contract_invocation() -> String {
+    let cd = CreateContractData::new([111; 32], ADD_I32);
+    let ledger_info = default_ledger_info();
+    let ttl_entry = TtlEntry {
+        key_hash: Hash(Sha256::digest(cd.wasm_key.to_xdr(DEFAULT_XDR_RW_LIMITS).unwrap()).into()),
+        live_until_ledger_seq: ledger_info.sequence_number + 100,
+    };
+    format!(
+        "# Create a contract from the existing Wasm.\n\
+         host_fn {}\nresources {}\nsource_account {}\nauth_entry {}\nledger_entry {} {}\n{}\
+         expected_result {}\n",
+        b64(&cd.host_fn),
+        b64(&resources(
+            vec![cd.wasm_key.clone()],
+            vec![cd.contract_key.clone()]
+        )),
+        b64(&cd.deployer),
+        b64(&cd.auth_entry),
+        b64(&cd.wasm_entry),
+        b64(&ttl_entry),
+        ledger_info_lines(&ledger_info),
+        b64(&ScVal::Address(cd.contract_address.clone())),
+    )
+}
+
+fn upload_wasm_invocation(expected_cpu_insns: u64) -> String {
+    format!(
+        "host_fn {}\nresources {}\nsource_account {}\n{}\
+         expected_result {}\nexpected_cpu_insns {}\n",
+        b64(&upload_wasm_host_fn(ADD_I32)),
+        b64(&resources(vec![], vec![get_wasm_key(ADD_I32)])),
+        b64(&get_account_id([123; 32])),
+        ledger_info_lines(&default_ledger_info()),
+        b64(&bytes_sc_val(&get_wasm_hash(ADD_I32))),
+        expected_cpu_insns,
+    )
+}
+
+fn replay(path: &Path) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_soroban-env-replay"))
+        .arg(path)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("soroban-replay-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn replay_single_invocation() {
+    let dir = test_dir("single");
+    let path = dir.join("create.invocation");
+    fs::write(&path, create_contract_invocation()).unwrap();
+
+    let (success, stdout) = replay(&path);
+    assert!(success, "{stdout}");
+    assert!(stdout.contains("result: Address("));
+    assert!(stdout.contains("replayed 1 invocation(s), 0 with mismatches"));
+
+    // The receipt is deterministic, so the replay has to match it.
+    let receipt = stdout
+        .lines()
+        .find_map(|l| l.strip_prefix("receipt: "))
+        .unwrap()
+        .to_string();
+    let mut text = create_contract_invocation();
+    text.push_str(&format!("expected_receipt {receipt}\n"));
+    fs::write(&path, text).unwrap();
+    let (success, stdout) = replay(&path);
+    assert!(success, "{stdout}");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn replay_directory_with_mismatch() {
+    let dir = test_dir("dir");
+    fs::write(dir.join("a.invocation"), create_contract_invocation()).unwrap();
+    fs::write(dir.join("b.invocation"), upload_wasm_invocation(1)).unwrap();
+    // Files without the `.invocation` extension are ignored.
+    fs::write(dir.join("notes.txt"), "not an invocation").unwrap();
+
+    let (success, stdout) = replay(&dir);
+    assert!(!success);
+    assert!(stdout.contains("MISMATCH cpu instructions: expected 1"));
+    assert!(stdout.contains("replayed 2 invocation(s), 1 with mismatches"));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn replay_invalid_invocation() {
+    let dir = test_dir("invalid");
+    let path = dir.join("bad.invocation");
+    fs::write(&path, "host_fn not-base64\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_soroban-env-replay"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("line 1: invalid XDR"));
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,270 @@
+use crate::network_config::NetworkConfig;
+use crate::resources::estimate_max_transaction_size_for_operation;
+use crate::simulation::{
+    InvokeHostFunctionSimulationResult, RestoreOpSimulationResult, SimulationAdjustmentConfig,
+};
+use anyhow::{anyhow, bail, ensure, Result};
+use soroban_env_host::fees::{compute_transaction_resource_fee, TransactionResources};
+use soroban_env_host::xdr::{
+    ExtensionPoint, FeeBumpTransaction, FeeBumpTransactionEnvelope, FeeBumpTransactionExt,
+    FeeBumpTransactionInnerTx, InvokeHostFunctionOp, Memo, MuxedAccount, Operation, OperationBody,
+    RestoreFootprintOp, SequenceNumber, SorobanAuthorizationEntry, SorobanTransactionData,
+    SorobanTransactionDataExt, Transaction, TransactionEnvelope, TransactionExt,
+    TransactionV1Envelope,
+};
+
+/// Minimum inclusion fee per operation accepted by the network, in stroops.
+pub const MIN_INCLUSION_FEE: u32 = 100;
+
+/// Defines the inclusion fee (the fee for including the transaction into the
+/// ledger, as opposed to the resource fee) of the assembled transactions.
+///
+/// The inclusion fee is defined per operation, i.e. it's charged twice for
+/// the fee bump transactions (for the inner operation and for the fee bump
+/// itself).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InclusionFeePolicy {
+    /// Use the network minimum inclusion fee ([`MIN_INCLUSION_FEE`]).
+    Minimum,
+    /// Use the provided inclusion fee.
+    Fixed(u32),
+    /// Use the `fee` of the transaction passed to [`assemble_transaction`]
+    /// as the inclusion fee.
+    FromTransaction,
+}
+
+/// Transactions assembled from the simulation results via
+/// [`assemble_transaction`].
+///
+/// The transactions are kept unsigned, so that the authorization entries
+/// can still be replaced with the signed ones via
+/// [`AssembledTransaction::set_signed_auth`]. The envelopes to sign are built
+/// with [`AssembledTransaction::envelope`] and
+/// [`AssembledTransaction::restore_envelope`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AssembledTransaction {
+    /// `RestoreFootprintOp` transaction that has to be applied before
+    /// `transaction` in order to restore the archived entries it needs.
+    pub restore_transaction: Option<Transaction>,
+    /// `InvokeHostFunctionOp` transaction.
+    pub transaction: Transaction,
+    /// Inclusion fee per operation.
+    pub inclusion_fee: u32,
+    /// When set, the envelopes are wrapped into the fee bump transactions
+    /// paid by this account.
+    pub fee_bump_source: Option<MuxedAccount>,
+}
+
+/// Assembles the transaction with the single `InvokeHostFunctionOp` from the
+/// provided unsigned `transaction` and the result of its simulation.
+///
+/// The simulated authorization entries and Soroban transaction data are
+/// attached to the transaction and its fee is set to the sum of the
+/// inclusion fee defined by `inclusion_fee_policy` and the simulated resource
+/// fee.
+///
+/// When `restore_preamble` is provided (or when `simulation_result` has its
+/// own `restore_preamble`), the `RestoreFootprintOp` transaction with the
+/// same source account and preconditions is assembled as well. It uses the
+/// sequence number of `transaction`, and `transaction` uses the next sequence
+/// number. Otherwise the sequence number of `transaction` is preserved.
+///
+/// When `fee_bump_source` is provided, the envelopes will be wrapped into
+/// fee bump transactions paid by that account.
+pub fn assemble_transaction(
+    transaction: Transaction,
+    simulation_result: &InvokeHostFunctionSimulationResult,
+    inclusion_fee_policy: &InclusionFeePolicy,
+    restore_preamble: Option<&RestoreOpSimulationResult>,
+    fee_bump_source: Option<MuxedAccount>,
+) -> Result<AssembledTransaction> {
+    if let Err(e) = &simulation_result.invoke_result {
+        bail!("can't assemble transaction for failed simulation: {e:?}");
+    }
+    let transaction_data = simulation_result
+        .transaction_data
+        .clone()
+        .ok_or_else(|| anyhow!("simulation result is missing transaction data"))?;
+    let inclusion_fee = match inclusion_fee_policy {
+        InclusionFeePolicy::Minimum => MIN_INCLUSION_FEE,
+        InclusionFeePolicy::Fixed(fee) => *fee,
+        InclusionFeePolicy::FromTransaction => transaction.fee,
+    };
+    let mut transaction = transaction;
+    let op = invoke_host_function_op_mut(&mut transaction)?;
+    op.auth = simulation_result.auth.clone().try_into()?;
+    transaction.fee = transaction_fee(inclusion_fee, &transaction_data)?;
+    transaction.ext = TransactionExt::V1(transaction_data);
+
+    let restore_transaction_data = restore_preamble
+        .map(|restore| &restore.transaction_data)
+        .or(simulation_result
+            .restore_preamble
+            .as_ref()
+            .map(|restore| &restore.transaction_data));
+    let restore_transaction = match restore_transaction_data {
+        Some(restore_transaction_data) => {
+            let restore_transaction = Transaction {
+                source_account: transaction.source_account.clone(),
+                fee: transaction_fee(inclusion_fee, restore_transaction_data)?,
+                seq_num: transaction.seq_num.clone(),
+                cond: transaction.cond.clone(),
+                memo: Memo::None,
+                operations: vec![Operation {
+                    source_account: None,
+                    body: OperationBody::RestoreFootprint(RestoreFootprintOp {
+                        ext: ExtensionPoint::V0,
+                    }),
+                }]
+                .try_into()?,
+                ext: TransactionExt::V1(restore_transaction_data.clone()),
+            };
+            transaction.seq_num = SequenceNumber(
+                transaction
+                    .seq_num
+                    .0
+                    .checked_add(1)
+                    .ok_or_else(|| anyhow!("sequence number overflow"))?,
+            );
+            Some(restore_transaction)
+        }
+        None => None,
+    };
+    Ok(AssembledTransaction {
+        restore_transaction,
+        transaction,
+        inclusion_fee,
+        fee_bump_source,
+    })
+}
+
+impl AssembledTransaction {
+    /// Replaces the authorization entries of the transaction with the signed
+    /// ones and increases the resource fee to account for the transaction
+    /// size increase due to the signatures.
+    ///
+    /// `network_config` and `adjustment_config` have to be the same as the
+    /// ones used for the simulation. The other resources are not changed, as
+    /// the signed entries have already been accounted for by the
+    /// simulation (e.g. the signature verification instructions are estimated
+    /// with the worst case signatures).
+    pub fn set_signed_auth(
+        &mut self,
+        signed_auth: Vec<SorobanAuthorizationEntry>,
+        network_config: &NetworkConfig,
+        adjustment_config: &SimulationAdjustmentConfig,
+    ) -> Result<()> {
+        let transaction_size_fee = |transaction: &Transaction| -> Result<i64> {
+            let TransactionExt::V1(transaction_data) = &transaction.ext else {
+                bail!("transaction is missing Soroban transaction data");
+            };
+            let restored_rw_entry_ids = match &transaction_data.ext {
+                SorobanTransactionDataExt::V0 => vec![],
+                SorobanTransactionDataExt::V1(ext) => ext.archived_soroban_entries.to_vec(),
+            };
+            let transaction_size_bytes =
+                adjustment_config
+                    .tx_size
+                    .adjust_u32(estimate_max_transaction_size_for_operation(
+                        transaction.operations[0].body.clone(),
+                        &transaction_data.resources,
+                        &restored_rw_entry_ids,
+                    )?);
+            // Transaction size only contributes to the non-refundable fee.
+            let (fee, _) = compute_transaction_resource_fee(
+                &TransactionResources {
+                    instructions: 0,
+                    disk_read_entries: 0,
+                    write_entries: 0,
+                    disk_read_bytes: 0,
+                    write_bytes: 0,
+                    contract_events_size_bytes: 0,
+                    transaction_size_bytes,
+                },
+                &network_config.fee_configuration,
+            );
+            Ok(fee)
+        };
+        let fee_before = transaction_size_fee(&self.transaction)?;
+        invoke_host_function_op_mut(&mut self.transaction)?.auth = signed_auth.try_into()?;
+        let fee_increase = transaction_size_fee(&self.transaction)?.saturating_sub(fee_before);
+        if fee_increase > 0 {
+            let TransactionExt::V1(transaction_data) = &mut self.transaction.ext else {
+                bail!("transaction is missing Soroban transaction data");
+            };
+            transaction_data.resource_fee = transaction_data
+                .resource_fee
+                .checked_add(fee_increase)
+                .ok_or_else(|| anyhow!("resource fee overflow"))?;
+            self.transaction.fee = transaction_fee(self.inclusion_fee, transaction_data)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the envelope of the `InvokeHostFunctionOp` transaction to
+    /// sign.
+    pub fn envelope(&self) -> Result<TransactionEnvelope> {
+        self.wrap_transaction(&self.transaction)
+    }
+
+    /// Returns the envelope of the `RestoreFootprintOp` transaction to sign,
+    /// if restoration is necessary.
+    pub fn restore_envelope(&self) -> Result<Option<TransactionEnvelope>> {
+        self.restore_transaction
+            .as_ref()
+            .map(|tx| self.wrap_transaction(tx))
+            .transpose()
+    }
+
+    fn wrap_transaction(&self, transaction: &Transaction) -> Result<TransactionEnvelope> {
+        let envelope = TransactionV1Envelope {
+            tx: transaction.clone(),
+            signatures: Default::default(),
+        };
+        let Some(fee_source) = &self.fee_bump_source else {
+            return Ok(TransactionEnvelope::Tx(envelope));
+        };
+        let TransactionExt::V1(transaction_data) = &transaction.ext else {
+            bail!("transaction is missing Soroban transaction data");
+        };
+        // The fee bump is charged the inclusion fee for the inner operation
+        // and for itself.
+        let fee = (self.inclusion_fee as i64)
+            .checked_mul(2)
+            .and_then(|fee| fee.checked_add(transaction_data.resource_fee))
+            .ok_or_else(|| anyhow!("fee bump fee overflow"))?;
+        Ok(TransactionEnvelope::TxFeeBump(FeeBumpTransactionEnvelope {
+            tx: FeeBumpTransaction {
+                fee_source: fee_source.clone(),
+                fee,
+                inner_tx: FeeBumpTransactionInnerTx::Tx(envelope),
+                ext: FeeBumpTransactionExt::V0,
+            },
+            signatures: Default::default(),
+        }))
+    }
+}
+
+fn invoke_host_function_op_mut(transaction: &mut Transaction) -> Result<&mut InvokeHostFunctionOp> {
+    ensure!(
+        transaction.operations.len() == 1,
+        "Soroban transaction must have exactly one operation"
+    );
+    match transaction
+        .operations
+        .iter_mut()
+        .next()
+        .map(|op| &mut op.body)
+    {
+        Some(OperationBody::InvokeHostFunction(op)) => Ok(op),
+        _ => bail!("transaction operation must be InvokeHostFunctionOp"),
+    }
+}
+
+fn transaction_fee(inclusion_fee: u32, transaction_data: &SorobanTransactionData) -> Result<u32> {
+    let fee = (inclusion_fee as i64)
+        .checked_add(transaction_data.resource_fee)
+        .ok_or_else(|| anyhow!("transaction fee overflow"))?;
+    fee.try_into()
+        .map_err(|_| anyhow!("transaction fee {fee} doesn't fit into u32"))
+}
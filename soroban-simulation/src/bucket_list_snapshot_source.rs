@@ -0,0 +1,225 @@
+use crate::xdr_record_file::{RecordFiles, RecordLocation};
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use soroban_env_host::ledger_info::get_key_durability;
+use soroban_env_host::storage::{EntryWithLiveUntil, SnapshotSource};
+use soroban_env_host::xdr::{
+    BucketEntry, BucketListType, BucketMetadata, BucketMetadataExt, ContractDataDurability, Hash,
+    HotArchiveBucketEntry, LedgerEntry, LedgerEntryData, LedgerKey, LedgerKeyTtl, ReadXdr,
+    ScErrorCode, ScErrorType, WriteXdr,
+};
+use soroban_env_host::{HostError, DEFAULT_XDR_RW_LIMITS};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Live until ledger reported for the entries that are stored in the hot
+/// archive.
+///
+/// The hot archive doesn't store the TTLs: the TTL entry is deleted when its
+/// entry is evicted from the live bucket list, and the restoration assigns a
+/// new TTL that doesn't depend on the old one. The archived entries are thus
+/// reported with a live until ledger that is below any valid ledger sequence,
+/// which makes the host (as well as `AutoRestoringSnapshotSource`) treat them
+/// as archived entries that have to be restored before they can be accessed,
+/// as they would be on the network.
+pub const ARCHIVED_ENTRY_LIVE_UNTIL_LEDGER: u32 = 0;
+
+/// The `SnapshotSource` implementation that reads the ledger state from the
+/// stellar-core bucket files.
+///
+/// The source is built from the (uncompressed) files of the live bucket list
+/// and, optionally, of the hot archive bucket list. Every file is a stream of
+/// `BucketEntry` (or `HotArchiveBucketEntry` for the hot archive) XDR records,
+/// as written by stellar-core. The files have to be ordered from the newest to
+/// the oldest, i.e. `curr` bucket of level 0, `snap` bucket of level 0, `curr`
+/// bucket of level 1 and so on. The newest version of every key is used, and
+/// `DEADENTRY` (or `HOT_ARCHIVE_LIVE` for the hot archive) shadows the older
+/// versions of the key.
+///
+/// The live until ledgers of the contract data and code entries are derived
+/// from the respective `TtlEntry` in the live bucket list. The persistent
+/// entries that are only present in the hot archive are reported with
+/// [`ARCHIVED_ENTRY_LIVE_UNTIL_LEDGER`].
+///
+/// The buckets are indexed and validated when the source is created, and only
+/// the accessed entries are read from the files. Reading an entry may still
+/// fail if the files have been modified after the source has been created. As
+/// `SnapshotSource` errors can't carry any context, such failures are
+/// reported to the host as `(Storage, InternalError)` errors, and the detailed
+/// error can be retrieved via [`BucketListSnapshotSource::take_last_error`].
+pub struct BucketListSnapshotSource {
+    live_buckets: RecordFiles,
+    hot_archive_buckets: RecordFiles,
+    // `None` value means that the newest version of the entry is a tombstone.
+    live_index: BTreeMap<LedgerKey, Option<RecordLocation>>,
+    hot_archive_index: BTreeMap<LedgerKey, Option<RecordLocation>>,
+    last_error: RefCell<Option<anyhow::Error>>,
+}
+
+impl BucketListSnapshotSource {
+    /// Opens and indexes the bucket files, see [`BucketListSnapshotSource`]
+    /// for the expected order of the files.
+    pub fn open(
+        live_bucket_paths: &[impl AsRef<Path>],
+        hot_archive_bucket_paths: &[impl AsRef<Path>],
+    ) -> Result<Self> {
+        let live_buckets = RecordFiles::open(to_path_bufs(live_bucket_paths))?;
+        let hot_archive_buckets = RecordFiles::open(to_path_bufs(hot_archive_bucket_paths))?;
+        let mut live_index = BTreeMap::new();
+        for file_index in 0..live_buckets.len() {
+            let mut bucket_index = BTreeMap::new();
+            live_buckets.for_each_record(file_index, |location, record| {
+                match BucketEntry::from_xdr(record, DEFAULT_XDR_RW_LIMITS)? {
+                    BucketEntry::Liveentry(entry) | BucketEntry::Initentry(entry) => {
+                        bucket_index.insert(entry.to_key(), Some(location));
+                    }
+                    BucketEntry::Deadentry(key) => {
+                        bucket_index.insert(key, None);
+                    }
+                    BucketEntry::Metaentry(meta) => {
+                        check_bucket_list_type(&meta, BucketListType::Live)?
+                    }
+                }
+                Ok(())
+            })?;
+            merge_older_bucket(&mut live_index, bucket_index);
+        }
+        let mut hot_archive_index = BTreeMap::new();
+        for file_index in 0..hot_archive_buckets.len() {
+            let mut bucket_index = BTreeMap::new();
+            hot_archive_buckets.for_each_record(file_index, |location, record| {
+                match HotArchiveBucketEntry::from_xdr(record, DEFAULT_XDR_RW_LIMITS)? {
+                    HotArchiveBucketEntry::Archived(entry) => {
+                        bucket_index.insert(entry.to_key(), Some(location));
+                    }
+                    HotArchiveBucketEntry::Live(key) => {
+                        bucket_index.insert(key, None);
+                    }
+                    HotArchiveBucketEntry::Metaentry(meta) => {
+                        check_bucket_list_type(&meta, BucketListType::HotArchive)?
+                    }
+                }
+                Ok(())
+            })?;
+            merge_older_bucket(&mut hot_archive_index, bucket_index);
+        }
+        // Every live contract data and code entry must have a TTL entry.
+        for (key, location) in &live_index {
+            if location.is_some()
+                && get_key_durability(key).is_some()
+                && !matches!(live_index.get(&ttl_key(key)?), Some(Some(_)))
+            {
+                bail!("missing TTL entry for {key:?}");
+            }
+        }
+        Ok(Self {
+            live_buckets,
+            hot_archive_buckets,
+            live_index,
+            hot_archive_index,
+            last_error: RefCell::new(None),
+        })
+    }
+
+    /// Returns the detailed error of the last failed `SnapshotSource::get`
+    /// call (if any), and resets it.
+    pub fn take_last_error(&self) -> Option<anyhow::Error> {
+        self.last_error.borrow_mut().take()
+    }
+
+    fn live_entry(&self, key: &LedgerKey) -> Result<Option<LedgerEntry>> {
+        match self.live_index.get(key) {
+            Some(Some(location)) => {
+                let record = self.live_buckets.read_record(*location)?;
+                match BucketEntry::from_xdr(record, DEFAULT_XDR_RW_LIMITS)? {
+                    BucketEntry::Liveentry(entry) | BucketEntry::Initentry(entry) => {
+                        Ok(Some(entry))
+                    }
+                    _ => bail!("bucket file has been modified"),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn archived_entry(&self, key: &LedgerKey) -> Result<Option<LedgerEntry>> {
+        match self.hot_archive_index.get(key) {
+            Some(Some(location)) => {
+                let record = self.hot_archive_buckets.read_record(*location)?;
+                match HotArchiveBucketEntry::from_xdr(record, DEFAULT_XDR_RW_LIMITS)? {
+                    HotArchiveBucketEntry::Archived(entry) => Ok(Some(entry)),
+                    _ => bail!("bucket file has been modified"),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn get_entry(&self, key: &LedgerKey) -> Result<Option<EntryWithLiveUntil>> {
+        let durability = get_key_durability(key);
+        if let Some(entry) = self.live_entry(key)? {
+            let live_until = match durability {
+                Some(_) => match self.live_entry(&ttl_key(key)?)?.map(|e| e.data) {
+                    Some(LedgerEntryData::Ttl(ttl)) => Some(ttl.live_until_ledger_seq),
+                    _ => bail!("missing TTL entry for {key:?}"),
+                },
+                None => None,
+            };
+            return Ok(Some((Rc::new(entry), live_until)));
+        }
+        if durability == Some(ContractDataDurability::Persistent) {
+            if let Some(entry) = self.archived_entry(key)? {
+                return Ok(Some((
+                    Rc::new(entry),
+                    Some(ARCHIVED_ENTRY_LIVE_UNTIL_LEDGER),
+                )));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl SnapshotSource for BucketListSnapshotSource {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        self.get_entry(key).map_err(|e| {
+            *self.last_error.borrow_mut() =
+                Some(e.context(format!("failed to read the entry for {key:?}")));
+            HostError::from((ScErrorType::Storage, ScErrorCode::InternalError))
+        })
+    }
+}
+
+fn ttl_key(key: &LedgerKey) -> Result<LedgerKey> {
+    let encoded_key = key
+        .to_xdr(DEFAULT_XDR_RW_LIMITS)
+        .context("failed to encode the ledger key")?;
+    Ok(LedgerKey::Ttl(LedgerKeyTtl {
+        key_hash: Hash(Sha256::digest(encoded_key).into()),
+    }))
+}
+
+fn to_path_bufs(paths: &[impl AsRef<Path>]) -> Vec<PathBuf> {
+    paths.iter().map(|p| p.as_ref().to_path_buf()).collect()
+}
+
+fn check_bucket_list_type(meta: &BucketMetadata, expected: BucketListType) -> Result<()> {
+    match &meta.ext {
+        BucketMetadataExt::V1(bucket_list_type) if *bucket_list_type != expected => {
+            bail!("unexpected bucket list type {bucket_list_type:?}, expected {expected:?}")
+        }
+        _ => Ok(()),
+    }
+}
+
+// Adds the keys from the `bucket_index` that are not shadowed by the newer
+// buckets that have already been added to the `index`.
+fn merge_older_bucket(
+    index: &mut BTreeMap<LedgerKey, Option<RecordLocation>>,
+    bucket_index: BTreeMap<LedgerKey, Option<RecordLocation>>,
+) {
+    for (key, location) in bucket_index {
+        index.entry(key).or_insert(location);
+    }
+}
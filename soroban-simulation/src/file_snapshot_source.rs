@@ -0,0 +1,191 @@
+use crate::snapshot_source::ledger_change_to_update;
+use crate::xdr_record_file::{write_record, RecordFiles, RecordLocation};
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use soroban_env_host::e2e_invoke::LedgerEntryChange;
+use soroban_env_host::storage::{EntryWithLiveUntil, SnapshotSource};
+use soroban_env_host::xdr::{
+    LedgerEntry, LedgerKey, Limited, ReadXdr, ScErrorCode, ScErrorType, WriteXdr,
+};
+use soroban_env_host::{HostError, DEFAULT_XDR_RW_LIMITS};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+/// The `SnapshotSource` implementation that reads the ledger state from the
+/// snapshot files on disk.
+///
+/// Every snapshot file is a sequence of XDR records, each record containing a
+/// `LedgerEntry` XDR immediately followed by the XDR of its optional live
+/// until ledger (`Option<u32>`, that must be set for the contract data and
+/// code entries and only for them). Every record is prefixed with the 4-byte
+/// XDR record marking header. Such files can be produced via
+/// [`write_snapshot_file`].
+///
+/// The snapshot may consist of a single file or of a directory of files. In
+/// the latter case the files are processed in the order of their names, and
+/// the records in the later files override the records for the same keys in
+/// the earlier files (as well as the later records in a single file override
+/// the earlier ones).
+///
+/// The entries are not loaded into memory. Instead, the files are scanned on
+/// the first access in order to build an index from the SHA-256 hash of the
+/// `LedgerKey` XDR to the record location, and then only the accessed records
+/// are read from disk. The files are opened only when they are read.
+///
+/// As `SnapshotSource` errors can't carry any context, the failures to read
+/// the files are reported to the host as `(Storage, InternalError)` errors,
+/// and the detailed error can be retrieved via
+/// [`FileSnapshotSource::take_last_error`].
+///
+/// The snapshot can be updated incrementally with the ledger changes produced
+/// by the host function invocations via
+/// [`FileSnapshotSource::apply_ledger_changes`]. The updates are kept in memory
+/// and are not written back to the files.
+pub struct FileSnapshotSource {
+    files: RecordFiles,
+    index: RefCell<Option<BTreeMap<[u8; 32], RecordLocation>>>,
+    updates: BTreeMap<Rc<LedgerKey>, Option<EntryWithLiveUntil>>,
+    last_error: RefCell<Option<anyhow::Error>>,
+}
+
+impl FileSnapshotSource {
+    /// Opens the snapshot stored in a single file, or in all the files of a
+    /// directory (non-recursively).
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let paths = if path.is_dir() {
+            let mut paths = vec![];
+            for dir_entry in path
+                .read_dir()
+                .with_context(|| format!("failed to read directory {}", path.display()))?
+            {
+                let file_path = dir_entry?.path();
+                if file_path.is_file() {
+                    paths.push(file_path);
+                }
+            }
+            paths.sort();
+            paths
+        } else {
+            vec![path.to_path_buf()]
+        };
+        Ok(Self {
+            files: RecordFiles::open(paths)?,
+            index: RefCell::new(None),
+            updates: BTreeMap::new(),
+            last_error: RefCell::new(None),
+        })
+    }
+
+    /// Builds the key index unless it has already been built.
+    ///
+    /// The index is built automatically on the first access to the snapshot,
+    /// this allows building it upfront and reporting the detailed error if
+    /// any of the files is malformed.
+    pub fn build_index(&self) -> Result<()> {
+        if self.index.borrow().is_some() {
+            return Ok(());
+        }
+        let mut index = BTreeMap::new();
+        for file_index in 0..self.files.len() {
+            self.files.for_each_record(file_index, |location, record| {
+                let (entry, _) = decode_record(record)?;
+                index.insert(key_hash(&entry.to_key())?, location);
+                Ok(())
+            })?;
+        }
+        *self.index.borrow_mut() = Some(index);
+        Ok(())
+    }
+
+    /// Applies the ledger changes produced by a host function invocation to
+    /// the snapshot.
+    ///
+    /// Modified entries are updated, removed entries are removed, and the
+    /// live until ledgers of the read-only entries are extended.
+    pub fn apply_ledger_changes(&mut self, changes: &[LedgerEntryChange]) -> Result<()> {
+        for change in changes {
+            if let Some((key, update)) = ledger_change_to_update(self, change)? {
+                self.updates.insert(key, update);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the detailed error of the last failed `SnapshotSource::get`
+    /// call (if any), and resets it.
+    pub fn take_last_error(&self) -> Option<anyhow::Error> {
+        self.last_error.borrow_mut().take()
+    }
+
+    fn get_entry(&self, key: &LedgerKey) -> Result<Option<EntryWithLiveUntil>> {
+        self.build_index()?;
+        let key_hash = key_hash(key)?;
+        let location = self
+            .index
+            .borrow()
+            .as_ref()
+            .and_then(|index| index.get(&key_hash).copied());
+        let Some(location) = location else {
+            return Ok(None);
+        };
+        let (entry, live_until) = decode_record(&self.files.read_record(location)?)?;
+        if entry.to_key() != *key {
+            bail!("snapshot file has been modified");
+        }
+        Ok(Some((Rc::new(entry), live_until)))
+    }
+}
+
+impl SnapshotSource for FileSnapshotSource {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        if let Some(update) = self.updates.get(key) {
+            return Ok(update.clone());
+        }
+        self.get_entry(key).map_err(|e| {
+            *self.last_error.borrow_mut() =
+                Some(e.context(format!("failed to read the entry for {key:?}")));
+            HostError::from((ScErrorType::Storage, ScErrorCode::InternalError))
+        })
+    }
+}
+
+/// Writes the ledger entries with their live until ledgers to a snapshot file
+/// that can be read by [`FileSnapshotSource`].
+pub fn write_snapshot_file<'a>(
+    path: impl AsRef<Path>,
+    entries: impl IntoIterator<Item = (&'a LedgerEntry, Option<u32>)>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let mut writer = BufWriter::new(
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?,
+    );
+    for (entry, live_until) in entries {
+        let mut record = entry.to_xdr(DEFAULT_XDR_RW_LIMITS)?;
+        record.extend(live_until.to_xdr(DEFAULT_XDR_RW_LIMITS)?);
+        write_record(&mut writer, &record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn key_hash(key: &LedgerKey) -> Result<[u8; 32]> {
+    let encoded_key = key
+        .to_xdr(DEFAULT_XDR_RW_LIMITS)
+        .context("failed to encode the ledger key")?;
+    Ok(Sha256::digest(encoded_key).into())
+}
+
+fn decode_record(record: &[u8]) -> Result<(LedgerEntry, Option<u32>)> {
+    let mut reader = Limited::new(record, DEFAULT_XDR_RW_LIMITS);
+    let entry = LedgerEntry::read_xdr(&mut reader)?;
+    let live_until = Option::<u32>::read_xdr(&mut reader)?;
+    if !reader.inner.is_empty() {
+        bail!("unexpected trailing bytes in the record");
+    }
+    Ok((entry, live_until))
+}
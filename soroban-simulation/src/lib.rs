@@ -1,8 +1,28 @@
 pub mod simulation;
-pub use network_config::NetworkConfig;
-pub use snapshot_source::AutoRestoringSnapshotSource;
+pub use assemble::{
+    assemble_transaction, AssembledTransaction, InclusionFeePolicy, MIN_INCLUSION_FEE,
+};
+pub use bucket_list_snapshot_source::{BucketListSnapshotSource, ARCHIVED_ENTRY_LIVE_UNTIL_LEDGER};
+pub use file_snapshot_source::{write_snapshot_file, FileSnapshotSource};
+pub use network_config::{ConfigSettingDiff, NetworkConfig, NetworkConfigSettings};
+pub use scenario::{ScenarioSimulator, ScenarioStepResult, SCENARIO_LEDGER_CLOSE_TIME_SECONDS};
+pub use scval_diff::{
+    ContractDataDiff, DisplayScValPath, ScValChange, ScValChangeKind, ScValPathElement,
+};
+pub use snapshot_source::{AutoRestoringSnapshotSource, LayeredSnapshotSource};
+pub use time_travel::{
+    advance_ledger_info, simulate_invoke_host_function_op_at_future_ledger, ExpiredLedgerEntry,
+    TimeTravelSimulationResult,
+};
+mod assemble;
+mod bucket_list_snapshot_source;
+mod file_snapshot_source;
 mod network_config;
+mod scenario;
+mod scval_diff;
 mod snapshot_source;
+mod time_travel;
+mod xdr_record_file;
 
 mod resources;
 #[cfg(test)]
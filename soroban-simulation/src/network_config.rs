@@ -4,12 +4,16 @@ use soroban_env_host::fees::{
     compute_rent_write_fee_per_1kb, FeeConfiguration, RentFeeConfiguration,
     RentWriteFeeConfiguration,
 };
-use soroban_env_host::storage::SnapshotSource;
+use soroban_env_host::storage::{EntryWithLiveUntil, SnapshotSource, Storage};
 use soroban_env_host::xdr::{
-    ConfigSettingEntry, ConfigSettingId, ContractCostParams, LedgerEntry, LedgerEntryData,
-    LedgerKey, LedgerKeyConfigSetting,
+    ConfigSettingContractBandwidthV0, ConfigSettingContractComputeV0,
+    ConfigSettingContractEventsV0, ConfigSettingContractExecutionLanesV0,
+    ConfigSettingContractHistoricalDataV0, ConfigSettingContractLedgerCostExtV0,
+    ConfigSettingContractLedgerCostV0, ConfigSettingContractParallelComputeV0, ConfigSettingEntry,
+    ConfigSettingId, ConfigSettingScpTiming, ContractCostParams, LedgerEntry, LedgerEntryData,
+    LedgerEntryExt, LedgerKey, LedgerKeyConfigSetting, StateArchivalSettings,
 };
-use soroban_env_host::LedgerInfo;
+use soroban_env_host::{Host, HostError, LedgerInfo, ModuleCache};
 use std::rc::Rc;
 
 const CPU_SHADOW_LIMIT_FACTOR: u64 = 10;
@@ -17,8 +21,11 @@ const MEMORY_SHADOW_LIMIT_FACTOR: u64 = 2;
 
 /// Network configuration necessary for Soroban operation simulations.
 ///
-/// This should normally be loaded from the ledger.
+/// This should normally be loaded from the ledger. Offline users may also
+/// load it from JSON or TOML (with `serde` feature enabled), or derive it from
+/// the full set of settings via [`NetworkConfigSettings::to_network_config`].
 #[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NetworkConfig {
     pub fee_configuration: FeeConfiguration,
     pub rent_fee_configuration: RentFeeConfiguration,
@@ -53,6 +60,43 @@ fn load_configuration_setting(
     }
 }
 
+/// Full set of the Soroban network settings that are stored in the
+/// `ConfigSetting` ledger entries and that may be changed by the network
+/// upgrades.
+///
+/// Unlike [`NetworkConfig`], this contains the settings that don't affect
+/// the simulation (such as the ledger-wide limits) as well, so it can be
+/// converted back to the ledger entries. The entries that are maintained by
+/// the network itself (`LiveSorobanStateSizeWindow` and `EvictionIterator`)
+/// are not included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NetworkConfigSettings {
+    pub contract_max_size_bytes: u32,
+    pub compute: ConfigSettingContractComputeV0,
+    pub ledger_cost: ConfigSettingContractLedgerCostV0,
+    pub ledger_cost_ext: ConfigSettingContractLedgerCostExtV0,
+    pub historical_data: ConfigSettingContractHistoricalDataV0,
+    pub events: ConfigSettingContractEventsV0,
+    pub bandwidth: ConfigSettingContractBandwidthV0,
+    pub cpu_cost_params: ContractCostParams,
+    pub memory_cost_params: ContractCostParams,
+    pub contract_data_key_size_bytes: u32,
+    pub contract_data_entry_size_bytes: u32,
+    pub state_archival: StateArchivalSettings,
+    pub execution_lanes: ConfigSettingContractExecutionLanesV0,
+    pub parallel_compute: ConfigSettingContractParallelComputeV0,
+    pub scp_timing: ConfigSettingScpTiming,
+}
+
+/// Change of a single setting between two [`NetworkConfigSettings`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigSettingDiff {
+    pub setting_id: ConfigSettingId,
+    pub before: ConfigSettingEntry,
+    pub after: ConfigSettingEntry,
+}
+
 macro_rules! load_setting {
     ($snapshot:ident, $enum_variant:ident) => {
         match load_configuration_setting($snapshot, ConfigSettingId::$enum_variant)? {
@@ -138,6 +182,76 @@ impl NetworkConfig {
         ledger_info.max_entry_ttl = self.max_entry_ttl;
     }
 
+    /// Checks that the configuration is internally consistent, i.e. that
+    /// it can be used for the simulation.
+    ///
+    /// Returns an error that lists all the detected issues.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = vec![];
+        self.collect_validation_errors(&mut errors);
+        validation_result(errors)
+    }
+
+    fn collect_validation_errors(&self, errors: &mut Vec<String>) {
+        if self.tx_max_instructions <= 0 {
+            errors.push("`tx_max_instructions` must be positive".to_string());
+        }
+        if self.tx_memory_limit == 0 {
+            errors.push("`tx_memory_limit` must be positive".to_string());
+        }
+        let rent = &self.rent_fee_configuration;
+        if rent.persistent_rent_rate_denominator <= 0 {
+            errors.push("`persistent_rent_rate_denominator` must be positive".to_string());
+        }
+        if rent.temporary_rent_rate_denominator <= 0 {
+            errors.push("`temporary_rent_rate_denominator` must be positive".to_string());
+        }
+        let fees = &self.fee_configuration;
+        for (name, fee) in [
+            (
+                "fee_per_instruction_increment",
+                fees.fee_per_instruction_increment,
+            ),
+            ("fee_per_disk_read_entry", fees.fee_per_disk_read_entry),
+            ("fee_per_write_entry", fees.fee_per_write_entry),
+            ("fee_per_disk_read_1kb", fees.fee_per_disk_read_1kb),
+            ("fee_per_write_1kb", fees.fee_per_write_1kb),
+            ("fee_per_historical_1kb", fees.fee_per_historical_1kb),
+            (
+                "fee_per_contract_event_1kb",
+                fees.fee_per_contract_event_1kb,
+            ),
+            (
+                "fee_per_transaction_size_1kb",
+                fees.fee_per_transaction_size_1kb,
+            ),
+            ("fee_per_rent_1kb", rent.fee_per_rent_1kb),
+        ] {
+            if fee < 0 {
+                errors.push(format!("`{name}` must not be negative"));
+            }
+        }
+        if rent.fee_per_write_1kb != fees.fee_per_write_1kb {
+            errors.push("`fee_per_write_1kb` differs between the fee configurations".to_string());
+        }
+        if rent.fee_per_write_entry != fees.fee_per_write_entry {
+            errors.push("`fee_per_write_entry` differs between the fee configurations".to_string());
+        }
+        if self.min_temp_entry_ttl == 0 || self.min_persistent_entry_ttl == 0 {
+            errors.push("minimum entry TTLs must be positive".to_string());
+        }
+        if self.min_temp_entry_ttl > self.max_entry_ttl
+            || self.min_persistent_entry_ttl > self.max_entry_ttl
+        {
+            errors.push("minimum entry TTLs must not exceed `max_entry_ttl`".to_string());
+        }
+        if self.cpu_cost_params.0.len() != self.memory_cost_params.0.len() {
+            errors.push(
+                "CPU and memory cost parameters must have the same number of entries".to_string(),
+            );
+        }
+    }
+
     pub(crate) fn create_budget(&self) -> Result<Budget> {
         let cpu_shadow_limit =
             (self.tx_max_instructions as u64).saturating_mul(CPU_SHADOW_LIMIT_FACTOR);
@@ -153,4 +267,327 @@ impl NetworkConfig {
         )
         .context("cannot create budget from network configuration")
     }
+
+    /// Creates an empty module cache for reusing the parsed contracts across
+    /// the simulations with this configuration (see
+    /// `SimulationOptions::module_cache`).
+    pub fn create_module_cache(&self) -> Result<ModuleCache> {
+        let host = Host::with_storage_and_budget(Storage::default(), self.create_budget()?);
+        ModuleCache::new(&host).context("cannot create module cache")
+    }
+}
+
+// Serves the settings as a snapshot that only contains the configuration
+// setting entries.
+struct SettingsSnapshotSource(Vec<ConfigSettingEntry>);
+
+impl SnapshotSource for SettingsSnapshotSource {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        let LedgerKey::ConfigSetting(key) = key.as_ref() else {
+            return Ok(None);
+        };
+        Ok(self
+            .0
+            .iter()
+            .find(|setting| setting.discriminant() == key.config_setting_id)
+            .map(|setting| {
+                let entry = LedgerEntry {
+                    last_modified_ledger_seq: 0,
+                    data: LedgerEntryData::ConfigSetting(setting.clone()),
+                    ext: LedgerEntryExt::V0,
+                };
+                (Rc::new(entry), None)
+            }))
+    }
+}
+
+fn validation_result(errors: Vec<String>) -> Result<()> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        bail!("invalid network configuration: {}", errors.join("; "))
+    }
+}
+
+impl NetworkConfigSettings {
+    /// Returns the settings that resemble the settings of Stellar testnet
+    /// at protocol 23, with the calibrated default cost parameters of the
+    /// host.
+    ///
+    /// This is meant for offline simulation and testing when no ledger
+    /// snapshot is available. The actual network settings change via
+    /// upgrades, so the settings should be loaded from the ledger (see
+    /// [`NetworkConfigSettings::load_from_snapshot`]) whenever the
+    /// simulation results have to match the network.
+    pub fn testnet_like() -> Result<Self> {
+        let (cpu_cost_params, memory_cost_params) = Budget::default()
+            .get_cost_params()
+            .context("cannot get the default cost parameters")?;
+        Ok(Self {
+            contract_max_size_bytes: 65_536,
+            compute: ConfigSettingContractComputeV0 {
+                ledger_max_instructions: 500_000_000,
+                tx_max_instructions: 100_000_000,
+                fee_rate_per_instructions_increment: 25,
+                tx_memory_limit: 41_943_040,
+            },
+            ledger_cost: ConfigSettingContractLedgerCostV0 {
+                ledger_max_disk_read_entries: 1_000,
+                ledger_max_disk_read_bytes: 7_000_000,
+                ledger_max_write_ledger_entries: 500,
+                ledger_max_write_bytes: 286_720,
+                tx_max_disk_read_entries: 100,
+                tx_max_disk_read_bytes: 200_000,
+                tx_max_write_ledger_entries: 50,
+                tx_max_write_bytes: 132_096,
+                fee_disk_read_ledger_entry: 6_250,
+                fee_write_ledger_entry: 10_000,
+                fee_disk_read1_kb: 1_786,
+                soroban_state_target_size_bytes: 3_000_000_000,
+                rent_fee1_kb_soroban_state_size_low: 1_000,
+                rent_fee1_kb_soroban_state_size_high: 10_000,
+                soroban_state_rent_fee_growth_factor: 5_000,
+            },
+            ledger_cost_ext: ConfigSettingContractLedgerCostExtV0 {
+                tx_max_footprint_entries: 100,
+                fee_write1_kb: 3_500,
+            },
+            historical_data: ConfigSettingContractHistoricalDataV0 {
+                fee_historical1_kb: 16_235,
+            },
+            events: ConfigSettingContractEventsV0 {
+                tx_max_contract_events_size_bytes: 16_384,
+                fee_contract_events1_kb: 10_000,
+            },
+            bandwidth: ConfigSettingContractBandwidthV0 {
+                ledger_max_txs_size_bytes: 133_120,
+                tx_max_size_bytes: 132_096,
+                fee_tx_size1_kb: 1_624,
+            },
+            cpu_cost_params,
+            memory_cost_params,
+            contract_data_key_size_bytes: 250,
+            contract_data_entry_size_bytes: 65_536,
+            state_archival: StateArchivalSettings {
+                max_entry_ttl: 3_110_400,
+                min_temporary_ttl: 17_280,
+                min_persistent_ttl: 2_073_600,
+                persistent_rent_rate_denominator: 1_215,
+                temp_rent_rate_denominator: 2_430,
+                max_entries_to_archive: 1_000,
+                live_soroban_state_size_window_sample_size: 30,
+                live_soroban_state_size_window_sample_period: 64,
+                eviction_scan_size: 100_000,
+                starting_eviction_scan_level: 7,
+            },
+            execution_lanes: ConfigSettingContractExecutionLanesV0 {
+                ledger_max_tx_count: 100,
+            },
+            parallel_compute: ConfigSettingContractParallelComputeV0 {
+                ledger_max_dependent_tx_clusters: 1,
+            },
+            scp_timing: ConfigSettingScpTiming {
+                ledger_target_close_time_milliseconds: 5_000,
+                nomination_timeout_initial_milliseconds: 1_000,
+                nomination_timeout_increment_milliseconds: 1_000,
+                ballot_timeout_initial_milliseconds: 1_000,
+                ballot_timeout_increment_milliseconds: 1_000,
+            },
+        })
+    }
+
+    /// Loads all the settings from the ledger snapshot.
+    ///
+    /// This fails when the snapshot doesn't contain any of the settings.
+    pub fn load_from_snapshot(snapshot: &impl SnapshotSource) -> Result<Self> {
+        Ok(Self {
+            contract_max_size_bytes: load_setting!(snapshot, ContractMaxSizeBytes),
+            compute: load_setting!(snapshot, ContractComputeV0),
+            ledger_cost: load_setting!(snapshot, ContractLedgerCostV0),
+            ledger_cost_ext: load_setting!(snapshot, ContractLedgerCostExtV0),
+            historical_data: load_setting!(snapshot, ContractHistoricalDataV0),
+            events: load_setting!(snapshot, ContractEventsV0),
+            bandwidth: load_setting!(snapshot, ContractBandwidthV0),
+            cpu_cost_params: load_setting!(snapshot, ContractCostParamsCpuInstructions),
+            memory_cost_params: load_setting!(snapshot, ContractCostParamsMemoryBytes),
+            contract_data_key_size_bytes: load_setting!(snapshot, ContractDataKeySizeBytes),
+            contract_data_entry_size_bytes: load_setting!(snapshot, ContractDataEntrySizeBytes),
+            state_archival: load_setting!(snapshot, StateArchival),
+            execution_lanes: load_setting!(snapshot, ContractExecutionLanes),
+            parallel_compute: load_setting!(snapshot, ContractParallelComputeV0),
+            scp_timing: load_setting!(snapshot, ScpTiming),
+        })
+    }
+
+    /// Converts the settings to the `ConfigSettingEntry` values, ordered by
+    /// the setting id.
+    pub fn to_config_setting_entries(&self) -> Vec<ConfigSettingEntry> {
+        vec![
+            ConfigSettingEntry::ContractMaxSizeBytes(self.contract_max_size_bytes),
+            ConfigSettingEntry::ContractComputeV0(self.compute.clone()),
+            ConfigSettingEntry::ContractLedgerCostV0(self.ledger_cost.clone()),
+            ConfigSettingEntry::ContractHistoricalDataV0(self.historical_data.clone()),
+            ConfigSettingEntry::ContractEventsV0(self.events.clone()),
+            ConfigSettingEntry::ContractBandwidthV0(self.bandwidth.clone()),
+            ConfigSettingEntry::ContractCostParamsCpuInstructions(self.cpu_cost_params.clone()),
+            ConfigSettingEntry::ContractCostParamsMemoryBytes(self.memory_cost_params.clone()),
+            ConfigSettingEntry::ContractDataKeySizeBytes(self.contract_data_key_size_bytes),
+            ConfigSettingEntry::ContractDataEntrySizeBytes(self.contract_data_entry_size_bytes),
+            ConfigSettingEntry::StateArchival(self.state_archival.clone()),
+            ConfigSettingEntry::ContractExecutionLanes(self.execution_lanes.clone()),
+            ConfigSettingEntry::ContractParallelComputeV0(self.parallel_compute.clone()),
+            ConfigSettingEntry::ContractLedgerCostExtV0(self.ledger_cost_ext.clone()),
+            ConfigSettingEntry::ScpTiming(self.scp_timing.clone()),
+        ]
+    }
+
+    /// Derives the simulation configuration from the settings.
+    ///
+    /// `bucket_list_size` has the same meaning as for
+    /// [`NetworkConfig::load_from_snapshot`].
+    pub fn to_network_config(&self, bucket_list_size: u64) -> Result<NetworkConfig> {
+        NetworkConfig::load_from_snapshot(
+            &SettingsSnapshotSource(self.to_config_setting_entries()),
+            bucket_list_size,
+        )
+    }
+
+    /// Checks that the settings are internally consistent, e.g. that the
+    /// per-transaction limits don't exceed the respective ledger-wide limits.
+    ///
+    /// Returns an error that lists all the detected issues.
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = vec![];
+        let compute = &self.compute;
+        let ledger_cost = &self.ledger_cost;
+        for (name, tx_limit, ledger_limit) in [
+            (
+                "instructions",
+                compute.tx_max_instructions,
+                compute.ledger_max_instructions,
+            ),
+            (
+                "disk read entries",
+                ledger_cost.tx_max_disk_read_entries.into(),
+                ledger_cost.ledger_max_disk_read_entries.into(),
+            ),
+            (
+                "disk read bytes",
+                ledger_cost.tx_max_disk_read_bytes.into(),
+                ledger_cost.ledger_max_disk_read_bytes.into(),
+            ),
+            (
+                "write entries",
+                ledger_cost.tx_max_write_ledger_entries.into(),
+                ledger_cost.ledger_max_write_ledger_entries.into(),
+            ),
+            (
+                "write bytes",
+                ledger_cost.tx_max_write_bytes.into(),
+                ledger_cost.ledger_max_write_bytes.into(),
+            ),
+            (
+                "transaction size",
+                self.bandwidth.tx_max_size_bytes.into(),
+                self.bandwidth.ledger_max_txs_size_bytes.into(),
+            ),
+        ] {
+            if tx_limit > ledger_limit {
+                errors.push(format!(
+                    "per-transaction {name} limit ({tx_limit}) exceeds the ledger limit ({ledger_limit})"
+                ));
+            }
+        }
+        // Entries that exceed the write limit can never be created.
+        for (name, size) in [
+            ("contract_max_size_bytes", self.contract_max_size_bytes),
+            (
+                "contract_data_entry_size_bytes",
+                self.contract_data_entry_size_bytes,
+            ),
+        ] {
+            if size > ledger_cost.tx_max_write_bytes {
+                errors.push(format!(
+                    "`{name}` ({size}) exceeds the per-transaction write bytes limit ({})",
+                    ledger_cost.tx_max_write_bytes
+                ));
+            }
+        }
+        if ledger_cost.soroban_state_target_size_bytes <= 0 {
+            errors.push("`soroban_state_target_size_bytes` must be positive".to_string());
+        }
+        if ledger_cost.rent_fee1_kb_soroban_state_size_low < 0
+            || ledger_cost.rent_fee1_kb_soroban_state_size_low
+                > ledger_cost.rent_fee1_kb_soroban_state_size_high
+        {
+            errors.push(
+                "rent fees must be non-negative and must not decrease with the state size"
+                    .to_string(),
+            );
+        }
+        if self.execution_lanes.ledger_max_tx_count == 0 {
+            errors.push("`ledger_max_tx_count` must be positive".to_string());
+        }
+        if self.parallel_compute.ledger_max_dependent_tx_clusters == 0 {
+            errors.push("`ledger_max_dependent_tx_clusters` must be positive".to_string());
+        }
+        // The validation doesn't depend on the rent fee, so the bucket list
+        // size doesn't matter here.
+        self.to_network_config(0)?
+            .collect_validation_errors(&mut errors);
+        validation_result(errors)
+    }
+
+    /// Returns the settings that differ between `self` and `other` (e.g.
+    /// the settings before and after a network upgrade), ordered by the
+    /// setting id.
+    ///
+    /// The `after` entries of the diff form the set of the settings that
+    /// need to be upgraded in order to get from `self` to `other`.
+    pub fn diff(&self, other: &Self) -> Vec<ConfigSettingDiff> {
+        self.to_config_setting_entries()
+            .into_iter()
+            .zip(other.to_config_setting_entries())
+            .filter(|(before, after)| before != after)
+            .map(|(before, after)| ConfigSettingDiff {
+                setting_id: before.discriminant(),
+                before,
+                after,
+            })
+            .collect()
+    }
+}
+
+// Defines the JSON and TOML conversions for the serializable configuration
+// types.
+#[cfg(feature = "serde")]
+macro_rules! impl_text_formats {
+    ($type:ty) => {
+        impl $type {
+            /// Parses the value from JSON.
+            pub fn from_json(json: &str) -> Result<Self> {
+                serde_json::from_str(json).context("cannot parse JSON")
+            }
+
+            /// Serializes the value to pretty-printed JSON.
+            pub fn to_json(&self) -> Result<String> {
+                serde_json::to_string_pretty(self).context("cannot serialize to JSON")
+            }
+
+            /// Parses the value from TOML.
+            pub fn from_toml(toml: &str) -> Result<Self> {
+                toml::from_str(toml).context("cannot parse TOML")
+            }
+
+            /// Serializes the value to TOML.
+            pub fn to_toml(&self) -> Result<String> {
+                toml::to_string(self).context("cannot serialize to TOML")
+            }
+        }
+    };
 }
+
+#[cfg(feature = "serde")]
+impl_text_formats!(NetworkConfig);
+#[cfg(feature = "serde")]
+impl_text_formats!(NetworkConfigSettings);
@@ -32,7 +32,7 @@ impl SimulationAdjustmentConfig {
 }
 
 impl SimulationAdjustmentFactor {
-    fn adjust_u32(&self, value: u32) -> u32 {
+    pub(crate) fn adjust_u32(&self, value: u32) -> u32 {
         // `0` typically means that resource hasn't been used at all,
         // so adjusting it with an additive factor would likely waste
         // resources unnecessarily.
@@ -239,7 +239,7 @@ pub(crate) fn simulate_restore_op_resources(
     Ok((resources, rent_changes))
 }
 
-fn estimate_max_transaction_size_for_operation(
+pub(crate) fn estimate_max_transaction_size_for_operation(
     operation: OperationBody,
     resources: &SorobanResources,
     restored_rw_entry_ids: &Vec<u32>,
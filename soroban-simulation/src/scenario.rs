@@ -0,0 +1,173 @@
+use crate::network_config::NetworkConfig;
+use crate::simulation::{
+    simulate_invoke_host_function_op_with_ledger_changes, InvokeHostFunctionSimulationResult,
+    LedgerEntryDiff, SimulationAdjustmentConfig, SimulationOptions,
+};
+use crate::snapshot_source::LayeredSnapshotSource;
+use crate::time_travel::advance_ledger_info;
+use anyhow::{anyhow, Result};
+use soroban_env_host::e2e_invoke::RecordingInvocationAuthMode;
+use soroban_env_host::storage::{EntryWithLiveUntil, SnapshotSource};
+use soroban_env_host::xdr::{
+    AccountId, ContractDataDurability, HostFunction, LedgerKey, ScAddress,
+};
+use soroban_env_host::{HostError, LedgerInfo};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Approximate ledger close time used to advance the ledger timestamp
+/// together with the ledger sequence number.
+pub const SCENARIO_LEDGER_CLOSE_TIME_SECONDS: u64 = 5;
+
+/// Result of simulating a single step of the scenario.
+#[derive(Debug)]
+pub struct ScenarioStepResult {
+    /// Index of the step in the scenario, starting from 0.
+    pub step_index: u32,
+    /// Ledger sequence number the step has been simulated at.
+    pub ledger_sequence_number: u32,
+    /// Simulation result of the step, which contains the resources and fee
+    /// (in `transaction_data`) and events of the step.
+    pub simulation_result: InvokeHostFunctionSimulationResult,
+}
+
+/// Simulates a sequence of dependent host function invocations, such as
+/// 'upload Wasm, deploy contract, initialize contract, invoke contract'.
+///
+/// The simulator keeps the ledger state on top of the base snapshot. After
+/// every successful step the ledger changes of the step (including the TTL
+/// extensions and the automatic restorations) are applied to the state, so
+/// that the next step observes them. The failed steps don't modify the state.
+///
+/// Every step (successful or not) closes a ledger, i.e. the next step is
+/// simulated at the next ledger sequence number. Use
+/// [`ScenarioSimulator::advance_ledgers`] to simulate longer gaps between the
+/// steps, e.g. in order to observe the entry expiration.
+pub struct ScenarioSimulator<T: SnapshotSource + 'static> {
+    snapshot_source: Rc<ScenarioSnapshotSource<T>>,
+    network_config: NetworkConfig,
+    adjustment_config: SimulationAdjustmentConfig,
+    ledger_info: LedgerInfo,
+    step_count: u32,
+}
+
+// Allows sharing the layered snapshot with the host during the simulation,
+// while still being able to modify it in between the simulations.
+struct ScenarioSnapshotSource<T: SnapshotSource>(RefCell<LayeredSnapshotSource<T>>);
+
+impl<T: SnapshotSource> SnapshotSource for ScenarioSnapshotSource<T> {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        self.0.borrow().get(key)
+    }
+
+    fn contract_data_keys(
+        &self,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+    ) -> Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        self.0.borrow().contract_data_keys(contract, durability)
+    }
+}
+
+impl<T: SnapshotSource + 'static> ScenarioSimulator<T> {
+    /// Creates the simulator with the initial ledger state defined by
+    /// `snapshot_source` and the ledger of the first step defined by
+    /// `ledger_info`.
+    pub fn new(
+        snapshot_source: Rc<T>,
+        network_config: NetworkConfig,
+        adjustment_config: SimulationAdjustmentConfig,
+        ledger_info: LedgerInfo,
+    ) -> Self {
+        Self {
+            snapshot_source: Rc::new(ScenarioSnapshotSource(RefCell::new(
+                LayeredSnapshotSource::new(snapshot_source),
+            ))),
+            network_config,
+            adjustment_config,
+            ledger_info,
+            step_count: 0,
+        }
+    }
+
+    /// Simulates the next step of the scenario at the current ledger and
+    /// applies its ledger changes to the state in case of success.
+    ///
+    /// The parameters have the same meaning as for
+    /// [`crate::simulation::simulate_invoke_host_function_op`]. Note, that the
+    /// recorded authorization entries are not signed, i.e. the steps are
+    /// simulated as if all the authorizations have been signed correctly.
+    pub fn simulate_step(
+        &mut self,
+        host_fn: HostFunction,
+        auth_mode: RecordingInvocationAuthMode,
+        source_account: &AccountId,
+        base_prng_seed: [u8; 32],
+        enable_diagnostics: bool,
+    ) -> Result<ScenarioStepResult> {
+        let (simulation_result, ledger_changes) =
+            simulate_invoke_host_function_op_with_ledger_changes(
+                self.snapshot_source.clone(),
+                &self.network_config,
+                &self.adjustment_config,
+                &self.ledger_info,
+                host_fn,
+                auth_mode,
+                source_account,
+                base_prng_seed,
+                enable_diagnostics,
+                &SimulationOptions::default(),
+            )?;
+        self.snapshot_source
+            .0
+            .borrow_mut()
+            .apply_ledger_changes(&ledger_changes)?;
+        let step_result = ScenarioStepResult {
+            step_index: self.step_count,
+            ledger_sequence_number: self.ledger_info.sequence_number,
+            simulation_result,
+        };
+        self.step_count += 1;
+        self.advance_ledgers(1)?;
+        Ok(step_result)
+    }
+
+    /// Advances the current ledger by `ledgers` ledgers without simulating
+    /// any steps.
+    ///
+    /// The ledger timestamp is advanced by
+    /// [`SCENARIO_LEDGER_CLOSE_TIME_SECONDS`] per ledger.
+    pub fn advance_ledgers(&mut self, ledgers: u32) -> Result<()> {
+        self.ledger_info = advance_ledger_info(
+            &self.ledger_info,
+            ledgers,
+            SCENARIO_LEDGER_CLOSE_TIME_SECONDS * ledgers as u64,
+        )?;
+        Ok(())
+    }
+
+    /// Returns the ledger info that the next step will be simulated with.
+    pub fn ledger_info(&self) -> &LedgerInfo {
+        &self.ledger_info
+    }
+
+    /// Returns the number of the steps simulated so far.
+    pub fn step_count(&self) -> u32 {
+        self.step_count
+    }
+
+    /// Returns the current state of the ledger entry (with its live until
+    /// ledger).
+    pub fn get_entry(&self, key: &LedgerKey) -> Result<Option<EntryWithLiveUntil>> {
+        self.snapshot_source
+            .get(&Rc::new(key.clone()))
+            .map_err(|e| anyhow!("failed to read the entry: {e:?}"))
+    }
+
+    /// Returns the differences between the current state and the initial
+    /// state for all the entries modified by the scenario so far, ordered by
+    /// key.
+    pub fn ledger_diff(&self) -> Result<Vec<LedgerEntryDiff>> {
+        self.snapshot_source.0.borrow().diff_against_base()
+    }
+}
@@ -0,0 +1,288 @@
+use crate::simulation::LedgerEntryDiff;
+use soroban_env_host::events::{DisplayScAddress, DisplayScVal};
+use soroban_env_host::xdr::{
+    ContractDataDurability, ContractDataEntry, LedgerEntry, LedgerEntryData, ScAddress,
+    ScContractInstance, ScMap, ScVal,
+};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Single step of the path from the contract data entry value to a nested
+/// value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScValPathElement {
+    /// Value of a map entry with the given key.
+    MapKey(ScVal),
+    /// Element of a vector with the given index.
+    VecIndex(u32),
+    /// Executable of a contract instance.
+    InstanceExecutable,
+    /// Value of a contract instance storage entry with the given key.
+    InstanceStorage(ScVal),
+}
+
+/// Kind of a change of a (possibly nested) contract data value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScValChangeKind {
+    Added(ScVal),
+    Removed(ScVal),
+    Changed { before: ScVal, after: ScVal },
+}
+
+/// Change of a value at `path` within the contract data entry value.
+///
+/// The empty `path` means that the whole entry value has been changed, which
+/// is also the case for the created and removed entries.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScValChange {
+    pub path: Vec<ScValPathElement>,
+    pub kind: ScValChangeKind,
+}
+
+/// Semantic difference between the states of a contract data entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ContractDataDiff {
+    pub contract: ScAddress,
+    pub key: ScVal,
+    pub durability: ContractDataDurability,
+    /// Changes in the order of their paths (with the map keys ordered by
+    /// value). Empty if the entry value hasn't changed
+    /// (e.g. when the entry has only been rewritten with the same value).
+    pub changes: Vec<ScValChange>,
+}
+
+impl LedgerEntryDiff {
+    /// Computes the semantic difference between the states of the contract
+    /// data entry, i.e. the paths of the maps, vectors and contract instance
+    /// storage values that have been added, removed or changed.
+    ///
+    /// Returns `None` for entries other than `ContractData`.
+    pub fn contract_data_diff(&self) -> Option<ContractDataDiff> {
+        let (before, after) = (
+            self.state_before.as_ref().and_then(contract_data),
+            self.state_after.as_ref().and_then(contract_data),
+        );
+        let (contract, key, durability) = match (before, after) {
+            (Some(e), _) | (None, Some(e)) => (e.contract.clone(), e.key.clone(), e.durability),
+            (None, None) => return None,
+        };
+        let mut changes = vec![];
+        let mut path = vec![];
+        diff_optional_scval(
+            before.map(|e| &e.val),
+            after.map(|e| &e.val),
+            &mut path,
+            &mut changes,
+        );
+        Some(ContractDataDiff {
+            contract,
+            key,
+            durability,
+            changes,
+        })
+    }
+}
+
+fn contract_data(entry: &LedgerEntry) -> Option<&ContractDataEntry> {
+    match &entry.data {
+        LedgerEntryData::ContractData(data) => Some(data),
+        _ => None,
+    }
+}
+
+fn diff_optional_scval(
+    before: Option<&ScVal>,
+    after: Option<&ScVal>,
+    path: &mut Vec<ScValPathElement>,
+    changes: &mut Vec<ScValChange>,
+) {
+    let kind = match (before, after) {
+        (Some(before), Some(after)) => return diff_scval(before, after, path, changes),
+        (Some(before), None) => ScValChangeKind::Removed(before.clone()),
+        (None, Some(after)) => ScValChangeKind::Added(after.clone()),
+        (None, None) => return,
+    };
+    changes.push(ScValChange {
+        path: path.clone(),
+        kind,
+    });
+}
+
+fn diff_scval(
+    before: &ScVal,
+    after: &ScVal,
+    path: &mut Vec<ScValPathElement>,
+    changes: &mut Vec<ScValChange>,
+) {
+    if before == after {
+        return;
+    }
+    match (before, after) {
+        (ScVal::Map(before_map), ScVal::Map(after_map)) => diff_maps(
+            before_map.as_ref(),
+            after_map.as_ref(),
+            ScValPathElement::MapKey,
+            path,
+            changes,
+        ),
+        (ScVal::Vec(before_vec), ScVal::Vec(after_vec)) => {
+            let before_vec = before_vec.as_ref().map_or(&[][..], |v| v.as_slice());
+            let after_vec = after_vec.as_ref().map_or(&[][..], |v| v.as_slice());
+            for i in 0..before_vec.len().max(after_vec.len()) {
+                path.push(ScValPathElement::VecIndex(i as u32));
+                diff_optional_scval(before_vec.get(i), after_vec.get(i), path, changes);
+                path.pop();
+            }
+        }
+        (ScVal::ContractInstance(before_instance), ScVal::ContractInstance(after_instance)) => {
+            diff_instances(before_instance, after_instance, path, changes)
+        }
+        _ => changes.push(ScValChange {
+            path: path.clone(),
+            kind: ScValChangeKind::Changed {
+                before: before.clone(),
+                after: after.clone(),
+            },
+        }),
+    }
+}
+
+fn diff_instances(
+    before: &ScContractInstance,
+    after: &ScContractInstance,
+    path: &mut Vec<ScValPathElement>,
+    changes: &mut Vec<ScValChange>,
+) {
+    if before.executable != after.executable {
+        let mut executable_path = path.clone();
+        executable_path.push(ScValPathElement::InstanceExecutable);
+        changes.push(ScValChange {
+            path: executable_path,
+            kind: ScValChangeKind::Changed {
+                before: ScVal::ContractInstance(ScContractInstance {
+                    executable: before.executable.clone(),
+                    storage: None,
+                }),
+                after: ScVal::ContractInstance(ScContractInstance {
+                    executable: after.executable.clone(),
+                    storage: None,
+                }),
+            },
+        });
+    }
+    diff_maps(
+        before.storage.as_ref(),
+        after.storage.as_ref(),
+        ScValPathElement::InstanceStorage,
+        path,
+        changes,
+    );
+}
+
+fn diff_maps(
+    before: Option<&ScMap>,
+    after: Option<&ScMap>,
+    path_element: fn(ScVal) -> ScValPathElement,
+    path: &mut Vec<ScValPathElement>,
+    changes: &mut Vec<ScValChange>,
+) {
+    // The `None` maps are treated as empty maps. Note, that the keys are
+    // matched by value, so this doesn't rely on the map being sorted.
+    let mut entries: BTreeMap<&ScVal, (Option<&ScVal>, Option<&ScVal>)> = BTreeMap::new();
+    for e in before.iter().flat_map(|m| m.iter()) {
+        entries.entry(&e.key).or_default().0 = Some(&e.val);
+    }
+    for e in after.iter().flat_map(|m| m.iter()) {
+        entries.entry(&e.key).or_default().1 = Some(&e.val);
+    }
+    for (key, (before_val, after_val)) in entries {
+        path.push(path_element(key.clone()));
+        diff_optional_scval(before_val, after_val, path, changes);
+        path.pop();
+    }
+}
+
+// Renders the storage keys in the way they are usually defined by contracts,
+// i.e. as enum variants (`Admin`, `Balance(G...)`).
+struct DisplayStorageKey<'a>(&'a ScVal);
+
+impl fmt::Display for DisplayStorageKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            ScVal::LedgerKeyContractInstance => write!(f, "instance"),
+            ScVal::Vec(Some(v)) if matches!(v.first(), Some(ScVal::Symbol(_))) => {
+                if let Some(ScVal::Symbol(variant)) = v.first() {
+                    write!(f, "{}", variant.0)?;
+                }
+                if v.len() > 1 {
+                    write!(f, "(")?;
+                    for (i, arg) in v.iter().skip(1).enumerate() {
+                        if i != 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", DisplayScVal(arg))?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            val => write!(f, "{}", DisplayScVal(val)),
+        }
+    }
+}
+
+/// Wrapper for displaying the path of a change relative to the given
+/// contract data key, e.g. `instance.storage[Admin]` or
+/// `Balance(G...).amount`.
+pub struct DisplayScValPath<'a> {
+    pub key: &'a ScVal,
+    pub path: &'a [ScValPathElement],
+}
+
+impl fmt::Display for DisplayScValPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", DisplayStorageKey(self.key))?;
+        for element in self.path {
+            match element {
+                ScValPathElement::MapKey(ScVal::Symbol(field)) => write!(f, ".{}", field.0)?,
+                ScValPathElement::MapKey(key) => write!(f, "[{}]", DisplayScVal(key))?,
+                ScValPathElement::VecIndex(i) => write!(f, "[{i}]")?,
+                ScValPathElement::InstanceExecutable => write!(f, ".executable")?,
+                ScValPathElement::InstanceStorage(key) => {
+                    write!(f, ".storage[{}]", DisplayStorageKey(key))?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ContractDataDiff {
+    /// Renders the contract and durability of the entry followed by one line
+    /// per change, prefixed with `+` for the added values, `-` for the
+    /// removed values and `~` for the changed values.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let durability = match self.durability {
+            ContractDataDurability::Temporary => "temporary",
+            ContractDataDurability::Persistent => "persistent",
+        };
+        write!(f, "{} ({durability})", DisplayScAddress(&self.contract))?;
+        for change in &self.changes {
+            let path = DisplayScValPath {
+                key: &self.key,
+                path: &change.path,
+            };
+            match &change.kind {
+                ScValChangeKind::Added(val) => write!(f, "\n+ {path}: {}", DisplayScVal(val))?,
+                ScValChangeKind::Removed(val) => write!(f, "\n- {path}: {}", DisplayScVal(val))?,
+                ScValChangeKind::Changed { before, after } => write!(
+                    f,
+                    "\n~ {path}: {} -> {}",
+                    DisplayScVal(before),
+                    DisplayScVal(after)
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -3,13 +3,13 @@ use crate::resources::{
     compute_adjusted_transaction_resources, compute_resource_fee, simulate_extend_ttl_op_resources,
     simulate_restore_op_resources,
 };
-use crate::snapshot_source::SimulationSnapshotSource;
+use crate::snapshot_source::{AutoRestoringSnapshotSource, SimulationSnapshotSource};
 use anyhow::Result;
 use soroban_env_host::e2e_invoke::extract_rent_changes;
 use soroban_env_host::xdr::SorobanResourcesExtV0;
 use soroban_env_host::{
-    e2e_invoke::invoke_host_function_in_recording_mode,
-    e2e_invoke::{LedgerEntryChange, RecordingInvocationAuthMode},
+    e2e_invoke::invoke_host_function_in_recording_mode_with_options,
+    e2e_invoke::{LedgerEntryChange, RecordingInvocationAuthMode, RecordingInvocationOptions},
     storage::SnapshotSource,
     xdr::{
         AccountId, ContractEvent, DiagnosticEvent, HostFunction, InvokeHostFunctionOp, LedgerKey,
@@ -17,7 +17,7 @@ use soroban_env_host::{
         SorobanTransactionDataExt,
     },
     xdr::{ExtendFootprintTtlOp, ExtensionPoint, LedgerEntry, ReadXdr, RestoreFootprintOp},
-    HostError, LedgerInfo, DEFAULT_XDR_RW_LIMITS,
+    CallTreeNode, HostError, LedgerInfo, ModuleCache, DEFAULT_XDR_RW_LIMITS,
 };
 use std::rc::Rc;
 
@@ -39,6 +39,49 @@ pub struct SimulationAdjustmentConfig {
     pub refundable_fee: SimulationAdjustmentFactor,
 }
 
+/// Optional features of `simulate_invoke_host_function_op_with_options`.
+///
+/// None of the options affects the simulated resources and fees of the
+/// invocation itself.
+#[derive(Clone, Default)]
+pub struct SimulationOptions {
+    /// Populate `call_tree` of the result with the resources consumed by
+    /// every contract call made during the invocation.
+    ///
+    /// This is meant for explaining the resource consumption of the
+    /// invocation (e.g. which nested call has consumed the most
+    /// instructions).
+    pub call_tree: bool,
+    /// Instead of automatically restoring the archived entries accessed by
+    /// the invocation, populate `restore_preamble` of the result with the
+    /// simulated `RestoreFootprintOp` for these entries.
+    ///
+    /// The invocation resources and fee are then computed as if the
+    /// invocation has been executed right after the restoration, i.e. in the
+    /// same ledger and with all the archived entries already restored. This
+    /// is useful when the restoration and the invocation don't fit into a
+    /// single transaction, or when the restoration should be paid for
+    /// separately.
+    pub restore_preamble: bool,
+    /// Reuse the parsed contracts from this cache instead of parsing them
+    /// from scratch.
+    ///
+    /// The cache is meant to be long-lived and shared between the
+    /// simulations: all the contracts loaded by the invocation are added to
+    /// it. The parsing is still accounted for as if the contracts weren't
+    /// cached. The cache should be created via
+    /// `NetworkConfig::create_module_cache` and has to be re-created
+    /// whenever the network configuration or the protocol version changes.
+    ///
+    /// Nothing is ever evicted from the cache, so it grows with every
+    /// distinct contract that is simulated. The caller owns the cache's
+    /// lifetime and is responsible for bounding its size, e.g. by replacing
+    /// it with a freshly created cache periodically. Note that removing the
+    /// modules from the cache doesn't release the code compiled into the
+    /// cache's engine.
+    pub module_cache: Option<ModuleCache>,
+}
+
 /// Represents the state of a `LedgerEntry` before and after the
 /// transaction execution.
 /// `None` represents that entry was not present or removed.
@@ -79,6 +122,30 @@ pub struct InvokeHostFunctionSimulationResult {
     /// the transaction execution.
     /// Empty for failed invocations.
     pub modified_entries: Vec<LedgerEntryDiff>,
+    /// Resources consumed by every contract call made during the
+    /// simulation, organized as a tree rooted at the host function.
+    /// This is only populated when `SimulationOptions::call_tree` is set,
+    /// and also for the invocations that have failed (unless the failure
+    /// has happened outside of the host function, e.g. during the footprint
+    /// processing).
+    pub call_tree: Option<CallTreeNode>,
+    /// Restoration of the archived entries that has to be performed before
+    /// the invocation, in case if it accesses any archived entries.
+    /// This is only populated when `SimulationOptions::restore_preamble` is
+    /// set, and only for the successful invocations.
+    pub restore_preamble: Option<RestorePreamble>,
+}
+
+/// Simulated `RestoreFootprintOp` that restores all the archived entries
+/// accessed by the simulated invocation.
+#[derive(Eq, PartialEq, Debug)]
+pub struct RestorePreamble {
+    /// Keys of the archived entries to restore, i.e. the read-write footprint
+    /// of the restore operation.
+    pub keys_to_restore: Vec<LedgerKey>,
+    /// Soroban transaction extension for the restore operation containing
+    /// the simulated resources and the estimated resource fee.
+    pub transaction_data: SorobanTransactionData,
 }
 
 /// Result of simulating `ExtendFootprintTtlOp` operation.
@@ -129,10 +196,89 @@ pub fn simulate_invoke_host_function_op(
     base_prng_seed: [u8; 32],
     enable_diagnostics: bool,
 ) -> Result<InvokeHostFunctionSimulationResult> {
-    let snapshot_source = Rc::new(SimulationSnapshotSource::new_from_rc(snapshot_source));
+    simulate_invoke_host_function_op_with_ledger_changes(
+        snapshot_source,
+        network_config,
+        adjustment_config,
+        ledger_info,
+        host_fn,
+        auth_mode,
+        source_account,
+        base_prng_seed,
+        enable_diagnostics,
+        &SimulationOptions::default(),
+    )
+    .map(|(simulation_result, _)| simulation_result)
+}
+
+/// Same as `simulate_invoke_host_function_op`, but additionally enables the
+/// optional simulation features specified by `options`.
+///
+/// The options can be freely combined with each other (e.g. the module cache
+/// can be used together with the restore preamble).
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_invoke_host_function_op_with_options(
+    snapshot_source: Rc<dyn SnapshotSource>,
+    network_config: &NetworkConfig,
+    adjustment_config: &SimulationAdjustmentConfig,
+    ledger_info: &LedgerInfo,
+    host_fn: HostFunction,
+    auth_mode: RecordingInvocationAuthMode,
+    source_account: &AccountId,
+    base_prng_seed: [u8; 32],
+    enable_diagnostics: bool,
+    options: &SimulationOptions,
+) -> Result<InvokeHostFunctionSimulationResult> {
+    simulate_invoke_host_function_op_with_ledger_changes(
+        snapshot_source,
+        network_config,
+        adjustment_config,
+        ledger_info,
+        host_fn,
+        auth_mode,
+        source_account,
+        base_prng_seed,
+        enable_diagnostics,
+        options,
+    )
+    .map(|(simulation_result, _)| simulation_result)
+}
+
+// Same as `simulate_invoke_host_function_op_with_options`, but also returns
+// the ledger changes of the successful invocations (including the TTL changes
+// that aren't a part of the simulation result).
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn simulate_invoke_host_function_op_with_ledger_changes(
+    snapshot_source: Rc<dyn SnapshotSource>,
+    network_config: &NetworkConfig,
+    adjustment_config: &SimulationAdjustmentConfig,
+    ledger_info: &LedgerInfo,
+    host_fn: HostFunction,
+    auth_mode: RecordingInvocationAuthMode,
+    source_account: &AccountId,
+    base_prng_seed: [u8; 32],
+    enable_diagnostics: bool,
+    options: &SimulationOptions,
+) -> Result<(InvokeHostFunctionSimulationResult, Vec<LedgerEntryChange>)> {
+    let simulation_snapshot_source =
+        Rc::new(SimulationSnapshotSource::new_from_rc(snapshot_source));
+    // Archived entries are restored in the snapshot itself, so that the host
+    // observes them as live entries and doesn't restore them automatically.
+    let auto_restoring_snapshot_source = if options.restore_preamble {
+        Some(Rc::new(AutoRestoringSnapshotSource::new(
+            simulation_snapshot_source.clone(),
+            ledger_info,
+        )?))
+    } else {
+        None
+    };
+    let snapshot_source: Rc<dyn SnapshotSource> = match &auto_restoring_snapshot_source {
+        Some(s) => s.clone(),
+        None => simulation_snapshot_source,
+    };
     let budget = network_config.create_budget()?;
     let mut diagnostic_events = vec![];
-    let recording_result = invoke_host_function_in_recording_mode(
+    let mut recording_result = invoke_host_function_in_recording_mode_with_options(
         &budget,
         enable_diagnostics,
         &host_fn,
@@ -141,6 +287,10 @@ pub fn simulate_invoke_host_function_op(
         ledger_info.clone(),
         snapshot_source.clone(),
         base_prng_seed,
+        RecordingInvocationOptions {
+            enable_call_tree: options.call_tree,
+            module_cache: options.module_cache.clone(),
+        },
         &mut diagnostic_events,
     );
     let invoke_result = match &recording_result {
@@ -162,12 +312,17 @@ pub fn simulate_invoke_host_function_op(
         contract_events: vec![],
         transaction_data: None,
         modified_entries: vec![],
+        call_tree: recording_result
+            .as_mut()
+            .ok()
+            .and_then(|r| r.call_tree.take()),
+        restore_preamble: None,
     };
     let Ok(recording_result) = recording_result else {
-        return Ok(simulation_result);
+        return Ok((simulation_result, vec![]));
     };
     if recording_result.invoke_result.is_err() {
-        return Ok(simulation_result);
+        return Ok((simulation_result, vec![]));
     }
     // Fill the remaining fields only for successful invocations.
     simulation_result.auth = recording_result.auth;
@@ -202,8 +357,21 @@ pub fn simulate_invoke_host_function_op(
         &recording_result.restored_rw_entry_indices,
         resource_fee,
     )?);
+    if let Some(auto_restoring_snapshot_source) = auto_restoring_snapshot_source {
+        simulation_result.restore_preamble = auto_restoring_snapshot_source
+            .simulate_restore_keys_op(network_config, adjustment_config, ledger_info)?
+            .map(|restore| RestorePreamble {
+                keys_to_restore: restore
+                    .transaction_data
+                    .resources
+                    .footprint
+                    .read_write
+                    .to_vec(),
+                transaction_data: restore.transaction_data,
+            });
+    }
 
-    Ok(simulation_result)
+    Ok((simulation_result, recording_result.ledger_changes))
 }
 
 /// Simulates `ExtendFootprintTtlOp` operation specified via its
@@ -1,11 +1,12 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use soroban_env_host::e2e_invoke::{LedgerEntryChange, TypedLedgerEntryChange};
 use soroban_env_host::ledger_info::get_key_durability;
 use soroban_env_host::storage::EntryWithLiveUntil;
 use soroban_env_host::xdr::{
     AccountEntry, AccountEntryExt, AccountEntryExtensionV1, AccountEntryExtensionV1Ext,
     AccountEntryExtensionV2, AccountEntryExtensionV2Ext, AccountEntryExtensionV3,
-    ContractDataDurability, ExtensionPoint, LedgerEntryData, Liabilities, ScErrorCode, ScErrorType,
-    SponsorshipDescriptor, TimePoint,
+    ContractDataDurability, ExtensionPoint, LedgerEntryData, Liabilities, ScAddress, ScErrorCode,
+    ScErrorType, SponsorshipDescriptor, TimePoint,
 };
 use soroban_env_host::LedgerInfo;
 use soroban_env_host::{storage::SnapshotSource, xdr::LedgerKey, HostError};
@@ -14,7 +15,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
 
 use crate::simulation::{
-    simulate_restore_op, RestoreOpSimulationResult, SimulationAdjustmentConfig,
+    simulate_restore_op, LedgerEntryDiff, RestoreOpSimulationResult, SimulationAdjustmentConfig,
 };
 use crate::NetworkConfig;
 
@@ -113,6 +114,220 @@ impl<T: SnapshotSource> SnapshotSource for AutoRestoringSnapshotSource<T> {
             Ok(None)
         }
     }
+
+    fn contract_data_keys(
+        &self,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+    ) -> Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        self.snapshot_source
+            .contract_data_keys(contract, durability)
+    }
+}
+
+type LedgerEntryUpdates = BTreeMap<Rc<LedgerKey>, Option<EntryWithLiveUntil>>;
+
+/// The copy-on-write `SnapshotSource` implementation that keeps the ledger
+/// state modifications on top of a base `SnapshotSource`.
+///
+/// Use this to chain simulations: the ledger changes produced by a simulated
+/// (or executed) host function invocation can be applied to this snapshot
+/// before simulating the next invocation. The base snapshot is never
+/// modified.
+///
+/// The modifications are organized in layers that allow nested checkpoints:
+/// [`LayeredSnapshotSource::checkpoint`] starts a new layer that can be either
+/// merged into the previous layer via [`LayeredSnapshotSource::commit`], or
+/// discarded via [`LayeredSnapshotSource::rollback`].
+pub struct LayeredSnapshotSource<T: SnapshotSource> {
+    base: Rc<T>,
+    // Layers from the oldest to the newest; there is always at least one
+    // layer.
+    layers: Vec<LedgerEntryUpdates>,
+}
+
+impl<T: SnapshotSource> LayeredSnapshotSource<T> {
+    pub fn new(base: Rc<T>) -> Self {
+        Self {
+            base,
+            layers: vec![LedgerEntryUpdates::new()],
+        }
+    }
+
+    /// Returns the base snapshot source.
+    pub fn base(&self) -> &Rc<T> {
+        &self.base
+    }
+
+    /// Applies the ledger changes produced by a host function invocation to
+    /// the current layer.
+    ///
+    /// Modified entries are updated, removed entries are removed, and the
+    /// live until ledgers of the read-only entries are extended.
+    pub fn apply_ledger_changes(&mut self, changes: &[LedgerEntryChange]) -> Result<()> {
+        for change in changes {
+            if let Some((key, update)) = ledger_change_to_update(self, change)? {
+                self.current_layer().insert(key, update);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the entry diffs (e.g. `modified_entries` of the simulation
+    /// result) to the current layer.
+    ///
+    /// The diffs don't contain the live until ledgers, so the existing live
+    /// entries keep their live until ledgers, while the new (or restored)
+    /// entries get the minimum live until ledger for the given `ledger_info`,
+    /// as the host would set for them. Prefer
+    /// [`LayeredSnapshotSource::apply_ledger_changes`] when the exact TTL
+    /// extensions matter.
+    pub fn apply_entry_diffs(
+        &mut self,
+        diffs: &[LedgerEntryDiff],
+        ledger_info: &LedgerInfo,
+    ) -> Result<()> {
+        for diff in diffs {
+            let key = match (&diff.state_after, &diff.state_before) {
+                (Some(entry), _) | (None, Some(entry)) => Rc::new(entry.to_key()),
+                (None, None) => continue,
+            };
+            let update = match &diff.state_after {
+                Some(entry) => {
+                    let live_until = match get_key_durability(&key) {
+                        Some(durability) => {
+                            let existing_live_until = self
+                                .get(&key)?
+                                .and_then(|(_, live_until)| live_until)
+                                .filter(|live_until| *live_until >= ledger_info.sequence_number);
+                            match existing_live_until {
+                                Some(live_until) => Some(live_until),
+                                None => Some(
+                                    ledger_info
+                                        .min_live_until_ledger_checked(durability)
+                                        .ok_or_else(|| {
+                                            anyhow!("minimum live until ledger overflows - ledger info is misconfigured")
+                                        })?,
+                                ),
+                            }
+                        }
+                        None => None,
+                    };
+                    Some((Rc::new(entry.clone()), live_until))
+                }
+                None => None,
+            };
+            self.current_layer().insert(key, update);
+        }
+        Ok(())
+    }
+
+    /// Starts a new layer for the subsequent modifications.
+    pub fn checkpoint(&mut self) {
+        self.layers.push(LedgerEntryUpdates::new());
+    }
+
+    /// Returns the number of checkpoints that haven't been committed or
+    /// rolled back yet.
+    pub fn checkpoint_depth(&self) -> usize {
+        self.layers.len() - 1
+    }
+
+    /// Merges the modifications made since the latest checkpoint into the
+    /// previous layer.
+    pub fn commit(&mut self) -> Result<()> {
+        if self.layers.len() < 2 {
+            bail!("no checkpoint to commit");
+        }
+        let layer = self.layers.pop().unwrap_or_default();
+        self.current_layer().extend(layer);
+        Ok(())
+    }
+
+    /// Discards the modifications made since the latest checkpoint.
+    pub fn rollback(&mut self) -> Result<()> {
+        if self.layers.len() < 2 {
+            bail!("no checkpoint to roll back");
+        }
+        self.layers.pop();
+        Ok(())
+    }
+
+    /// Returns the differences between the current state and the base
+    /// snapshot for all the modified entries, ordered by key.
+    ///
+    /// Entries with only the live until ledger modified are not included.
+    pub fn diff_against_base(&self) -> Result<Vec<LedgerEntryDiff>> {
+        let mut updates = LedgerEntryUpdates::new();
+        for layer in &self.layers {
+            updates.extend(layer.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        let mut diffs = vec![];
+        for (key, update) in updates {
+            let state_before = self.base.get(&key)?.map(|(e, _)| e.as_ref().clone());
+            let state_after = update.map(|(e, _)| e.as_ref().clone());
+            if state_before != state_after {
+                diffs.push(LedgerEntryDiff {
+                    state_before,
+                    state_after,
+                });
+            }
+        }
+        Ok(diffs)
+    }
+
+    fn current_layer(&mut self) -> &mut LedgerEntryUpdates {
+        // There is always at least one layer.
+        let last = self.layers.len() - 1;
+        &mut self.layers[last]
+    }
+}
+
+impl<T: SnapshotSource> SnapshotSource for LayeredSnapshotSource<T> {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        for layer in self.layers.iter().rev() {
+            if let Some(update) = layer.get(key) {
+                return Ok(update.clone());
+            }
+        }
+        self.base.get(key)
+    }
+
+    fn contract_data_keys(
+        &self,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+    ) -> Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        let Some(base_keys) = self.base.contract_data_keys(contract, durability)? else {
+            return Ok(None);
+        };
+        // The keys removed by the layers are still returned, which is fine
+        // as `get` returns `None` for them.
+        let mut keys: BTreeSet<Rc<LedgerKey>> = base_keys.into_iter().collect();
+        for layer in &self.layers {
+            keys.extend(
+                layer
+                    .keys()
+                    .filter(|key| {
+                        matches!(key.as_ref(), LedgerKey::ContractData(cd)
+                            if cd.contract == *contract && cd.durability == durability)
+                    })
+                    .cloned(),
+            );
+        }
+        Ok(Some(keys.into_iter().collect()))
+    }
+}
+
+// Converts a ledger change produced by the host into the new state of the
+// entry, as defined by `TypedLedgerEntryChange::updated_entry`.
+pub(crate) fn ledger_change_to_update(
+    snapshot: &(impl SnapshotSource + ?Sized),
+    change: &LedgerEntryChange,
+) -> Result<Option<(Rc<LedgerKey>, Option<EntryWithLiveUntil>)>> {
+    TypedLedgerEntryChange::from_encoded(change)?
+        .updated_entry(snapshot)
+        .context("cannot apply the ledger change")
 }
 
 #[derive(Default)]
@@ -163,6 +378,17 @@ impl SnapshotSource for SnapshotSourceHolder<'_> {
             SnapshotSourceHolder::Rc(r) => r.get(key),
         }
     }
+
+    fn contract_data_keys(
+        &self,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+    ) -> Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        match self {
+            SnapshotSourceHolder::Ref(r) => r.contract_data_keys(contract, durability),
+            SnapshotSourceHolder::Rc(r) => r.contract_data_keys(contract, durability),
+        }
+    }
 }
 
 // This is an internal wrapper for the snapshot sources used in the simulation.
@@ -198,6 +424,14 @@ impl SnapshotSource for SimulationSnapshotSource<'_> {
             .borrow_mut()
             .maybe_update_entry(key, self.inner_snapshot.get(key)?))
     }
+
+    fn contract_data_keys(
+        &self,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+    ) -> Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        self.inner_snapshot.contract_data_keys(contract, durability)
+    }
 }
 
 fn update_account_entry(account_entry: &mut AccountEntry) {
@@ -1,3 +1,9 @@
+mod assemble;
+mod bucket_list_snapshot_source;
+mod file_snapshot_source;
 mod network_config;
+mod scenario;
+mod scval_diff;
 mod simulation;
 mod snapshot_source;
+mod time_travel;
@@ -0,0 +1,362 @@
+use crate::assemble::{assemble_transaction, InclusionFeePolicy, MIN_INCLUSION_FEE};
+use crate::simulation::{
+    InvokeHostFunctionSimulationResult, RestoreOpSimulationResult, RestorePreamble,
+    SimulationAdjustmentConfig,
+};
+use crate::NetworkConfig;
+use pretty_assertions::assert_eq;
+use soroban_env_host::e2e_testutils::get_account_id;
+use soroban_env_host::fees::FeeConfiguration;
+use soroban_env_host::xdr::{
+    ContractId, ExtendFootprintTtlOp, ExtensionPoint, FeeBumpTransactionInnerTx, Hash,
+    HostFunction, InvokeContractArgs, InvokeHostFunctionOp, LedgerFootprint, Memo, MuxedAccount,
+    Operation, OperationBody, Preconditions, ScAddress, ScBytes, ScErrorCode, ScErrorType,
+    ScSymbol, ScVal, SequenceNumber, SorobanAddressCredentials, SorobanAuthorizationEntry,
+    SorobanAuthorizedFunction, SorobanAuthorizedInvocation, SorobanCredentials, SorobanResources,
+    SorobanTransactionData, SorobanTransactionDataExt, Transaction, TransactionEnvelope,
+    TransactionExt, TransactionV1Envelope, Uint256,
+};
+use soroban_env_host::HostError;
+
+fn invoke_fn() -> InvokeContractArgs {
+    InvokeContractArgs {
+        contract_address: ScAddress::Contract(ContractId(Hash([1; 32]))),
+        function_name: ScSymbol("transfer".try_into().unwrap()),
+        args: Default::default(),
+    }
+}
+
+fn test_transaction(fee: u32) -> Transaction {
+    Transaction {
+        source_account: MuxedAccount::Ed25519(Uint256([2; 32])),
+        fee,
+        seq_num: SequenceNumber(10),
+        cond: Preconditions::None,
+        memo: Memo::Text("memo".try_into().unwrap()),
+        operations: vec![Operation {
+            source_account: None,
+            body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+                host_function: HostFunction::InvokeContract(invoke_fn()),
+                auth: Default::default(),
+            }),
+        }]
+        .try_into()
+        .unwrap(),
+        ext: TransactionExt::V0,
+    }
+}
+
+fn transaction_data(resource_fee: i64) -> SorobanTransactionData {
+    SorobanTransactionData {
+        ext: SorobanTransactionDataExt::V0,
+        resources: SorobanResources {
+            footprint: LedgerFootprint {
+                read_only: Default::default(),
+                read_write: Default::default(),
+            },
+            instructions: 1_000_000,
+            disk_read_bytes: 100,
+            write_bytes: 200,
+        },
+        resource_fee,
+    }
+}
+
+fn auth_entry(signature: ScVal) -> SorobanAuthorizationEntry {
+    SorobanAuthorizationEntry {
+        credentials: SorobanCredentials::Address(SorobanAddressCredentials {
+            address: ScAddress::Account(get_account_id([3; 32])),
+            nonce: 123,
+            signature_expiration_ledger: 1000,
+            signature,
+        }),
+        root_invocation: SorobanAuthorizedInvocation {
+            function: SorobanAuthorizedFunction::ContractFn(invoke_fn()),
+            sub_invocations: Default::default(),
+        },
+    }
+}
+
+fn simulation_result(resource_fee: i64) -> InvokeHostFunctionSimulationResult {
+    InvokeHostFunctionSimulationResult {
+        invoke_result: Ok(ScVal::Void),
+        auth: vec![auth_entry(ScVal::Void)],
+        contract_events: vec![],
+        diagnostic_events: vec![],
+        transaction_data: Some(transaction_data(resource_fee)),
+        simulated_instructions: 1_000_000,
+        simulated_memory: 1000,
+        modified_entries: vec![],
+        call_tree: None,
+        restore_preamble: None,
+    }
+}
+
+fn tx_data(tx: &Transaction) -> &SorobanTransactionData {
+    match &tx.ext {
+        TransactionExt::V1(data) => data,
+        TransactionExt::V0 => panic!("missing transaction data"),
+    }
+}
+
+fn op_auth(tx: &Transaction) -> Vec<SorobanAuthorizationEntry> {
+    match &tx.operations[0].body {
+        OperationBody::InvokeHostFunction(op) => op.auth.to_vec(),
+        _ => panic!("unexpected operation"),
+    }
+}
+
+#[test]
+fn test_assemble_transaction_with_inclusion_fee_policies() {
+    let sim_res = simulation_result(5000);
+
+    let assembled = assemble_transaction(
+        test_transaction(300),
+        &sim_res,
+        &InclusionFeePolicy::Fixed(1000),
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(assembled.inclusion_fee, 1000);
+    assert_eq!(assembled.restore_transaction, None);
+    assert_eq!(assembled.transaction.fee, 6000);
+    assert_eq!(assembled.transaction.seq_num, SequenceNumber(10));
+    assert_eq!(
+        assembled.transaction.memo,
+        Memo::Text("memo".try_into().unwrap())
+    );
+    assert_eq!(tx_data(&assembled.transaction), &transaction_data(5000));
+    assert_eq!(op_auth(&assembled.transaction), sim_res.auth);
+    assert_eq!(
+        assembled.envelope().unwrap(),
+        TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx: assembled.transaction.clone(),
+            signatures: Default::default(),
+        })
+    );
+    assert_eq!(assembled.restore_envelope().unwrap(), None);
+
+    let assembled = assemble_transaction(
+        test_transaction(300),
+        &sim_res,
+        &InclusionFeePolicy::FromTransaction,
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(assembled.inclusion_fee, 300);
+    assert_eq!(assembled.transaction.fee, 5300);
+
+    let assembled = assemble_transaction(
+        test_transaction(300),
+        &sim_res,
+        &InclusionFeePolicy::Minimum,
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(assembled.inclusion_fee, MIN_INCLUSION_FEE);
+    assert_eq!(assembled.transaction.fee, 5000 + MIN_INCLUSION_FEE);
+}
+
+#[test]
+fn test_assemble_transaction_with_restore_preamble() {
+    let restore = RestoreOpSimulationResult {
+        transaction_data: transaction_data(700),
+    };
+    let assembled = assemble_transaction(
+        test_transaction(0),
+        &simulation_result(5000),
+        &InclusionFeePolicy::Fixed(200),
+        Some(&restore),
+        None,
+    )
+    .unwrap();
+    let restore_tx = assembled.restore_transaction.as_ref().unwrap();
+    assert_eq!(restore_tx.seq_num, SequenceNumber(10));
+    assert_eq!(restore_tx.fee, 900);
+    assert_eq!(restore_tx.memo, Memo::None);
+    assert_eq!(
+        restore_tx.source_account,
+        assembled.transaction.source_account
+    );
+    assert!(matches!(
+        restore_tx.operations[0].body,
+        OperationBody::RestoreFootprint(_)
+    ));
+    assert_eq!(tx_data(restore_tx), &transaction_data(700));
+
+    assert_eq!(assembled.transaction.seq_num, SequenceNumber(11));
+    assert_eq!(assembled.transaction.fee, 5200);
+    assert!(assembled.restore_envelope().unwrap().is_some());
+
+    // Restore preamble of the simulation result is used by default.
+    let mut sim_res = simulation_result(5000);
+    sim_res.restore_preamble = Some(RestorePreamble {
+        keys_to_restore: vec![],
+        transaction_data: transaction_data(700),
+    });
+    let assembled_from_preamble = assemble_transaction(
+        test_transaction(0),
+        &sim_res,
+        &InclusionFeePolicy::Fixed(200),
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(assembled_from_preamble, assembled);
+}
+
+#[test]
+fn test_assemble_transaction_with_fee_bump() {
+    let fee_source = MuxedAccount::Ed25519(Uint256([4; 32]));
+    let assembled = assemble_transaction(
+        test_transaction(0),
+        &simulation_result(5000),
+        &InclusionFeePolicy::Fixed(200),
+        Some(&RestoreOpSimulationResult {
+            transaction_data: transaction_data(700),
+        }),
+        Some(fee_source.clone()),
+    )
+    .unwrap();
+    let TransactionEnvelope::TxFeeBump(envelope) = assembled.envelope().unwrap() else {
+        panic!("expected fee bump envelope");
+    };
+    assert_eq!(envelope.tx.fee_source, fee_source);
+    assert_eq!(envelope.tx.fee, 5400);
+    let FeeBumpTransactionInnerTx::Tx(inner) = &envelope.tx.inner_tx;
+    assert_eq!(inner.tx, assembled.transaction);
+    assert!(inner.signatures.is_empty());
+
+    let Some(TransactionEnvelope::TxFeeBump(restore_envelope)) =
+        assembled.restore_envelope().unwrap()
+    else {
+        panic!("expected fee bump restore envelope");
+    };
+    assert_eq!(restore_envelope.tx.fee, 1100);
+}
+
+#[test]
+fn test_assembled_transaction_set_signed_auth() {
+    let network_config = NetworkConfig {
+        fee_configuration: FeeConfiguration {
+            fee_per_transaction_size_1kb: 10_000,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut assembled = assemble_transaction(
+        test_transaction(0),
+        &simulation_result(5000),
+        &InclusionFeePolicy::Fixed(100),
+        None,
+        None,
+    )
+    .unwrap();
+    let signed_auth = vec![auth_entry(ScVal::Bytes(ScBytes(
+        vec![7; 64].try_into().unwrap(),
+    )))];
+    assembled
+        .set_signed_auth(
+            signed_auth.clone(),
+            &network_config,
+            &SimulationAdjustmentConfig::no_adjustments(),
+        )
+        .unwrap();
+    assert_eq!(op_auth(&assembled.transaction), signed_auth);
+    // 64 signature bytes, 4 bytes for the vector length and 4 bytes for the
+    // `ScBytes` discriminant instead of `Void` (which also takes 4 bytes), at
+    // 10000 stroops per KB.
+    let resource_fee = tx_data(&assembled.transaction).resource_fee;
+    assert_eq!(resource_fee, 5000 + 664);
+    assert_eq!(assembled.transaction.fee as i64, resource_fee + 100);
+
+    // Re-setting the same auth doesn't change the fee.
+    assembled
+        .set_signed_auth(
+            signed_auth,
+            &network_config,
+            &SimulationAdjustmentConfig::no_adjustments(),
+        )
+        .unwrap();
+    assert_eq!(tx_data(&assembled.transaction).resource_fee, resource_fee);
+}
+
+#[test]
+fn test_assemble_transaction_errors() {
+    let sim_res = simulation_result(5000);
+
+    let mut failed_sim_res = simulation_result(5000);
+    failed_sim_res.invoke_result = Err(HostError::from((
+        ScErrorType::Context,
+        ScErrorCode::InvalidAction,
+    )));
+    failed_sim_res.transaction_data = None;
+    assert!(assemble_transaction(
+        test_transaction(0),
+        &failed_sim_res,
+        &InclusionFeePolicy::Minimum,
+        None,
+        None,
+    )
+    .is_err());
+
+    let mut no_data_sim_res = simulation_result(5000);
+    no_data_sim_res.transaction_data = None;
+    assert!(assemble_transaction(
+        test_transaction(0),
+        &no_data_sim_res,
+        &InclusionFeePolicy::Minimum,
+        None,
+        None,
+    )
+    .is_err());
+
+    let mut wrong_op_tx = test_transaction(0);
+    wrong_op_tx.operations = vec![Operation {
+        source_account: None,
+        body: OperationBody::ExtendFootprintTtl(ExtendFootprintTtlOp {
+            ext: ExtensionPoint::V0,
+            extend_to: 100,
+        }),
+    }]
+    .try_into()
+    .unwrap();
+    assert!(assemble_transaction(
+        wrong_op_tx,
+        &sim_res,
+        &InclusionFeePolicy::Minimum,
+        None,
+        None
+    )
+    .is_err());
+
+    let mut two_ops_tx = test_transaction(0);
+    two_ops_tx.operations = [
+        two_ops_tx.operations.to_vec(),
+        two_ops_tx.operations.to_vec(),
+    ]
+    .concat()
+    .try_into()
+    .unwrap();
+    assert!(assemble_transaction(
+        two_ops_tx,
+        &sim_res,
+        &InclusionFeePolicy::Minimum,
+        None,
+        None
+    )
+    .is_err());
+
+    // The fee has to fit into `u32`.
+    assert!(assemble_transaction(
+        test_transaction(0),
+        &simulation_result(u32::MAX as i64),
+        &InclusionFeePolicy::Fixed(1),
+        None,
+        None,
+    )
+    .is_err());
+}
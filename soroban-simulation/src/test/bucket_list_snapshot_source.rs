@@ -0,0 +1,238 @@
+use crate::bucket_list_snapshot_source::{
+    BucketListSnapshotSource, ARCHIVED_ENTRY_LIVE_UNTIL_LEDGER,
+};
+use crate::testutils::temp_entry;
+use crate::xdr_record_file::write_record;
+use crate::AutoRestoringSnapshotSource;
+use pretty_assertions::assert_eq;
+use sha2::{Digest, Sha256};
+use soroban_env_host::e2e_testutils::{
+    account_entry, get_account_id, ledger_entry, wasm_entry_non_validated,
+};
+use soroban_env_host::storage::SnapshotSource;
+use soroban_env_host::xdr::{
+    BucketEntry, BucketListType, BucketMetadata, BucketMetadataExt, ExtensionPoint,
+    HotArchiveBucketEntry, LedgerEntry, LedgerEntryData, LedgerKey, ScErrorType, ScVal, TtlEntry,
+    WriteXdr,
+};
+use soroban_env_host::{LedgerInfo, DEFAULT_XDR_RW_LIMITS};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "soroban-bucket-list-snapshot-{}-{name}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_bucket<T: WriteXdr>(path: &Path, entries: &[T]) {
+    let mut bytes = vec![];
+    for entry in entries {
+        write_record(&mut bytes, &entry.to_xdr(DEFAULT_XDR_RW_LIMITS).unwrap()).unwrap();
+    }
+    std::fs::write(path, bytes).unwrap();
+}
+
+fn meta(bucket_list_type: BucketListType) -> BucketMetadata {
+    BucketMetadata {
+        ledger_version: 23,
+        ext: BucketMetadataExt::V1(bucket_list_type),
+    }
+}
+
+fn key(entry: &LedgerEntry) -> LedgerKey {
+    entry.to_key()
+}
+
+fn ttl_entry(entry: &LedgerEntry, live_until_ledger_seq: u32) -> LedgerEntry {
+    let key_hash: [u8; 32] =
+        Sha256::digest(key(entry).to_xdr(DEFAULT_XDR_RW_LIMITS).unwrap()).into();
+    ledger_entry(LedgerEntryData::Ttl(TtlEntry {
+        key_hash: key_hash.into(),
+        live_until_ledger_seq,
+    }))
+}
+
+fn temp_entry_with_val(key: &[u8], val: ScVal) -> LedgerEntry {
+    let mut entry = temp_entry(key);
+    if let LedgerEntryData::ContractData(data) = &mut entry.data {
+        data.val = val;
+    }
+    entry
+}
+
+fn get(
+    snapshot: &BucketListSnapshotSource,
+    entry: &LedgerEntry,
+) -> Option<(LedgerEntry, Option<u32>)> {
+    snapshot
+        .get(&Rc::new(key(entry)))
+        .unwrap()
+        .map(|(e, live_until)| (e.as_ref().clone(), live_until))
+}
+
+#[test]
+fn test_bucket_list_snapshot_source() {
+    let dir = temp_dir("basic");
+    let account = account_entry(&get_account_id([1; 32]));
+    let old_temp = temp_entry_with_val(b"1", ScVal::U32(1));
+    let new_temp = temp_entry_with_val(b"1", ScVal::U32(2));
+    let dead_temp = temp_entry(b"2");
+    let init_temp = temp_entry(b"3");
+    let missing_temp = temp_entry(b"4");
+    let live_wasm = wasm_entry_non_validated(b"1");
+    let archived_wasm = wasm_entry_non_validated(b"2");
+    let restored_wasm = wasm_entry_non_validated(b"3");
+    let evicted_temp = temp_entry(b"5");
+
+    let level0 = dir.join("level0.xdr");
+    let level1 = dir.join("level1.xdr");
+    let hot_archive0 = dir.join("hot_archive0.xdr");
+    let hot_archive1 = dir.join("hot_archive1.xdr");
+    write_bucket(
+        &level0,
+        &[
+            BucketEntry::Metaentry(meta(BucketListType::Live)),
+            BucketEntry::Liveentry(new_temp.clone()),
+            BucketEntry::Liveentry(ttl_entry(&new_temp, 200)),
+            BucketEntry::Deadentry(key(&dead_temp)),
+            BucketEntry::Deadentry(key(&ttl_entry(&dead_temp, 0))),
+            BucketEntry::Initentry(init_temp.clone()),
+            BucketEntry::Initentry(ttl_entry(&init_temp, 300)),
+        ],
+    );
+    write_bucket(
+        &level1,
+        &[
+            BucketEntry::Metaentry(meta(BucketListType::Live)),
+            BucketEntry::Liveentry(account.clone()),
+            BucketEntry::Liveentry(old_temp.clone()),
+            BucketEntry::Liveentry(ttl_entry(&old_temp, 100)),
+            BucketEntry::Liveentry(dead_temp.clone()),
+            BucketEntry::Liveentry(ttl_entry(&dead_temp, 100)),
+            BucketEntry::Liveentry(live_wasm.clone()),
+            BucketEntry::Liveentry(ttl_entry(&live_wasm, 400)),
+        ],
+    );
+    write_bucket(
+        &hot_archive0,
+        &[
+            HotArchiveBucketEntry::Metaentry(meta(BucketListType::HotArchive)),
+            HotArchiveBucketEntry::Live(key(&restored_wasm)),
+        ],
+    );
+    write_bucket(
+        &hot_archive1,
+        &[
+            HotArchiveBucketEntry::Metaentry(meta(BucketListType::HotArchive)),
+            HotArchiveBucketEntry::Archived(archived_wasm.clone()),
+            HotArchiveBucketEntry::Archived(restored_wasm.clone()),
+            // Only the persistent entries may be archived, but make sure
+            // that the temporary entries are never served from the archive.
+            HotArchiveBucketEntry::Archived(evicted_temp.clone()),
+        ],
+    );
+
+    let snapshot =
+        BucketListSnapshotSource::open(&[&level0, &level1], &[&hot_archive0, &hot_archive1])
+            .unwrap();
+    assert_eq!(get(&snapshot, &account), Some((account.clone(), None)));
+    assert_eq!(get(&snapshot, &old_temp), Some((new_temp, Some(200))));
+    assert_eq!(get(&snapshot, &dead_temp), None);
+    assert_eq!(get(&snapshot, &init_temp), Some((init_temp, Some(300))));
+    assert_eq!(get(&snapshot, &missing_temp), None);
+    assert_eq!(get(&snapshot, &live_wasm), Some((live_wasm, Some(400))));
+    assert_eq!(
+        get(&snapshot, &archived_wasm),
+        Some((
+            archived_wasm.clone(),
+            Some(ARCHIVED_ENTRY_LIVE_UNTIL_LEDGER)
+        ))
+    );
+    assert_eq!(get(&snapshot, &restored_wasm), None);
+    assert_eq!(get(&snapshot, &evicted_temp), None);
+
+    // The archived entries have to be restored before they can be accessed.
+    let snapshot = Rc::new(snapshot);
+    let ledger_info = LedgerInfo {
+        sequence_number: 1,
+        min_persistent_entry_ttl: 1000,
+        ..Default::default()
+    };
+    let auto_restoring_snapshot =
+        AutoRestoringSnapshotSource::new(snapshot.clone(), &ledger_info).unwrap();
+    assert_eq!(
+        auto_restoring_snapshot
+            .get(&Rc::new(key(&archived_wasm)))
+            .unwrap()
+            .map(|(e, live_until)| (e.as_ref().clone(), live_until)),
+        Some((archived_wasm, Some(1000)))
+    );
+
+    // The older buckets alone still contain the shadowed versions.
+    let snapshot = BucketListSnapshotSource::open(&[&level1], &[&hot_archive1]).unwrap();
+    assert_eq!(get(&snapshot, &old_temp), Some((old_temp, Some(100))));
+    assert_eq!(get(&snapshot, &dead_temp), Some((dead_temp, Some(100))));
+    assert_eq!(get(&snapshot, &account), Some((account, None)));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_bucket_list_snapshot_source_errors() {
+    let dir = temp_dir("errors");
+    let no_paths: &[&Path] = &[];
+    let temp = temp_entry(b"1");
+
+    // Hot archive bucket passed as a live bucket.
+    let hot_archive = dir.join("hot_archive.xdr");
+    write_bucket(
+        &hot_archive,
+        &[BucketEntry::Metaentry(meta(BucketListType::HotArchive))],
+    );
+    let err = BucketListSnapshotSource::open(&[&hot_archive], no_paths)
+        .err()
+        .unwrap();
+    assert!(format!("{err:#}").contains("unexpected bucket list type"));
+
+    // Malformed bucket.
+    let malformed = dir.join("malformed.xdr");
+    write_bucket(&malformed, &[ExtensionPoint::V0]);
+    let err = BucketListSnapshotSource::open(&[&malformed], no_paths)
+        .err()
+        .unwrap();
+    assert!(format!("{err:#}").contains("malformed record at offset 0"));
+
+    // Contract data entry without TTL.
+    let missing_ttl = dir.join("missing_ttl.xdr");
+    write_bucket(&missing_ttl, &[BucketEntry::Liveentry(temp.clone())]);
+    let err = BucketListSnapshotSource::open(&[&missing_ttl], no_paths)
+        .err()
+        .unwrap();
+    assert!(format!("{err:#}").contains("missing TTL entry"));
+
+    // Bucket modified after the source has been created.
+    let modified = dir.join("modified.xdr");
+    write_bucket(
+        &modified,
+        &[
+            BucketEntry::Liveentry(temp.clone()),
+            BucketEntry::Liveentry(ttl_entry(&temp, 100)),
+        ],
+    );
+    let snapshot = BucketListSnapshotSource::open(&[&modified], no_paths).unwrap();
+    assert!(snapshot.take_last_error().is_none());
+    std::fs::write(&modified, []).unwrap();
+    let err = snapshot.get(&Rc::new(key(&temp))).err().unwrap();
+    assert!(err.error.is_type(ScErrorType::Storage));
+    let err = snapshot.take_last_error().unwrap();
+    assert!(format!("{err:#}").contains("failed to read record at offset 0"));
+    assert!(snapshot.take_last_error().is_none());
+
+    assert!(BucketListSnapshotSource::open(&[dir.join("missing.xdr")], no_paths).is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
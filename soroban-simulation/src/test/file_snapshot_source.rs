@@ -0,0 +1,159 @@
+use crate::file_snapshot_source::{write_snapshot_file, FileSnapshotSource};
+use crate::testutils::{ledger_entry_to_ledger_key, temp_entry};
+use pretty_assertions::assert_eq;
+use soroban_env_host::e2e_invoke::{LedgerEntryChange, LedgerEntryLiveUntilChange};
+use soroban_env_host::e2e_testutils::{account_entry, get_account_id, wasm_entry_non_validated};
+use soroban_env_host::storage::SnapshotSource;
+use soroban_env_host::xdr::{
+    ContractDataDurability, LedgerEntry, LedgerEntryType, LedgerKey, ScErrorType, WriteXdr,
+};
+use soroban_env_host::DEFAULT_XDR_RW_LIMITS;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "soroban-file-snapshot-{}-{name}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn key(entry: &LedgerEntry) -> Rc<LedgerKey> {
+    Rc::new(ledger_entry_to_ledger_key(entry).unwrap())
+}
+
+fn get(snapshot: &FileSnapshotSource, entry: &LedgerEntry) -> Option<(LedgerEntry, Option<u32>)> {
+    snapshot
+        .get(&key(entry))
+        .unwrap()
+        .map(|(e, live_until)| (e.as_ref().clone(), live_until))
+}
+
+fn ttl_change(
+    entry_type: LedgerEntryType,
+    new_live_until_ledger: u32,
+) -> LedgerEntryLiveUntilChange {
+    LedgerEntryLiveUntilChange {
+        key_hash: vec![0; 32],
+        durability: ContractDataDurability::Temporary,
+        entry_type,
+        old_live_until_ledger: 0,
+        new_live_until_ledger,
+    }
+}
+
+#[test]
+fn test_file_snapshot_source() {
+    let dir = temp_dir("basic");
+    let wasm = wasm_entry_non_validated(b"1");
+    let account = account_entry(&get_account_id([1; 32]));
+    let temp = temp_entry(b"2");
+    let new_temp = temp_entry(b"3");
+    let missing = temp_entry(b"4");
+    write_snapshot_file(
+        dir.join("0.xdr"),
+        [(&wasm, Some(100)), (&account, None), (&temp, Some(50))],
+    )
+    .unwrap();
+    // Later files override the earlier ones.
+    write_snapshot_file(dir.join("1.xdr"), [(&temp, Some(200))]).unwrap();
+
+    let mut snapshot = FileSnapshotSource::open(&dir).unwrap();
+    assert_eq!(get(&snapshot, &wasm), Some((wasm.clone(), Some(100))));
+    assert_eq!(get(&snapshot, &account), Some((account.clone(), None)));
+    assert_eq!(get(&snapshot, &temp), Some((temp.clone(), Some(200))));
+    assert_eq!(get(&snapshot, &missing), None);
+
+    let encoded_key = |e: &LedgerEntry| key(e).to_xdr(DEFAULT_XDR_RW_LIMITS).unwrap();
+    snapshot
+        .apply_ledger_changes(&[
+            // TTL extension of a read-only entry.
+            LedgerEntryChange {
+                read_only: true,
+                encoded_key: encoded_key(&wasm),
+                ttl_change: Some(ttl_change(LedgerEntryType::ContractCode, 500)),
+                ..Default::default()
+            },
+            // Read-only entry without TTL is not affected.
+            LedgerEntryChange {
+                read_only: true,
+                encoded_key: encoded_key(&account),
+                ..Default::default()
+            },
+            // Removal.
+            LedgerEntryChange {
+                read_only: false,
+                encoded_key: encoded_key(&temp),
+                encoded_new_value: None,
+                ..Default::default()
+            },
+            // Creation.
+            LedgerEntryChange {
+                read_only: false,
+                encoded_key: encoded_key(&new_temp),
+                encoded_new_value: Some(new_temp.to_xdr(DEFAULT_XDR_RW_LIMITS).unwrap()),
+                ttl_change: Some(ttl_change(LedgerEntryType::ContractData, 300)),
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+    assert_eq!(get(&snapshot, &wasm), Some((wasm, Some(500))));
+    assert_eq!(get(&snapshot, &account), Some((account, None)));
+    assert_eq!(get(&snapshot, &temp), None);
+    assert_eq!(get(&snapshot, &new_temp), Some((new_temp, Some(300))));
+
+    // The files are not modified.
+    let snapshot = FileSnapshotSource::open(dir.join("0.xdr")).unwrap();
+    assert_eq!(get(&snapshot, &temp), Some((temp, Some(50))));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_file_snapshot_source_malformed_file() {
+    let dir = temp_dir("malformed");
+    let wasm = wasm_entry_non_validated(b"1");
+    let path = dir.join("snapshot.xdr");
+    write_snapshot_file(&path, [(&wasm, Some(100))]).unwrap();
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes.truncate(bytes.len() - 1);
+    std::fs::write(&path, bytes).unwrap();
+
+    let snapshot = FileSnapshotSource::open(&path).unwrap();
+    let err = snapshot.build_index().unwrap_err();
+    assert!(format!("{err:#}").contains("truncated record at offset 0"));
+    let err = snapshot.get(&key(&wasm)).err().unwrap();
+    assert!(err.error.is_type(ScErrorType::Storage));
+    let err = snapshot.take_last_error().unwrap();
+    assert!(format!("{err:#}").contains("truncated record at offset 0"));
+    assert!(snapshot.take_last_error().is_none());
+
+    // The files are only opened when they are read, so removing a file after
+    // the index has been built only fails the accesses to its entries.
+    let files_dir = temp_dir("removed");
+    let temp = temp_entry(b"2");
+    write_snapshot_file(files_dir.join("0.xdr"), [(&wasm, Some(100))]).unwrap();
+    write_snapshot_file(files_dir.join("1.xdr"), [(&temp, Some(200))]).unwrap();
+    let snapshot = FileSnapshotSource::open(&files_dir).unwrap();
+    snapshot.build_index().unwrap();
+    std::fs::remove_file(files_dir.join("0.xdr")).unwrap();
+    assert_eq!(get(&snapshot, &temp), Some((temp, Some(200))));
+    assert!(snapshot.get(&key(&wasm)).is_err());
+    let err = snapshot.take_last_error().unwrap();
+    assert!(format!("{err:#}").contains("failed to open"));
+    let _ = std::fs::remove_dir_all(&files_dir);
+
+    // The record length exceeding the file size is rejected without
+    // allocating the record buffer.
+    let huge_path = dir.join("huge.xdr");
+    std::fs::write(&huge_path, u32::MAX.to_be_bytes()).unwrap();
+    let snapshot = FileSnapshotSource::open(&huge_path).unwrap();
+    let err = snapshot.build_index().unwrap_err();
+    assert!(format!("{err:#}").contains("truncated record at offset 0"));
+
+    assert!(FileSnapshotSource::open(dir.join("missing.xdr")).is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
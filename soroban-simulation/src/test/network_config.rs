@@ -1,13 +1,16 @@
-use crate::network_config::NetworkConfig;
+use crate::network_config::{ConfigSettingDiff, NetworkConfig, NetworkConfigSettings};
 use soroban_env_host::{
+    budget::Budget,
     e2e_testutils::ledger_entry,
     fees::{FeeConfiguration, RentFeeConfiguration},
     xdr::{
         ConfigSettingContractBandwidthV0, ConfigSettingContractComputeV0,
-        ConfigSettingContractEventsV0, ConfigSettingContractHistoricalDataV0,
-        ConfigSettingContractLedgerCostExtV0, ConfigSettingContractLedgerCostV0,
-        ConfigSettingEntry, ContractCostParamEntry, ContractCostParams, ContractCostType,
-        ExtensionPoint, LedgerEntry, LedgerEntryData, StateArchivalSettings,
+        ConfigSettingContractEventsV0, ConfigSettingContractExecutionLanesV0,
+        ConfigSettingContractHistoricalDataV0, ConfigSettingContractLedgerCostExtV0,
+        ConfigSettingContractLedgerCostV0, ConfigSettingContractParallelComputeV0,
+        ConfigSettingEntry, ConfigSettingId, ConfigSettingScpTiming, ContractCostParamEntry,
+        ContractCostParams, ContractCostType, ExtensionPoint, LedgerEntry, LedgerEntryData,
+        StateArchivalSettings,
     },
     LedgerInfo,
 };
@@ -226,3 +229,255 @@ fn test_fill_ledger_info() {
         }
     )
 }
+
+fn cost_params(terms: &[(i64, i64)]) -> ContractCostParams {
+    ContractCostParams(
+        terms
+            .iter()
+            .map(|(const_term, linear_term)| ContractCostParamEntry {
+                ext: ExtensionPoint::V0,
+                const_term: *const_term,
+                linear_term: *linear_term,
+            })
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap(),
+    )
+}
+
+fn test_settings() -> NetworkConfigSettings {
+    NetworkConfigSettings {
+        contract_max_size_bytes: 65_536,
+        compute: ConfigSettingContractComputeV0 {
+            ledger_max_instructions: 500_000_000,
+            tx_max_instructions: 100_000_000,
+            fee_rate_per_instructions_increment: 25,
+            tx_memory_limit: 40_000_000,
+        },
+        ledger_cost: ConfigSettingContractLedgerCostV0 {
+            ledger_max_disk_read_entries: 500,
+            ledger_max_disk_read_bytes: 3_500_000,
+            ledger_max_write_ledger_entries: 250,
+            ledger_max_write_bytes: 143_360,
+            tx_max_disk_read_entries: 100,
+            tx_max_disk_read_bytes: 200_000,
+            tx_max_write_ledger_entries: 50,
+            tx_max_write_bytes: 132_096,
+            fee_disk_read_ledger_entry: 6_250,
+            fee_write_ledger_entry: 10_000,
+            fee_disk_read1_kb: 1_786,
+            soroban_state_target_size_bytes: 100_000_000_000_000,
+            rent_fee1_kb_soroban_state_size_low: 1_000_000,
+            rent_fee1_kb_soroban_state_size_high: 1_000_000_000,
+            soroban_state_rent_fee_growth_factor: 50,
+        },
+        ledger_cost_ext: ConfigSettingContractLedgerCostExtV0 {
+            tx_max_footprint_entries: 100,
+            fee_write1_kb: 3_500,
+        },
+        historical_data: ConfigSettingContractHistoricalDataV0 {
+            fee_historical1_kb: 16_235,
+        },
+        events: ConfigSettingContractEventsV0 {
+            tx_max_contract_events_size_bytes: 16_384,
+            fee_contract_events1_kb: 10_000,
+        },
+        bandwidth: ConfigSettingContractBandwidthV0 {
+            ledger_max_txs_size_bytes: 133_120,
+            tx_max_size_bytes: 132_096,
+            fee_tx_size1_kb: 1_624,
+        },
+        cpu_cost_params: cost_params(&[(35, 36), (37, 38)]),
+        memory_cost_params: cost_params(&[(39, 40), (41, 42)]),
+        contract_data_key_size_bytes: 250,
+        contract_data_entry_size_bytes: 65_536,
+        state_archival: StateArchivalSettings {
+            max_entry_ttl: 3_110_400,
+            min_temporary_ttl: 17_280,
+            min_persistent_ttl: 2_073_600,
+            persistent_rent_rate_denominator: 1_215,
+            temp_rent_rate_denominator: 2_430,
+            max_entries_to_archive: 1_000,
+            live_soroban_state_size_window_sample_size: 30,
+            live_soroban_state_size_window_sample_period: 64,
+            eviction_scan_size: 100_000,
+            starting_eviction_scan_level: 7,
+        },
+        execution_lanes: ConfigSettingContractExecutionLanesV0 {
+            ledger_max_tx_count: 100,
+        },
+        parallel_compute: ConfigSettingContractParallelComputeV0 {
+            ledger_max_dependent_tx_clusters: 1,
+        },
+        scp_timing: ConfigSettingScpTiming {
+            ledger_target_close_time_milliseconds: 5_000,
+            nomination_timeout_initial_milliseconds: 1_000,
+            nomination_timeout_increment_milliseconds: 1_000,
+            ballot_timeout_initial_milliseconds: 1_000,
+            ballot_timeout_increment_milliseconds: 1_000,
+        },
+    }
+}
+
+#[test]
+fn test_network_config_settings_to_entries_round_trip() {
+    let settings = test_settings();
+    let entries = settings.to_config_setting_entries();
+    assert_eq!(
+        entries.iter().map(|e| e.discriminant()).collect::<Vec<_>>(),
+        ConfigSettingEntry::VARIANTS
+            .into_iter()
+            .filter(|id| !matches!(
+                id,
+                ConfigSettingId::LiveSorobanStateSizeWindow | ConfigSettingId::EvictionIterator
+            ))
+            .collect::<Vec<_>>()
+    );
+    let snapshot_source =
+        MockSnapshotSource::from_entries(entries.into_iter().map(config_entry).collect()).unwrap();
+    assert_eq!(
+        NetworkConfigSettings::load_from_snapshot(&snapshot_source).unwrap(),
+        settings
+    );
+    assert_eq!(
+        settings.to_network_config(150_000_000_000_000).unwrap(),
+        NetworkConfig::load_from_snapshot(&snapshot_source, 150_000_000_000_000).unwrap()
+    );
+}
+
+#[test]
+fn test_validate_network_config_settings() {
+    let settings = test_settings();
+    settings.validate().unwrap();
+    settings.to_network_config(0).unwrap().validate().unwrap();
+
+    let mut invalid_settings = settings.clone();
+    invalid_settings.ledger_cost.tx_max_write_bytes = 200_000;
+    invalid_settings.compute.tx_max_instructions = 600_000_000;
+    invalid_settings.state_archival.temp_rent_rate_denominator = 0;
+    invalid_settings.state_archival.min_persistent_ttl = 4_000_000;
+    invalid_settings.memory_cost_params = cost_params(&[(39, 40)]);
+    let err = invalid_settings.validate().unwrap_err().to_string();
+    assert_eq!(
+        err,
+        "invalid network configuration: \
+         per-transaction instructions limit (600000000) exceeds the ledger limit (500000000); \
+         per-transaction write bytes limit (200000) exceeds the ledger limit (143360); \
+         `temporary_rent_rate_denominator` must be positive; \
+         minimum entry TTLs must not exceed `max_entry_ttl`; \
+         CPU and memory cost parameters must have the same number of entries"
+    );
+
+    let mut invalid_settings = settings.clone();
+    invalid_settings.contract_data_entry_size_bytes = 200_000;
+    invalid_settings
+        .ledger_cost
+        .rent_fee1_kb_soroban_state_size_low = 2_000_000_000;
+    assert!(invalid_settings.validate().is_err());
+
+    let invalid_config = NetworkConfig {
+        tx_max_instructions: 100,
+        tx_memory_limit: 100,
+        ..Default::default()
+    };
+    let err = invalid_config.validate().unwrap_err().to_string();
+    assert_eq!(
+        err,
+        "invalid network configuration: \
+         `persistent_rent_rate_denominator` must be positive; \
+         `temporary_rent_rate_denominator` must be positive; \
+         minimum entry TTLs must be positive"
+    );
+}
+
+#[test]
+fn test_testnet_like_network_config_settings() {
+    let settings = NetworkConfigSettings::testnet_like().unwrap();
+    settings.validate().unwrap();
+    let (cpu_cost_params, memory_cost_params) = Budget::default().get_cost_params().unwrap();
+    assert_eq!(settings.cpu_cost_params, cpu_cost_params);
+    assert_eq!(settings.memory_cost_params, memory_cost_params);
+
+    let config = settings.to_network_config(0).unwrap();
+    config.validate().unwrap();
+    assert_eq!(config.tx_max_instructions, 100_000_000);
+    assert_eq!(config.max_entry_ttl, 3_110_400);
+    config.create_budget().unwrap();
+}
+
+#[test]
+fn test_network_config_settings_diff() {
+    let before = test_settings();
+    assert!(before.diff(&before).is_empty());
+
+    let mut after = before.clone();
+    after.ledger_cost.tx_max_write_bytes = 100_000;
+    after.ledger_cost.fee_write_ledger_entry = 20_000;
+    after.contract_data_key_size_bytes = 300;
+    assert_eq!(
+        before.diff(&after),
+        vec![
+            ConfigSettingDiff {
+                setting_id: ConfigSettingId::ContractLedgerCostV0,
+                before: ConfigSettingEntry::ContractLedgerCostV0(before.ledger_cost.clone()),
+                after: ConfigSettingEntry::ContractLedgerCostV0(after.ledger_cost.clone()),
+            },
+            ConfigSettingDiff {
+                setting_id: ConfigSettingId::ContractDataKeySizeBytes,
+                before: ConfigSettingEntry::ContractDataKeySizeBytes(250),
+                after: ConfigSettingEntry::ContractDataKeySizeBytes(300),
+            },
+        ]
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_network_config_text_formats_round_trip() {
+    let settings = test_settings();
+    let json = settings.to_json().unwrap();
+    assert_eq!(NetworkConfigSettings::from_json(&json).unwrap(), settings);
+    let toml = settings.to_toml().unwrap();
+    assert_eq!(NetworkConfigSettings::from_toml(&toml).unwrap(), settings);
+
+    let config = settings.to_network_config(150_000_000_000_000).unwrap();
+    let json = config.to_json().unwrap();
+    assert_eq!(NetworkConfig::from_json(&json).unwrap(), config);
+    let toml = config.to_toml().unwrap();
+    assert_eq!(NetworkConfig::from_toml(&toml).unwrap(), config);
+
+    // Configuration can also be written by hand.
+    let config = NetworkConfig::from_toml(
+        r#"
+        tx_max_instructions = 100000000
+        tx_memory_limit = 40000000
+        min_temp_entry_ttl = 16
+        min_persistent_entry_ttl = 4096
+        max_entry_ttl = 6312000
+        cpu_cost_params = []
+        memory_cost_params = []
+
+        [fee_configuration]
+        fee_per_instruction_increment = 25
+        fee_per_disk_read_entry = 6250
+        fee_per_write_entry = 10000
+        fee_per_disk_read_1kb = 1786
+        fee_per_write_1kb = 3500
+        fee_per_historical_1kb = 16235
+        fee_per_contract_event_1kb = 10000
+        fee_per_transaction_size_1kb = 1624
+
+        [rent_fee_configuration]
+        fee_per_write_1kb = 3500
+        fee_per_rent_1kb = 5000
+        fee_per_write_entry = 10000
+        persistent_rent_rate_denominator = 1215
+        temporary_rent_rate_denominator = 2430
+        "#,
+    )
+    .unwrap();
+    config.validate().unwrap();
+    assert_eq!(config.fee_configuration.fee_per_write_1kb, 3500);
+    assert_eq!(config.max_entry_ttl, 6_312_000);
+    assert!(NetworkConfig::from_json("{}").is_err());
+}
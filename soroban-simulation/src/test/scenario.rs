@@ -0,0 +1,154 @@
+use crate::scenario::{ScenarioSimulator, SCENARIO_LEDGER_CLOSE_TIME_SECONDS};
+use crate::simulation::SimulationAdjustmentConfig;
+use crate::test::simulation::default_network_config;
+use crate::testutils::MockSnapshotSource;
+use pretty_assertions::assert_eq;
+use soroban_env_host::e2e_invoke::RecordingInvocationAuthMode;
+use soroban_env_host::e2e_testutils::{
+    bytes_sc_val, default_ledger_info, get_account_id, get_wasm_hash, get_wasm_key,
+    upload_wasm_host_fn, CreateContractData,
+};
+use soroban_env_host::xdr::{
+    ContractDataDurability, HostFunction, InvokeContractArgs, LedgerKey, LedgerKeyContractData,
+    ScAddress, ScSymbol, ScVal,
+};
+use soroban_test_wasms::CONTRACT_STORAGE;
+use std::rc::Rc;
+
+fn invoke_host_fn(contract: &ScAddress, function_name: &str, args: Vec<ScVal>) -> HostFunction {
+    HostFunction::InvokeContract(InvokeContractArgs {
+        contract_address: contract.clone(),
+        function_name: ScSymbol(function_name.try_into().unwrap()),
+        args: args.try_into().unwrap(),
+    })
+}
+
+fn sym(s: &str) -> ScVal {
+    ScVal::Symbol(ScSymbol(s.try_into().unwrap()))
+}
+
+#[test]
+fn test_scenario_simulator_applies_changes_between_steps() {
+    let source_account = get_account_id([123; 32]);
+    let ledger_info = default_ledger_info();
+    let contract = CreateContractData::new([1; 32], CONTRACT_STORAGE);
+    let contract_address = contract.contract_address.clone();
+    let mut simulator = ScenarioSimulator::new(
+        Rc::new(MockSnapshotSource::from_entries(vec![]).unwrap()),
+        default_network_config(),
+        SimulationAdjustmentConfig::no_adjustments(),
+        ledger_info.clone(),
+    );
+    let simulate_step = |simulator: &mut ScenarioSimulator<_>, host_fn| {
+        simulator
+            .simulate_step(
+                host_fn,
+                RecordingInvocationAuthMode::Recording(true),
+                &source_account,
+                [1; 32],
+                false,
+            )
+            .unwrap()
+    };
+
+    let upload = simulate_step(&mut simulator, upload_wasm_host_fn(CONTRACT_STORAGE));
+    assert_eq!(upload.step_index, 0);
+    assert_eq!(upload.ledger_sequence_number, ledger_info.sequence_number);
+    assert_eq!(
+        upload.simulation_result.invoke_result.unwrap(),
+        bytes_sc_val(&get_wasm_hash(CONTRACT_STORAGE))
+    );
+
+    // Contract can only be created after its Wasm has been uploaded.
+    let create = simulate_step(&mut simulator, contract.host_fn.clone());
+    assert_eq!(create.step_index, 1);
+    assert_eq!(
+        create.ledger_sequence_number,
+        ledger_info.sequence_number + 1
+    );
+    assert_eq!(
+        create.simulation_result.invoke_result.unwrap(),
+        ScVal::Address(contract_address.clone())
+    );
+
+    let put = simulate_step(
+        &mut simulator,
+        invoke_host_fn(
+            &contract_address,
+            "put_temporary",
+            vec![sym("key"), ScVal::U64(5)],
+        ),
+    );
+    assert!(put.simulation_result.invoke_result.is_ok());
+    let put_resources = &put
+        .simulation_result
+        .transaction_data
+        .as_ref()
+        .unwrap()
+        .resources;
+    assert!(put_resources.write_bytes > 0);
+
+    let get = simulate_step(
+        &mut simulator,
+        invoke_host_fn(&contract_address, "get_temporary", vec![sym("key")]),
+    );
+    assert_eq!(get.simulation_result.invoke_result.unwrap(), ScVal::U64(5));
+
+    // Failed steps don't modify the state, but still close the ledger.
+    let failed = simulate_step(
+        &mut simulator,
+        invoke_host_fn(&contract_address, "get_persistent", vec![sym("key")]),
+    );
+    assert!(failed.simulation_result.invoke_result.is_err());
+    assert_eq!(simulator.step_count(), 5);
+    assert_eq!(
+        simulator.ledger_info().sequence_number,
+        ledger_info.sequence_number + 5
+    );
+
+    let temp_key = LedgerKey::ContractData(LedgerKeyContractData {
+        contract: contract_address.clone(),
+        key: sym("key"),
+        durability: ContractDataDurability::Temporary,
+    });
+    let (_, live_until) = simulator.get_entry(&temp_key).unwrap().unwrap();
+    let live_until = live_until.unwrap();
+    assert_eq!(
+        live_until,
+        put.ledger_sequence_number + ledger_info.min_temp_entry_ttl - 1
+    );
+
+    // The temporary entry expires once the ledger passes its live until
+    // ledger.
+    let ledgers_to_expiration = live_until + 1 - simulator.ledger_info().sequence_number;
+    simulator.advance_ledgers(ledgers_to_expiration).unwrap();
+    assert_eq!(
+        simulator.ledger_info().timestamp,
+        ledger_info.timestamp
+            + SCENARIO_LEDGER_CLOSE_TIME_SECONDS * (5 + ledgers_to_expiration) as u64
+    );
+    let has = simulate_step(
+        &mut simulator,
+        invoke_host_fn(&contract_address, "has_temporary", vec![sym("key")]),
+    );
+    assert_eq!(has.ledger_sequence_number, live_until + 1);
+    assert_eq!(
+        has.simulation_result.invoke_result.unwrap(),
+        ScVal::Bool(false)
+    );
+
+    let modified_keys: Vec<LedgerKey> = simulator
+        .ledger_diff()
+        .unwrap()
+        .into_iter()
+        .map(|diff| diff.state_after.unwrap().to_key())
+        .collect();
+    assert_eq!(
+        modified_keys,
+        vec![
+            temp_key,
+            contract.contract_key.clone(),
+            get_wasm_key(CONTRACT_STORAGE)
+        ]
+    );
+}
@@ -0,0 +1,222 @@
+use crate::scval_diff::{ScValChange, ScValChangeKind, ScValPathElement};
+use crate::simulation::LedgerEntryDiff;
+use pretty_assertions::assert_eq;
+use soroban_env_host::e2e_testutils::{account_entry, get_account_id, ledger_entry};
+use soroban_env_host::events::DisplayScAddress;
+use soroban_env_host::xdr::{
+    ContractDataDurability, ContractDataEntry, ContractExecutable, ContractId, ExtensionPoint,
+    Hash, LedgerEntry, LedgerEntryData, ScAddress, ScContractInstance, ScMap, ScMapEntry, ScSymbol,
+    ScVal,
+};
+
+fn sym(s: &str) -> ScVal {
+    ScVal::Symbol(ScSymbol(s.try_into().unwrap()))
+}
+
+fn map(entries: Vec<(ScVal, ScVal)>) -> ScMap {
+    ScMap(
+        entries
+            .into_iter()
+            .map(|(key, val)| ScMapEntry { key, val })
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap(),
+    )
+}
+
+fn enum_key(args: Vec<ScVal>) -> ScVal {
+    ScVal::Vec(Some(args.try_into().unwrap()))
+}
+
+fn contract() -> ScAddress {
+    ScAddress::Contract(ContractId(Hash([1; 32])))
+}
+
+fn user() -> ScAddress {
+    ScAddress::Contract(ContractId(Hash([2; 32])))
+}
+
+fn data_entry(key: ScVal, val: ScVal) -> LedgerEntry {
+    ledger_entry(LedgerEntryData::ContractData(ContractDataEntry {
+        ext: ExtensionPoint::V0,
+        contract: contract(),
+        key,
+        durability: ContractDataDurability::Persistent,
+        val,
+    }))
+}
+
+fn instance_entry(wasm_hash: [u8; 32], storage: Vec<(ScVal, ScVal)>) -> LedgerEntry {
+    data_entry(
+        ScVal::LedgerKeyContractInstance,
+        ScVal::ContractInstance(ScContractInstance {
+            executable: ContractExecutable::Wasm(Hash(wasm_hash)),
+            storage: Some(map(storage)),
+        }),
+    )
+}
+
+fn balance_entry(amount: u32, flags: Vec<ScVal>) -> LedgerEntry {
+    data_entry(
+        enum_key(vec![sym("Balance"), ScVal::Address(user())]),
+        ScVal::Map(Some(map(vec![
+            (sym("amount"), ScVal::U32(amount)),
+            (sym("flags"), ScVal::Vec(Some(flags.try_into().unwrap()))),
+        ]))),
+    )
+}
+
+#[test]
+fn test_contract_data_diff_instance() {
+    let admin_key = enum_key(vec![sym("Admin")]);
+    let diff = LedgerEntryDiff {
+        state_before: Some(instance_entry(
+            [0; 32],
+            vec![
+                (admin_key.clone(), ScVal::Address(user())),
+                (sym("Paused"), ScVal::Bool(false)),
+            ],
+        )),
+        state_after: Some(instance_entry(
+            [0; 32],
+            vec![
+                (admin_key.clone(), ScVal::Address(contract())),
+                (sym("Version"), ScVal::U32(2)),
+            ],
+        )),
+    };
+    let cd_diff = diff.contract_data_diff().unwrap();
+    assert_eq!(cd_diff.contract, contract());
+    assert_eq!(cd_diff.key, ScVal::LedgerKeyContractInstance);
+    assert_eq!(cd_diff.durability, ContractDataDurability::Persistent);
+    assert_eq!(
+        cd_diff.changes,
+        vec![
+            ScValChange {
+                path: vec![ScValPathElement::InstanceStorage(sym("Paused"))],
+                kind: ScValChangeKind::Removed(ScVal::Bool(false)),
+            },
+            ScValChange {
+                path: vec![ScValPathElement::InstanceStorage(sym("Version"))],
+                kind: ScValChangeKind::Added(ScVal::U32(2)),
+            },
+            ScValChange {
+                path: vec![ScValPathElement::InstanceStorage(admin_key)],
+                kind: ScValChangeKind::Changed {
+                    before: ScVal::Address(user()),
+                    after: ScVal::Address(contract()),
+                },
+            },
+        ]
+    );
+    let contract_strkey = DisplayScAddress(&contract()).to_string();
+    let user_strkey = DisplayScAddress(&user()).to_string();
+    assert_eq!(
+        cd_diff.to_string(),
+        format!(
+            "{contract_strkey} (persistent)\n\
+             - instance.storage[Paused]: false\n\
+             + instance.storage[Version]: 2\n\
+             ~ instance.storage[Admin]: {user_strkey} -> {contract_strkey}"
+        )
+    );
+
+    // Executable update.
+    let diff = LedgerEntryDiff {
+        state_before: Some(instance_entry([0; 32], vec![])),
+        state_after: Some(instance_entry([1; 32], vec![])),
+    };
+    let cd_diff = diff.contract_data_diff().unwrap();
+    assert_eq!(cd_diff.changes.len(), 1);
+    assert_eq!(
+        cd_diff.changes[0].path,
+        vec![ScValPathElement::InstanceExecutable]
+    );
+    assert_eq!(
+        cd_diff.to_string(),
+        format!(
+            "{contract_strkey} (persistent)\n\
+             ~ instance.executable: ContractInstance(Wasm({})) -> ContractInstance(Wasm({}))",
+            Hash([0; 32]),
+            Hash([1; 32])
+        )
+    );
+}
+
+#[test]
+fn test_contract_data_diff_nested_values() {
+    let user_strkey = DisplayScAddress(&user()).to_string();
+    let diff = LedgerEntryDiff {
+        state_before: Some(balance_entry(10, vec![ScVal::U32(1), ScVal::U32(2)])),
+        state_after: Some(balance_entry(
+            20,
+            vec![ScVal::U32(1), ScVal::U32(3), sym("x")],
+        )),
+    };
+    assert_eq!(
+        diff.contract_data_diff().unwrap().to_string(),
+        format!(
+            "{} (persistent)\n\
+             ~ Balance({user_strkey}).amount: 10 -> 20\n\
+             ~ Balance({user_strkey}).flags[1]: 2 -> 3\n\
+             + Balance({user_strkey}).flags[2]: x",
+            DisplayScAddress(&contract())
+        )
+    );
+
+    // Created and removed entries are reported as the whole value change.
+    let entry = balance_entry(10, vec![]);
+    let LedgerEntryData::ContractData(data) = &entry.data else {
+        unreachable!()
+    };
+    let diff = LedgerEntryDiff {
+        state_before: None,
+        state_after: Some(entry.clone()),
+    };
+    assert_eq!(
+        diff.contract_data_diff().unwrap().changes,
+        vec![ScValChange {
+            path: vec![],
+            kind: ScValChangeKind::Added(data.val.clone()),
+        }]
+    );
+    let diff = LedgerEntryDiff {
+        state_before: Some(entry.clone()),
+        state_after: None,
+    };
+    assert_eq!(
+        diff.contract_data_diff().unwrap().changes,
+        vec![ScValChange {
+            path: vec![],
+            kind: ScValChangeKind::Removed(data.val.clone()),
+        }]
+    );
+
+    // Rewrite with the same value has no changes.
+    let diff = LedgerEntryDiff {
+        state_before: Some(entry.clone()),
+        state_after: Some(entry),
+    };
+    assert!(diff.contract_data_diff().unwrap().changes.is_empty());
+
+    // Type change is reported as a change of the whole value.
+    let key = sym("counter");
+    let diff = LedgerEntryDiff {
+        state_before: Some(data_entry(key.clone(), ScVal::U32(1))),
+        state_after: Some(data_entry(key, ScVal::Vec(None))),
+    };
+    assert_eq!(
+        diff.contract_data_diff().unwrap().to_string(),
+        format!(
+            "{} (persistent)\n~ counter: 1 -> []",
+            DisplayScAddress(&contract())
+        )
+    );
+
+    // Non-contract data entries have no semantic diff.
+    let diff = LedgerEntryDiff {
+        state_before: None,
+        state_after: Some(account_entry(&get_account_id([0; 32]))),
+    };
+    assert_eq!(diff.contract_data_diff(), None);
+}
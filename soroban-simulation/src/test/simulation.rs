@@ -1,7 +1,8 @@
 use crate::simulation::{
-    simulate_extend_ttl_op, simulate_invoke_host_function_op, simulate_restore_op,
-    ExtendTtlOpSimulationResult, LedgerEntryDiff, RestoreOpSimulationResult,
-    SimulationAdjustmentConfig, SimulationAdjustmentFactor,
+    simulate_extend_ttl_op, simulate_invoke_host_function_op,
+    simulate_invoke_host_function_op_with_options, simulate_restore_op,
+    ExtendTtlOpSimulationResult, LedgerEntryDiff, RestoreOpSimulationResult, RestorePreamble,
+    SimulationAdjustmentConfig, SimulationAdjustmentFactor, SimulationOptions,
 };
 use crate::testutils::{ledger_entry_to_ledger_key, temp_entry, MockSnapshotSource};
 use crate::NetworkConfig;
@@ -30,7 +31,7 @@ use soroban_test_wasms::{ADD_I32, AUTH_TEST_CONTRACT, TRY_CALL_SAC};
 use std::rc::Rc;
 use tap::prelude::*;
 
-fn default_network_config() -> NetworkConfig {
+pub(crate) fn default_network_config() -> NetworkConfig {
     let default_entry = ContractCostParamEntry {
         ext: ExtensionPoint::V0,
         const_term: 0,
@@ -500,6 +501,167 @@ fn test_simulate_invoke_contract_with_auth() {
     );
 }
 
+#[test]
+fn test_simulate_invoke_contract_with_call_tree() {
+    let contracts = [
+        CreateContractData::new([1; 32], AUTH_TEST_CONTRACT),
+        CreateContractData::new([2; 32], AUTH_TEST_CONTRACT),
+    ];
+    let tree = AuthContractInvocationNode {
+        address: contracts[0].contract_address.clone(),
+        children: vec![AuthContractInvocationNode {
+            address: contracts[1].contract_address.clone(),
+            children: vec![],
+        }],
+    };
+    let source_account = get_account_id([123; 32]);
+    let host_fn = auth_contract_invocation(vec![ScAddress::Account(source_account.clone())], tree);
+    let ledger_info = default_ledger_info();
+    let network_config = default_network_config();
+    let snapshot_source = Rc::new(
+        MockSnapshotSource::from_entries(vec![
+            (
+                contracts[0].wasm_entry.clone(),
+                Some(ledger_info.sequence_number + 100),
+            ),
+            (
+                contracts[0].contract_entry.clone(),
+                Some(ledger_info.sequence_number + 1000),
+            ),
+            (
+                contracts[1].contract_entry.clone(),
+                Some(ledger_info.sequence_number + 1000),
+            ),
+        ])
+        .unwrap(),
+    );
+    let simulate = |with_call_tree: bool| {
+        simulate_invoke_host_function_op_with_options(
+            snapshot_source.clone(),
+            &network_config,
+            &SimulationAdjustmentConfig::no_adjustments(),
+            &ledger_info,
+            host_fn.clone(),
+            RecordingInvocationAuthMode::Recording(true),
+            &source_account,
+            [1; 32],
+            false,
+            &SimulationOptions {
+                call_tree: with_call_tree,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    let res_without_tree = simulate(false);
+    assert_eq!(res_without_tree.call_tree, None);
+    let mut res = simulate(true);
+    let call_tree = res.call_tree.take().unwrap();
+    // Call tree metering doesn't affect the simulation results.
+    assert_eq!(res.transaction_data, res_without_tree.transaction_data);
+    assert_eq!(
+        res.simulated_instructions,
+        res_without_tree.simulated_instructions
+    );
+    assert_eq!(res.simulated_memory, res_without_tree.simulated_memory);
+
+    assert_eq!(call_tree.contract_id, None);
+    assert_eq!(call_tree.function_name, "InvokeContract");
+    assert!(!call_tree.failed);
+    assert!(call_tree.inclusive.instructions <= res.simulated_instructions as u64);
+    assert_eq!(call_tree.inclusive.read_entries, 3);
+    assert_eq!(call_tree.inclusive.write_entries, 0);
+
+    assert_eq!(call_tree.children.len(), 1);
+    let root_call = &call_tree.children[0];
+    let ScAddress::Contract(contract_id) = &contracts[0].contract_address else {
+        panic!("expected contract address");
+    };
+    assert_eq!(root_call.contract_id.as_ref(), Some(contract_id));
+    assert_eq!(root_call.function_name, "tree_fn");
+    assert_eq!(root_call.children.len(), 1);
+    let nested_call = &root_call.children[0];
+    assert_eq!(nested_call.function_name, "tree_fn");
+    assert!(nested_call.children.is_empty());
+    assert!(nested_call.inclusive.instructions > 0);
+    assert!(root_call.inclusive.instructions > nested_call.inclusive.instructions);
+}
+
+#[test]
+fn test_simulate_invoke_contract_with_module_cache() {
+    let contracts = [
+        CreateContractData::new([1; 32], AUTH_TEST_CONTRACT),
+        CreateContractData::new([2; 32], AUTH_TEST_CONTRACT),
+    ];
+    let tree = AuthContractInvocationNode {
+        address: contracts[0].contract_address.clone(),
+        children: vec![AuthContractInvocationNode {
+            address: contracts[1].contract_address.clone(),
+            children: vec![],
+        }],
+    };
+    let source_account = get_account_id([123; 32]);
+    let host_fn = auth_contract_invocation(vec![ScAddress::Account(source_account.clone())], tree);
+    let ledger_info = default_ledger_info();
+    let network_config = default_network_config();
+    let snapshot_source = Rc::new(
+        MockSnapshotSource::from_entries(vec![
+            (
+                contracts[0].wasm_entry.clone(),
+                Some(ledger_info.sequence_number + 100),
+            ),
+            (
+                contracts[0].contract_entry.clone(),
+                Some(ledger_info.sequence_number + 1000),
+            ),
+            (
+                contracts[1].contract_entry.clone(),
+                Some(ledger_info.sequence_number + 1000),
+            ),
+        ])
+        .unwrap(),
+    );
+    let module_cache = network_config.create_module_cache().unwrap();
+    let simulate = |with_module_cache: bool| {
+        simulate_invoke_host_function_op_with_options(
+            snapshot_source.clone(),
+            &network_config,
+            &SimulationAdjustmentConfig::no_adjustments(),
+            &ledger_info,
+            host_fn.clone(),
+            RecordingInvocationAuthMode::Recording(true),
+            &source_account,
+            [1; 32],
+            false,
+            &SimulationOptions {
+                module_cache: with_module_cache.then(|| module_cache.clone()),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    let uncached_res = simulate(false);
+    assert_eq!(uncached_res.invoke_result.as_ref().unwrap(), &ScVal::Void);
+    let wasm_hash = Hash(get_wasm_hash(AUTH_TEST_CONTRACT));
+    assert!(!module_cache.contains_module(&wasm_hash).unwrap());
+    // The first simulation populates the cache and the subsequent ones reuse
+    // it, while all of them produce the same results as the uncached one.
+    for _ in 0..2 {
+        let res = simulate(true);
+        assert!(module_cache.contains_module(&wasm_hash).unwrap());
+        assert_eq!(res.invoke_result.unwrap(), ScVal::Void);
+        assert_eq!(res.auth, uncached_res.auth);
+        assert_eq!(res.transaction_data, uncached_res.transaction_data);
+        assert_eq!(
+            res.simulated_instructions,
+            uncached_res.simulated_instructions
+        );
+        assert_eq!(res.simulated_memory, uncached_res.simulated_memory);
+    }
+}
+
 #[test]
 fn test_simulate_invoke_contract_with_autorestore() {
     let contracts = vec![
@@ -609,6 +771,189 @@ fn test_simulate_invoke_contract_with_autorestore() {
     );
 }
 
+#[test]
+fn test_simulate_invoke_contract_with_restore_preamble() {
+    let contracts = [
+        CreateContractData::new([1; 32], AUTH_TEST_CONTRACT),
+        CreateContractData::new([2; 32], AUTH_TEST_CONTRACT),
+    ];
+    let tree = AuthContractInvocationNode {
+        address: contracts[0].contract_address.clone(),
+        children: vec![AuthContractInvocationNode {
+            address: contracts[1].contract_address.clone(),
+            children: vec![],
+        }],
+    };
+    let source_account = get_account_id([123; 32]);
+    let host_fn = auth_contract_invocation(vec![ScAddress::Account(source_account.clone())], tree);
+    let ledger_info = default_ledger_info();
+    let network_config = default_network_config();
+    let snapshot_source = |archived_entries_live_until: u32| {
+        Rc::new(
+            MockSnapshotSource::from_entries(vec![
+                (
+                    contracts[0].wasm_entry.clone(),
+                    Some(archived_entries_live_until),
+                ),
+                (
+                    contracts[0].contract_entry.clone(),
+                    Some(ledger_info.sequence_number + 1000),
+                ),
+                (
+                    contracts[1].contract_entry.clone(),
+                    Some(archived_entries_live_until),
+                ),
+            ])
+            .unwrap(),
+        )
+    };
+    let archived_snapshot_source = snapshot_source(ledger_info.sequence_number - 1);
+
+    let res = simulate_invoke_host_function_op_with_options(
+        archived_snapshot_source.clone(),
+        &network_config,
+        &SimulationAdjustmentConfig::no_adjustments(),
+        &ledger_info,
+        host_fn.clone(),
+        RecordingInvocationAuthMode::Recording(true),
+        &source_account,
+        [1; 32],
+        false,
+        &SimulationOptions {
+            restore_preamble: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(res.invoke_result.unwrap(), ScVal::Void);
+    let keys_to_restore = vec![
+        contracts[1].contract_key.clone(),
+        contracts[0].wasm_key.clone(),
+    ]
+    .tap_mut(|v| v.sort());
+    let restore_result = simulate_restore_op(
+        archived_snapshot_source.as_ref(),
+        &network_config,
+        &SimulationAdjustmentConfig::no_adjustments(),
+        &ledger_info,
+        &keys_to_restore,
+    )
+    .unwrap();
+    assert_eq!(
+        res.restore_preamble,
+        Some(RestorePreamble {
+            keys_to_restore,
+            transaction_data: restore_result.transaction_data,
+        })
+    );
+
+    // The invocation is simulated as if the entries have been restored in
+    // the same ledger.
+    let restored_res = simulate_invoke_host_function_op(
+        snapshot_source(ledger_info.sequence_number + ledger_info.min_persistent_entry_ttl - 1),
+        &network_config,
+        &SimulationAdjustmentConfig::no_adjustments(),
+        &ledger_info,
+        host_fn,
+        RecordingInvocationAuthMode::Recording(true),
+        &source_account,
+        [1; 32],
+        false,
+    )
+    .unwrap();
+    assert_eq!(restored_res.restore_preamble, None);
+    assert_eq!(res.transaction_data, restored_res.transaction_data);
+    let transaction_data = res.transaction_data.unwrap();
+    assert_eq!(transaction_data.ext, SorobanTransactionDataExt::V0);
+    assert!(transaction_data.resources.footprint.read_write.is_empty());
+    assert_eq!(
+        res.simulated_instructions,
+        restored_res.simulated_instructions
+    );
+    assert!(res.modified_entries.is_empty());
+}
+
+#[test]
+fn test_simulate_invoke_contract_with_combined_options() {
+    let contracts = [
+        CreateContractData::new([1; 32], AUTH_TEST_CONTRACT),
+        CreateContractData::new([2; 32], AUTH_TEST_CONTRACT),
+    ];
+    let tree = AuthContractInvocationNode {
+        address: contracts[0].contract_address.clone(),
+        children: vec![AuthContractInvocationNode {
+            address: contracts[1].contract_address.clone(),
+            children: vec![],
+        }],
+    };
+    let source_account = get_account_id([123; 32]);
+    let host_fn = auth_contract_invocation(vec![ScAddress::Account(source_account.clone())], tree);
+    let ledger_info = default_ledger_info();
+    let network_config = default_network_config();
+    let snapshot_source = Rc::new(
+        MockSnapshotSource::from_entries(vec![
+            (
+                contracts[0].wasm_entry.clone(),
+                Some(ledger_info.sequence_number - 1),
+            ),
+            (
+                contracts[0].contract_entry.clone(),
+                Some(ledger_info.sequence_number + 1000),
+            ),
+            (
+                contracts[1].contract_entry.clone(),
+                Some(ledger_info.sequence_number + 1000),
+            ),
+        ])
+        .unwrap(),
+    );
+    let module_cache = network_config.create_module_cache().unwrap();
+    let simulate = |options: &SimulationOptions| {
+        simulate_invoke_host_function_op_with_options(
+            snapshot_source.clone(),
+            &network_config,
+            &SimulationAdjustmentConfig::no_adjustments(),
+            &ledger_info,
+            host_fn.clone(),
+            RecordingInvocationAuthMode::Recording(true),
+            &source_account,
+            [1; 32],
+            false,
+            options,
+        )
+        .unwrap()
+    };
+
+    let preamble_res = simulate(&SimulationOptions {
+        restore_preamble: true,
+        ..Default::default()
+    });
+    assert!(preamble_res.call_tree.is_none());
+    let wasm_hash = Hash(get_wasm_hash(AUTH_TEST_CONTRACT));
+    // Every option has the same effect as when it is used on its own.
+    for _ in 0..2 {
+        let res = simulate(&SimulationOptions {
+            call_tree: true,
+            restore_preamble: true,
+            module_cache: Some(module_cache.clone()),
+        });
+        assert!(module_cache.contains_module(&wasm_hash).unwrap());
+        assert_eq!(res.invoke_result.unwrap(), ScVal::Void);
+        assert!(res.call_tree.is_some());
+        assert_eq!(
+            res.restore_preamble.as_ref().unwrap().keys_to_restore,
+            vec![contracts[0].wasm_key.clone()]
+        );
+        assert_eq!(res.restore_preamble, preamble_res.restore_preamble);
+        assert_eq!(res.transaction_data, preamble_res.transaction_data);
+        assert_eq!(
+            res.simulated_instructions,
+            preamble_res.simulated_instructions
+        );
+        assert_eq!(res.simulated_memory, preamble_res.simulated_memory);
+    }
+}
+
 #[test]
 fn test_simulate_extend_ttl_op() {
     let ledger_info = default_ledger_info();
@@ -1,8 +1,11 @@
 use crate::network_config::NetworkConfig;
-use crate::simulation::{RestoreOpSimulationResult, SimulationAdjustmentConfig};
-use crate::snapshot_source::{AutoRestoringSnapshotSource, SimulationSnapshotSource};
+use crate::simulation::{LedgerEntryDiff, RestoreOpSimulationResult, SimulationAdjustmentConfig};
+use crate::snapshot_source::{
+    AutoRestoringSnapshotSource, LayeredSnapshotSource, SimulationSnapshotSource,
+};
 use crate::testutils::{ledger_entry_to_ledger_key, temp_entry, MockSnapshotSource};
 use pretty_assertions::assert_eq;
+use soroban_env_host::e2e_invoke::{LedgerEntryChange, LedgerEntryLiveUntilChange};
 use soroban_env_host::e2e_testutils::{
     account_entry, get_account_id, ledger_entry, wasm_entry_non_validated,
 };
@@ -10,12 +13,13 @@ use soroban_env_host::fees::{FeeConfiguration, RentFeeConfiguration};
 use soroban_env_host::storage::SnapshotSource;
 use soroban_env_host::xdr::{
     AccountEntry, AccountEntryExt, AccountEntryExtensionV1, AccountEntryExtensionV1Ext,
-    AccountEntryExtensionV2, AccountEntryExtensionV2Ext, AccountEntryExtensionV3, ExtensionPoint,
-    LedgerEntryData, LedgerFootprint, Liabilities, SequenceNumber, Signer, SignerKey,
+    AccountEntryExtensionV2, AccountEntryExtensionV2Ext, AccountEntryExtensionV3,
+    ContractDataDurability, ContractId, ExtensionPoint, Hash, LedgerEntry, LedgerEntryData,
+    LedgerEntryType, LedgerFootprint, Liabilities, ScAddress, SequenceNumber, Signer, SignerKey,
     SorobanResources, SorobanTransactionData, SorobanTransactionDataExt, SponsorshipDescriptor,
-    Thresholds, TimePoint, Uint256,
+    Thresholds, TimePoint, Uint256, WriteXdr,
 };
-use soroban_env_host::LedgerInfo;
+use soroban_env_host::{LedgerInfo, DEFAULT_XDR_RW_LIMITS};
 use std::rc::Rc;
 
 #[test]
@@ -373,3 +377,204 @@ fn test_simulation_snapshot_source_creates_account_extensions() {
         Some((Rc::new(account_with_ext_v3), None))
     );
 }
+
+#[test]
+fn test_layered_snapshot_source() {
+    let account = account_entry(&get_account_id([1; 32]));
+    let mut updated_account = account.clone();
+    if let LedgerEntryData::Account(acc) = &mut updated_account.data {
+        acc.balance = 1_000;
+    }
+    let wasm = wasm_entry_non_validated(b"1");
+    let removed_temp = temp_entry(b"2");
+    let created_temp = temp_entry(b"3");
+    let diff_temp = temp_entry(b"4");
+    let base = Rc::new(
+        MockSnapshotSource::from_entries(vec![
+            (account.clone(), None),
+            (wasm.clone(), Some(100)),
+            (removed_temp.clone(), Some(50)),
+        ])
+        .unwrap(),
+    );
+    let ledger_info = LedgerInfo {
+        sequence_number: 10,
+        min_temp_entry_ttl: 16,
+        min_persistent_entry_ttl: 100,
+        ..Default::default()
+    };
+    let get = |snapshot: &LayeredSnapshotSource<MockSnapshotSource>, entry: &LedgerEntry| {
+        snapshot
+            .get(&Rc::new(ledger_entry_to_ledger_key(entry).unwrap()))
+            .unwrap()
+            .map(|(e, live_until)| (e.as_ref().clone(), live_until))
+    };
+    let encoded_key = |e: &LedgerEntry| {
+        ledger_entry_to_ledger_key(e)
+            .unwrap()
+            .to_xdr(DEFAULT_XDR_RW_LIMITS)
+            .unwrap()
+    };
+    let ttl_change = |entry_type, new_live_until_ledger| {
+        Some(LedgerEntryLiveUntilChange {
+            key_hash: vec![0; 32],
+            durability: ContractDataDurability::Temporary,
+            entry_type,
+            old_live_until_ledger: 0,
+            new_live_until_ledger,
+        })
+    };
+
+    let mut snapshot = LayeredSnapshotSource::new(base.clone());
+    snapshot
+        .apply_ledger_changes(&[
+            LedgerEntryChange {
+                read_only: true,
+                encoded_key: encoded_key(&wasm),
+                ttl_change: ttl_change(LedgerEntryType::ContractCode, 500),
+                ..Default::default()
+            },
+            LedgerEntryChange {
+                read_only: false,
+                encoded_key: encoded_key(&removed_temp),
+                ..Default::default()
+            },
+            LedgerEntryChange {
+                read_only: false,
+                encoded_key: encoded_key(&created_temp),
+                encoded_new_value: Some(created_temp.to_xdr(DEFAULT_XDR_RW_LIMITS).unwrap()),
+                ttl_change: ttl_change(LedgerEntryType::ContractData, 300),
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+    assert_eq!(get(&snapshot, &wasm), Some((wasm.clone(), Some(500))));
+    assert_eq!(get(&snapshot, &removed_temp), None);
+    assert_eq!(
+        get(&snapshot, &created_temp),
+        Some((created_temp.clone(), Some(300)))
+    );
+    // The base is not modified.
+    assert_eq!(
+        base.get(&Rc::new(ledger_entry_to_ledger_key(&wasm).unwrap()))
+            .unwrap()
+            .unwrap()
+            .1,
+        Some(100)
+    );
+    // The contract data keys of the base and the layers are enumerated
+    // together, including the removed entries that `get` doesn't return.
+    let contract = ScAddress::Contract(ContractId(Hash([0; 32])));
+    assert_eq!(
+        snapshot
+            .contract_data_keys(&contract, ContractDataDurability::Temporary)
+            .unwrap(),
+        Some(vec![
+            Rc::new(ledger_entry_to_ledger_key(&removed_temp).unwrap()),
+            Rc::new(ledger_entry_to_ledger_key(&created_temp).unwrap()),
+        ])
+    );
+    assert_eq!(
+        snapshot
+            .contract_data_keys(&contract, ContractDataDurability::Persistent)
+            .unwrap(),
+        Some(vec![])
+    );
+
+    // Read-only changes that don't extend the TTL are ignored, while
+    // extending a missing entry is an error.
+    snapshot
+        .apply_ledger_changes(&[LedgerEntryChange {
+            read_only: true,
+            encoded_key: encoded_key(&wasm),
+            ttl_change: Some(LedgerEntryLiveUntilChange {
+                key_hash: vec![0; 32],
+                durability: ContractDataDurability::Persistent,
+                entry_type: LedgerEntryType::ContractCode,
+                old_live_until_ledger: 500,
+                new_live_until_ledger: 400,
+            }),
+            ..Default::default()
+        }])
+        .unwrap();
+    assert_eq!(get(&snapshot, &wasm), Some((wasm.clone(), Some(500))));
+    assert!(snapshot
+        .apply_ledger_changes(&[LedgerEntryChange {
+            read_only: true,
+            encoded_key: encoded_key(&diff_temp),
+            ttl_change: ttl_change(LedgerEntryType::ContractData, 300),
+            ..Default::default()
+        }])
+        .is_err());
+
+    // Rolled back modifications are discarded.
+    let diffs = vec![
+        LedgerEntryDiff {
+            state_before: Some(account.clone()),
+            state_after: Some(updated_account.clone()),
+        },
+        LedgerEntryDiff {
+            state_before: None,
+            state_after: Some(diff_temp.clone()),
+        },
+    ];
+    snapshot.checkpoint();
+    snapshot.apply_entry_diffs(&diffs, &ledger_info).unwrap();
+    assert_eq!(
+        get(&snapshot, &account),
+        Some((updated_account.clone(), None))
+    );
+    assert_eq!(
+        get(&snapshot, &diff_temp),
+        Some((diff_temp.clone(), Some(25)))
+    );
+    snapshot.rollback().unwrap();
+    assert_eq!(snapshot.checkpoint_depth(), 0);
+    assert_eq!(get(&snapshot, &account), Some((account.clone(), None)));
+    assert_eq!(get(&snapshot, &diff_temp), None);
+
+    // Nested checkpoints.
+    snapshot.checkpoint();
+    snapshot.apply_entry_diffs(&diffs, &ledger_info).unwrap();
+    snapshot.checkpoint();
+    assert_eq!(snapshot.checkpoint_depth(), 2);
+    snapshot
+        .apply_entry_diffs(
+            &[LedgerEntryDiff {
+                state_before: Some(wasm.clone()),
+                state_after: None,
+            }],
+            &ledger_info,
+        )
+        .unwrap();
+    assert_eq!(get(&snapshot, &wasm), None);
+    snapshot.rollback().unwrap();
+    assert_eq!(get(&snapshot, &wasm), Some((wasm.clone(), Some(500))));
+    snapshot.commit().unwrap();
+    assert_eq!(snapshot.checkpoint_depth(), 0);
+    assert!(snapshot.commit().is_err());
+    assert!(snapshot.rollback().is_err());
+
+    // TTL-only modification of `wasm` is not a part of the diff.
+    assert_eq!(
+        snapshot.diff_against_base().unwrap(),
+        vec![
+            LedgerEntryDiff {
+                state_before: Some(account),
+                state_after: Some(updated_account),
+            },
+            LedgerEntryDiff {
+                state_before: Some(removed_temp),
+                state_after: None,
+            },
+            LedgerEntryDiff {
+                state_before: None,
+                state_after: Some(created_temp),
+            },
+            LedgerEntryDiff {
+                state_before: None,
+                state_after: Some(diff_temp),
+            },
+        ]
+    );
+}
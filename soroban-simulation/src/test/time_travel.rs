@@ -0,0 +1,162 @@
+use crate::simulation::{simulate_invoke_host_function_op, SimulationAdjustmentConfig};
+use crate::test::simulation::default_network_config;
+use crate::testutils::{ledger_entry_to_ledger_key, MockSnapshotSource};
+use crate::time_travel::{
+    advance_ledger_info, simulate_invoke_host_function_op_at_future_ledger, ExpiredLedgerEntry,
+};
+use pretty_assertions::assert_eq;
+use soroban_env_host::e2e_invoke::RecordingInvocationAuthMode;
+use soroban_env_host::e2e_testutils::{
+    default_ledger_info, get_account_id, ledger_entry, CreateContractData,
+};
+use soroban_env_host::xdr::{
+    ContractDataDurability, ContractDataEntry, ExtensionPoint, HostFunction, InvokeContractArgs,
+    LedgerEntryData, ScSymbol, ScVal,
+};
+use soroban_test_wasms::CONTRACT_STORAGE;
+use std::rc::Rc;
+
+#[test]
+fn test_advance_ledger_info() {
+    let ledger_info = default_ledger_info();
+    let advanced = advance_ledger_info(&ledger_info, 100, 500).unwrap();
+    assert_eq!(advanced.sequence_number, ledger_info.sequence_number + 100);
+    assert_eq!(advanced.timestamp, ledger_info.timestamp + 500);
+    assert_eq!(advanced.network_id, ledger_info.network_id);
+
+    assert!(advance_ledger_info(&ledger_info, u32::MAX, 0).is_err());
+    assert!(advance_ledger_info(&ledger_info, 0, u64::MAX).is_err());
+}
+
+#[test]
+fn test_simulate_invoke_contract_at_future_ledger() {
+    let source_account = get_account_id([123; 32]);
+    let ledger_info = default_ledger_info();
+    let network_config = default_network_config();
+    let contract = CreateContractData::new([1; 32], CONTRACT_STORAGE);
+    let key = ScVal::Symbol(ScSymbol("key".try_into().unwrap()));
+    let temp_entry = ledger_entry(LedgerEntryData::ContractData(ContractDataEntry {
+        ext: ExtensionPoint::V0,
+        contract: contract.contract_address.clone(),
+        key: key.clone(),
+        durability: ContractDataDurability::Temporary,
+        val: ScVal::U64(5),
+    }));
+    let temp_key = ledger_entry_to_ledger_key(&temp_entry).unwrap();
+    let snapshot_source = Rc::new(
+        MockSnapshotSource::from_entries(vec![
+            (
+                contract.wasm_entry.clone(),
+                Some(ledger_info.sequence_number + 1000),
+            ),
+            (
+                contract.contract_entry.clone(),
+                Some(ledger_info.sequence_number + 20),
+            ),
+            (temp_entry, Some(ledger_info.sequence_number + 10)),
+        ])
+        .unwrap(),
+    );
+    let host_fn = HostFunction::InvokeContract(InvokeContractArgs {
+        contract_address: contract.contract_address.clone(),
+        function_name: ScSymbol("has_temporary".try_into().unwrap()),
+        args: vec![key].try_into().unwrap(),
+    });
+    let simulate_at = |ledgers: u32| {
+        simulate_invoke_host_function_op_at_future_ledger(
+            snapshot_source.clone(),
+            &network_config,
+            &SimulationAdjustmentConfig::no_adjustments(),
+            &ledger_info,
+            ledgers,
+            5 * ledgers as u64,
+            host_fn.clone(),
+            RecordingInvocationAuthMode::Recording(true),
+            &source_account,
+            [1; 32],
+            false,
+        )
+        .unwrap()
+    };
+
+    // Nothing expires in the near future, so the result is the same as for
+    // the regular simulation.
+    let res = simulate_at(5);
+    assert_eq!(
+        res.ledger_info.sequence_number,
+        ledger_info.sequence_number + 5
+    );
+    assert_eq!(res.ledger_info.timestamp, ledger_info.timestamp + 25);
+    assert_eq!(
+        res.simulation_result.invoke_result.as_ref().unwrap(),
+        &ScVal::Bool(true)
+    );
+    assert!(res.expired_entries.is_empty());
+    let current_res = simulate_invoke_host_function_op(
+        snapshot_source.clone(),
+        &network_config,
+        &SimulationAdjustmentConfig::no_adjustments(),
+        &ledger_info,
+        host_fn.clone(),
+        RecordingInvocationAuthMode::Recording(true),
+        &source_account,
+        [1; 32],
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        res.simulation_result.transaction_data,
+        current_res.transaction_data
+    );
+
+    // The temporary entry expires first.
+    let res = simulate_at(15);
+    assert_eq!(
+        res.simulation_result.invoke_result.as_ref().unwrap(),
+        &ScVal::Bool(false)
+    );
+    assert_eq!(
+        res.expired_entries,
+        vec![ExpiredLedgerEntry {
+            key: temp_key.clone(),
+            durability: ContractDataDurability::Temporary,
+            live_until_ledger: ledger_info.sequence_number + 10,
+            live_at_current_ledger: true,
+        }]
+    );
+    assert_eq!(res.extend_to, 15);
+    assert_eq!(res.keys_to_extend(), vec![temp_key.clone()]);
+    assert!(res.keys_to_restore().is_empty());
+
+    // Then the contract instance becomes archived and has to be restored by
+    // the invocation.
+    let res = simulate_at(100);
+    assert_eq!(
+        res.simulation_result.invoke_result.as_ref().unwrap(),
+        &ScVal::Bool(false)
+    );
+    let mut expected_entries = vec![
+        ExpiredLedgerEntry {
+            key: contract.contract_key.clone(),
+            durability: ContractDataDurability::Persistent,
+            live_until_ledger: ledger_info.sequence_number + 20,
+            live_at_current_ledger: true,
+        },
+        ExpiredLedgerEntry {
+            key: temp_key.clone(),
+            durability: ContractDataDurability::Temporary,
+            live_until_ledger: ledger_info.sequence_number + 10,
+            live_at_current_ledger: true,
+        },
+    ];
+    expected_entries.sort_by(|a, b| a.key.cmp(&b.key));
+    assert_eq!(res.expired_entries, expected_entries);
+    assert_eq!(res.extend_to, 100);
+    assert_eq!(res.keys_to_restore(), vec![contract.contract_key.clone()]);
+    let transaction_data = res.simulation_result.transaction_data.unwrap();
+    assert_eq!(
+        transaction_data.resources.footprint.read_write.to_vec(),
+        vec![contract.contract_key.clone()]
+    );
+    assert_ne!(transaction_data, current_res.transaction_data.unwrap());
+}
@@ -65,6 +65,23 @@ impl SnapshotSource for MockSnapshotSource {
             Ok(None)
         }
     }
+
+    fn contract_data_keys(
+        &self,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+    ) -> std::result::Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        Ok(Some(
+            self.map
+                .keys()
+                .filter(|key| {
+                    matches!(key.as_ref(), LedgerKey::ContractData(cd)
+                        if cd.contract == *contract && cd.durability == durability)
+                })
+                .cloned()
+                .collect(),
+        ))
+    }
 }
 
 pub fn temp_entry(key: &[u8]) -> LedgerEntry {
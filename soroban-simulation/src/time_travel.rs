@@ -0,0 +1,202 @@
+use crate::network_config::NetworkConfig;
+use crate::simulation::{
+    simulate_invoke_host_function_op, InvokeHostFunctionSimulationResult,
+    SimulationAdjustmentConfig,
+};
+use anyhow::{anyhow, Result};
+use soroban_env_host::e2e_invoke::RecordingInvocationAuthMode;
+use soroban_env_host::ledger_info::get_key_durability;
+use soroban_env_host::storage::{EntryWithLiveUntil, SnapshotSource};
+use soroban_env_host::xdr::{
+    AccountId, ContractDataDurability, HostFunction, LedgerKey, ScAddress, ScErrorCode, ScErrorType,
+};
+use soroban_env_host::{HostError, LedgerInfo};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Ledger entry accessed by the time travel simulation that is no longer
+/// live at the target ledger.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ExpiredLedgerEntry {
+    pub key: LedgerKey,
+    pub durability: ContractDataDurability,
+    /// Live until ledger of the entry in the original snapshot.
+    pub live_until_ledger: u32,
+    /// Whether the entry is still live at the original ledger, i.e. whether
+    /// its TTL can still be extended in order to prevent the expiration.
+    pub live_at_current_ledger: bool,
+}
+
+/// Result of simulating `InvokeHostFunctionOp` operation at a future ledger.
+#[derive(Debug)]
+pub struct TimeTravelSimulationResult {
+    /// Ledger info the invocation has been simulated with.
+    pub ledger_info: LedgerInfo,
+    /// Simulation result of the invocation at the target ledger. The archived
+    /// persistent entries are automatically restored by the invocation (and
+    /// the restoration is accounted for in the resources and fee), while the
+    /// expired temporary entries don't exist anymore.
+    pub simulation_result: InvokeHostFunctionSimulationResult,
+    /// All the accessed entries that are not live at the target ledger,
+    /// ordered by key.
+    pub expired_entries: Vec<ExpiredLedgerEntry>,
+    /// The `extend_to` value of `ExtendFootprintTtlOp` submitted at the
+    /// original ledger that keeps the entries live until the target ledger.
+    /// Note, that the extension is only possible if this doesn't exceed the
+    /// maximum entry TTL of the network.
+    pub extend_to: u32,
+}
+
+impl TimeTravelSimulationResult {
+    /// Returns the keys of the entries that are live at the original ledger
+    /// and that have to be extended by `extend_to` ledgers in order to stay
+    /// live at the target ledger.
+    pub fn keys_to_extend(&self) -> Vec<LedgerKey> {
+        self.expired_entries
+            .iter()
+            .filter(|e| e.live_at_current_ledger)
+            .map(|e| e.key.clone())
+            .collect()
+    }
+
+    /// Returns the keys of the persistent entries that will be archived at
+    /// the target ledger (unless extended beforehand) and thus will have to
+    /// be restored.
+    pub fn keys_to_restore(&self) -> Vec<LedgerKey> {
+        self.expired_entries
+            .iter()
+            .filter(|e| e.durability == ContractDataDurability::Persistent)
+            .map(|e| e.key.clone())
+            .collect()
+    }
+}
+
+/// Returns the copy of `ledger_info` with the sequence number advanced by
+/// `ledgers` and the timestamp advanced by `seconds`.
+pub fn advance_ledger_info(
+    ledger_info: &LedgerInfo,
+    ledgers: u32,
+    seconds: u64,
+) -> Result<LedgerInfo> {
+    let mut ledger_info = ledger_info.clone();
+    ledger_info.sequence_number = ledger_info
+        .sequence_number
+        .checked_add(ledgers)
+        .ok_or_else(|| anyhow!("ledger sequence number overflow"))?;
+    ledger_info.timestamp = ledger_info
+        .timestamp
+        .checked_add(seconds)
+        .ok_or_else(|| anyhow!("ledger timestamp overflow"))?;
+    Ok(ledger_info)
+}
+
+// Snapshot view at the target ledger: the expired temporary entries are
+// removed, while the archived persistent entries are kept (with their
+// original TTL), so that the host restores them automatically. Records the
+// TTL of every accessed entry with durability.
+struct TimeTravelSnapshotSource {
+    snapshot_source: Rc<dyn SnapshotSource>,
+    target_ledger_sequence: u32,
+    accessed_entries: RefCell<BTreeMap<Rc<LedgerKey>, u32>>,
+}
+
+impl SnapshotSource for TimeTravelSnapshotSource {
+    fn get(&self, key: &Rc<LedgerKey>) -> Result<Option<EntryWithLiveUntil>, HostError> {
+        let Some((entry, live_until)) = self.snapshot_source.get(key)? else {
+            return Ok(None);
+        };
+        let Some(durability) = get_key_durability(key.as_ref()) else {
+            return Ok(Some((entry, live_until)));
+        };
+        let live_until = live_until.ok_or_else(|| {
+            // Entries with durability must have TTL.
+            HostError::from((ScErrorType::Storage, ScErrorCode::InternalError))
+        })?;
+        self.accessed_entries
+            .try_borrow_mut()
+            .map_err(|_| HostError::from((ScErrorType::Context, ScErrorCode::InternalError)))?
+            .insert(key.clone(), live_until);
+        if live_until < self.target_ledger_sequence
+            && durability == ContractDataDurability::Temporary
+        {
+            return Ok(None);
+        }
+        Ok(Some((entry, Some(live_until))))
+    }
+
+    fn contract_data_keys(
+        &self,
+        contract: &ScAddress,
+        durability: ContractDataDurability,
+    ) -> Result<Option<Vec<Rc<LedgerKey>>>, HostError> {
+        self.snapshot_source
+            .contract_data_keys(contract, durability)
+    }
+}
+
+/// Simulates `InvokeHostFunctionOp` operation as if it was executed
+/// `ledgers` ledgers and `seconds` seconds after the ledger defined by
+/// `ledger_info`.
+///
+/// The ledger state is the one defined by `snapshot_source`, but with the
+/// TTL expiration applied at the target ledger: the expired temporary
+/// entries disappear and the expired persistent entries become archived.
+/// The accessed entries that are affected by the expiration are reported in
+/// the result, so that they can be extended before the target ledger (or
+/// restored after it).
+///
+/// The rest of parameters have the same meaning as for
+/// [`simulate_invoke_host_function_op`].
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_invoke_host_function_op_at_future_ledger(
+    snapshot_source: Rc<dyn SnapshotSource>,
+    network_config: &NetworkConfig,
+    adjustment_config: &SimulationAdjustmentConfig,
+    ledger_info: &LedgerInfo,
+    ledgers: u32,
+    seconds: u64,
+    host_fn: HostFunction,
+    auth_mode: RecordingInvocationAuthMode,
+    source_account: &AccountId,
+    base_prng_seed: [u8; 32],
+    enable_diagnostics: bool,
+) -> Result<TimeTravelSimulationResult> {
+    let target_ledger_info = advance_ledger_info(ledger_info, ledgers, seconds)?;
+    let time_travel_snapshot_source = Rc::new(TimeTravelSnapshotSource {
+        snapshot_source,
+        target_ledger_sequence: target_ledger_info.sequence_number,
+        accessed_entries: RefCell::new(Default::default()),
+    });
+    let simulation_result = simulate_invoke_host_function_op(
+        time_travel_snapshot_source.clone(),
+        network_config,
+        adjustment_config,
+        &target_ledger_info,
+        host_fn,
+        auth_mode,
+        source_account,
+        base_prng_seed,
+        enable_diagnostics,
+    )?;
+    let mut expired_entries = vec![];
+    for (key, live_until) in time_travel_snapshot_source.accessed_entries.borrow().iter() {
+        if *live_until >= target_ledger_info.sequence_number {
+            continue;
+        }
+        let durability = get_key_durability(key.as_ref())
+            .ok_or_else(|| anyhow!("accessed entry must have durability"))?;
+        expired_entries.push(ExpiredLedgerEntry {
+            key: key.as_ref().clone(),
+            durability,
+            live_until_ledger: *live_until,
+            live_at_current_ledger: *live_until >= ledger_info.sequence_number,
+        });
+    }
+    Ok(TimeTravelSimulationResult {
+        ledger_info: target_ledger_info,
+        simulation_result,
+        expired_entries,
+        extend_to: ledgers,
+    })
+}
@@ -0,0 +1,146 @@
+// Helpers for the files that consist of the XDR records, as used by
+// stellar-core for the bucket files.
+//
+// Every record is prefixed with a 4-byte header defined by the XDR record
+// marking standard (RFC 5531): the high bit marks the last fragment of the
+// record and the remaining bits contain the fragment length. Only
+// single-fragment records are supported.
+use anyhow::{anyhow, bail, Context, Result};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+const LAST_FRAGMENT_BIT: u32 = 0x8000_0000;
+
+// Location of a single record within the record files.
+#[derive(Clone, Copy)]
+pub(crate) struct RecordLocation {
+    file_index: usize,
+    offset: u64,
+    len: u32,
+}
+
+// A set of record files that are read on demand.
+//
+// The files are opened only when they are accessed, and at most one file is
+// kept open at a time, so that any number of files can be used.
+pub(crate) struct RecordFiles {
+    paths: Vec<PathBuf>,
+    open_file: RefCell<Option<(usize, File)>>,
+}
+
+impl RecordFiles {
+    pub(crate) fn open(paths: Vec<PathBuf>) -> Result<Self> {
+        for path in &paths {
+            if !path.is_file() {
+                bail!("{} is not a file", path.display());
+            }
+        }
+        Ok(Self {
+            paths,
+            open_file: RefCell::new(None),
+        })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    // Calls `f` for every record of the file in order.
+    pub(crate) fn for_each_record(
+        &self,
+        file_index: usize,
+        mut f: impl FnMut(RecordLocation, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let mut open_file = self.open_file.borrow_mut();
+        let file = self.get_file(&mut open_file, file_index)?;
+        let path = &self.paths[file_index];
+        let file_len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(file);
+        let mut offset = 0_u64;
+        let mut record = vec![];
+        while offset < file_len {
+            let mut header = [0; 4];
+            reader.read_exact(&mut header).with_context(|| {
+                format!(
+                    "{}: truncated record header at offset {offset}",
+                    path.display()
+                )
+            })?;
+            let header = u32::from_be_bytes(header);
+            if header & LAST_FRAGMENT_BIT == 0 {
+                bail!(
+                    "{}: multi-fragment record at offset {offset}",
+                    path.display()
+                );
+            }
+            let len = header & !LAST_FRAGMENT_BIT;
+            // Don't trust the header to allocate more than the file contains.
+            if offset + 4 + len as u64 > file_len {
+                bail!("{}: truncated record at offset {offset}", path.display());
+            }
+            record.resize(len as usize, 0);
+            reader.read_exact(&mut record).with_context(|| {
+                format!("{}: truncated record at offset {offset}", path.display())
+            })?;
+            let location = RecordLocation {
+                file_index,
+                offset: offset + 4,
+                len,
+            };
+            f(location, &record).with_context(|| {
+                format!("{}: malformed record at offset {offset}", path.display())
+            })?;
+            offset += 4 + len as u64;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_record(&self, location: RecordLocation) -> Result<Vec<u8>> {
+        let mut open_file = self.open_file.borrow_mut();
+        let file = self.get_file(&mut open_file, location.file_index)?;
+        let mut record = vec![0; location.len as usize];
+        file.seek(SeekFrom::Start(location.offset))
+            .and_then(|_| file.read_exact(&mut record))
+            .with_context(|| {
+                format!(
+                    "{}: failed to read record at offset {}",
+                    self.paths[location.file_index].display(),
+                    location.offset - 4
+                )
+            })?;
+        Ok(record)
+    }
+
+    // Returns the file with the given index, opening it instead of the
+    // currently open file if necessary.
+    fn get_file<'a>(
+        &self,
+        open_file: &'a mut Option<(usize, File)>,
+        file_index: usize,
+    ) -> Result<&'a mut File> {
+        let file = match open_file.take() {
+            Some((index, file)) if index == file_index => file,
+            _ => {
+                let path = self
+                    .paths
+                    .get(file_index)
+                    .ok_or_else(|| anyhow!("file index {file_index} is out of range"))?;
+                File::open(path).with_context(|| format!("failed to open {}", path.display()))?
+            }
+        };
+        Ok(&mut open_file.insert((file_index, file)).1)
+    }
+}
+
+pub(crate) fn write_record(writer: &mut impl Write, record: &[u8]) -> Result<()> {
+    let len = u32::try_from(record.len())
+        .ok()
+        .filter(|len| len & LAST_FRAGMENT_BIT == 0)
+        .ok_or_else(|| anyhow!("record is too large"))?;
+    writer.write_all(&(len | LAST_FRAGMENT_BIT).to_be_bytes())?;
+    writer.write_all(record)?;
+    Ok(())
+}